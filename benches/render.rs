@@ -0,0 +1,139 @@
+//! What runs on every frame: parsing session output into the terminal
+//! emulator (escape sequences and SGR colors), turning its rows into spans,
+//! and rendering and wrapping the chat's markdown.
+//!
+//! `cargo bench --bench render`
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use ratatui::text::Span;
+use sheesh_rs::{tabs::terminal::TermEmulator, ui::markdown};
+
+/// Session output as a busy host produces it: colored `ls -l`, log lines
+/// with 256-color and truecolor timestamps, and a progress bar redrawn
+/// with carriage returns.
+fn session_output(lines: usize) -> Vec<u8> {
+    let mut out = String::new();
+    for i in 0..lines {
+        match i % 4 {
+            0 => out.push_str(&format!(
+                "drwxr-xr-x  4 deploy deploy 4096 Oct 16 12:{:02} \x1b[01;34mrelease-{}\x1b[0m  \
+                 -rwxr-xr-x 1 deploy deploy 1822 Oct 16 \x1b[01;32mdeploy.sh\x1b[0m  \x1b[01;36mcurrent\x1b[0m -> release-{}\r\n",
+                i % 60,
+                i,
+                i
+            )),
+            1 => out.push_str(&format!(
+                "\x1b[38;5;244m2026-10-16T12:00:{:02}Z\x1b[0m \x1b[1;31mERROR\x1b[0m worker[{}]: request \
+                 \x1b[38;2;205;115;80mGET /api/v1/orders/{}\x1b[0m failed: upstream timed out after 30s\r\n",
+                i % 60,
+                i % 16,
+                i * 7
+            )),
+            2 => out.push_str(&format!(
+                "\x1b[2m{:>6}\x1b[22m  \x1b[32mINFO\x1b[39m  cache hit ratio \x1b[1m{}.{}%\x1b[0m, \
+                 \x1b[7m evictions \x1b[27m {}, p99 \x1b[4m{} ms\x1b[24m\r\n",
+                i,
+                90 + i % 10,
+                i % 10,
+                i * 3,
+                i % 250
+            )),
+            _ => {
+                for step in 0..4 {
+                    let done = step * 10;
+                    out.push_str(&format!(
+                        "\r\x1b[K\x1b[33m[{}{}]\x1b[0m {}%",
+                        "#".repeat(done),
+                        " ".repeat(40 - done),
+                        done * 100 / 40
+                    ));
+                }
+                out.push_str("\r\n");
+            }
+        }
+    }
+    out.into_bytes()
+}
+
+/// An assistant transcript: questions, answers with inline markup, lists,
+/// fenced shell blocks and a table, as the chat keeps them line by line.
+fn transcript(messages: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for i in 0..messages {
+        lines.push(format!("You: why is `nginx` returning **502** on host web-{} since the deploy?", i));
+        lines.push(String::new());
+        lines.push(format!(
+            "Claude: The upstream on **port 80{:02}** is refusing connections, so nginx has nothing to proxy to. \
+             The *most likely* cause is that `app.service` failed to start after the deploy; the __error log__ \
+             should say so. Check it with `journalctl -u app -n 50` and look for _bind_ or *permission* errors.",
+            i % 100
+        ));
+        lines.push("      ".to_string());
+        lines.push("      ## What to check".to_string());
+        lines.push("      - whether the service is **running**: `systemctl status app`".to_string());
+        lines.push("      - whether something else holds the port (`ss -ltnp`), which is *common* after restarts".to_string());
+        lines.push("      ```bash".to_string());
+        lines.push("      sudo systemctl restart app && sudo journalctl -u app -f --since '5 min ago'".to_string());
+        lines.push("      ```".to_string());
+        lines.push("      | unit | state | since |".to_string());
+        lines.push("      | app.service | failed | 12:04 |".to_string());
+        lines.push(format!("      · 12:{:02} · claude · 812 in / 164 out", i % 60));
+        lines.push(String::new());
+    }
+    lines
+}
+
+fn terminal(c: &mut Criterion) {
+    let output = session_output(2000);
+    // ssh hands the output over in reads of up to 8 KiB.
+    let reads: Vec<&[u8]> = output.chunks(8192).collect();
+
+    c.bench_function("terminal/process", |b| {
+        b.iter_batched(
+            || TermEmulator::new(50, 200),
+            |mut emu| {
+                for read in &reads {
+                    emu.process(read);
+                }
+                emu
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut emu = TermEmulator::new(50, 200);
+    for read in &reads {
+        emu.process(read);
+    }
+    c.bench_function("terminal/all_rows", |b| b.iter(|| black_box(emu.all_rows())));
+}
+
+fn chat(c: &mut Criterion) {
+    let lines = transcript(60);
+    let in_code = markdown::code_lines(lines.iter().map(String::as_str));
+
+    c.bench_function("markdown/parse_inline", |b| {
+        b.iter(|| {
+            let mut spans: Vec<Span<'static>> = Vec::new();
+            for line in &lines {
+                spans.clear();
+                markdown::parse_inline(markdown::line_content(line), &mut spans);
+                black_box(&spans);
+            }
+        })
+    });
+
+    c.bench_function("markdown/render_and_wrap", |b| {
+        b.iter(|| {
+            for (line, &code) in lines.iter().zip(&in_code) {
+                let rendered = markdown::render_line(line, code);
+                black_box(markdown::wrap_spans(rendered.spans, 72));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, terminal, chat);
+criterion_main!(benches);
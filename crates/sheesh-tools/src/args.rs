@@ -0,0 +1,163 @@
+//! Typed tool arguments. A tool declares its arguments once, as a struct in
+//! the [`tool_args!`] macro; the macro derives both the schema sent to the
+//! model (field docs become descriptions, `= value` becomes the default) and
+//! the deserialisation of the model's input into that struct.
+
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::schema::{Param, ParamType, object_schema};
+
+/// Rust types usable as tool argument fields.
+pub trait SchemaType {
+    fn param_type() -> ParamType;
+    /// Whether the model must always supply a value.
+    const REQUIRED: bool = true;
+}
+
+impl SchemaType for String {
+    fn param_type() -> ParamType {
+        ParamType::String
+    }
+}
+
+impl SchemaType for bool {
+    fn param_type() -> ParamType {
+        ParamType::Boolean
+    }
+}
+
+impl SchemaType for f64 {
+    fn param_type() -> ParamType {
+        ParamType::Number
+    }
+}
+
+macro_rules! integer_schema_type {
+    ($($t:ty),*) => {$(
+        impl SchemaType for $t {
+            fn param_type() -> ParamType {
+                ParamType::Integer
+            }
+        }
+    )*};
+}
+
+integer_schema_type!(u32, u64, usize, i64);
+
+impl<T: SchemaType> SchemaType for Vec<T> {
+    fn param_type() -> ParamType {
+        ParamType::Array(Box::new(T::param_type()))
+    }
+}
+
+impl<T: SchemaType> SchemaType for Option<T> {
+    fn param_type() -> ParamType {
+        T::param_type()
+    }
+    const REQUIRED: bool = false;
+}
+
+/// Arguments of a tool, declared with [`tool_args!`].
+pub trait ToolArgs: DeserializeOwned {
+    /// Parameters for the tool's schema, one per field.
+    fn params() -> Vec<Param>;
+
+    /// Deserialise a tool call's input, filling in declared defaults first.
+    /// Errors name the tool, e.g. "edit_file: missing field `old_text`".
+    fn parse(tool: &str, input: &Value) -> Result<Self> {
+        let mut input = if input.is_object() { input.clone() } else { json!({}) };
+        for param in Self::params() {
+            if let Some(default) = param.default
+                && input.get(&param.name).is_none_or(Value::is_null)
+            {
+                input[&param.name] = default;
+            }
+        }
+        serde_json::from_value(input).map_err(|e| anyhow!("{}: {}", tool, e))
+    }
+
+    /// Schema of the arguments as a JSON object.
+    fn schema() -> Value {
+        object_schema(&Self::params())
+    }
+}
+
+/// Param for a field of type `T`. Used by [`tool_args!`].
+pub fn field_param<T: SchemaType>(name: &str, docs: &[&str], default: Option<Value>) -> Param {
+    let description = docs.iter().map(|d| d.trim()).collect::<Vec<_>>().join(" ");
+    Param {
+        name: name.to_string(),
+        ty: T::param_type(),
+        description: (!description.is_empty()).then_some(description),
+        required: T::REQUIRED && default.is_none(),
+        default,
+    }
+}
+
+/// Declare a tool's argument struct and derive its schema and parsing:
+///
+/// ```text
+/// tool_args! {
+///     /// Arguments of `disk_usage`.
+///     pub struct DiskUsageArgs {
+///         /// Directory to analyse.
+///         path: String = "/",
+///         /// How many directories to list.
+///         limit: u64 = 10,
+///     }
+/// }
+/// ```
+///
+/// Fields are required unless they are `Option<_>` or have a default.
+macro_rules! tool_args {
+    (
+        $(#[doc = $sdoc:literal])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $field:ident : $ty:ty $(= $default:expr)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[doc = $sdoc])*
+        #[derive(Debug, Clone, serde::Deserialize)]
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::args::ToolArgs for $name {
+            fn params() -> Vec<$crate::schema::Param> {
+                vec![$(
+                    $crate::args::field_param::<$ty>(
+                        stringify!($field),
+                        &[$($doc),*],
+                        $crate::args::arg_default!($($default)?),
+                    )
+                ),*]
+            }
+        }
+
+        impl $crate::args::SchemaType for $name {
+            fn param_type() -> $crate::schema::ParamType {
+                $crate::schema::ParamType::Object(<$name as $crate::args::ToolArgs>::params())
+            }
+        }
+    };
+}
+
+/// `Some(default)` as JSON, or `None` when the field declares no default.
+macro_rules! arg_default {
+    () => {
+        None
+    };
+    ($default:expr) => {
+        Some(serde_json::json!($default))
+    };
+}
+
+pub(crate) use {arg_default, tool_args};
@@ -0,0 +1,225 @@
+//! Remote operations tools can run directly on a session, outside the PTY.
+//! An [`SshContext`] only has to implement [`exec`](SshContext::exec); the
+//! file helpers are built on it with the same shell snippets the PTY tools
+//! use, and can be overridden by backends with a faster path (e.g. SFTP).
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+
+use crate::shell_quote;
+use crate::telemetry::ProgressSink;
+use crate::write::{FileWrite, WriteKind};
+
+/// Result of a command run with [`SshContext::exec`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Exit status, or `None` if the command was killed by a signal.
+    pub status: Option<i32>,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+/// A remote host tools can act on.
+pub trait SshContext {
+    /// Run `command` through the remote user's shell and wait for it to exit.
+    fn exec(&self, command: &str) -> Result<ExecOutput>;
+
+    /// Like [`exec`](Self::exec), reporting output as it arrives. Backends
+    /// that can't stream report once, when the command has exited.
+    fn exec_with_progress(&self, command: &str, progress: &dyn ProgressSink) -> Result<ExecOutput> {
+        let out = self.exec(command)?;
+        progress.report(out.stdout.len() + out.stderr.len(), None);
+        Ok(out)
+    }
+
+    fn path_exists(&self, path: &str) -> Result<bool> {
+        Ok(self.exec(&format!("test -e {}", shell_quote(path)))?.success())
+    }
+
+    /// Names of the entries in directory `path`, including dotfiles.
+    fn list_dir(&self, path: &str) -> Result<Vec<String>> {
+        let out = self.exec(&format!("ls -A1 -- {}", shell_quote(path)))?;
+        if !out.success() {
+            bail!("listing {} failed: {}", path, out.stderr.trim());
+        }
+        Ok(out.stdout.lines().map(str::to_string).collect())
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        let out = self.exec(&format!("cat -- {}", shell_quote(path)))?;
+        if !out.success() {
+            bail!("reading {} failed: {}", path, out.stderr.trim());
+        }
+        Ok(out.stdout)
+    }
+
+    /// Replace the content of `path`, creating it if needed.
+    fn write_file(&self, path: &str, content: &str) -> Result<()> {
+        let write = FileWrite { path: path.to_string(), kind: WriteKind::Replace { content: content.to_string() } };
+        let out = self.exec(&write.write_command(content, None))?;
+        if !out.success() {
+            bail!("writing {} failed: {}", path, out.stderr.trim());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Exists(String),
+    List(String),
+    Read(String),
+}
+
+#[derive(Debug, Clone)]
+enum Cached {
+    Exists(bool),
+    List(Vec<String>),
+    Read(String),
+}
+
+/// Decorator that remembers `path_exists`, `list_dir` and `read_file`
+/// answers for a short time, so a model re-reading the same file during one
+/// turn doesn't pay for the round trip again. Anything that may change the
+/// host (`exec`, `write_file`) forgets everything.
+pub struct CachedContext<C> {
+    inner: C,
+    ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, (Instant, Cached)>>,
+}
+
+impl<C: SshContext> CachedContext<C> {
+    pub fn new(inner: C, ttl: Duration) -> Self {
+        Self { inner, ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// Forget every cached answer, e.g. when a new agent turn starts.
+    pub fn invalidate(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Cached> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((at, value)) if at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: CacheKey, value: Cached) {
+        self.entries.lock().unwrap().insert(key, (Instant::now(), value));
+    }
+}
+
+impl<C: SshContext> SshContext for CachedContext<C> {
+    fn exec(&self, command: &str) -> Result<ExecOutput> {
+        self.invalidate();
+        self.inner.exec(command)
+    }
+
+    fn exec_with_progress(&self, command: &str, progress: &dyn ProgressSink) -> Result<ExecOutput> {
+        self.invalidate();
+        self.inner.exec_with_progress(command, progress)
+    }
+
+    fn path_exists(&self, path: &str) -> Result<bool> {
+        let key = CacheKey::Exists(path.to_string());
+        if let Some(Cached::Exists(exists)) = self.cached(&key) {
+            return Ok(exists);
+        }
+        let exists = self.inner.path_exists(path)?;
+        self.store(key, Cached::Exists(exists));
+        Ok(exists)
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<String>> {
+        let key = CacheKey::List(path.to_string());
+        if let Some(Cached::List(entries)) = self.cached(&key) {
+            return Ok(entries);
+        }
+        let entries = self.inner.list_dir(path)?;
+        self.store(key, Cached::List(entries.clone()));
+        Ok(entries)
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        let key = CacheKey::Read(path.to_string());
+        if let Some(Cached::Read(content)) = self.cached(&key) {
+            return Ok(content);
+        }
+        let content = self.inner.read_file(path)?;
+        self.store(key, Cached::Read(content.clone()));
+        Ok(content)
+    }
+
+    fn write_file(&self, path: &str, content: &str) -> Result<()> {
+        self.invalidate();
+        self.inner.write_file(path, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Host whose files all read as their own path, recording each command.
+    #[derive(Default)]
+    struct Echo(Mutex<Vec<String>>);
+
+    impl Echo {
+        fn commands(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl SshContext for Echo {
+        fn exec(&self, command: &str) -> Result<ExecOutput> {
+            self.0.lock().unwrap().push(command.to_string());
+            let stdout = command.rsplit(' ').next().unwrap_or_default().trim_matches('\'').to_string();
+            Ok(ExecOutput { stdout, stderr: String::new(), status: Some(0) })
+        }
+    }
+
+    #[test]
+    fn cached_context_answers_reads_again_until_something_may_change() {
+        let ctx = CachedContext::new(Echo::default(), Duration::from_secs(60));
+        for _ in 0..2 {
+            assert_eq!(ctx.read_file("/etc/hosts").unwrap(), "/etc/hosts");
+            assert!(ctx.path_exists("/etc/hosts").unwrap());
+            assert_eq!(ctx.list_dir("/etc").unwrap(), ["/etc"]);
+        }
+        assert_eq!(ctx.inner().commands(), ["cat -- '/etc/hosts'", "test -e '/etc/hosts'", "ls -A1 -- '/etc'"]);
+
+        ctx.exec("true").unwrap();
+        ctx.read_file("/etc/hosts").unwrap();
+        ctx.write_file("/etc/hosts", "::1 localhost\n").unwrap();
+        ctx.read_file("/etc/hosts").unwrap();
+        let commands = ctx.inner().commands();
+        assert_eq!(commands.iter().filter(|c| c.starts_with("cat ")).count(), 3, "{:#?}", commands);
+    }
+
+    #[test]
+    fn cached_answers_expire() {
+        let ctx = CachedContext::new(Echo::default(), Duration::ZERO);
+        ctx.path_exists("/srv").unwrap();
+        ctx.path_exists("/srv").unwrap();
+        assert_eq!(ctx.inner().commands().len(), 2);
+    }
+}
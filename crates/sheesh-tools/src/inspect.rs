@@ -0,0 +1,143 @@
+//! Read-only inspection tools. Each builds one POSIX `sh` script that works
+//! out the host's flavour (package manager, init system, …) by itself and
+//! prints its findings as labelled `key: value` lines, so the model gets the
+//! same shape of answer on every distro instead of guessing which tool exists.
+//!
+//! Scripts run through `sh -c` so they behave the same whatever the user's
+//! login shell is; arguments are passed as positional parameters, never
+//! spliced into the script.
+
+use crate::shell_quote;
+
+/// Run `script` under `sh` with `args` as `$1`, `$2`, ….
+fn sh_command(script: &str, args: &[&str]) -> String {
+    let mut cmd = format!("sh -c {} sh", shell_quote(script));
+    for arg in args {
+        cmd.push(' ');
+        cmd.push_str(&shell_quote(arg));
+    }
+    cmd
+}
+
+// Sets `v` to the installed version (empty when not installed) and `l` to
+// the newest version in the locally cached repository metadata.
+const PACKAGE_INFO: &str = r#"p="$1"; v=; l=
+if command -v dpkg-query >/dev/null 2>&1; then m=apt
+  v=$(dpkg-query -W -f='${Status} ${Version}\n' "$p" 2>/dev/null | awk '$3=="installed"{print $4}')
+  l=$(apt-cache policy "$p" 2>/dev/null | awk '/Candidate:/{print $2}'); [ "$l" = "(none)" ] && l=
+elif command -v rpm >/dev/null 2>&1 && { command -v dnf || command -v yum; } >/dev/null 2>&1; then
+  m=$(command -v dnf >/dev/null 2>&1 && echo dnf || echo yum)
+  rpm -q "$p" >/dev/null 2>&1 && v=$(rpm -q --qf '%{VERSION}-%{RELEASE}\n' "$p" | tail -n1)
+  if [ -n "$v" ]; then s=--upgrades; else s=--available; fi
+  l=$($m -q list $s "$p" 2>/dev/null | awk -v p="$p" 'index($1,p".")==1{print $2}' | tail -n1); [ -z "$l" ] && l=$v
+elif command -v pacman >/dev/null 2>&1; then m=pacman
+  v=$(pacman -Q "$p" 2>/dev/null | awk '{print $2}')
+  l=$(pacman -Si "$p" 2>/dev/null | awk -F': *' '/^Version/{print $2; exit}')
+elif command -v apk >/dev/null 2>&1; then m=apk
+  v=$(apk list -I "$p" 2>/dev/null | awk '{print $1; exit}'); v=${v#"$p"-}
+  if [ -n "$v" ]; then l=$(apk list -u "$p" 2>/dev/null | awk '{print $1; exit}'); l=${l#"$p"-}; [ -z "$l" ] && l=$v
+  else l=$(apk list "$p" 2>/dev/null | awk '{print $1; exit}'); l=${l#"$p"-}; fi
+else echo "error: no supported package manager (apt, dnf, yum, pacman, apk)"; exit 1; fi
+echo "manager: $m"; echo "package: $p"
+if [ -n "$v" ]; then echo "installed: yes"; echo "version: $v"; else echo "installed: no"; fi
+echo "latest: ${l:-unknown}"
+if [ -n "$v" ] && [ -n "$l" ] && [ "$l" != "$v" ]; then echo "update_available: yes"; else echo "update_available: no"; fi"#;
+
+/// Shell command answering whether `package` is installed, at which version,
+/// and whether the repositories have a newer one.
+pub fn package_info_command(package: &str) -> String {
+    sh_command(PACKAGE_INFO, &[package])
+}
+
+// `du` stays on the path's filesystem, one level deep, and gives up after a
+// minute; the biggest `$2` entries are printed largest first.
+const DISK_USAGE: &str = r#"d="$1"; n="$2"
+df -hP "$d" 2>/dev/null | awk 'NR==2{print "filesystem: "$1; print "mounted_on: "$6; print "size: "$2; print "used: "$3" ("$5")"; print "available: "$4}'
+df -iP "$d" 2>/dev/null | awk 'NR==2 && $5!="-"{print "inodes_used: "$5}'
+t=; command -v timeout >/dev/null 2>&1 && t="timeout 60"
+o=$($t du -xk -d 1 "$d" 2>/dev/null); rc=$?
+[ -z "$o" ] && { echo "error: cannot read $d"; exit 1; }
+echo "largest:"
+printf '%s\n' "$o" | sort -rn | head -n $((n + 1)) | awk -F'\t' '{s=$1; u="K"; if(s>=1024){s/=1024;u="M"} if(s>=1024){s/=1024;u="G"} if(s>=1024){s/=1024;u="T"} printf "  %7.1f%s  %s\n", s, u, $2}'
+[ $rc -eq 124 ] && echo "note: du stopped after 60s; sizes are partial"
+[ $rc -eq 1 ] && echo "note: some directories could not be read; sizes are lower bounds"
+exit 0"#;
+
+/// Shell command showing how full the filesystem holding `path` is and its
+/// `limit` biggest directories (the first `largest` line is `path` itself).
+pub fn disk_usage_command(path: &str, limit: u64) -> String {
+    sh_command(DISK_USAGE, &[path, &limit.to_string()])
+}
+
+// `cron SOURCE [USER]` turns crontab lines on stdin into job rows; without
+// USER the line's sixth field is the user (system crontab format).
+const LIST_SCHEDULED: &str = r#"cron() { awk -v src="$1" -v user="$2" '
+/^[[:space:]]*(#|$)/ || /^[[:space:]]*[A-Za-z_][A-Za-z0-9_]*[[:space:]]*=/ {next}
+{ if ($1 ~ /^@/) {s=$1; i=2} else {s=$1" "$2" "$3" "$4" "$5; i=6}
+  u=user; if (u=="") {u=$i; i++}
+  c=$i; for (i++; i<=NF; i++) c=c" "$i
+  print src" | "s" | "u" | "c }'; }
+jobs() {
+  crontab -l 2>/dev/null | cron "crontab" "$me"
+  for f in /var/spool/cron/crontabs/* /var/spool/cron/*; do
+    [ -f "$f" ] && [ "${f##*/}" != "$me" ] && [ -r "$f" ] && cron "crontab" "${f##*/}" < "$f"
+  done
+  [ -r /etc/crontab ] && cron /etc/crontab < /etc/crontab
+  for f in /etc/cron.d/*; do [ -f "$f" ] && [ -r "$f" ] && cron "$f" < "$f"; done
+  for p in hourly daily weekly monthly; do
+    for f in /etc/cron.$p/*; do [ -f "$f" ] && [ -x "$f" ] && echo "/etc/cron.$p | @$p | root | $f"; done
+  done
+  command -v systemctl >/dev/null 2>&1 || return 0
+  systemctl list-timers --all --no-legend --no-pager 2>/dev/null |
+    awk '{for (i=1; i<=NF; i++) if ($i ~ /\.timer$/) {print $i, $(i+1); break}}' |
+    while read -r t a; do
+      s=$(systemctl show "$t" -p TimersCalendar -p TimersMonotonic --no-pager 2>/dev/null |
+        sed -n 's/.*{ *\(On[A-Za-z]*=[^;]*[^; ]\) *;.*/\1/p' | paste -sd, -)
+      n=$(systemctl show "$t" -p NextElapseUSecRealtime --value 2>/dev/null)
+      u=$(systemctl show "$a" -p User --value 2>/dev/null)
+      c=$(systemctl show "$a" -p ExecStart --value 2>/dev/null | sed -n 's/.*argv\[\]=\([^;]*[^; ]\) *;.*/\1/p' | head -n1)
+      echo "timer $t | ${s:-?}${n:+ (next $n)} | ${u:-root} | $a: ${c:-?}"
+    done
+}
+me=$(id -un); o=$(jobs)
+echo "source | schedule | user | command"
+if [ -n "$o" ]; then printf '%s\n' "$o"; else echo "(no scheduled jobs found)"; fi
+[ -d /var/spool/cron ] && [ ! -r /var/spool/cron/crontabs ] && [ ! -r /var/spool/cron/root ] && [ "$me" != root ] &&
+  echo "note: other users' crontabs are only readable as root"
+exit 0"#;
+
+/// Shell command listing cron jobs (the user's and, where readable, other
+/// users' crontabs, /etc/crontab, /etc/cron.d and the periodic directories)
+/// and systemd timers as `source | schedule | user | command` rows.
+pub fn list_scheduled_command() -> String {
+    sh_command(LIST_SCHEDULED, &[])
+}
+
+// Long lists (status, diff stat) are cut at 30 lines with a count of the rest.
+const GIT_INFO: &str = r#"d="$1"; n="$2"
+cap() { awk 'NR<=30{print "  "$0} END{if (NR>30) print "  … "NR-30" more"}'; }
+command -v git >/dev/null 2>&1 || { echo "error: git is not installed"; exit 1; }
+cd "$d" 2>/dev/null || { echo "error: cannot enter $d"; exit 1; }
+top=$(git rev-parse --show-toplevel 2>&1) || { echo "error: $top"; exit 1; }
+g() { git --no-pager -c color.ui=false "$@" 2>/dev/null; }
+echo "repository: $top"
+b=$(g symbolic-ref --short -q HEAD); echo "branch: ${b:-(detached HEAD)}"
+echo "head: $(g log -1 --format='%h %s' || echo '(no commits)')"
+t=$(g describe --tags --dirty); [ -n "$t" ] && echo "describe: $t"
+r=$(g remote get-url origin) && echo "origin: $r"
+if u=$(g rev-parse --abbrev-ref --symbolic-full-name '@{u}'); then
+  echo "upstream: $u"
+  g rev-list --left-right --count 'HEAD...@{u}' | awk '{print "ahead: "$1; print "behind: "$2}'
+fi
+s=$(g status --porcelain); echo "dirty: $([ -n "$s" ] && echo yes || echo no)"
+if [ -n "$s" ]; then
+  echo "status:"; printf '%s\n' "$s" | cap
+  echo "diff_stat:"; g diff HEAD --stat=120 | cap
+fi
+echo "recent_commits:"; g log -n "$n" --format='%h %ad %an: %s' --date=short | cap"#;
+
+/// Shell command summarising the git checkout containing `path`: branch,
+/// HEAD, upstream drift, uncommitted changes and the last `commits` commits.
+pub fn git_info_command(path: &str, commits: u64) -> String {
+    sh_command(GIT_INFO, &[path, &commits.to_string()])
+}
@@ -1,78 +1,616 @@
 use anyhow::Result;
-use serde_json::{json, Value};
+use serde_json::Value;
 
-/// All tool definitions in Anthropic's input_schema format.
-/// Providers targeting other APIs (OpenAI, Ollama) should convert as needed.
-pub fn all_tools() -> Value {
-    json!([
-        {
-            "name": "run_command",
-            "description": "Execute an arbitrary shell command on the user's remote SSH session. \
-                             The user will be shown the command and must approve before it runs.",
-            "input_schema": {
-                "type": "object",
-                "properties": {
-                    "command": { "type": "string", "description": "The exact shell command to execute." },
-                    "description": { "type": "string", "description": "One-sentence plain-English explanation of what this command does." }
-                },
-                "required": ["command"]
+use args::tool_args;
+
+pub mod args;
+pub mod context;
+pub mod inspect;
+pub mod local;
+pub mod mock;
+pub mod output;
+pub mod registry;
+pub mod schema;
+pub mod telemetry;
+pub mod write;
+
+pub use args::{SchemaType, ToolArgs};
+pub use context::{CachedContext, ExecOutput, SshContext};
+pub use local::{LocalPermission, LocalTool};
+pub use mock::{MockCall, MockSshContext};
+pub use output::{OutputLimits, OutputStore};
+pub use registry::{CallProgress, ToolDef, ToolGroup, ToolRegistry};
+pub use schema::{Param, ParamType};
+pub use telemetry::{ProgressSink, ToolCallEnd, ToolObserver, ToolProgress};
+pub use write::{FileWrite, WriteKind, diff_stat, unified_diff};
+
+tool_args! {
+    /// Arguments of `run_command`.
+    pub struct RunCommandArgs {
+        /// The exact shell command to execute.
+        command: String,
+        /// One-sentence plain-English explanation of what this command does.
+        description: Option<String>,
+    }
+}
+
+tool_args! {
+    /// Arguments of `make_dir`.
+    pub struct MakeDirArgs {
+        /// Absolute or relative path of the directory to create.
+        path: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `touch_file`.
+    pub struct TouchFileArgs {
+        /// Path of the file to create or touch.
+        file: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `read_file`.
+    pub struct ReadFileArgs {
+        /// Path of the file to read.
+        file: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `write_file`.
+    pub struct WriteFileArgs {
+        /// Path of the file to write.
+        file: String,
+        /// Complete new content of the file.
+        content: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `append_file`.
+    pub struct AppendFileArgs {
+        /// Path of the file to append to.
+        file: String,
+        /// Text to append; include a trailing newline.
+        content: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `edit_file`.
+    pub struct EditFileArgs {
+        /// Path of the file to edit.
+        file: String,
+        /// Exact text to replace, including whitespace.
+        old_text: String,
+        /// Replacement text.
+        new_text: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `rollback_file`.
+    pub struct RollbackFileArgs {
+        /// Path of the file to restore, as passed to the write tool.
+        file: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `download_file`.
+    pub struct DownloadFileArgs {
+        /// Remote path of the file to download.
+        file: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `package_info`.
+    pub struct PackageInfoArgs {
+        /// Package name as the distro spells it, e.g. nginx or openssh-server.
+        package: String,
+    }
+}
+
+tool_args! {
+    /// Arguments of `disk_usage`.
+    pub struct DiskUsageArgs {
+        /// Directory to analyse. Defaults to /.
+        path: String = "/",
+        /// How many directories to list (default 10, max 30).
+        limit: u64 = 10,
+    }
+}
+
+tool_args! {
+    /// Arguments of `git_info`.
+    pub struct GitInfoArgs {
+        /// Any path inside the repository. Defaults to the current directory.
+        path: String = ".",
+        /// How many recent commits to list (default 5, max 20).
+        commits: u64 = 5,
+    }
+}
+
+tool_args! {
+    /// Arguments of `list_dir`.
+    pub struct ListDirArgs {
+        /// Directory path to list. Defaults to current directory.
+        path: String = ".",
+    }
+}
+
+tool_args! {
+    /// Arguments of `read_more`.
+    pub struct ReadMoreArgs {
+        /// Token from the omission marker, e.g. out-3.
+        token: String,
+        /// 1-based line to start reading at.
+        start_line: usize,
+    }
+}
+
+/// Every built-in tool, grouped for [`ToolRegistry`] filtering.
+pub fn builtin_tools() -> Vec<ToolDef> {
+    vec![
+        ToolDef::new(
+            "run_command",
+            ToolGroup::Core,
+            "Execute an arbitrary shell command on the user's remote SSH session. The user will be shown the \
+             command and must approve before it runs. The command must be non-interactive and must \
+             terminate: output is captured until it goes quiet, so never open editors, pagers or REPLs (vim, \
+             less, top, python) or follow output (tail -f). Use cat/head, top -bn1, --no-pager for \
+             systemctl/journalctl/git, and tail -n instead.",
+        )
+        .args::<RunCommandArgs>(),
+        ToolDef::new(
+            "system_information",
+            ToolGroup::Core,
+            "Return the SSH connection settings for the current session (host, user, port, description, \
+             identity file, extra options). No PTY interaction needed.",
+        ),
+        ToolDef::new(
+            "make_dir",
+            ToolGroup::Write,
+            "Create a directory (and any missing parents) on the remote host using mkdir -p.",
+        )
+        .args::<MakeDirArgs>(),
+        ToolDef::new(
+            "touch_file",
+            ToolGroup::Write,
+            "Create an empty file (or update its timestamp) on the remote host using touch.",
+        )
+        .args::<TouchFileArgs>(),
+        ToolDef::new(
+            "read_file",
+            ToolGroup::Fs,
+            "Read and return the contents of a file on the remote host using cat.",
+        )
+        .args::<ReadFileArgs>(),
+        ToolDef::new(
+            "write_file",
+            ToolGroup::Write,
+            "Create or overwrite a file on the remote host with the given content. The user is shown a diff \
+             against the current file and must approve it before it is written. Like append_file and \
+             edit_file, the previous version is first copied to <file>.sheesh.bak-<timestamp> and can be \
+             restored with rollback_file.",
+        )
+        .args::<WriteFileArgs>(),
+        ToolDef::new(
+            "append_file",
+            ToolGroup::Write,
+            "Append content to the end of a file on the remote host, creating it if needed. The user reviews \
+             the change as a diff before it is written.",
+        )
+        .args::<AppendFileArgs>(),
+        ToolDef::new(
+            "edit_file",
+            ToolGroup::Write,
+            "Replace one exact snippet of a file on the remote host. old_text must appear exactly once in \
+             the file; include surrounding lines to make it unique. Prefer this over write_file for changes \
+             to existing files. The user reviews the change as a diff before it is written.",
+        )
+        .args::<EditFileArgs>(),
+        ToolDef::new(
+            "rollback_file",
+            ToolGroup::Write,
+            "Undo the latest write_file, append_file or edit_file change made to a file this session, \
+             restoring the backup taken before it (or removing the file if the write created it). Call \
+             repeatedly to step further back. The user reviews the change as a diff first.",
+        )
+        .args::<RollbackFileArgs>(),
+        ToolDef::new(
+            "download_file",
+            ToolGroup::Net,
+            "Copy a file from the remote host to the user's local machine (their downloads directory) with \
+             scp and return the local path. Use it to hand the user logs, core dumps or configs. The user \
+             must approve the transfer.",
+        )
+        .args::<DownloadFileArgs>(),
+        ToolDef::new(
+            "package_info",
+            ToolGroup::Inspect,
+            "Check whether a package is installed on the remote host, its version, and whether an update is \
+             available. Works out the package manager itself (apt, dnf, yum, pacman or apk) and answers with \
+             manager, package, installed, version, latest and update_available lines. Uses the local \
+             repository metadata; it does not refresh it.",
+        )
+        .args::<PackageInfoArgs>(),
+        ToolDef::new(
+            "disk_usage",
+            ToolGroup::Inspect,
+            "Find what is filling a disk in one call: size, usage and inode usage of the filesystem holding \
+             a path, then its largest subdirectories (same filesystem, one level deep), biggest first. Call \
+             again on one of the listed directories to drill down.",
+        )
+        .args::<DiskUsageArgs>(),
+        ToolDef::new(
+            "list_scheduled",
+            ToolGroup::Inspect,
+            "List everything scheduled to run on the remote host: the user's crontab, other users' crontabs \
+             when readable, /etc/crontab, /etc/cron.d, the cron.hourly/daily/weekly/monthly scripts and \
+             systemd timers with their next run. Answers with one 'source | schedule | user | command' row \
+             per job.",
+        ),
+        ToolDef::new(
+            "git_info",
+            ToolGroup::Inspect,
+            "Show what is checked out in a git repository on the remote host: repository root, branch, HEAD \
+             commit, tag description, origin URL, ahead/behind counts against the upstream, uncommitted \
+             changes with a diff stat, and the most recent commits. Read-only.",
+        )
+        .args::<GitInfoArgs>(),
+        ToolDef::new(
+            "list_dir",
+            ToolGroup::Fs,
+            "List the contents of a directory on the remote host using ls -la.",
+        )
+        .args::<ListDirArgs>(),
+        ToolDef::new(
+            "read_terminal",
+            ToolGroup::Core,
+            "Read the recent output from the user's terminal. The first call returns the last lines of captured \
+             terminal output; later calls return only what was printed since the previous call. Use this to \
+             understand what is currently happening in the SSH session.",
+        )
+        .param(Param::boolean("full").describe("Return the recent output again instead of only the new part.")),
+        ToolDef::new(
+            "read_more",
+            ToolGroup::Core,
+            "Read part of a long tool output that was shortened. Shortened results keep their first and last \
+             lines around a marker naming the omitted lines and a token; pass that token and the line to \
+             start from. Only ask for what you need.",
+        )
+        .args::<ReadMoreArgs>(),
+        ToolDef::new(
+            "update_plan",
+            ToolGroup::Core,
+            "Publish or update the step-by-step plan for a multi-step task. The user sees it as a live \
+             checklist. Always send the full list of steps. Mark a step in_progress before working on it and \
+             done, failed or skipped once it is finished.",
+        )
+        .param(
+            Param::array(
+                "steps",
+                ParamType::Object(vec![
+                    Param::string("step").describe("Short imperative description of the step.").required(),
+                    Param::string("command").describe(
+                        "The exact shell command the step runs, if it runs one. Only commands named in the \
+                         approved plan run without asking.",
+                    ),
+                    Param::one_of("status", &["pending", "in_progress", "done", "failed", "skipped"]).required(),
+                ]),
+            )
+            .required(),
+        ),
+    ]
+}
+
+/// Programs that run the rest of their arguments as a command: the name, its
+/// options that take a separate value, and how many operands come before the
+/// command (`timeout 5 rm …`).
+const WRAPPERS: &[(&str, &[&str], usize)] = &[
+    (
+        "sudo",
+        &[
+            "-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-T", "-U", "--user", "--group", "--close-from",
+            "--chdir", "--host", "--prompt", "--role", "--type", "--command-timeout", "--other-user",
+        ],
+        0,
+    ),
+    ("doas", &["-u", "-C"], 0),
+    ("env", &["-u", "-C", "-S", "--unset", "--chdir", "--split-string"], 0),
+    ("nohup", &[], 0),
+    ("nice", &["-n", "--adjustment"], 0),
+    ("ionice", &["-c", "-n", "--class", "--classdata"], 0),
+    ("time", &[], 0),
+    ("stdbuf", &["-i", "-o", "-e"], 0),
+    ("setsid", &[], 0),
+    ("exec", &["-a"], 0),
+    ("timeout", &["-s", "-k", "--signal", "--kill-after"], 1),
+    (
+        "xargs",
+        &[
+            "-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s", "--arg-file", "--delimiter", "--eof", "--replace",
+            "--max-lines", "--max-args", "--max-procs", "--max-chars",
+        ],
+        0,
+    ),
+];
+
+/// Shells whose `-c` argument is itself a command line.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "ash"];
+
+/// Split a command line into its simple commands, each as the program's
+/// basename and its arguments. Segments end at `| ; & \n`, and `$(…)`,
+/// backticks and subshells are looked into. `VAR=value` prefixes and
+/// wrappers (`sudo -u root`, `env`, `timeout 5`, `xargs`, `bash -c '…'`)
+/// are skipped to reach the program they run. Quoting is not interpreted
+/// beyond trimming quotes off words, so this errs towards finding commands.
+fn simple_commands(command: &str) -> Vec<(&str, Vec<&str>)> {
+    let bytes = command.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (i, &c) in bytes.iter().enumerate() {
+        let split = match c {
+            b'|' | b';' | b'\n' | b'(' | b')' | b'`' => true,
+            // `&` in `2>&1`, `&>file` or `<&3` belongs to a redirect.
+            b'&' => {
+                !(i > 0 && matches!(bytes[i - 1], b'>' | b'<')) && bytes.get(i + 1) != Some(&b'>')
             }
-        },
-        {
-            "name": "system_information",
-            "description": "Return the SSH connection settings for the current session (host, user, port, description, identity file, extra options). No PTY interaction needed.",
-            "input_schema": { "type": "object", "properties": {}, "required": [] }
-        },
-        {
-            "name": "make_dir",
-            "description": "Create a directory (and any missing parents) on the remote host using mkdir -p.",
-            "input_schema": {
-                "type": "object",
-                "properties": {
-                    "path": { "type": "string", "description": "Absolute or relative path of the directory to create." }
-                },
-                "required": ["path"]
+            _ => false,
+        };
+        if split {
+            segments.push(&command[start..i]);
+            start = i + 1;
+        }
+    }
+    segments.push(&command[start..]);
+
+    segments
+        .into_iter()
+        .filter_map(|segment| {
+            let words: Vec<&str> = segment
+                .split_whitespace()
+                .map(|w| w.trim_matches(['\'', '"']))
+                .filter(|w| !w.is_empty())
+                .collect();
+            command_words(&words)
+        })
+        .collect()
+}
+
+/// The program one simple command runs and its arguments, looking through
+/// `VAR=value` prefixes and [`WRAPPERS`].
+fn command_words<'a>(mut words: &[&'a str]) -> Option<(&'a str, Vec<&'a str>)> {
+    loop {
+        while words.first().is_some_and(|w| w.contains('=') && !w.starts_with('-')) {
+            words = &words[1..];
+        }
+        let program = *words.first()?;
+        let program = program.rsplit('/').next().unwrap_or(program);
+
+        let mut at = 1;
+        if let Some((_, with_value, operands)) = WRAPPERS.iter().find(|(name, ..)| *name == program) {
+            while at < words.len() && words[at].starts_with('-') {
+                if words[at] == "--" {
+                    at += 1;
+                    break;
+                }
+                at += if with_value.contains(&words[at]) { 2 } else { 1 };
             }
-        },
-        {
-            "name": "touch_file",
-            "description": "Create an empty file (or update its timestamp) on the remote host using touch.",
-            "input_schema": {
-                "type": "object",
-                "properties": {
-                    "file": { "type": "string", "description": "Path of the file to create or touch." }
-                },
-                "required": ["file"]
+            at += operands;
+        } else if SHELLS.contains(&program) {
+            let c = words.iter().position(|w| w.starts_with('-') && !w.starts_with("--") && w.contains('c'));
+            match c {
+                Some(c) => at = c + 1,
+                None => return Some((program, words[1..].to_vec())),
             }
-        },
-        {
-            "name": "read_file",
-            "description": "Read and return the contents of a file on the remote host using cat.",
-            "input_schema": {
-                "type": "object",
-                "properties": {
-                    "file": { "type": "string", "description": "Path of the file to read." }
-                },
-                "required": ["file"]
+        } else {
+            return Some((program, words[1..].to_vec()));
+        }
+
+        // A wrapper with nothing to run (`sudo -i`, `xargs`) is the command itself.
+        if at >= words.len() {
+            return Some((program, words[1..].to_vec()));
+        }
+        words = &words[at..];
+    }
+}
+
+/// True if `args` contains `flag` itself or as `flag=value`.
+fn has_flag(args: &[&str], flag: &str) -> bool {
+    args.iter().any(|a| *a == flag || a.starts_with(&format!("{}=", flag)))
+}
+
+/// True if the short option `c` appears in `args`, possibly clustered (`-bn1`).
+fn has_short(args: &[&str], c: char) -> bool {
+    args.iter().any(|a| a.starts_with('-') && !a.starts_with("--") && a[1..].contains(c))
+}
+
+/// Check a shell command for programs that wait for keyboard input or never
+/// exit (editors, pagers, REPLs, `tail -f`), which would wedge output capture.
+/// Returns a short human-readable warning for the first offending segment.
+pub fn interactive_warning(command: &str) -> Option<String> {
+    let commands = simple_commands(command);
+
+    for (i, (program, args)) in commands.iter().enumerate() {
+        let program = *program;
+        let has = |flag: &str| has_flag(args, flag);
+        let short = |c: char| has_short(args, c);
+        // Pagers only kick in when the segment writes to the terminal.
+        let last = i == commands.len() - 1;
+
+        let warning = match program {
+            "vi" | "vim" | "nvim" | "nano" | "emacs" | "pico" | "mc" | "ranger" => {
+                Some(format!("`{}` is an interactive editor", program))
             }
-        },
-        {
-            "name": "list_dir",
-            "description": "List the contents of a directory on the remote host using ls -la.",
-            "input_schema": {
-                "type": "object",
-                "properties": {
-                    "path": { "type": "string", "description": "Directory path to list. Defaults to current directory." }
-                },
-                "required": []
+            "htop" | "btop" | "atop" | "iftop" | "nmtui" | "tmux" | "screen" | "watch" => {
+                Some(format!("`{}` runs full-screen until quit", program))
+            }
+            "less" | "more" | "most" => Some(format!("`{}` is a pager and waits for input", program)),
+            "top" if !short('b') => Some("`top` is interactive; use `top -bn1`".into()),
+            "man" if last => Some("`man` opens a pager; pipe it through `col -b | head`".into()),
+            "tail" | "journalctl" if short('f') || has("--follow") => {
+                Some(format!("`{} -f` never exits", program))
             }
-        },
+            "journalctl" if last && !has("--no-pager") => {
+                Some("`journalctl` opens a pager; add --no-pager".into())
+            }
+            "systemctl" if last
+                && !has("--no-pager")
+                && matches!(
+                    args.first(),
+                    Some(&("status" | "show" | "cat" | "list-units" | "list-timers" | "list-unit-files"))
+                ) =>
+            {
+                Some("`systemctl` opens a pager; add --no-pager".into())
+            }
+            "git" if last
+                && !has("--no-pager")
+                && matches!(args.first(), Some(&("log" | "diff" | "show" | "blame"))) =>
+            {
+                Some("`git` opens a pager; use `git --no-pager …`".into())
+            }
+            "python" | "python3" | "node" | "irb" | "sqlite3" | "redis-cli" if args.is_empty() => {
+                Some(format!("`{}` without arguments starts a REPL", program))
+            }
+            "mysql" if !short('e') && !has("--execute") => {
+                Some("`mysql` without -e starts an interactive shell".into())
+            }
+            "psql" if !short('c') && !has("--command") && !short('f') => {
+                Some("`psql` without -c starts an interactive shell".into())
+            }
+            "ssh" if args.iter().filter(|a| !a.starts_with('-')).count() <= 1 => {
+                Some("`ssh` without a remote command opens an interactive session".into())
+            }
+            _ => None,
+        };
+        if warning.is_some() {
+            return warning;
+        }
+    }
+    None
+}
+
+/// Check a shell command for steps that delete data, stop services or are
+/// otherwise hard to undo. Returns a short human-readable reason for the
+/// first offending segment.
+pub fn destructive_reason(command: &str) -> Option<String> {
+    if let Some(target) = truncating_redirect(command) {
+        return Some(format!("`>` overwrites {}", target));
+    }
+    simple_commands(command).into_iter().find_map(|(program, args)| destructive_program(program, &args))
+}
+
+/// The first file a `>` redirect truncates, for any file descriptor and
+/// whether or not a space follows (`x>f`, `2>f`, `&>f`, `>| f`). Appends,
+/// descriptor copies (`2>&1`) and the null and terminal devices don't count.
+fn truncating_redirect(command: &str) -> Option<&str> {
+    let bytes = command.as_bytes();
+    for (i, &c) in bytes.iter().enumerate() {
+        // `>>` (checked at its first `>`), `<>`, and arrows such as `->` or `=>`.
+        if c != b'>' || (i > 0 && matches!(bytes[i - 1], b'>' | b'<' | b'-' | b'=')) {
+            continue;
+        }
+        let mut rest = &command[i + 1..];
+        if rest.starts_with(['>', '=', '(']) {
+            continue;
+        }
+        if let Some(r) = rest.strip_prefix('|') {
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('&') {
+            if r.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                continue;
+            }
+            rest = r;
+        }
+        let target = rest
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || ";|&<>()".contains(c))
+            .next()
+            .unwrap_or("")
+            .trim_matches(['\'', '"']);
+        let harmless = matches!(target, "" | "/dev/null" | "/dev/stdout" | "/dev/stderr" | "/dev/tty")
+            || target.starts_with("/dev/fd/");
+        if !harmless {
+            return Some(target);
+        }
+    }
+    None
+}
+
+/// Why running `program` with `args` is hard to undo, if it is.
+fn destructive_program(program: &str, args: &[&str]) -> Option<String> {
+    let has = |flag: &str| has_flag(args, flag);
+    let short = |c: char| has_short(args, c);
+    let sub = |names: &[&str]| args.iter().any(|a| names.contains(a));
+
+    match program {
+        "rm" | "rmdir" | "shred" | "unlink" => Some(format!("`{}` deletes files", program)),
+        "mv" => Some("`mv` can overwrite files".into()),
+        "cp" | "install" if sub(&["/dev/null", "/dev/zero"]) => {
+            Some(format!("`{}` from a device empties its target", program))
+        }
+        "truncate" => Some("`truncate` discards file contents".into()),
+        "tee" if !short('a') && !has("--append") && !args.is_empty() => Some("`tee` overwrites its output file".into()),
+        "sed" | "perl" if short('i') || has("--in-place") => Some(format!("`{} -i` rewrites files in place", program)),
+        "find" if sub(&["-delete"]) => Some("`find -delete` deletes files".into()),
+        "find" => {
+            let exec = args.iter().position(|a| matches!(*a, "-exec" | "-execdir" | "-ok" | "-okdir"))?;
+            let (program, args) = command_words(&args[exec + 1..])?;
+            destructive_program(program, &args)
+        }
+        "rsync" if args.iter().any(|a| a.starts_with("--delete") || *a == "--remove-source-files") => {
+            Some("`rsync` deletes files".into())
+        }
+        "dd" | "fdisk" | "sfdisk" | "parted" | "wipefs" | "mkswap" => Some(format!("`{}` writes to disks", program)),
+        p if p.starts_with("mkfs") => Some(format!("`{}` formats a filesystem", program)),
+        "shutdown" | "reboot" | "halt" | "poweroff" => Some(format!("`{}` stops the machine", program)),
+        "kill" | "killall" | "pkill" => Some(format!("`{}` terminates processes", program)),
+        "systemctl" | "service"
+            if sub(&["stop", "restart", "disable", "mask", "kill", "reboot", "poweroff", "isolate"]) =>
+        {
+            Some(format!("`{}` stops or restarts services", program))
+        }
+        "apt" | "apt-get" | "yum" | "dnf" | "zypper" | "apk" | "snap"
+            if sub(&["remove", "purge", "autoremove", "erase", "del"]) =>
+        {
+            Some(format!("`{}` removes packages", program))
+        }
+        "pacman" if args.iter().any(|a| a.starts_with("-R")) => Some("`pacman -R` removes packages".into()),
+        "userdel" | "groupdel" | "deluser" | "delgroup" => Some(format!("`{}` deletes accounts", program)),
+        "chmod" | "chown" | "chgrp" if short('R') || has("--recursive") => {
+            Some(format!("`{} -R` changes a whole tree", program))
+        }
+        // `chmod 000` or `chmod a-rwx` locks everyone out, owner included.
+        "chmod"
+            if args.iter().any(|a| {
+                (!a.is_empty() && a.bytes().all(|b| b == b'0')) || matches!(*a, "a-rwx" | "ugo-rwx" | "a=")
+            }) =>
+        {
+            Some("`chmod` removes all permissions".into())
+        }
+        "git" if (sub(&["reset"]) && has("--hard"))
+            || sub(&["clean"])
+            || (sub(&["push"]) && (has("--force") || has("-f")))
+            || (sub(&["checkout", "restore"]) && has("--")) =>
         {
-            "name": "read_terminal",
-            "description": "Read the recent output from the user's terminal. Returns the last lines of captured terminal output. Use this to understand what is currently happening in the SSH session.",
-            "input_schema": { "type": "object", "properties": {}, "required": [] }
+            Some("`git` discards changes".into())
         }
-    ])
+        "docker" | "podman" if sub(&["rm", "rmi", "prune", "kill", "stop"]) => {
+            Some(format!("`{}` removes or stops containers", program))
+        }
+        "iptables" | "ip6tables" | "nft" | "ufw"
+            if short('F') || short('X') || short('D') || sub(&["flush", "delete", "disable", "reset"]) =>
+        {
+            Some(format!("`{}` changes firewall rules", program))
+        }
+        "crontab" if short('r') => Some("`crontab -r` removes the crontab".into()),
+        _ => None,
+    }
 }
 
 /// Wrap a path/filename in single quotes, escaping any embedded single quotes.
@@ -83,11 +621,24 @@ pub fn shell_quote(s: &str) -> String {
 /// Provider-agnostic result of dispatching a tool call by name.
 /// The caller (LLM provider) maps this to its own event type and appends
 /// any provider-specific history blocks before forwarding upstream.
+#[derive(Debug, Clone)]
 pub enum ToolResult {
-    /// Tool is resolved locally by the application (no PTY needed).
-    Local { id: String, name: String },
+    /// Tool is answered from the application's own session state (no PTY needed).
+    Local { id: String, name: String, input: Value },
     /// Tool maps to a shell command that should be run on the PTY.
     Command { id: String, command: String, description: Option<String> },
+    /// Tool writes a file. The host must preview the change as a diff and
+    /// get the user's approval before running the write (see [`write`]).
+    Write { id: String, write: FileWrite, description: Option<String> },
+    /// Tool copies a remote file to the local machine (outside the PTY).
+    Download { id: String, path: String },
+    /// Tool runs on the local machine (see [`local`]); the host checks
+    /// `permission`, then calls [`ToolRegistry::run_local`].
+    Machine { id: String, name: String, input: Value, permission: LocalPermission },
+    /// The call can't be made (a tool not offered here, or arguments that
+    /// don't fit it); the host answers it with `error` so the model can
+    /// correct itself, and the turn's other calls go ahead.
+    Rejected { id: String, name: String, error: String },
 }
 
 /// Dispatch a tool call by `name` + `input` JSON to a [`ToolResult`].
@@ -96,47 +647,196 @@ pub fn dispatch(id: impl Into<String>, name: impl Into<String>, input: &Value) -
     let name = name.into();
 
     match name.as_str() {
-        "system_information" | "read_terminal" => {
+        "system_information" | "read_terminal" | "update_plan" | "read_more" => {
             log::debug!("[sheesh-tools] local tool: {}", name);
-            Ok(ToolResult::Local { id, name })
+            Ok(ToolResult::Local { id, name, input: input.clone() })
         }
         "run_command" => {
-            let command = input["command"]
-                .as_str()
-                .ok_or_else(|| anyhow::anyhow!("run_command missing 'command' field"))?
-                .to_string();
-            let description = input["description"].as_str().map(|s| s.to_string());
+            let RunCommandArgs { command, description } = RunCommandArgs::parse(&name, input)?;
             log::debug!("[sheesh-tools] run_command command={:?}", command);
             Ok(ToolResult::Command { id, command, description })
         }
         "make_dir" => {
-            let path = input["path"].as_str().unwrap_or(".");
-            let command = format!("mkdir -p {}", shell_quote(path));
+            let MakeDirArgs { path } = MakeDirArgs::parse(&name, input)?;
+            let command = format!("mkdir -p {}", shell_quote(&path));
             let description = Some(format!("Create directory {}", path));
             log::debug!("[sheesh-tools] make_dir path={:?}", path);
             Ok(ToolResult::Command { id, command, description })
         }
         "touch_file" => {
-            let file = input["file"].as_str().unwrap_or("");
-            let command = format!("touch {}", shell_quote(file));
+            let TouchFileArgs { file } = TouchFileArgs::parse(&name, input)?;
+            let command = format!("touch {}", shell_quote(&file));
             let description = Some(format!("Create/touch file {}", file));
             log::debug!("[sheesh-tools] touch_file file={:?}", file);
             Ok(ToolResult::Command { id, command, description })
         }
         "read_file" => {
-            let file = input["file"].as_str().unwrap_or("");
-            let command = format!("cat {}", shell_quote(file));
+            let ReadFileArgs { file } = ReadFileArgs::parse(&name, input)?;
+            let command = format!("cat {}", shell_quote(&file));
             let description = Some(format!("Read file {}", file));
             log::debug!("[sheesh-tools] read_file file={:?}", file);
             Ok(ToolResult::Command { id, command, description })
         }
+        "write_file" | "append_file" | "edit_file" | "rollback_file" => {
+            let (path, kind, description) = match name.as_str() {
+                "write_file" => {
+                    let WriteFileArgs { file, content } = WriteFileArgs::parse(&name, input)?;
+                    let description = format!("Write file {}", file);
+                    (file, WriteKind::Replace { content }, description)
+                }
+                "append_file" => {
+                    let AppendFileArgs { file, content } = AppendFileArgs::parse(&name, input)?;
+                    let description = format!("Append to {}", file);
+                    (file, WriteKind::Append { content }, description)
+                }
+                "rollback_file" => {
+                    let RollbackFileArgs { file } = RollbackFileArgs::parse(&name, input)?;
+                    let description = format!("Roll back {}", file);
+                    (file, WriteKind::Rollback, description)
+                }
+                _ => {
+                    let EditFileArgs { file, old_text, new_text } = EditFileArgs::parse(&name, input)?;
+                    let description = format!("Edit {}", file);
+                    (file, WriteKind::Edit { old: old_text, new: new_text }, description)
+                }
+            };
+            log::debug!("[sheesh-tools] {} file={:?}", name, path);
+            Ok(ToolResult::Write { id, write: FileWrite { path, kind }, description: Some(description) })
+        }
+        "download_file" => {
+            let DownloadFileArgs { file: path } = DownloadFileArgs::parse(&name, input)?;
+            log::debug!("[sheesh-tools] download_file file={:?}", path);
+            Ok(ToolResult::Download { id, path })
+        }
+        "package_info" => {
+            let PackageInfoArgs { package } = PackageInfoArgs::parse(&name, input)?;
+            let command = inspect::package_info_command(&package);
+            let description = Some(format!("Look up package {}", package));
+            log::debug!("[sheesh-tools] package_info package={:?}", package);
+            Ok(ToolResult::Command { id, command, description })
+        }
+        "disk_usage" => {
+            let DiskUsageArgs { path, limit } = DiskUsageArgs::parse(&name, input)?;
+            let limit = limit.clamp(1, 30);
+            let command = inspect::disk_usage_command(&path, limit);
+            let description = Some(format!("Show disk usage under {}", path));
+            log::debug!("[sheesh-tools] disk_usage path={:?} limit={}", path, limit);
+            Ok(ToolResult::Command { id, command, description })
+        }
+        "list_scheduled" => {
+            log::debug!("[sheesh-tools] list_scheduled");
+            Ok(ToolResult::Command {
+                id,
+                command: inspect::list_scheduled_command(),
+                description: Some("List cron jobs and systemd timers".into()),
+            })
+        }
+        "git_info" => {
+            let GitInfoArgs { path, commits } = GitInfoArgs::parse(&name, input)?;
+            let commits = commits.clamp(1, 20);
+            let command = inspect::git_info_command(&path, commits);
+            let description = Some(format!("Show git status of {}", path));
+            log::debug!("[sheesh-tools] git_info path={:?} commits={}", path, commits);
+            Ok(ToolResult::Command { id, command, description })
+        }
         "list_dir" => {
-            let path = input["path"].as_str().unwrap_or(".");
-            let command = format!("ls -la {}", shell_quote(path));
+            let ListDirArgs { path } = ListDirArgs::parse(&name, input)?;
+            let command = format!("ls -la {}", shell_quote(&path));
             let description = Some(format!("List directory {}", path));
             log::debug!("[sheesh-tools] list_dir path={:?}", path);
             Ok(ToolResult::Command { id, command, description })
         }
         other => Err(anyhow::anyhow!("unknown tool: {}", other)),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destructive_commands_are_found_through_wrappers_and_redirects() {
+        let cases = [
+            ("rm -rf /tmp/x", "`rm` deletes files"),
+            ("sudo rm -rf /", "`rm` deletes files"),
+            ("sudo -u root rm -rf /", "`rm` deletes files"),
+            ("sudo -E -u root -- rm -rf /", "`rm` deletes files"),
+            ("sudo --user=root rm -rf /", "`rm` deletes files"),
+            ("env rm -rf /srv", "`rm` deletes files"),
+            ("env -i PATH=/bin rm -rf /srv", "`rm` deletes files"),
+            ("LANG=C rm x", "`rm` deletes files"),
+            ("nohup rm -rf /srv &", "`rm` deletes files"),
+            ("timeout 5 rm -rf /srv", "`rm` deletes files"),
+            ("timeout -s KILL 5 systemctl stop nginx", "`systemctl` stops or restarts services"),
+            ("ls *.log | xargs rm", "`rm` deletes files"),
+            ("find . -name '*.tmp' -print0 | xargs -0 -n 10 rm -f", "`rm` deletes files"),
+            ("bash -c 'rm -rf /srv'", "`rm` deletes files"),
+            ("sh -c \"cd /srv && rm -rf data\"", "`rm` deletes files"),
+            ("sudo bash -lc 'systemctl restart nginx'", "`systemctl` stops or restarts services"),
+            ("echo $(rm -rf /srv)", "`rm` deletes files"),
+            ("echo `rm -rf /srv`", "`rm` deletes files"),
+            ("(cd /srv && rm -rf data)", "`rm` deletes files"),
+            ("find / -name core -delete", "`find -delete` deletes files"),
+            ("find /tmp -exec rm {} \\;", "`rm` deletes files"),
+            ("sed -i 's/a/b/' /etc/hosts", "`sed -i` rewrites files in place"),
+            ("sed -i.bak -e 's/a/b/' f", "`sed -i` rewrites files in place"),
+            ("perl -pi -e 's/a/b/' f", "`perl -i` rewrites files in place"),
+            ("chmod 000 /etc/shadow", "`chmod` removes all permissions"),
+            ("chmod a-rwx f", "`chmod` removes all permissions"),
+            ("chmod -R 755 /srv", "`chmod -R` changes a whole tree"),
+            ("cp /dev/null /var/log/syslog", "`cp` from a device empties its target"),
+            ("rsync -a --delete src/ dst/", "`rsync` deletes files"),
+            ("echo x > /etc/passwd", "`>` overwrites /etc/passwd"),
+            ("echo x>/etc/passwd", "`>` overwrites /etc/passwd"),
+            ("cmd 2>/var/log/app.log", "`>` overwrites /var/log/app.log"),
+            ("cmd 2> err.txt", "`>` overwrites err.txt"),
+            ("cmd &>/tmp/out", "`>` overwrites /tmp/out"),
+            ("cmd >| /tmp/out", "`>` overwrites /tmp/out"),
+            ("cmd >&/tmp/out", "`>` overwrites /tmp/out"),
+            ("cat x > /dev/sda", "`>` overwrites /dev/sda"),
+            ("bash -c 'echo x > /etc/motd'", "`>` overwrites /etc/motd"),
+        ];
+        for (command, reason) in cases {
+            assert_eq!(destructive_reason(command).as_deref(), Some(reason), "{}", command);
+        }
+    }
+
+    #[test]
+    fn harmless_commands_are_not_destructive() {
+        let cases = [
+            "ls -la /srv",
+            "df -h",
+            "cat /etc/hosts 2>/dev/null",
+            "make 2>&1 | tee -a build.log",
+            "grep -r foo . >/dev/null 2>&1",
+            "echo done >&2",
+            "cmd &>/dev/null",
+            "echo x >> /var/log/notes",
+            "cmd 2>>errors.log",
+            "diff <(sort a) <(sort b)",
+            "git log --format='%h -> %s' -n 5",
+            "awk '$1 >= 5' data",
+            "sudo -u postgres psql -c 'select 1'",
+            "timeout 5 curl -s localhost",
+            "find /var/log -name '*.gz' -exec ls -l {} \\;",
+            "sed -n 1,5p /etc/hosts",
+            "chmod 644 f",
+            "chmod g-rwx f",
+            "cp a b",
+            "bash script.sh",
+            "env",
+            "sudo -i",
+        ];
+        for command in cases {
+            assert_eq!(destructive_reason(command), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn interactive_programs_are_found_through_wrappers() {
+        assert!(interactive_warning("sudo -u www-data vim /etc/nginx.conf").is_some());
+        assert!(interactive_warning("time less /var/log/syslog").is_some());
+        assert!(interactive_warning("bash -c 'tail -f /var/log/syslog'").is_some());
+        assert_eq!(interactive_warning("tail -n 50 /var/log/syslog 2>&1 | grep error"), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Tools that run on the user's own machine rather than the remote host:
+//! they need no session, but they act with the user's local identity and
+//! network, so each one says whether a call needs the user's approval.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::registry::ToolDef;
+
+/// How the host must treat a call before running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalPermission {
+    /// Read-only access to sheesh's own state; run without asking.
+    Allow,
+    /// Show this summary of what the call will do and wait for the user.
+    /// Auto-approval scopes for remote commands never cover it.
+    Ask(String),
+}
+
+/// A tool that runs on this machine. `run` may block (e.g. on the network),
+/// so hosts call it off the UI thread.
+pub trait LocalTool: Send + Sync {
+    /// Name, group, description and arguments offered to the model.
+    fn def(&self) -> ToolDef;
+
+    fn permission(&self, input: &Value) -> LocalPermission;
+
+    /// Run the call and return the text handed back to the model.
+    fn run(&self, input: &Value) -> Result<String>;
+}
+
+/// The local tools of a registry by name, shared between its clones.
+#[derive(Clone, Default)]
+pub(crate) struct LocalTools(Vec<(String, Arc<dyn LocalTool>)>);
+
+impl fmt::Debug for LocalTools {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter().map(|(name, _)| name)).finish()
+    }
+}
+
+impl LocalTools {
+    pub(crate) fn add(&mut self, name: String, tool: Arc<dyn LocalTool>) {
+        self.0.retain(|(n, _)| *n != name);
+        self.0.push((name, tool));
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Arc<dyn LocalTool>> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, tool)| tool)
+    }
+}
@@ -0,0 +1,209 @@
+//! In-memory [`SshContext`] for exercising tools without a live session: a
+//! fake filesystem, scripted command outputs, and a log of everything the
+//! tool did, with assertion helpers over it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+
+use crate::context::{ExecOutput, SshContext};
+
+/// Something a tool did through a [`MockSshContext`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    Exec(String),
+    PathExists(String),
+    ListDir(String),
+    ReadFile(String),
+    WriteFile(String),
+}
+
+/// Fake host. Files and directories live in memory; `exec` answers from
+/// scripted responses, matched by exact command first and then by prefix.
+/// Unscripted commands fail with status 127.
+#[derive(Debug, Default)]
+pub struct MockSshContext {
+    files: Mutex<BTreeMap<String, String>>,
+    dirs: Mutex<BTreeSet<String>>,
+    responses: Vec<(String, ExecOutput)>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+fn normalize(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" => "/".to_string(),
+        p => p.to_string(),
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) => "/",
+        Some(i) => &path[..i],
+        None => ".",
+    }
+}
+
+impl MockSshContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file (and its parent directories).
+    pub fn with_file(self, path: &str, content: &str) -> Self {
+        let path = normalize(path);
+        self.add_parents(&path);
+        self.files.lock().unwrap().insert(path, content.to_string());
+        self
+    }
+
+    /// Add an empty directory (and its parents).
+    pub fn with_dir(self, path: &str) -> Self {
+        let path = normalize(path);
+        self.add_parents(&path);
+        self.dirs.lock().unwrap().insert(path);
+        self
+    }
+
+    /// Answer `command` (or any command starting with it) with `output`.
+    pub fn respond(mut self, command: &str, output: ExecOutput) -> Self {
+        self.responses.push((command.to_string(), output));
+        self
+    }
+
+    /// Answer `command` with `stdout` and exit status 0.
+    pub fn respond_ok(self, command: &str, stdout: &str) -> Self {
+        self.respond(command, ExecOutput { stdout: stdout.to_string(), stderr: String::new(), status: Some(0) })
+    }
+
+    fn add_parents(&self, path: &str) {
+        let mut dirs = self.dirs.lock().unwrap();
+        let mut dir = parent(path);
+        while dir != "/" && dir != "." && dirs.insert(dir.to_string()) {
+            dir = parent(dir);
+        }
+        dirs.insert("/".to_string());
+    }
+
+    fn record(&self, call: MockCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    /// Everything done so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Commands passed to `exec`, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.calls()
+            .into_iter()
+            .filter_map(|c| match c {
+                MockCall::Exec(command) => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Current content of a fake file.
+    pub fn file(&self, path: &str) -> Option<String> {
+        self.files.lock().unwrap().get(&normalize(path)).cloned()
+    }
+
+    /// Panic unless some command containing `fragment` was run.
+    #[track_caller]
+    pub fn assert_ran(&self, fragment: &str) {
+        let commands = self.commands();
+        assert!(
+            commands.iter().any(|c| c.contains(fragment)),
+            "expected a command containing {:?}, ran: {:#?}",
+            fragment,
+            commands
+        );
+    }
+
+    /// Panic if any command containing `fragment` was run.
+    #[track_caller]
+    pub fn assert_not_ran(&self, fragment: &str) {
+        let commands = self.commands();
+        assert!(
+            !commands.iter().any(|c| c.contains(fragment)),
+            "expected no command containing {:?}, ran: {:#?}",
+            fragment,
+            commands
+        );
+    }
+
+    /// Panic unless fake file `path` holds exactly `expected`.
+    #[track_caller]
+    pub fn assert_file(&self, path: &str, expected: &str) {
+        assert_eq!(self.file(path).as_deref(), Some(expected), "content of {}", path);
+    }
+}
+
+impl SshContext for MockSshContext {
+    fn exec(&self, command: &str) -> Result<ExecOutput> {
+        self.record(MockCall::Exec(command.to_string()));
+        let scripted = self
+            .responses
+            .iter()
+            .find(|(c, _)| c == command)
+            .or_else(|| self.responses.iter().find(|(c, _)| command.starts_with(c.as_str())));
+        Ok(match scripted {
+            Some((_, output)) => output.clone(),
+            None => ExecOutput {
+                stdout: String::new(),
+                stderr: format!("mock: no response scripted for {:?}", command),
+                status: Some(127),
+            },
+        })
+    }
+
+    fn path_exists(&self, path: &str) -> Result<bool> {
+        self.record(MockCall::PathExists(path.to_string()));
+        let path = normalize(path);
+        Ok(self.files.lock().unwrap().contains_key(&path) || self.dirs.lock().unwrap().contains(&path))
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<String>> {
+        self.record(MockCall::ListDir(path.to_string()));
+        let path = normalize(path);
+        let dirs = self.dirs.lock().unwrap();
+        // "/" and "." (the session's working directory) always exist.
+        if path != "/" && path != "." && !dirs.contains(&path) {
+            bail!("listing {} failed: No such file or directory", path);
+        }
+        let files = self.files.lock().unwrap();
+        let mut entries: Vec<String> = files
+            .keys()
+            .chain(dirs.iter())
+            .filter(|p| p.as_str() != path && parent(p) == path)
+            .map(|p| p.rsplit('/').next().unwrap_or(p).to_string())
+            .collect();
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        self.record(MockCall::ReadFile(path.to_string()));
+        match self.file(path) {
+            Some(content) => Ok(content),
+            None => bail!("reading {} failed: No such file or directory", path),
+        }
+    }
+
+    fn write_file(&self, path: &str, content: &str) -> Result<()> {
+        self.record(MockCall::WriteFile(path.to_string()));
+        let path = normalize(path);
+        if self.dirs.lock().unwrap().contains(&path) {
+            bail!("writing {} failed: Is a directory", path);
+        }
+        let dir = parent(&path);
+        if dir != "/" && dir != "." && !self.dirs.lock().unwrap().contains(dir) {
+            bail!("writing {} failed: No such file or directory", path);
+        }
+        self.files.lock().unwrap().insert(path, content.to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Output limiting shared by every tool result. Anything over the configured
+//! line or byte budget is cut down to its head and tail; the full text stays
+//! in an [`OutputStore`] and the model can page through the omitted middle
+//! with the `read_more` tool using the token named in the marker line.
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Result, anyhow};
+
+/// How many truncated outputs stay available to `read_more`.
+const KEPT_OUTPUTS: usize = 32;
+
+/// Size budget for a single tool result.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLimits {
+    pub max_lines: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self { max_lines: 400, max_bytes: 16 * 1024 }
+    }
+}
+
+impl OutputLimits {
+    fn fits(&self, text: &str) -> bool {
+        text.len() <= self.max_bytes && text.lines().count() <= self.max_lines
+    }
+}
+
+/// Full text of recently truncated outputs, keyed by continuation token.
+#[derive(Debug, Default)]
+pub struct OutputStore {
+    next: u64,
+    outputs: HashMap<String, Vec<String>>,
+    order: VecDeque<String>,
+}
+
+impl OutputStore {
+    /// Return `output` unchanged if it fits `limits`; otherwise keep its
+    /// first and last lines (half the budget each) around a marker naming the
+    /// omitted range and a continuation token for `read_more`.
+    pub fn limit(&mut self, output: &str, limits: &OutputLimits) -> String {
+        if limits.fits(output) {
+            return output.to_string();
+        }
+        // Very long lines are split so a page never has to cut one.
+        let width = (limits.max_bytes / 4).max(1);
+        let lines: Vec<String> = output.lines().flat_map(|l| split_line(l, width)).collect();
+        let half = OutputLimits { max_lines: limits.max_lines / 2, max_bytes: limits.max_bytes / 2 };
+        let head = take_lines(lines.iter(), &half);
+        let mut tail = take_lines(lines[head.len()..].iter().rev(), &half);
+        tail.reverse();
+        let tail_start = lines.len() - tail.len();
+        if tail_start <= head.len() {
+            return output.to_string();
+        }
+
+        let token = format!("out-{}", self.next);
+        self.next += 1;
+        let marker = format!(
+            "[… lines {}–{} of {} omitted ({} bytes in all). Call read_more with token \"{}\" and start_line {} to read them …]",
+            head.len() + 1,
+            tail_start,
+            lines.len(),
+            output.len(),
+            token,
+            head.len() + 1,
+        );
+        let mut text = head.join("\n");
+        text.push('\n');
+        text.push_str(&marker);
+        for line in &tail {
+            text.push('\n');
+            text.push_str(line);
+        }
+
+        self.order.push_back(token.clone());
+        self.outputs.insert(token, lines);
+        if self.order.len() > KEPT_OUTPUTS
+            && let Some(old) = self.order.pop_front()
+        {
+            self.outputs.remove(&old);
+        }
+        text
+    }
+
+    /// One page of a truncated output, starting at 1-based `start_line`.
+    pub fn read_more(&self, token: &str, start_line: usize, limits: &OutputLimits) -> Result<String> {
+        let lines = self
+            .outputs
+            .get(token)
+            .ok_or_else(|| anyhow!("unknown or expired output token {:?}", token))?;
+        let start = start_line.max(1);
+        if start > lines.len() {
+            return Err(anyhow!("{} has only {} lines", token, lines.len()));
+        }
+        let page = take_lines(lines[start - 1..].iter(), limits);
+        let end = start - 1 + page.len();
+        let mut text = format!("[{} lines {}–{} of {}]\n{}", token, start, end, lines.len(), page.join("\n"));
+        if end < lines.len() {
+            text.push_str(&format!("\n[… more: call read_more with token \"{}\" and start_line {} …]", token, end + 1));
+        }
+        Ok(text)
+    }
+}
+
+/// Lines from `lines` until either budget runs out, but at least one.
+fn take_lines<'a>(lines: impl Iterator<Item = &'a String>, limits: &OutputLimits) -> Vec<String> {
+    let mut taken = Vec::new();
+    let mut bytes = 0;
+    for line in lines.take(limits.max_lines.max(1)) {
+        if !taken.is_empty() && bytes + line.len() + 1 > limits.max_bytes {
+            break;
+        }
+        bytes += line.len() + 1;
+        taken.push(line.clone());
+    }
+    taken
+}
+
+/// `line` in pieces of at most `width` bytes, split at character boundaries.
+fn split_line(mut line: &str, width: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    while line.len() > width {
+        let mut cut = width;
+        while !line.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        if cut == 0 {
+            cut = line.chars().next().map_or(line.len(), char::len_utf8);
+        }
+        let (piece, rest) = line.split_at(cut);
+        pieces.push(piece.to_string());
+        line = rest;
+    }
+    pieces.push(line.to_string());
+    pieces
+}
@@ -0,0 +1,365 @@
+//! Tool registry: every built-in tool tagged with the group it belongs to,
+//! so hosts can switch whole groups off (e.g. no write tools on production
+//! machines) and send the model only the tools it may call, serialised in
+//! whichever format the provider speaks.
+
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+
+use crate::args::ToolArgs;
+use crate::local::{LocalTool, LocalTools};
+use crate::schema::{Param, object_schema};
+use crate::telemetry::{ProgressSink, Telemetry, ToolObserver, ToolProgress};
+use crate::{ToolResult, builtin_tools, dispatch};
+
+/// Family a tool belongs to; the unit in which tools are enabled or disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolGroup {
+    /// Commands, terminal and session tools the assistant is built around.
+    Core,
+    /// Reading files and listing directories.
+    Fs,
+    /// Creating, changing and restoring files.
+    Write,
+    /// Moving data across the network (downloads).
+    Net,
+    /// Read-only host inspection (packages, disks, schedules, git).
+    Inspect,
+}
+
+impl ToolGroup {
+    pub const ALL: [ToolGroup; 5] = [Self::Core, Self::Fs, Self::Write, Self::Net, Self::Inspect];
+
+    /// Name used in config files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Core => "core",
+            Self::Fs => "fs",
+            Self::Write => "write",
+            Self::Net => "net",
+            Self::Inspect => "inspect",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// One registered tool.
+#[derive(Debug, Clone)]
+pub struct ToolDef {
+    pub name: String,
+    pub group: ToolGroup,
+    pub description: String,
+    pub params: Vec<Param>,
+}
+
+impl ToolDef {
+    pub fn new(name: impl Into<String>, group: ToolGroup, description: impl Into<String>) -> Self {
+        Self { name: name.into(), group, description: description.into(), params: vec![] }
+    }
+
+    /// Add an argument.
+    pub fn param(mut self, param: Param) -> Self {
+        self.params.push(param);
+        self
+    }
+
+    /// Add every argument of a [`tool_args!`](crate::args) struct.
+    pub fn args<A: ToolArgs>(mut self) -> Self {
+        self.params.extend(A::params());
+        self
+    }
+
+    /// JSON Schema of the tool's arguments.
+    pub fn input_schema(&self) -> Value {
+        object_schema(&self.params)
+    }
+
+    /// Anthropic Messages API `tools` entry.
+    pub fn to_anthropic(&self) -> Value {
+        json!({ "name": self.name, "description": self.description, "input_schema": self.input_schema() })
+    }
+
+    /// OpenAI Chat Completions function-calling `tools` entry (also used by
+    /// OpenAI-compatible servers such as Ollama).
+    pub fn to_openai(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": { "name": self.name, "description": self.description, "parameters": self.input_schema() },
+        })
+    }
+
+    /// Model Context Protocol `tools/list` entry.
+    pub fn to_mcp(&self) -> Value {
+        json!({ "name": self.name, "description": self.description, "inputSchema": self.input_schema() })
+    }
+}
+
+/// The set of tools offered to the model for one session.
+#[derive(Debug, Clone)]
+pub struct ToolRegistry {
+    tools: Vec<ToolDef>,
+    local: LocalTools,
+    telemetry: Telemetry,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ToolRegistry {
+    /// Every built-in tool.
+    pub fn builtin() -> Self {
+        Self { tools: builtin_tools(), local: LocalTools::default(), telemetry: Telemetry::default() }
+    }
+
+    /// This registry minus every tool in `groups`.
+    pub fn without_groups(mut self, groups: &[ToolGroup]) -> Self {
+        self.tools.retain(|t| !groups.contains(&t.group));
+        self
+    }
+
+    /// This registry minus the tools that act on the host: `run_command`
+    /// and the write group. What is left only looks.
+    pub fn read_only(mut self) -> Self {
+        self.tools.retain(|t| t.name != "run_command" && t.group != ToolGroup::Write);
+        self
+    }
+
+    /// Add a tool that runs on this machine, replacing any tool of the same
+    /// name.
+    pub fn with_local(mut self, tool: Arc<dyn LocalTool>) -> Self {
+        let def = tool.def();
+        self.tools.retain(|t| t.name != def.name);
+        self.local.add(def.name.clone(), tool);
+        self.tools.push(def);
+        self
+    }
+
+    /// Run local tool `name`. Blocks for as long as the tool does.
+    pub fn run_local(&self, name: &str, input: &Value) -> Result<String> {
+        match self.local.get(name) {
+            Some(tool) if self.contains(name) => tool.run(input),
+            _ => bail!("{} is not a local tool on this connection", name),
+        }
+    }
+
+    /// Report tool calls started and finished through this registry (and
+    /// its clones) to `observer`.
+    pub fn observe(mut self, observer: Arc<dyn ToolObserver>) -> Self {
+        self.telemetry.add(observer);
+        self
+    }
+
+    /// Tell observers call `id` of tool `name` has started running.
+    pub fn call_started(&self, id: &str, name: &str) {
+        self.telemetry.start(id, name);
+    }
+
+    /// Tell observers how far running call `id` has got. Returns the
+    /// progress as reported, or `None` if the call isn't running.
+    pub fn call_progress(&self, id: &str, bytes: usize, note: Option<&str>) -> Option<ToolProgress> {
+        self.telemetry.progress(id, bytes, note)
+    }
+
+    /// Sink forwarding a tool's reports to [`call_progress`](Self::call_progress).
+    pub fn progress_sink<'a>(&'a self, id: &'a str) -> CallProgress<'a> {
+        CallProgress { registry: self, id }
+    }
+
+    /// Tell observers call `id` has finished with `result`, timed from
+    /// [`call_started`](Self::call_started).
+    pub fn call_finished(&self, id: &str, result: &str) {
+        self.telemetry.finish(id, result);
+    }
+
+    pub fn tools(&self) -> &[ToolDef] {
+        &self.tools
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.iter().any(|t| t.name == name)
+    }
+
+    /// `tools` array for the Anthropic Messages API.
+    pub fn to_anthropic(&self) -> Value {
+        Value::Array(self.tools.iter().map(ToolDef::to_anthropic).collect())
+    }
+
+    /// `tools` array for OpenAI-style function calling.
+    pub fn to_openai(&self) -> Value {
+        Value::Array(self.tools.iter().map(ToolDef::to_openai).collect())
+    }
+
+    /// Result of an MCP `tools/list` request.
+    pub fn to_mcp(&self) -> Value {
+        json!({ "tools": self.tools.iter().map(ToolDef::to_mcp).collect::<Vec<_>>() })
+    }
+
+    /// Like [`dispatch`], but knows about local tools, and a call it can't
+    /// make (a tool not registered here, bad arguments) comes back as
+    /// [`ToolResult::Rejected`] rather than failing the whole turn.
+    pub fn dispatch(&self, id: impl Into<String>, name: &str, input: &Value) -> ToolResult {
+        let id = id.into();
+        if !self.contains(name) {
+            let error = format!("tool {} is not available on this connection", name);
+            return ToolResult::Rejected { id, name: name.to_string(), error };
+        }
+        if let Some(tool) = self.local.get(name) {
+            let permission = tool.permission(input);
+            return ToolResult::Machine { id, name: name.to_string(), input: input.clone(), permission };
+        }
+        dispatch(id.clone(), name, input)
+            .unwrap_or_else(|e| ToolResult::Rejected { id, name: name.to_string(), error: format!("{:#}", e) })
+    }
+}
+
+/// [`ProgressSink`] for one call, from [`ToolRegistry::progress_sink`].
+pub struct CallProgress<'a> {
+    registry: &'a ToolRegistry,
+    id: &'a str,
+}
+
+impl ProgressSink for CallProgress<'_> {
+    fn report(&self, bytes: usize, note: Option<&str>) {
+        self.registry.call_progress(self.id, bytes, note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+
+    use super::*;
+    use crate::{ExecOutput, FileWrite, LocalPermission, MockSshContext, SshContext, WriteKind};
+
+    /// Run what a command tool turned into on `ctx`, as the host would.
+    fn run(ctx: &MockSshContext, result: ToolResult) -> String {
+        let ToolResult::Command { command, .. } = result else { panic!("not a command: {:?}", result) };
+        let out = ctx.exec(&command).unwrap();
+        assert!(out.success(), "{} failed: {}", command, out.stderr);
+        out.stdout
+    }
+
+    fn rejection(result: ToolResult) -> String {
+        match result {
+            ToolResult::Rejected { error, .. } => error,
+            other => panic!("not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_tools_run_quoted_commands() {
+        let registry = ToolRegistry::builtin();
+        let ctx = MockSshContext::new()
+            .respond_ok("mkdir -p", "")
+            .respond_ok("cat '/etc/it'\\''s'", "127.0.0.1 localhost\n");
+        run(&ctx, registry.dispatch("1", "make_dir", &json!({ "path": "/srv/my app" })));
+        let text = run(&ctx, registry.dispatch("2", "read_file", &json!({ "file": "/etc/it's" })));
+        assert_eq!(text, "127.0.0.1 localhost\n");
+        assert_eq!(ctx.commands(), ["mkdir -p '/srv/my app'", "cat '/etc/it'\\''s'"]);
+    }
+
+    #[test]
+    fn arguments_are_checked_and_defaulted() {
+        let registry = ToolRegistry::builtin();
+        let error = rejection(registry.dispatch("1", "edit_file", &json!({ "file": "/etc/motd", "new_text": "" })));
+        assert_eq!(error, "edit_file: missing field `old_text`");
+        let ToolResult::Command { command, .. } = registry.dispatch("2", "disk_usage", &json!({ "path": "/var" })) else {
+            panic!("disk_usage is a command");
+        };
+        assert!(command.contains("'/var'"), "{}", command);
+    }
+
+    #[test]
+    fn tools_not_offered_are_rejected() {
+        let registry = ToolRegistry::builtin().without_groups(&[ToolGroup::Inspect]).read_only();
+        assert!(registry.contains("read_file") && !registry.contains("run_command"));
+        assert!(!registry.tools().iter().any(|t| matches!(t.group, ToolGroup::Write | ToolGroup::Inspect)));
+        let error = rejection(registry.dispatch("1", "write_file", &json!({ "file": "/x", "content": "" })));
+        assert_eq!(error, "tool write_file is not available on this connection");
+        assert!(matches!(registry.dispatch("2", "git_info", &json!({})), ToolResult::Rejected { .. }));
+        assert!(matches!(registry.dispatch("3", "no_such_tool", &json!({})), ToolResult::Rejected { .. }));
+    }
+
+    #[test]
+    fn edits_read_then_back_up_then_write() {
+        let before = "Welcome\nto host\n";
+        let ctx = MockSshContext::new()
+            .respond_ok("if [ -e", &format!("sheesh-read-begin\n{}\nsheesh-read-end\n", STANDARD.encode(before)))
+            .respond_ok("cp -p", "sheesh-write-ok\n");
+        let input = json!({ "file": "/etc/motd", "old_text": "to host", "new_text": "to db1" });
+        let ToolResult::Write { write, .. } = ToolRegistry::builtin().dispatch("1", "edit_file", &input) else {
+            panic!("edit_file is a write");
+        };
+        assert!(matches!(write.kind, WriteKind::Edit { .. }));
+
+        let read = ctx.exec(&write.read_command()).unwrap();
+        let current = write.parse_read_output(&read.stdout).unwrap();
+        assert_eq!(current.as_deref(), Some(before));
+        let after = write.apply(current.as_deref()).unwrap();
+        assert_eq!(after, "Welcome\nto db1\n");
+
+        let written = ctx.exec(&write.write_command(&after, Some(&write.backup_path(1700000000)))).unwrap();
+        write.check_write_output(&written.stdout).unwrap();
+        ctx.assert_ran("cp -p '/etc/motd' '/etc/motd.sheesh.bak-1700000000' && base64 -d > '/etc/motd'");
+        ctx.assert_not_ran("rm -f");
+    }
+
+    #[test]
+    fn a_write_that_fails_says_why() {
+        let denied =
+            ExecOutput { stdout: "sh: /root/x: Permission denied\n".into(), status: Some(1), ..Default::default() };
+        let ctx = MockSshContext::new().respond_ok("if [ -e", "sheesh-read-missing\n").respond("base64 -d", denied);
+        let input = json!({ "file": "/root/x", "old_text": "a", "new_text": "b" });
+        let ToolResult::Write { write, .. } = ToolRegistry::builtin().dispatch("1", "edit_file", &input) else {
+            panic!("edit_file is a write");
+        };
+        let current = write.parse_read_output(&ctx.exec(&write.read_command()).unwrap().stdout).unwrap();
+        assert_eq!(write.apply(current.as_deref()).unwrap_err().to_string(), "/root/x does not exist");
+
+        let replace = FileWrite { path: "/root/x".into(), kind: WriteKind::Replace { content: "b".into() } };
+        let written = ctx.exec(&replace.write_command("b", None)).unwrap();
+        let error = replace.check_write_output(&written.stdout).unwrap_err().to_string();
+        assert_eq!(error, "writing /root/x failed:\nsh: /root/x: Permission denied");
+    }
+
+    struct Fetch;
+
+    impl LocalTool for Fetch {
+        fn def(&self) -> ToolDef {
+            ToolDef::new("fetch_url", ToolGroup::Net, "Fetch a page.")
+        }
+
+        fn permission(&self, input: &Value) -> LocalPermission {
+            LocalPermission::Ask(format!("Fetch {}", input["url"].as_str().unwrap_or("?")))
+        }
+
+        fn run(&self, input: &Value) -> Result<String> {
+            Ok(format!("fetched {}", input["url"]))
+        }
+    }
+
+    #[test]
+    fn local_tools_ask_then_run_here() {
+        let registry = ToolRegistry::builtin().with_local(Arc::new(Fetch));
+        let input = json!({ "url": "https://example.com" });
+        match registry.dispatch("1", "fetch_url", &input) {
+            ToolResult::Machine { permission, .. } => {
+                assert_eq!(permission, LocalPermission::Ask("Fetch https://example.com".into()))
+            }
+            other => panic!("not a local call: {:?}", other),
+        }
+        assert_eq!(registry.run_local("fetch_url", &input).unwrap(), "fetched \"https://example.com\"");
+
+        let without = registry.without_groups(&[ToolGroup::Net]);
+        assert!(without.run_local("fetch_url", &input).is_err());
+        assert!(without.run_local("read_file", &input).is_err());
+    }
+}
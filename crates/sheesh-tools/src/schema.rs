@@ -0,0 +1,116 @@
+//! Schema model for tool arguments. Tools describe their parameters with
+//! [`Param`] builders and the registry turns them into JSON Schema, so
+//! enums, defaults, array items and nested objects reach every provider in
+//! the same shape.
+
+use serde_json::{Map, Value, json};
+
+/// Type of a tool parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// A string restricted to the listed values.
+    Enum(Vec<String>),
+    /// An array whose items all have this type.
+    Array(Box<ParamType>),
+    /// A nested object with its own properties.
+    Object(Vec<Param>),
+}
+
+impl ParamType {
+    /// JSON Schema for a value of this type.
+    pub fn schema(&self) -> Value {
+        match self {
+            Self::String => json!({ "type": "string" }),
+            Self::Integer => json!({ "type": "integer" }),
+            Self::Number => json!({ "type": "number" }),
+            Self::Boolean => json!({ "type": "boolean" }),
+            Self::Enum(values) => json!({ "type": "string", "enum": values }),
+            Self::Array(items) => json!({ "type": "array", "items": items.schema() }),
+            Self::Object(props) => object_schema(props),
+        }
+    }
+}
+
+/// A named tool parameter (or property of a nested object).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub ty: ParamType,
+    pub description: Option<String>,
+    pub required: bool,
+    /// Value the tool uses when the argument is left out.
+    pub default: Option<Value>,
+}
+
+impl Param {
+    pub fn new(name: impl Into<String>, ty: ParamType) -> Self {
+        Self { name: name.into(), ty, description: None, required: false, default: None }
+    }
+
+    pub fn string(name: impl Into<String>) -> Self {
+        Self::new(name, ParamType::String)
+    }
+
+    pub fn integer(name: impl Into<String>) -> Self {
+        Self::new(name, ParamType::Integer)
+    }
+
+    pub fn number(name: impl Into<String>) -> Self {
+        Self::new(name, ParamType::Number)
+    }
+
+    pub fn boolean(name: impl Into<String>) -> Self {
+        Self::new(name, ParamType::Boolean)
+    }
+
+    /// A string that must be one of `values`.
+    pub fn one_of(name: impl Into<String>, values: &[&str]) -> Self {
+        Self::new(name, ParamType::Enum(values.iter().map(|v| v.to_string()).collect()))
+    }
+
+    pub fn array(name: impl Into<String>, items: ParamType) -> Self {
+        Self::new(name, ParamType::Array(Box::new(items)))
+    }
+
+    pub fn object(name: impl Into<String>, props: Vec<Param>) -> Self {
+        Self::new(name, ParamType::Object(props))
+    }
+
+    pub fn describe(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn with_default(mut self, default: impl Into<Value>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    /// JSON Schema of this property.
+    pub fn schema(&self) -> Value {
+        let mut schema = self.ty.schema();
+        if let Some(description) = &self.description {
+            schema["description"] = json!(description);
+        }
+        if let Some(default) = &self.default {
+            schema["default"] = default.clone();
+        }
+        schema
+    }
+}
+
+/// JSON Schema of an object with `props` as its properties.
+pub fn object_schema(props: &[Param]) -> Value {
+    let properties: Map<String, Value> = props.iter().map(|p| (p.name.clone(), p.schema())).collect();
+    let required: Vec<&str> = props.iter().filter(|p| p.required).map(|p| p.name.as_str()).collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
@@ -0,0 +1,101 @@
+//! Hooks for watching tool calls run. The registry doesn't execute most
+//! tools itself (commands go through the host's PTY), so the host reports
+//! when a call starts and what it returned, and the registry times the call
+//! and tells every [`ToolObserver`] — for timings in the UI, an audit log,
+//! or both. Long-running calls can also report progress while they run
+//! through a [`ProgressSink`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A tool call that has finished running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallEnd {
+    pub id: String,
+    pub name: String,
+    pub duration: Duration,
+    /// Size of the result handed back to the model, before any limiting.
+    pub result_bytes: usize,
+}
+
+/// Progress of a tool call that is still running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProgress {
+    pub id: String,
+    pub name: String,
+    pub elapsed: Duration,
+    /// Output produced so far.
+    pub bytes: usize,
+    /// What the call is doing right now, e.g. "waiting for a password".
+    pub note: Option<String>,
+}
+
+/// Where a running tool reports how far it has got.
+pub trait ProgressSink {
+    fn report(&self, bytes: usize, note: Option<&str>);
+}
+
+/// Receives tool call events from a [`ToolRegistry`](crate::ToolRegistry).
+/// Called on the host's thread; keep it quick.
+pub trait ToolObserver: Send + Sync {
+    fn on_call_start(&self, _id: &str, _name: &str) {}
+    fn on_call_progress(&self, _progress: &ToolProgress) {}
+    fn on_call_end(&self, _call: &ToolCallEnd) {}
+}
+
+/// Observers plus the calls currently running. Clones share both, so a
+/// registry cloned into a completion thread still reports to the host.
+#[derive(Clone, Default)]
+pub(crate) struct Telemetry {
+    observers: Vec<Arc<dyn ToolObserver>>,
+    running: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl fmt::Debug for Telemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Telemetry").field("observers", &self.observers.len()).finish_non_exhaustive()
+    }
+}
+
+impl Telemetry {
+    pub(crate) fn add(&mut self, observer: Arc<dyn ToolObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub(crate) fn start(&self, id: &str, name: &str) {
+        self.running.lock().unwrap().insert(id.to_string(), (name.to_string(), Instant::now()));
+        for observer in &self.observers {
+            observer.on_call_start(id, name);
+        }
+    }
+
+    /// Report progress of call `id`; `None` if it isn't running.
+    pub(crate) fn progress(&self, id: &str, bytes: usize, note: Option<&str>) -> Option<ToolProgress> {
+        let (name, started) = self.running.lock().unwrap().get(id).cloned()?;
+        let progress = ToolProgress {
+            id: id.to_string(),
+            name,
+            elapsed: started.elapsed(),
+            bytes,
+            note: note.map(str::to_string),
+        };
+        for observer in &self.observers {
+            observer.on_call_progress(&progress);
+        }
+        Some(progress)
+    }
+
+    /// Report the end of call `id`. Calls that never started (declined or
+    /// cancelled before running) are ignored.
+    pub(crate) fn finish(&self, id: &str, result: &str) {
+        let Some((name, started)) = self.running.lock().unwrap().remove(id) else {
+            return;
+        };
+        let call = ToolCallEnd { id: id.to_string(), name, duration: started.elapsed(), result_bytes: result.len() };
+        for observer in &self.observers {
+            observer.on_call_end(&call);
+        }
+    }
+}
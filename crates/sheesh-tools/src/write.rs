@@ -0,0 +1,168 @@
+//! File-writing tools. Writes are never blind: the host first fetches the
+//! current content with [`FileWrite::read_command`], shows the user a diff of
+//! the change, and only runs [`FileWrite::write_command`] once it is approved.
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use similar::{ChangeTag, TextDiff};
+
+use crate::shell_quote;
+
+// Markers around the base64 dump printed by `read_command`. The command
+// spells them with split quotes so its own echo never matches.
+const READ_BEGIN: &str = "sheesh-read-begin";
+const READ_END: &str = "sheesh-read-end";
+const READ_MISSING: &str = "sheesh-read-missing";
+const WRITE_OK: &str = "sheesh-write-ok";
+const HEREDOC_END: &str = "SHEESH_EOF";
+
+/// How a write tool changes its target file.
+#[derive(Debug, Clone)]
+pub enum WriteKind {
+    /// Replace the whole file (`write_file`).
+    Replace { content: String },
+    /// Add to the end of the file (`append_file`).
+    Append { content: String },
+    /// Replace the single occurrence of `old` with `new` (`edit_file`).
+    Edit { old: String, new: String },
+    /// Undo the latest write of this session (`rollback_file`). The host
+    /// resolves it to a [`WriteKind::Restore`] from its backup records.
+    Rollback,
+    /// Put back the backup taken before an earlier write: copy `backup` over
+    /// the file, or delete the file if the write created it. `content` is
+    /// what the backup holds, for the diff preview.
+    Restore { backup: Option<String>, content: String },
+}
+
+/// A file modification requested by a write tool.
+#[derive(Debug, Clone)]
+pub struct FileWrite {
+    pub path: String,
+    pub kind: WriteKind,
+}
+
+impl FileWrite {
+    /// Shell command that prints the file as base64 between markers, or a
+    /// "missing" marker when it doesn't exist. Read-only.
+    pub fn read_command(&self) -> String {
+        let path = shell_quote(&self.path);
+        format!(
+            "if [ -e {path} ]; then echo 'sheesh-read''-begin'; base64 {path}; echo 'sheesh-read''-end'; \
+             else echo 'sheesh-read''-missing'; fi"
+        )
+    }
+
+    /// Decode the captured output of [`read_command`](Self::read_command).
+    /// Returns `None` when the file doesn't exist.
+    pub fn parse_read_output(&self, output: &str) -> Result<Option<String>> {
+        let lines: Vec<&str> = output.lines().map(str::trim).collect();
+        if lines.contains(&READ_MISSING) {
+            return Ok(None);
+        }
+        let start = lines
+            .iter()
+            .position(|l| *l == READ_BEGIN)
+            .ok_or_else(|| anyhow!("could not read {}", self.path))?;
+        let len = lines[start + 1..]
+            .iter()
+            .position(|l| *l == READ_END)
+            .ok_or_else(|| anyhow!("could not read {} (output was cut short)", self.path))?;
+        let encoded: String = lines[start + 1..start + 1 + len].concat();
+        let bytes = STANDARD
+            .decode(encoded)
+            .with_context(|| format!("could not read {} (permission denied?)", self.path))?;
+        String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| anyhow!("{} is not a text file", self.path))
+    }
+
+    /// The file's content after the write, given its current content
+    /// (`None` when it doesn't exist yet).
+    pub fn apply(&self, before: Option<&str>) -> Result<String> {
+        match &self.kind {
+            WriteKind::Replace { content } | WriteKind::Restore { content, .. } => Ok(content.clone()),
+            WriteKind::Rollback => bail!("no backup of {} was made this session", self.path),
+            WriteKind::Append { content } => Ok(format!("{}{}", before.unwrap_or(""), content)),
+            WriteKind::Edit { old, new } => {
+                let Some(before) = before else {
+                    bail!("{} does not exist", self.path);
+                };
+                match before.matches(old.as_str()).count() {
+                    1 => Ok(before.replacen(old.as_str(), new, 1)),
+                    0 => bail!("old_text was not found in {}", self.path),
+                    n => bail!("old_text matches {} places in {}; include more surrounding lines", n, self.path),
+                }
+            }
+        }
+    }
+
+    /// Where the file is copied before a write made at `timestamp` (unix seconds).
+    pub fn backup_path(&self, timestamp: u64) -> String {
+        format!("{}.sheesh.bak-{}", self.path, timestamp)
+    }
+
+    /// Shell command that writes `after` to the file, first copying the
+    /// current file to `backup` when given. The content travels as base64 in
+    /// a heredoc so quoting, control characters and long lines survive both
+    /// the shell and the terminal.
+    pub fn write_command(&self, after: &str, backup: Option<&str>) -> String {
+        let path = shell_quote(&self.path);
+        if let WriteKind::Restore { backup, .. } = &self.kind {
+            return match backup {
+                Some(backup) => format!("cp -p {} {} && echo 'sheesh-write''-ok'", shell_quote(backup), path),
+                None => format!("rm -f -- {} && echo 'sheesh-write''-ok'", path),
+            };
+        }
+        let backup = backup
+            .map(|b| format!("cp -p {} {} && ", path, shell_quote(b)))
+            .unwrap_or_default();
+        let encoded = STANDARD.encode(after);
+        let mut cmd = format!(
+            "{}base64 -d > {} <<'{}' && echo 'sheesh-write''-ok'\n",
+            backup, path, HEREDOC_END
+        );
+        for chunk in encoded.as_bytes().chunks(76) {
+            cmd.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+            cmd.push('\n');
+        }
+        cmd.push_str(HEREDOC_END);
+        cmd
+    }
+
+    /// Check the captured output of [`write_command`](Self::write_command).
+    /// On failure the error carries what the shell printed after the heredoc
+    /// (skipping the echoed base64 payload), e.g. "Permission denied".
+    pub fn check_write_output(&self, output: &str) -> Result<()> {
+        let mut tail: Vec<&str> = output
+            .lines()
+            .map(str::trim)
+            .rev()
+            .take_while(|l| !l.ends_with(HEREDOC_END))
+            .filter(|l| !l.is_empty())
+            .collect();
+        if tail.contains(&WRITE_OK) {
+            return Ok(());
+        }
+        tail.reverse();
+        bail!("writing {} failed:\n{}", self.path, tail.join("\n"))
+    }
+}
+
+/// Unified diff of a file change with three lines of context.
+pub fn unified_diff(path: &str, before: &str, after: &str) -> String {
+    TextDiff::from_lines(before, after)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path.trim_start_matches('/')), &format!("b/{}", path.trim_start_matches('/')))
+        .to_string()
+}
+
+/// Lines (added, removed) by a file change.
+pub fn diff_stat(before: &str, after: &str) -> (usize, usize) {
+    let diff = TextDiff::from_lines(before, after);
+    diff.iter_all_changes().fold((0, 0), |(added, removed), change| match change.tag() {
+        ChangeTag::Insert => (added + 1, removed),
+        ChangeTag::Delete => (added, removed + 1),
+        ChangeTag::Equal => (added, removed),
+    })
+}
@@ -0,0 +1,103 @@
+//! What ssh-agent holds, asked with `ssh-add -L`: shown in the listing's
+//! detail panel, and checked before connecting so a key the agent doesn't
+//! have is added first rather than found out about from a wall of
+//! `Permission denied (publickey)` (the non-interactive sessions behind
+//! `/man` and the platform check can't ask for a passphrase).
+
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, bail};
+
+use crate::config::expand_home;
+
+/// A key the agent holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentKey {
+    /// `ssh-ed25519`, `ssh-rsa`, …
+    pub kind: String,
+    /// The base64 public key.
+    pub blob: String,
+    /// Usually the file it was added from, or `user@host`.
+    pub comment: String,
+}
+
+impl AgentKey {
+    /// How the key is listed: its comment, or its type when it has none.
+    pub fn label(&self) -> &str {
+        if self.comment.is_empty() { self.kind.trim_start_matches("ssh-") } else { &self.comment }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentStatus {
+    /// No `SSH_AUTH_SOCK`, or nothing answering on it.
+    NotRunning,
+    Keys(Vec<AgentKey>),
+}
+
+impl AgentStatus {
+    /// Ask the agent for its keys.
+    pub fn query() -> AgentStatus {
+        if std::env::var_os("SSH_AUTH_SOCK").is_none() {
+            return AgentStatus::NotRunning;
+        }
+        let Ok(output) = Command::new("ssh-add").arg("-L").stdin(Stdio::null()).output() else {
+            return AgentStatus::NotRunning;
+        };
+        // 1: running with no keys (said on stdout); 2: not reachable.
+        match output.status.code() {
+            Some(0) => AgentStatus::Keys(parse_keys(&String::from_utf8_lossy(&output.stdout))),
+            Some(1) => AgentStatus::Keys(vec![]),
+            _ => AgentStatus::NotRunning,
+        }
+    }
+
+    /// Whether the agent holds the key in `identity` (a private key file,
+    /// read through its `.pub`). `None` when there is no agent or the
+    /// public half can't be read to tell.
+    pub fn holds(&self, identity: &str) -> Option<bool> {
+        let AgentStatus::Keys(keys) = self else {
+            return None;
+        };
+        let public = std::fs::read_to_string(format!("{}.pub", expand_home(identity).display())).ok()?;
+        let blob = public.split_whitespace().nth(1)?;
+        Some(keys.iter().any(|k| k.blob == blob))
+    }
+
+    /// One line for the detail panel, about `identity` if the connection
+    /// names one.
+    pub fn describe(&self, identity: Option<&str>) -> String {
+        let AgentStatus::Keys(keys) = self else {
+            return "not running".to_string();
+        };
+        let held = match keys.len() {
+            0 => "no keys".to_string(),
+            _ => keys.iter().map(AgentKey::label).collect::<Vec<_>>().join(", "),
+        };
+        match identity.map(|key| (key, self.holds(key))) {
+            Some((_, Some(true))) => format!("{} (this key loaded)", held),
+            Some((key, Some(false))) => format!("{} ({} not loaded)", held, key),
+            _ => held,
+        }
+    }
+}
+
+fn parse_keys(text: &str) -> Vec<AgentKey> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let (kind, blob) = (parts.next()?, parts.next()?);
+            Some(AgentKey { kind: kind.into(), blob: blob.into(), comment: parts.next().unwrap_or("").trim().into() })
+        })
+        .collect()
+}
+
+/// Run `ssh-add` for `identity` on the terminal sheesh was started from,
+/// which must be out of raw mode so the passphrase prompt works.
+pub fn add(identity: &str) -> Result<()> {
+    let status = Command::new("ssh-add").arg(expand_home(identity)).status()?;
+    if !status.success() {
+        bail!("ssh-add {} failed ({})", identity, status);
+    }
+    Ok(())
+}
@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 use crate::ssh::SSHConnection;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum ConnectedFocus {
     Terminal,
@@ -0,0 +1,204 @@
+//! `sheesh export <file>` and `sheesh import <file>`: the connections of
+//! the SSH config, with everything sheesh keeps about them (description,
+//! transport, environment, platform), as a JSON or YAML file a team can
+//! keep in a repository and merge into each member's own config.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::ssh::SSHConnection;
+
+/// Bumped if the file's layout changes in a way older versions misread.
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    sheesh: u32,
+    connections: Vec<SSHConnection>,
+}
+
+/// What to do with an imported connection whose alias is taken by a
+/// different one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnConflict {
+    /// Leave the existing connection alone.
+    Keep,
+    /// Overwrite it with the imported one.
+    Replace,
+    /// Add the imported one as `<alias>-2` (or the next free number).
+    Rename,
+}
+
+impl OnConflict {
+    pub fn parse(text: &str) -> Result<OnConflict> {
+        match text {
+            "keep" => Ok(OnConflict::Keep),
+            "replace" => Ok(OnConflict::Replace),
+            "rename" => Ok(OnConflict::Rename),
+            other => bail!("--on-conflict takes keep, replace or rename, not {:?}", other),
+        }
+    }
+}
+
+/// What an import did, alias by alias.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub added: Vec<String>,
+    /// Identical to what was there.
+    pub unchanged: Vec<String>,
+    pub replaced: Vec<String>,
+    /// Imported alias and the one it was added as.
+    pub renamed: Vec<(String, String)>,
+    /// Differing from what was there, which was kept.
+    pub kept: Vec<String>,
+}
+
+impl Report {
+    /// One line per kind of outcome, for the terminal.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![];
+        let mut list = |what: &str, aliases: Vec<String>| {
+            if !aliases.is_empty() {
+                lines.push(format!("{} {}: {}", aliases.len(), what, aliases.join(", ")));
+            }
+        };
+        list("added", self.added.clone());
+        list("unchanged", self.unchanged.clone());
+        list("replaced", self.replaced.clone());
+        list("added under a new alias", self.renamed.iter().map(|(from, to)| format!("{} as {}", from, to)).collect());
+        list("kept as they were (differing; --on-conflict replace or rename to take them)", self.kept.clone());
+        if lines.is_empty() { "Nothing to import.".to_string() } else { lines.join("\n") }
+    }
+}
+
+/// Whether `path` names a YAML file rather than JSON.
+fn is_yaml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+}
+
+/// Write `connections` to `path`, as YAML for `.yaml`/`.yml` and JSON
+/// otherwise.
+pub fn export(connections: &[SSHConnection], path: &Path) -> Result<()> {
+    let bundle = Bundle { sheesh: VERSION, connections: connections.to_vec() };
+    let text =
+        if is_yaml(path) { serde_yaml::to_string(&bundle)? } else { serde_json::to_string_pretty(&bundle)? + "\n" };
+    std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Read the connections of an exported file. Only `name` is required of
+/// each; the rest defaults as in a bare `Host` block.
+pub fn read(path: &Path) -> Result<Vec<SSHConnection>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let bundle: Bundle = if is_yaml(path) {
+        serde_yaml::from_str(&text).with_context(|| format!("reading {}", path.display()))?
+    } else {
+        serde_json::from_str(&text).with_context(|| format!("reading {}", path.display()))?
+    };
+    if bundle.sheesh > VERSION {
+        bail!("{} was written by a newer sheesh (format {})", path.display(), bundle.sheesh);
+    }
+    if let Some(conn) = bundle.connections.iter().find(|c| c.name.trim().is_empty()) {
+        bail!("{}: a connection has no name ({:?})", path.display(), conn.hostname);
+    }
+    Ok(bundle.connections)
+}
+
+/// Merge `imported` into `connections`, new aliases at the end.
+pub fn merge(connections: &mut Vec<SSHConnection>, imported: Vec<SSHConnection>, on_conflict: OnConflict) -> Report {
+    let mut report = Report::default();
+    for conn in imported {
+        let Some(i) = connections.iter().position(|c| c.name == conn.name) else {
+            report.added.push(conn.name.clone());
+            connections.push(conn);
+            continue;
+        };
+        // What `Host *` and the like add is worked out from the local file,
+        // so only the connection's own settings are compared.
+        let own = SSHConnection { inherited: Default::default(), ..connections[i].clone() };
+        if own == conn {
+            report.unchanged.push(conn.name);
+            continue;
+        }
+        match on_conflict {
+            OnConflict::Keep => report.kept.push(conn.name),
+            OnConflict::Replace => {
+                report.replaced.push(conn.name.clone());
+                connections[i] = conn;
+            }
+            OnConflict::Rename => {
+                let alias = (2..)
+                    .map(|n| format!("{}-{}", conn.name, n))
+                    .find(|alias| !connections.iter().any(|c| c.name == *alias))
+                    .unwrap_or_default();
+                report.renamed.push((conn.name.clone(), alias.clone()));
+                connections.push(SSHConnection { name: alias, host_patterns: None, ..conn });
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(name: &str, hostname: &str) -> SSHConnection {
+        SSHConnection { name: name.into(), hostname: hostname.into(), ..Default::default() }
+    }
+
+    fn names(connections: &[SSHConnection]) -> Vec<&str> {
+        connections.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_adds_new_aliases_at_the_end() {
+        let mut connections = vec![conn("web", "10.0.0.1")];
+        let report = merge(&mut connections, vec![conn("db", "10.0.0.2"), conn("web", "10.0.0.1")], OnConflict::Keep);
+        assert_eq!(names(&connections), ["web", "db"]);
+        assert_eq!(report, Report { added: vec!["db".into()], unchanged: vec!["web".into()], ..Default::default() });
+    }
+
+    #[test]
+    fn merge_ignores_what_the_local_file_adds() {
+        let mut local = conn("web", "10.0.0.1");
+        local.inherited.defaults.user = Some("deploy".into());
+        let mut connections = vec![local];
+        let report = merge(&mut connections, vec![conn("web", "10.0.0.1")], OnConflict::Replace);
+        assert_eq!(report.unchanged, ["web"]);
+        assert_eq!(connections[0].inherited.defaults.user.as_deref(), Some("deploy"));
+    }
+
+    #[test]
+    fn merge_settles_conflicts_as_asked() {
+        let existing = || vec![conn("web", "10.0.0.1"), conn("web-2", "10.0.0.3")];
+
+        let mut connections = existing();
+        let report = merge(&mut connections, vec![conn("web", "10.0.0.9")], OnConflict::Keep);
+        assert_eq!((report.kept, connections[0].hostname.as_str()), (vec!["web".to_string()], "10.0.0.1"));
+
+        let mut connections = existing();
+        let report = merge(&mut connections, vec![conn("web", "10.0.0.9")], OnConflict::Replace);
+        assert_eq!((report.replaced, connections[0].hostname.as_str()), (vec!["web".to_string()], "10.0.0.9"));
+        assert_eq!(connections.len(), 2);
+
+        let mut connections = existing();
+        let imported = SSHConnection { host_patterns: Some("web web-*".into()), ..conn("web", "10.0.0.9") };
+        let report = merge(&mut connections, vec![imported], OnConflict::Rename);
+        assert_eq!(report.renamed, [("web".to_string(), "web-3".to_string())]);
+        assert_eq!(names(&connections), ["web", "web-2", "web-3"]);
+        assert_eq!(connections[2].host_patterns, None);
+    }
+
+    #[test]
+    fn report_summary_says_what_happened() {
+        assert_eq!(Report::default().summary(), "Nothing to import.");
+        let report = Report {
+            added: vec!["db".into(), "cache".into()],
+            renamed: vec![("web".into(), "web-2".into())],
+            ..Default::default()
+        };
+        assert_eq!(report.summary(), "2 added: db, cache\n1 added under a new alias: web as web-2");
+    }
+}
@@ -2,44 +2,152 @@ use anyhow::{Context, Result};
 use std::{
     fs,
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 
-use crate::ssh::SSHConnection;
+use crate::llm::wildcard_match;
+use crate::ssh::{Backend, Inherited, Platform, SSHConnection, Transport, env_word, split_env};
 
-/// Returns the path to ~/.ssh/config, creating the file if it doesn't exist.
+/// Starts the comment lines above a `Host` line that hold what only sheesh
+/// reads: `export NAME=value` for `SSHConnection::export_env`, the declared
+/// shell and OS (`platform fish Alpine`), the ssh client when it is not the
+/// ssh binary (`backend native`), or the connection's transport when it is
+/// not ssh (e.g. `serial /dev/ttyUSB0 9600`).
+const SHEESH_COMMENT: &str = "sheesh: ";
+
+/// SSH config picked by `--ssh-config`, `$SSH_CONFIG` or the config file.
+static SSH_CONFIG: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Returns the path to ~/.ssh/config (`%USERPROFILE%\.ssh\config` on
+/// Windows, where OpenSSH looks for it), creating the file if it doesn't exist.
+pub fn default_ssh_config_path() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(".ssh").join("config")
+}
+
+/// The SSH config sheesh lists, for everything that reads it.
 pub fn ssh_config_path() -> PathBuf {
-    let home = dirs::home_dir().expect("cannot determine home directory");
-    home.join(".ssh").join("config")
+    SSH_CONFIG.lock().unwrap().clone().unwrap_or_else(default_ssh_config_path)
 }
 
-/// Parse all `Host` blocks from a ~/.ssh/config file into `SSHConnection`s.
-/// Wildcards (`Host *`) are ignored.
-pub fn load_connections(path: &Path) -> Result<Vec<SSHConnection>> {
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
-        Err(e) => return Err(e).context("reading ~/.ssh/config"),
-    };
+/// Make `path` the SSH config [`ssh_config_path`] returns.
+pub fn use_ssh_config(path: PathBuf) {
+    *SSH_CONFIG.lock().unwrap() = Some(path);
+}
 
-    let mut connections: Vec<SSHConnection> = vec![];
-    let mut current: Option<SSHConnection> = None;
-    let mut pending_comment = String::new();
+/// `path` with a leading `~/` (or `~\` on Windows) replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~').and_then(|rest| rest.strip_prefix(['/', std::path::MAIN_SEPARATOR])), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Expand `${VAR}` references and a leading `~` in every string of a parsed
+/// config file, so one file can be shared across machines. `$${` stands for
+/// a literal `${`; references to unset variables are left as written (with
+/// a warning in the log).
+pub fn interpolate(value: &mut toml::Value) {
+    match value {
+        toml::Value::String(s) => *s = interpolate_str(s),
+        toml::Value::Array(items) => items.iter_mut().for_each(interpolate),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| interpolate(v)),
+        _ => {}
+    }
+}
+
+fn interpolate_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        if let Some(before) = rest[..start].strip_suffix('$') {
+            out.push_str(before);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(len) = rest[start + 2..].find('}') else { break };
+        out.push_str(&rest[..start]);
+        let reference = &rest[start..start + 3 + len];
+        let name = &rest[start + 2..start + 2 + len];
+        match std::env::var(name) {
+            Ok(v) => out.push_str(&v),
+            Err(_) => {
+                log::warn!("[config] {} is not set — left as written", reference);
+                out.push_str(reference);
+            }
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    out.push_str(rest);
+    if out.strip_prefix('~').is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', std::path::MAIN_SEPARATOR]))
+        && let Some(home) = dirs::home_dir()
+    {
+        out.replace_range(..1, &home.to_string_lossy());
+    }
+    out
+}
+
+/// One `Host` or `Match` block of an SSH config (or, without a header, the
+/// lines before the first one): the comment above it, its header and its
+/// settings, trimmed.
+struct Block {
+    comment: Vec<String>,
+    /// Lowercased keyword (`host` or `match`) and the rest of the line.
+    header: Option<(String, String)>,
+    lines: Vec<String>,
+}
+
+impl Block {
+    /// The concrete aliases of a `Host` line; patterns (`*`, `?`) and
+    /// negations (`!name`) are not connections of their own.
+    fn aliases(&self) -> Vec<&str> {
+        match &self.header {
+            Some((key, value)) if key == "host" => value.split_whitespace().filter(|t| is_alias(t)).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The block as it was written, for blocks sheesh doesn't manage.
+    fn text(&self) -> String {
+        let mut out = String::new();
+        for line in &self.comment {
+            out.push_str(&format!("# {}\n", line));
+        }
+        let indent = match &self.header {
+            Some((key, value)) => {
+                out.push_str(&format!("{} {}\n", if key == "host" { "Host" } else { "Match" }, value));
+                "    "
+            }
+            None => "",
+        };
+        for line in &self.lines {
+            out.push_str(&format!("{}{}\n", indent, line));
+        }
+        out
+    }
+}
+
+fn is_alias(token: &str) -> bool {
+    !token.contains(['*', '?']) && !token.starts_with('!')
+}
+
+/// Split an SSH config into its blocks. A comment directly above a block
+/// (or anywhere after the previous block's settings) belongs to it.
+fn blocks(content: &str) -> Vec<Block> {
+    let mut blocks = vec![Block { comment: vec![], header: None, lines: vec![] }];
+    let mut pending_comment: Vec<String> = vec![];
 
     for line in content.lines() {
         let trimmed = line.trim();
 
         if trimmed.starts_with('#') {
-            let comment = trimmed.trim_start_matches('#').trim();
-            if !pending_comment.is_empty() {
-                pending_comment.push(' ');
-            }
-            pending_comment.push_str(comment);
+            pending_comment.push(trimmed.trim_start_matches('#').trim().to_string());
             continue;
         }
 
         if trimmed.is_empty() {
             // Blank line resets pending comment if no Host block has started
-            if current.is_none() {
+            if blocks.len() == 1 {
                 pending_comment.clear();
             }
             continue;
@@ -47,87 +155,288 @@ pub fn load_connections(path: &Path) -> Result<Vec<SSHConnection>> {
 
         let (key, value) = match trimmed.split_once(char::is_whitespace) {
             Some(pair) => (pair.0.to_lowercase(), pair.1.trim().to_string()),
-            None => continue,
+            None => (trimmed.to_lowercase(), String::new()),
         };
+        if key == "host" || key == "match" {
+            blocks.push(Block { comment: std::mem::take(&mut pending_comment), header: Some((key, value)), lines: vec![] });
+        } else if let Some(block) = blocks.last_mut() {
+            block.lines.push(trimmed.to_string());
+        }
+    }
+    blocks
+}
 
-        match key.as_str() {
-            "Host" | "host" => {
-                if let Some(conn) = current.take() {
-                    connections.push(conn);
-                }
-                // Skip wildcard blocks
-                if value == "*" {
-                    pending_comment.clear();
-                    continue;
-                }
-                current = Some(SSHConnection {
-                    name: value,
-                    description: std::mem::take(&mut pending_comment),
-                    ..Default::default()
-                });
-            }
-            "HostName" | "hostname" => {
-                if let Some(ref mut c) = current {
-                    c.hostname = value;
-                }
-            }
-            "User" | "user" => {
-                if let Some(ref mut c) = current {
-                    c.user = value;
-                }
-            }
-            "Port" | "port" => {
-                if let Some(ref mut c) = current {
-                    c.port = value.parse().unwrap_or(22);
-                }
-            }
-            "IdentityFile" | "identityfile" => {
-                if let Some(ref mut c) = current {
-                    c.identity_file = Some(value);
-                }
+/// Parse all `Host` blocks from a ~/.ssh/config file into `SSHConnection`s,
+/// one per alias when a `Host` line names several. Patterns (`Host *`,
+/// `web-*`) and `Match` blocks are not listed.
+pub fn load_connections(path: &Path) -> Result<Vec<SSHConnection>> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).context("reading ~/.ssh/config"),
+    };
+
+    let blocks = blocks(&content);
+    let mut connections: Vec<SSHConnection> = vec![];
+    for (index, block) in blocks.iter().enumerate() {
+        let aliases = block.aliases();
+        if aliases.is_empty() {
+            continue;
+        }
+        let mut conn = SSHConnection::default();
+        let mut description = vec![];
+        for line in &block.comment {
+            let directive = line.strip_prefix(SHEESH_COMMENT);
+            if let Some(pair) = directive.and_then(|d| d.strip_prefix("export ")) {
+                conn.export_env.push(pair.trim().to_string());
+            } else if let Some(platform) = directive.and_then(|d| d.strip_prefix("platform ")) {
+                conn.platform = Platform::parse(platform);
+            } else if let Some(backend) = directive.and_then(|d| d.strip_prefix("backend ")).and_then(Backend::parse) {
+                conn.backend = backend;
+            } else if let Some(transport) = directive.and_then(Transport::parse) {
+                conn.transport = transport;
+            } else {
+                description.push(line.as_str());
             }
-            _ => {
-                if let Some(ref mut c) = current {
-                    c.extra_options.push(format!("{} {}", key, value));
-                }
+        }
+        conn.description = description.join(" ");
+        if let Some((_, line)) = &block.header
+            && line.split_whitespace().count() > 1
+        {
+            conn.host_patterns = Some(line.clone());
+        }
+        for line in &block.lines {
+            let Some((key, value)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.to_lowercase().as_str() {
+                "hostname" => conn.hostname = value,
+                "user" => conn.user = value,
+                "port" => conn.port = value.parse().unwrap_or(22),
+                "identityfile" => conn.identity_file = Some(value),
+                "setenv" => conn.set_env.extend(split_env(&value)),
+                "sendenv" => conn.send_env.extend(value.split_whitespace().map(String::from)),
+                _ => conn.extra_options.push(format!("{} {}", key, value)),
             }
         }
+        for alias in aliases {
+            let inherited = inherited(&blocks, index, alias);
+            connections.push(SSHConnection { name: alias.to_string(), inherited, ..conn.clone() });
+        }
     }
 
-    if let Some(conn) = current {
-        connections.push(conn);
+    Ok(connections)
+}
+
+/// Keywords ssh refuses as `-o` options; an `Include` at the top of the
+/// file is for ssh's own reading of it.
+const NOT_OPTIONS: [&str; 2] = ["include", "match"];
+
+/// What the blocks other than `own` give `alias`: the lines before the first
+/// `Host` and every other `Host` block whose patterns match it. `Match`
+/// blocks depend on more than the name and are left to ssh.
+fn inherited(blocks: &[Block], own: usize, alias: &str) -> Inherited {
+    let mut inherited = Inherited::default();
+    for (index, block) in blocks.iter().enumerate() {
+        let source = match &block.header {
+            _ if index == own => continue,
+            None if block.lines.is_empty() => continue,
+            None => "(top of file)".to_string(),
+            Some((key, value)) if key == "host" && host_matches(value, alias) => format!("Host {}", value),
+            Some(_) => continue,
+        };
+        let settings = if index < own { &mut inherited.overrides } else { &mut inherited.defaults };
+        for line in &block.lines {
+            if let Some((key, value)) = line.split_once(char::is_whitespace)
+                && !NOT_OPTIONS.contains(&key.to_lowercase().as_str())
+            {
+                settings.set(key, value.trim());
+            }
+        }
+        settings.sources.push(source);
     }
+    inherited
+}
 
-    Ok(connections)
+/// Whether a `Host` line's patterns match `alias` the way ssh matches them:
+/// any pattern matches and no `!pattern` does.
+fn host_matches(line: &str, alias: &str) -> bool {
+    let alias = alias.to_lowercase();
+    let mut matched = false;
+    for token in line.split_whitespace().map(str::to_lowercase) {
+        match token.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, &alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(&token, &alias),
+        }
+    }
+    matched
 }
 
-/// Write connections back to ~/.ssh/config.
-/// Preserves the rest of the file (lines not belonging to any managed Host block).
+/// Write connections back to ~/.ssh/config. Blocks sheesh doesn't manage
+/// (lines before the first `Host`, pattern-only `Host` blocks, `Match`
+/// blocks) stay where they were, and each connection is written where its
+/// alias was; new ones go after the last connection, ahead of a trailing
+/// `Host *`. Aliases that came from one `Host` line and still share its
+/// settings are written back on that line, with its patterns.
 pub fn save_connections(path: &Path, connections: &[SSHConnection]) -> Result<()> {
+    let old = fs::read_to_string(path).unwrap_or_default();
+    let old_blocks = blocks(&old);
+    let last_managed = old_blocks.iter().rposition(|b| !b.aliases().is_empty());
+
     let mut out = String::new();
+    let mut written = vec![false; connections.len()];
+    // Host lines whose patterns have been written already.
+    let mut patterns_written: Vec<&str> = vec![];
+
+    for (index, block) in old_blocks.iter().enumerate() {
+        let aliases = block.aliases();
+        if aliases.is_empty() {
+            if block.header.is_some() || !block.lines.is_empty() {
+                out.push_str(&(block.text() + "\n"));
+            }
+        } else {
+            let here = |c: &SSHConnection| aliases.contains(&c.name.as_str());
+            write_hosts(&mut out, connections, &mut written, &mut patterns_written, here);
+        }
+        if Some(index) == last_managed {
+            write_hosts(&mut out, connections, &mut written, &mut patterns_written, |_| true);
+        }
+    }
+    write_hosts(&mut out, connections, &mut written, &mut patterns_written, |_| true);
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+
+    // Keep Windows line endings in a file that has them.
+    if old.contains("\r\n") {
+        out = out.replace('\n', "\r\n");
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("creating ~/.ssh directory")?;
+    }
+    fs::write(path, out).context("writing ~/.ssh/config")?;
+    Ok(())
+}
+
+/// Write the connections `wanted` picks that aren't written yet, each with
+/// the others still sharing its `Host` line.
+fn write_hosts<'a>(
+    out: &mut String,
+    connections: &'a [SSHConnection],
+    written: &mut [bool],
+    patterns_written: &mut Vec<&'a str>,
+    wanted: impl Fn(&SSHConnection) -> bool,
+) {
+    for (i, conn) in connections.iter().enumerate() {
+        if written[i] || !wanted(conn) {
+            continue;
+        }
+        let same_block = |other: &SSHConnection| {
+            other.host_patterns.is_some()
+                && other.host_patterns == conn.host_patterns
+                && SSHConnection { name: String::new(), inherited: Inherited::default(), ..other.clone() }
+                    == SSHConnection { name: String::new(), inherited: Inherited::default(), ..conn.clone() }
+        };
+        let mut names: Vec<&str> = vec![];
+        for (j, other) in connections.iter().enumerate().skip(i) {
+            if !written[j] && (j == i || same_block(other)) {
+                written[j] = true;
+                names.push(&other.name);
+            }
+        }
+        let host = match &conn.host_patterns {
+            Some(line) => {
+                // The original line's order, its patterns only the first time.
+                let first = !patterns_written.contains(&line.as_str());
+                patterns_written.push(line);
+                let mut tokens: Vec<&str> =
+                    line.split_whitespace().filter(|t| if is_alias(t) { names.contains(t) } else { first }).collect();
+                tokens.extend(names.iter().filter(|n| !line.split_whitespace().any(|t| t == **n)));
+                tokens.join(" ")
+            }
+            None => conn.name.clone(),
+        };
 
-    for conn in connections {
         if !conn.description.is_empty() {
             out.push_str(&format!("# {}\n", conn.description));
         }
-        out.push_str(&format!("Host {}\n", conn.name));
-        out.push_str(&format!("    HostName {}\n", conn.hostname));
-        out.push_str(&format!("    User {}\n", conn.user));
+        if !conn.transport.is_ssh() {
+            out.push_str(&format!("# {}{}\n", SHEESH_COMMENT, conn.transport.directive()));
+        }
+        if !conn.platform.is_auto() {
+            out.push_str(&format!("# {}platform {}\n", SHEESH_COMMENT, conn.platform.text()));
+        }
+        if !conn.backend.is_system() {
+            out.push_str(&format!("# {}backend {}\n", SHEESH_COMMENT, conn.backend.text()));
+        }
+        for pair in &conn.export_env {
+            out.push_str(&format!("# {}export {}\n", SHEESH_COMMENT, pair));
+        }
+        out.push_str(&format!("Host {}\n", host));
+        // Left out when empty, so a `Host *` user or host name still applies.
+        if !conn.hostname.is_empty() {
+            out.push_str(&format!("    HostName {}\n", conn.hostname));
+        }
+        if !conn.user.is_empty() {
+            out.push_str(&format!("    User {}\n", conn.user));
+        }
         if conn.port != 0 && conn.port != 22 {
             out.push_str(&format!("    Port {}\n", conn.port));
         }
         if let Some(ref key) = conn.identity_file {
             out.push_str(&format!("    IdentityFile {}\n", key));
         }
+        if !conn.set_env.is_empty() {
+            let pairs: Vec<String> = conn.set_env.iter().map(|pair| env_word(pair)).collect();
+            out.push_str(&format!("    SetEnv {}\n", pairs.join(" ")));
+        }
+        if !conn.send_env.is_empty() {
+            out.push_str(&format!("    SendEnv {}\n", conn.send_env.join(" ")));
+        }
         for opt in &conn.extra_options {
             out.push_str(&format!("    {}\n", opt));
         }
         out.push('\n');
     }
+}
 
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).context("creating ~/.ssh directory")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_matches_like_ssh() {
+        assert!(host_matches("web1 web2", "web2"));
+        assert!(host_matches("web*", "WEB10"));
+        assert!(host_matches("*.example.com !bastion.example.com", "db.example.com"));
+        assert!(!host_matches("*.example.com !bastion.example.com", "bastion.example.com"));
+        // A negation alone matches nothing.
+        assert!(!host_matches("!bastion", "web1"));
+        assert!(!host_matches("web?", "web10"));
+    }
+
+    #[test]
+    fn interpolate_expands_set_variables() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(interpolate_str("${PATH}:/opt/bin"), format!("{}:/opt/bin", path));
+    }
+
+    #[test]
+    fn interpolate_leaves_the_rest_as_written() {
+        assert_eq!(interpolate_str("${SHEESH_TEST_SURELY_UNSET}/x"), "${SHEESH_TEST_SURELY_UNSET}/x");
+        assert_eq!(interpolate_str("cost: $${PATH}"), "cost: ${PATH}");
+        assert_eq!(interpolate_str("${unclosed"), "${unclosed");
+        assert_eq!(interpolate_str("a~b"), "a~b");
+    }
+
+    #[test]
+    fn interpolate_expands_a_leading_tilde_through_tables() {
+        let Some(home) = dirs::home_dir() else { return };
+        let mut value: toml::Value = toml::from_str("dir = '~/logs'\nlist = ['~', '~user']").unwrap();
+        interpolate(&mut value);
+        assert_eq!(value["dir"].as_str(), Some(home.join("logs").to_string_lossy().as_ref()));
+        assert_eq!(value["list"][0].as_str(), Some(home.to_string_lossy().as_ref()));
+        assert_eq!(value["list"][1].as_str(), Some("~user"));
     }
-    fs::write(path, out).context("writing ~/.ssh/config")?;
-    Ok(())
 }
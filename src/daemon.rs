@@ -0,0 +1,298 @@
+//! Sessions that outlive the TUI. Each connection's ssh runs under a small
+//! holder process (`sheesh hold`) that owns the PTY, keeps the recent output
+//! and serves it over a Unix socket in the runtime directory. The TUI is a
+//! client: quitting with "leave running" just drops the socket, and the next
+//! connect to the same host (or `sheesh attach`) replays the output into a
+//! fresh terminal and carries on. Each session has its own holder, so the
+//! same host can be open more than once; a holder serves one client at a
+//! time and turns others away.
+//!
+//! Client → holder messages are framed as a kind byte, a big-endian u32
+//! length and the payload; holder → client is one byte saying whether the
+//! client was taken ([`ATTACHED`] or [`BUSY`]), then the raw PTY output.
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
+
+use crate::ssh::SSHConnection;
+
+/// Output a holder keeps for replay on attach.
+const HOLD_BYTES: usize = 4 * 1024 * 1024;
+
+/// How long to wait for a new holder's socket to appear.
+const START_TIMEOUT: Duration = Duration::from_secs(3);
+
+const INPUT: u8 = 0;
+const RESIZE: u8 = 1;
+const CLOSE: u8 = 2;
+
+/// The holder's first byte: the client is attached, its output follows.
+const ATTACHED: u8 = 0;
+/// The holder's first byte: another client is attached; the stream ends.
+const BUSY: u8 = 1;
+
+/// Where the holders' sockets live.
+fn socket_dir() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("sheesh"),
+        None => dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("sheesh").join("sessions"),
+    }
+}
+
+fn file_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' }).collect()
+}
+
+/// The socket of session `id` on connection `name`.
+fn socket_path(name: &str, id: u32) -> PathBuf {
+    socket_dir().join(format!("{}.{}.sock", file_name(name), id))
+}
+
+/// The session ids with a socket for connection `name`, in order.
+fn session_ids(name: &str) -> Vec<u32> {
+    let prefix = format!("{}.", file_name(name));
+    let mut ids: Vec<u32> = std::fs::read_dir(socket_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().into_string().ok()?;
+            file.strip_prefix(&prefix)?.strip_suffix(".sock")?.parse().ok()
+        })
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Attach to the holder at `path`: its stream, or `None` when another
+/// client has it. A socket nothing listens on is removed.
+fn attach(path: &Path) -> Result<Option<UnixStream>> {
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = std::fs::remove_file(path);
+            return Err(e).with_context(|| format!("connecting to {}", path.display()));
+        }
+    };
+    let mut answer = [0u8; 1];
+    stream.read_exact(&mut answer).with_context(|| format!("attaching to {}", path.display()))?;
+    Ok((answer[0] == ATTACHED).then_some(stream))
+}
+
+/// Connection names with a holder no client is attached to, once per
+/// holder, dropping stale sockets.
+pub fn detached(connections: &[SSHConnection]) -> Vec<String> {
+    let mut names = vec![];
+    for conn in connections {
+        for id in session_ids(&conn.name) {
+            if let Ok(Some(_)) = attach(&socket_path(&conn.name, id)) {
+                names.push(conn.name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// A TUI's connection to a holder. Writes are sent as terminal input.
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    /// Attach to a holder of `conn`'s left running, or start a new one when
+    /// every holder already has a client. Returns the client, the stream
+    /// its output arrives on, and whether it was already running (so the
+    /// replayed output is an earlier session's).
+    pub fn open(conn: &SSHConnection) -> Result<(Client, UnixStream, bool)> {
+        let ids = session_ids(&conn.name);
+        for id in &ids {
+            if let Ok(Some(stream)) = attach(&socket_path(&conn.name, *id)) {
+                return Ok((Client { stream: stream.try_clone()? }, stream, true));
+            }
+        }
+        // Ids of holders that ended were freed above.
+        let ids = session_ids(&conn.name);
+        let id = (0..).find(|id| !ids.contains(id)).unwrap_or_default();
+        let path = socket_path(&conn.name, id);
+        let dir = socket_dir();
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("creating {}", dir.display()))?;
+        let mut holder = Command::new(std::env::current_exe()?);
+        holder.arg("hold").arg(&path).args(conn.ssh_args());
+        holder.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        // Its own session, without the TUI's controlling terminal, so
+        // Ctrl+C, the TUI's exit and the terminal closing don't reach it.
+        // SAFETY: setsid is async-signal-safe and touches no memory.
+        unsafe {
+            holder.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        holder.spawn().context("starting the session holder")?;
+        let start = Instant::now();
+        loop {
+            match attach(&path) {
+                Ok(Some(stream)) => return Ok((Client { stream: stream.try_clone()? }, stream, false)),
+                Ok(None) => bail!("the new session holder at {} has another client", path.display()),
+                Err(_) if start.elapsed() < START_TIMEOUT => thread::sleep(Duration::from_millis(20)),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn try_clone(&self) -> Result<Client> {
+        Ok(Client { stream: self.stream.try_clone()? })
+    }
+
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        let mut size = rows.to_be_bytes().to_vec();
+        size.extend(cols.to_be_bytes());
+        let _ = self.send(RESIZE, &size);
+    }
+
+    /// End the session: the holder hangs up ssh and exits.
+    pub fn close(&mut self) {
+        let _ = self.send(CLOSE, &[]);
+    }
+
+    fn send(&mut self, kind: u8, payload: &[u8]) -> std::io::Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 5);
+        frame.push(kind);
+        frame.extend((payload.len() as u32).to_be_bytes());
+        frame.extend(payload);
+        self.stream.write_all(&frame)
+    }
+}
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.send(INPUT, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Output kept for replay and the client it goes to.
+#[derive(Default)]
+struct Held {
+    output: VecDeque<u8>,
+    /// The attached client and its number, so a client that went away
+    /// only lets go of its own place.
+    client: Option<(u64, UnixStream)>,
+    clients: u64,
+}
+
+/// `sheesh hold <socket> <ssh args…>`: run ssh in a PTY and serve it on
+/// `socket` until ssh exits or a client closes the session.
+pub fn hold(socket: &Path, ssh_args: &[String]) -> Result<()> {
+    let pair = NativePtySystem::default().openpty(PtySize { rows: 40, cols: 120, pixel_width: 0, pixel_height: 0 })?;
+    let mut cmd = CommandBuilder::new("ssh");
+    cmd.args(ssh_args);
+    let mut child = pair.slave.spawn_command(cmd)?;
+    drop(pair.slave);
+    let listener = UnixListener::bind(socket).with_context(|| format!("binding {}", socket.display()))?;
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let writer = Arc::new(Mutex::new(pair.master.take_writer()?));
+    let master = Arc::new(Mutex::new(pair.master));
+    let held = Arc::new(Mutex::new(Held::default()));
+    let killer = child.clone_killer();
+
+    let (held_c, socket_c) = (Arc::clone(&held), socket.to_path_buf());
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let mut held = held_c.lock().unwrap();
+            held.output.extend(&buf[..n]);
+            let excess = held.output.len().saturating_sub(HOLD_BYTES);
+            held.output.drain(..excess);
+            if let Some((_, client)) = &mut held.client
+                && client.write_all(&buf[..n]).is_err()
+            {
+                held.client = None;
+            }
+        }
+        let _ = child.wait();
+        let _ = std::fs::remove_file(&socket_c);
+        std::process::exit(0);
+    });
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let number = {
+            let mut held = held.lock().unwrap();
+            if held.client.is_some() {
+                let _ = stream.write_all(&[BUSY]);
+                continue;
+            }
+            let (a, b) = held.output.as_slices();
+            let Ok(output) = stream.try_clone() else { continue };
+            if stream.write_all(&[ATTACHED]).and_then(|_| stream.write_all(a)).and_then(|_| stream.write_all(b)).is_err()
+            {
+                continue;
+            }
+            held.clients += 1;
+            let number = held.clients;
+            held.client = Some((number, output));
+            number
+        };
+        let (writer, master, mut killer) = (Arc::clone(&writer), Arc::clone(&master), killer.clone_killer());
+        let (held, socket) = (Arc::clone(&held), socket.to_path_buf());
+        thread::spawn(move || {
+            let mut header = [0u8; 5];
+            while stream.read_exact(&mut header).is_ok() {
+                let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+                let mut payload = vec![0u8; len];
+                if stream.read_exact(&mut payload).is_err() {
+                    break;
+                }
+                match header[0] {
+                    INPUT => {
+                        let mut writer = writer.lock().unwrap();
+                        let _ = writer.write_all(&payload).and_then(|_| writer.flush());
+                    }
+                    RESIZE if len == 4 => {
+                        let rows = u16::from_be_bytes([payload[0], payload[1]]);
+                        let cols = u16::from_be_bytes([payload[2], payload[3]]);
+                        let _ = master.lock().unwrap().resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 });
+                    }
+                    CLOSE => {
+                        let _ = killer.kill();
+                        let _ = std::fs::remove_file(&socket);
+                        std::process::exit(0);
+                    }
+                    _ => {}
+                }
+            }
+            // Gone (left running, or the TUI ended): the next client may attach.
+            let mut held = held.lock().unwrap();
+            if held.client.as_ref().is_some_and(|(n, _)| *n == number) {
+                held.client = None;
+            }
+        });
+    }
+    bail!("listening on {} stopped", socket.display())
+}
@@ -9,8 +9,12 @@ pub enum Action {
     Disconnect,
     /// Send a command string to the terminal PTY (no trailing newline).
     SendToTerminal(String),
+    /// Type text at the terminal's prompt, leaving Enter to the user.
+    TypeInTerminal(String),
     /// Cancel an in-progress tool call and return to the user prompt.
     CancelToolCall,
+    /// Switch to the next `[profile.*]` of the config file.
+    SwitchProfile,
     /// No-op
     None,
 }
@@ -0,0 +1,239 @@
+//! "Export view": writes what a panel shows, colors included, to an ANSI
+//! text file (for `cat`/`less -R`) and an HTML file (for pasting into
+//! tickets), next to each other in the download directory. Also writes a
+//! terminal's whole scrollback, as plain text or with its colors.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier},
+    text::{Line, Span},
+};
+
+/// Write the `area` of `buffer` to `<dir>/sheesh-<label>-<time>.ansi` and
+/// `.html`, returning both paths.
+pub fn export_view(buffer: &Buffer, area: Rect, dir: &Path, label: &str) -> Result<(PathBuf, PathBuf)> {
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let label: String = label.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let base = dir.join(format!("sheesh-{}-{}", label, stamp));
+    let (ansi, html) = (base.with_extension("ansi"), base.with_extension("html"));
+    let area = area.intersection(buffer.area);
+    std::fs::write(&ansi, to_ansi(buffer, area)).with_context(|| format!("writing {}", ansi.display()))?;
+    std::fs::write(&html, to_html(buffer, area, &label)).with_context(|| format!("writing {}", html.display()))?;
+    Ok((ansi, html))
+}
+
+/// Write `lines` (rendered terminal rows, `width` cells wide) to `path`,
+/// with ANSI color codes when `ansi` is set.
+pub fn write_lines(lines: &[Line], width: u16, path: &Path, ansi: bool) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let area = Rect::new(0, 0, width, lines.len().min(u16::MAX as usize) as u16);
+    let mut buffer = Buffer::empty(area);
+    for (y, line) in lines.iter().enumerate().take(area.height as usize) {
+        buffer.set_line(0, y as u16, line, width);
+    }
+    let text = if ansi {
+        to_ansi(&buffer, area)
+    } else {
+        let mut out = String::new();
+        for row in runs(&buffer, area) {
+            row.iter().for_each(|(_, text)| out.push_str(text));
+            out.push('\n');
+        }
+        out
+    };
+    std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Cell attributes that change the output when they change.
+type Attrs = (Color, Color, Modifier);
+
+/// Rows of `area` as runs of (attributes, text), trailing blanks dropped.
+fn runs(buffer: &Buffer, area: Rect) -> Vec<Vec<(Attrs, String)>> {
+    (area.top()..area.bottom())
+        .map(|y| {
+            let mut row: Vec<(Attrs, String)> = vec![];
+            // Cells covered by the previous wide character.
+            let mut covered: usize = 0;
+            for x in area.left()..area.right() {
+                let cell = &buffer[(x, y)];
+                if covered > 0 || cell.skip {
+                    covered = covered.saturating_sub(1);
+                    continue;
+                }
+                covered = Span::raw(cell.symbol()).width().saturating_sub(1);
+                let attrs = (cell.fg, cell.bg, cell.modifier);
+                match row.last_mut() {
+                    Some((last, text)) if *last == attrs => text.push_str(cell.symbol()),
+                    _ => row.push((attrs, cell.symbol().to_string())),
+                }
+            }
+            while let Some((attrs, text)) = row.last_mut() {
+                if attrs.1 != Color::Reset {
+                    break;
+                }
+                let trimmed = text.trim_end().len();
+                text.truncate(trimmed);
+                if !text.is_empty() {
+                    break;
+                }
+                row.pop();
+            }
+            row
+        })
+        .collect()
+}
+
+fn to_ansi(buffer: &Buffer, area: Rect) -> String {
+    let mut out = String::new();
+    for row in runs(buffer, area) {
+        for ((fg, bg, modifier), text) in row {
+            let mut codes: Vec<String> = vec!["0".into()];
+            for (flag, code) in [
+                (Modifier::BOLD, "1"),
+                (Modifier::DIM, "2"),
+                (Modifier::ITALIC, "3"),
+                (Modifier::UNDERLINED, "4"),
+                (Modifier::REVERSED, "7"),
+                (Modifier::CROSSED_OUT, "9"),
+            ] {
+                if modifier.contains(flag) {
+                    codes.push(code.into());
+                }
+            }
+            codes.extend(sgr_color(fg, false));
+            codes.extend(sgr_color(bg, true));
+            let _ = write!(out, "\x1b[{}m{}", codes.join(";"), text);
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+fn sgr_color(color: Color, background: bool) -> Option<String> {
+    let base = if background { 40 } else { 30 };
+    let code = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        Color::Indexed(i) => return Some(format!("{};5;{}", base + 8, i)),
+        Color::Black => base,
+        Color::Red => base + 1,
+        Color::Green => base + 2,
+        Color::Yellow => base + 3,
+        Color::Blue => base + 4,
+        Color::Magenta => base + 5,
+        Color::Cyan => base + 6,
+        Color::Gray => base + 7,
+        Color::DarkGray => base + 60,
+        Color::LightRed => base + 61,
+        Color::LightGreen => base + 62,
+        Color::LightYellow => base + 63,
+        Color::LightBlue => base + 64,
+        Color::LightMagenta => base + 65,
+        Color::LightCyan => base + 66,
+        Color::White => base + 67,
+    };
+    Some(code.to_string())
+}
+
+fn to_html(buffer: &Buffer, area: Rect, title: &str) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head>\n\
+         <body style=\"background:#1e1e1e;color:#d4d4d4\">\n\
+         <pre style=\"font-family:monospace;line-height:1.2\">",
+        escape(title)
+    );
+    for row in runs(buffer, area) {
+        for ((fg, bg, modifier), text) in row {
+            let (mut fg, mut bg) = (css_color(fg), css_color(bg));
+            if modifier.contains(Modifier::REVERSED) {
+                (fg, bg) = (bg.or(Some("#1e1e1e".into())), fg.or(Some("#d4d4d4".into())));
+            }
+            let mut style = String::new();
+            if let Some(fg) = fg {
+                let _ = write!(style, "color:{};", fg);
+            }
+            if let Some(bg) = bg {
+                let _ = write!(style, "background:{};", bg);
+            }
+            for (flag, css) in [
+                (Modifier::BOLD, "font-weight:bold;"),
+                (Modifier::DIM, "opacity:0.6;"),
+                (Modifier::ITALIC, "font-style:italic;"),
+                (Modifier::UNDERLINED, "text-decoration:underline;"),
+                (Modifier::CROSSED_OUT, "text-decoration:line-through;"),
+            ] {
+                if modifier.contains(flag) {
+                    style.push_str(css);
+                }
+            }
+            if style.is_empty() {
+                out.push_str(&escape(&text));
+            } else {
+                let _ = write!(out, "<span style=\"{}\">{}</span>", style, escape(&text));
+            }
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body></html>\n");
+    out
+}
+
+/// CSS for `color`, using the xterm palette for the named colors.
+fn css_color(color: Color) -> Option<String> {
+    const PALETTE: [&str; 16] = [
+        "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5", "#7f7f7f",
+        "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+    ];
+    let index = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        Color::Indexed(i) => return Some(indexed_css(i, &PALETTE)),
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+    };
+    Some(PALETTE[index].to_string())
+}
+
+/// CSS for xterm 256-color index `i`: the 16 named colors, the 6×6×6 cube,
+/// then the gray ramp.
+fn indexed_css(i: u8, palette: &[&str; 16]) -> String {
+    match i {
+        0..=15 => palette[i as usize].to_string(),
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = i - 16;
+            format!("#{:02x}{:02x}{:02x}", level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (i - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", v, v, v)
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
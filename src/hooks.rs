@@ -0,0 +1,138 @@
+//! `[hooks]` — the user's own commands run on session events, e.g. to
+//! update a status board or forward a line to a SIEM. Each runs locally
+//! through the shell with what is known about the session in `SHEESH_*`
+//! environment variables. sheesh doesn't wait for them; one that fails is
+//! only logged.
+
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sheesh_tools::{ToolCallEnd, ToolObserver};
+
+use crate::ssh::SSHConnection;
+
+/// `[hooks]` section of config.toml: a shell command per event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// A session was opened.
+    pub on_connect: Option<String>,
+    /// The session on screen was closed, or all of them on quitting.
+    pub on_disconnect: Option<String>,
+    /// A tool call of the assistant finished.
+    pub on_tool_executed: Option<String>,
+    /// A session could not be opened, its host key changed, or the LLM
+    /// provider returned an error.
+    pub on_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    Connect,
+    Disconnect,
+    ToolExecuted,
+    Error,
+}
+
+impl HookEvent {
+    /// The value of `SHEESH_EVENT`.
+    pub fn text(self) -> &'static str {
+        match self {
+            HookEvent::Connect => "connect",
+            HookEvent::Disconnect => "disconnect",
+            HookEvent::ToolExecuted => "tool_executed",
+            HookEvent::Error => "error",
+        }
+    }
+}
+
+impl Hooks {
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        let command = match event {
+            HookEvent::Connect => &self.on_connect,
+            HookEvent::Disconnect => &self.on_disconnect,
+            HookEvent::ToolExecuted => &self.on_tool_executed,
+            HookEvent::Error => &self.on_error,
+        };
+        command.as_deref().filter(|c| !c.trim().is_empty())
+    }
+
+    /// Start the command for `event`, if there is one, with `conn`'s details
+    /// and `vars` in its environment.
+    pub fn run(&self, event: HookEvent, conn: &SSHConnection, vars: &[(&str, String)]) {
+        let Some(command) = self.command(event) else {
+            return;
+        };
+        let mut env = session_vars(conn);
+        env.push(("SHEESH_EVENT", event.text().to_string()));
+        env.extend(vars.iter().map(|(key, value)| (*key, value.clone())));
+        let mut shell = shell(command);
+        shell.envs(env).stdin(Stdio::null());
+        let command = command.to_string();
+        std::thread::spawn(move || match shell.output() {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => log::warn!(
+                "[hooks] {} `{}` {}: {}",
+                event.text(),
+                command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => log::warn!("[hooks] could not run {} `{}`: {}", event.text(), command, e),
+        });
+    }
+
+    /// Runs `on_tool_executed` for each tool call made on `conn`; add it to
+    /// the session's tool registry.
+    pub fn tool_observer(&self, conn: &SSHConnection) -> Arc<dyn ToolObserver> {
+        Arc::new(ToolHook { hooks: self.clone(), conn: conn.clone() })
+    }
+}
+
+/// What every hook is told about the session.
+fn session_vars(conn: &SSHConnection) -> Vec<(&'static str, String)> {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let transport = conn.transport.directive();
+    vec![
+        ("SHEESH_CONNECTION", conn.name.clone()),
+        ("SHEESH_HOST", conn.effective_hostname().to_string()),
+        ("SHEESH_USER", conn.effective_user().to_string()),
+        ("SHEESH_PORT", conn.effective_port().to_string()),
+        // `ssh`, or e.g. `serial /dev/ttyUSB0 9600` as in the edit form.
+        ("SHEESH_TRANSPORT", if transport.is_empty() { "ssh".into() } else { transport }),
+        ("SHEESH_TIME", time.to_string()),
+    ]
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+struct ToolHook {
+    hooks: Hooks,
+    conn: SSHConnection,
+}
+
+impl ToolObserver for ToolHook {
+    fn on_call_end(&self, call: &ToolCallEnd) {
+        let vars = [
+            ("SHEESH_TOOL", call.name.clone()),
+            ("SHEESH_TOOL_ID", call.id.clone()),
+            ("SHEESH_DURATION_MS", call.duration.as_millis().to_string()),
+            ("SHEESH_RESULT_BYTES", call.result_bytes.to_string()),
+        ];
+        self.hooks.run(HookEvent::ToolExecuted, &self.conn, &vars);
+    }
+}
@@ -0,0 +1,174 @@
+//! Facts about remote hosts (OS, kernel, login shell, running services)
+//! asked with [`DETECT_COMMAND`] and kept per Host alias in
+//! `<data dir>/sheesh/inventory.toml`, so a new session starts out knowing
+//! what earlier ones found and the assistant can skip the discovery commands.
+//! Nothing is taken from the output of other commands.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::store;
+
+/// Most services remembered per host.
+const MAX_SERVICES: usize = 30;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostFacts {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    /// Login shell, e.g. `zsh`. Like the OS and package manager, sent in the
+    /// session header, where a connection's declared platform overrides it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Services seen running, without the `.service` suffix.
+    pub services: Vec<String>,
+    /// Unix time the facts last changed.
+    pub updated: u64,
+}
+
+impl HostFacts {
+    /// Pick up the facts in the output of [`DETECT_COMMAND`]. Returns true
+    /// if anything changed.
+    pub fn learn(&mut self, output: &str) -> bool {
+        let before = self.clone();
+        let mut services = vec![];
+        for line in output.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("PRETTY_NAME=") {
+                self.os = Some(name.trim_matches(['"', '\'']).to_string());
+            } else if let Some(kernel) = line.strip_prefix("KERNEL=").filter(|k| !k.is_empty()) {
+                self.kernel = Some(kernel.to_string());
+            } else if let Some(shell) = line.strip_prefix("SHELL=").and_then(shell_name) {
+                self.shell = Some(shell);
+            } else if let Some(unit) = line.strip_prefix("SERVICE=").and_then(|u| u.strip_suffix(".service")) {
+                services.push(unit.to_string());
+            }
+        }
+        if !services.is_empty() {
+            services.truncate(MAX_SERVICES);
+            self.services = services;
+        }
+        let changed = *self != before;
+        if changed {
+            self.updated = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        }
+        changed
+    }
+
+    /// System message telling the assistant what else is already known,
+    /// if anything.
+    pub fn prompt(&self) -> Option<String> {
+        if self.kernel.is_none() && self.services.is_empty() {
+            return None;
+        }
+        let mut text = "Known facts about this host from earlier tool output (they may be out of date; \
+                        re-check only if something contradicts them):"
+            .to_string();
+        if let Some(kernel) = &self.kernel {
+            text.push_str(&format!("\n- kernel: {}", kernel));
+        }
+        if !self.services.is_empty() {
+            text.push_str(&format!("\n- running services: {}", self.services.join(", ")));
+        }
+        Some(text)
+    }
+}
+
+/// Prints the login shell, OS, kernel and running services in the lines
+/// [`HostFacts::learn`] reads, for hosts nothing is known about yet.
+pub const DETECT_COMMAND: &str = "echo SHELL=$SHELL; grep '^PRETTY_NAME=' /etc/os-release 2>/dev/null \
+     || echo PRETTY_NAME=$(uname -sr); echo KERNEL=$(uname -r); systemctl list-units --type=service \
+     --state=running --no-legend --plain 2>/dev/null | awk '{print \"SERVICE=\" $1}'";
+
+/// The package manager an OS comes with, going by its name, e.g.
+/// `Debian GNU/Linux 12` gives `apt`.
+pub fn package_manager_for(os: &str) -> Option<&'static str> {
+    let os = os.to_lowercase();
+    let families: [(&[&str], &str); 8] = [
+        (&["debian", "ubuntu", "mint", "raspbian", "kali"], "apt"),
+        (&["rhel", "red hat", "centos", "fedora", "rocky", "alma", "oracle", "amazon"], "dnf (yum on older releases)"),
+        (&["alpine"], "apk"),
+        (&["freebsd"], "pkg"),
+        (&["openbsd"], "pkg_add"),
+        (&["arch", "manjaro"], "pacman"),
+        (&["suse", "sles"], "zypper"),
+        (&["macos", "darwin"], "brew"),
+    ];
+    families.iter().find(|(names, _)| names.iter().any(|n| os.contains(n))).map(|(_, manager)| *manager)
+}
+
+/// What differs from POSIX sh when writing commands for `shell`.
+pub fn shell_syntax(shell: &str) -> Option<&'static str> {
+    match shell {
+        "fish" => {
+            Some("fish syntax: `set -x NAME value` to export, `(cmd)` for substitution, `; and`/`; or` on old versions")
+        }
+        "csh" | "tcsh" => Some("csh syntax: `setenv NAME value` to export, `>&` to redirect stderr too"),
+        "busybox" | "ash" => {
+            Some("busybox applets: GNU-only options (`grep -P`, `find -printf`, long options) may be missing")
+        }
+        _ => None,
+    }
+}
+
+/// Name of the shell at `path`, e.g. `/usr/bin/zsh` gives `zsh`.
+fn shell_name(path: &str) -> Option<String> {
+    let name = path.trim().strip_prefix('/')?.rsplit('/').next()?;
+    name.ends_with("sh").then(|| name.to_string())
+}
+
+/// Every host's facts, keyed by Host alias.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Inventory {
+    hosts: BTreeMap<String, HostFacts>,
+}
+
+/// Where the host memory is kept.
+pub fn inventory_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("sheesh")
+        .join("inventory.toml")
+}
+
+/// The host memory; empty if there is none yet, but an error if it can't
+/// be read (encrypted without the key) or doesn't parse, so that a damaged
+/// file is left for the user rather than written over.
+fn read_inventory() -> Result<Inventory> {
+    let path = inventory_path();
+    match store::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content).with_context(|| format!("parsing {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Inventory::default()),
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+/// Facts remembered for `alias` (empty if none).
+pub fn load(alias: &str) -> HostFacts {
+    match read_inventory() {
+        Ok(mut inventory) => inventory.hosts.remove(alias).unwrap_or_default(),
+        Err(e) => {
+            log::warn!("[inventory] {:#}", e);
+            HostFacts::default()
+        }
+    }
+}
+
+/// Remember `facts` for `alias`.
+pub fn save(alias: &str, facts: &HostFacts) -> Result<()> {
+    let mut inventory = read_inventory()?;
+    inventory.hosts.insert(alias.to_string(), facts.clone());
+    let path = inventory_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    store::write(&path, &toml::to_string(&inventory)?).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
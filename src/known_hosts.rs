@@ -0,0 +1,146 @@
+//! The host keys ssh has recorded for a connection, found with
+//! `ssh-keygen -F` (which sees through hashed entries), and removing one
+//! that went stale when the host was reinstalled — what the "REMOTE HOST
+//! IDENTIFICATION HAS CHANGED" message otherwise leaves to an editor. The
+//! native ssh client checks and records keys here too.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::expand_home;
+use crate::ssh::SSHConnection;
+
+/// What ssh prints, among much else, when a host's key no longer matches.
+pub const CHANGED: &str = "REMOTE HOST IDENTIFICATION HAS CHANGED";
+
+/// One recorded key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostKey {
+    /// 1-based line in the file.
+    pub line: usize,
+    /// `ED25519`, `RSA`, …
+    pub kind: String,
+    /// `SHA256:…`, as ssh shows it when asking to trust a host.
+    pub fingerprint: String,
+    /// The line as it is in the file, to check it is still there.
+    entry: String,
+}
+
+/// The keys recorded for one connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownHosts {
+    pub file: PathBuf,
+    /// The name ssh looks keys up by: the host, `[host]:port` off port 22,
+    /// or the `HostKeyAlias`.
+    pub host: String,
+    pub keys: Vec<HostKey>,
+}
+
+impl KnownHosts {
+    /// Look up `conn`'s keys in its `UserKnownHostsFile` (by default
+    /// `~/.ssh/known_hosts`).
+    pub fn lookup(conn: &SSHConnection) -> Result<KnownHosts> {
+        let file = conn
+            .option("userknownhostsfile")
+            .and_then(|files| files.split_whitespace().next())
+            .unwrap_or("~/.ssh/known_hosts");
+        let host = match (conn.option("hostkeyalias"), conn.effective_port()) {
+            (Some(alias), _) => alias.to_string(),
+            (None, 22) => conn.effective_hostname().to_string(),
+            (None, port) => format!("[{}]:{}", conn.effective_hostname(), port),
+        };
+        let mut known = KnownHosts { file: expand_home(file), host, keys: vec![] };
+        known.reload()?;
+        Ok(known)
+    }
+
+    /// Read the keys from the file again.
+    pub fn reload(&mut self) -> Result<()> {
+        self.keys.clear();
+        if !self.file.exists() {
+            return Ok(());
+        }
+        let entries = self.keygen(&["-F"])?;
+        let fingerprints = self.keygen(&["-l", "-F"])?;
+        for ((line, entry), (_, listed)) in entries.into_iter().zip(fingerprints) {
+            let mut words = listed.split_whitespace().skip(1);
+            let (kind, fingerprint) = (words.next().unwrap_or("?"), words.next().unwrap_or("?"));
+            self.keys.push(HostKey { line, kind: kind.into(), fingerprint: fingerprint.into(), entry });
+        }
+        Ok(())
+    }
+
+    /// Run `ssh-keygen <args> <host> -f <file>` and return the lines it
+    /// found, each after the `# Host … found: line N` comment naming it.
+    fn keygen(&self, args: &[&str]) -> Result<Vec<(usize, String)>> {
+        let output = Command::new("ssh-keygen")
+            .args(args)
+            .arg(&self.host)
+            .arg("-f")
+            .arg(&self.file)
+            .stdin(Stdio::null())
+            .output()
+            .context("running ssh-keygen")?;
+        // 1: nothing recorded for the host.
+        match output.status.code() {
+            Some(0) => {}
+            Some(1) => return Ok(vec![]),
+            _ => bail!("ssh-keygen -F {}: {}", self.host, String::from_utf8_lossy(&output.stderr).trim()),
+        }
+        let mut found = vec![];
+        let mut line = None;
+        for text in String::from_utf8_lossy(&output.stdout).lines() {
+            match text.strip_prefix("# Host ") {
+                Some(comment) => line = comment.rsplit("line ").next().and_then(|n| n.trim().parse().ok()),
+                None => found.extend(line.take().map(|n| (n, text.to_string()))),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Record `key` (`ssh-ed25519 AAAA…`) for the host at the end of the
+    /// file, as ssh does on being told to trust a new host.
+    pub fn trust(&mut self, key: &str) -> Result<()> {
+        let path = self.file.display().to_string();
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let text = std::fs::read_to_string(&self.file).unwrap_or_default();
+        let newline = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .with_context(|| format!("opening {}", path))?;
+        writeln!(file, "{}{} {}", newline, self.host, key).with_context(|| format!("writing {}", path))?;
+        self.reload()
+    }
+
+    /// Remove the `index`th key from the file, keeping the file as it was
+    /// in `<file>.old` the way `ssh-keygen -R` does. A line listing other
+    /// names too (`host,10.0.0.1 …`) goes for all of them, as with `-R`.
+    pub fn remove(&mut self, index: usize) -> Result<()> {
+        let Some(key) = self.keys.get(index) else {
+            return Ok(());
+        };
+        let path = self.file.display().to_string();
+        let text = std::fs::read_to_string(&self.file).with_context(|| format!("reading {}", path))?;
+        let mut lines: Vec<&str> = text.lines().collect();
+        // ssh-keygen printed the line as it is in the file, hashed or not;
+        // its names, type and key must still be there.
+        let index = key.line.saturating_sub(1);
+        let same = |line: &str| line.split_whitespace().take(3).eq(key.entry.split_whitespace().take(3));
+        if !lines.get(index).is_some_and(|line| same(line)) {
+            bail!("{} changed since it was read; look again", path);
+        }
+        lines.remove(index);
+        let old = format!("{}.old", path);
+        std::fs::write(&old, &text).with_context(|| format!("writing {}", old))?;
+        let trailing = if lines.is_empty() { "" } else { "\n" };
+        std::fs::write(&self.file, lines.join("\n") + trailing).with_context(|| format!("writing {}", path))?;
+        self.reload()
+    }
+}
@@ -0,0 +1,25 @@
+//! sheesh's modules. They live in a library so the benchmarks can reach
+//! them; `main.rs` is the event loop and layout on top.
+
+pub mod agent;
+pub mod app;
+pub mod bundle;
+pub mod chats;
+pub mod config;
+#[cfg(unix)]
+pub mod daemon;
+pub mod event;
+pub mod export;
+pub mod hooks;
+pub mod inventory;
+pub mod known_hosts;
+pub mod llm;
+pub mod metrics;
+pub mod native;
+pub mod notes;
+pub mod route;
+pub mod ssh;
+pub mod store;
+pub mod tabs;
+pub mod telnet;
+pub mod ui;
@@ -4,8 +4,9 @@ use anyhow::{Context, Result};
 use log::{debug, error, warn};
 use serde_json::{json, Value};
 
-use super::{ContentBlock, LLMEvent, LLMProvider, Message, RichMessage, Role};
-use sheesh_tools::{ToolResult, all_tools, dispatch};
+use super::debug::{dump, redact};
+use super::{ContentBlock, LLMEvent, LLMProvider, Message, RichMessage, Role, Usage};
+use sheesh_tools::ToolRegistry;
 
 const RETRY_DELAYS: &[Duration] = &[
     Duration::from_millis(500),
@@ -26,6 +27,8 @@ impl AnthropicProvider {
     fn post(&self, body: Value) -> Result<Value> {
         debug!("[Anthropic] POST /v1/messages model={} messages={}", self.model, body["messages"].as_array().map(|a| a.len()).unwrap_or(0));
 
+        dump("Anthropic", "request", &body);
+
         let client = reqwest::blocking::Client::new();
         let mut last_err: anyhow::Error = anyhow::anyhow!("no attempts made");
 
@@ -46,7 +49,7 @@ impl AnthropicProvider {
             {
                 Ok(r) => r,
                 Err(e) => {
-                    warn!("[Anthropic] request error (attempt {}): {}", attempt + 1, e);
+                    warn!("[Anthropic] request error (attempt {}): {}", attempt + 1, redact(&e.to_string()));
                     last_err = anyhow::Error::from(e).context("sending request to Anthropic");
                     continue;
                 }
@@ -64,14 +67,16 @@ impl AnthropicProvider {
                 }
             };
 
+            dump("Anthropic", "response", &json);
+
             if status.is_server_error() || status.as_u16() == 429 {
-                error!("[Anthropic] retryable error response (attempt {}): {}", attempt + 1, json);
+                error!("[Anthropic] retryable error response (attempt {}): {}", attempt + 1, redact(&error_message(&json)));
                 last_err = anyhow::anyhow!("Anthropic error {}: {}", status, json);
                 continue;
             }
 
             if !status.is_success() {
-                error!("[Anthropic] error response: {}", json);
+                error!("[Anthropic] error response {}: {}", status, redact(&error_message(&json)));
             }
 
             return Ok(json);
@@ -81,6 +86,12 @@ impl AnthropicProvider {
     }
 }
 
+/// The error message of an error response, without the rest of the body;
+/// the whole body goes to the `--debug-llm` dump.
+fn error_message(json: &Value) -> String {
+    json["error"]["message"].as_str().unwrap_or("(no message)").to_string()
+}
+
 /// Convert a `RichMessage` to the JSON format Anthropic expects.
 fn rich_to_json(m: &RichMessage) -> Value {
     let role = match m.role {
@@ -107,10 +118,11 @@ fn rich_to_json(m: &RichMessage) -> Value {
                 "name": name,
                 "input": input,
             }),
-            ContentBlock::ToolResult { tool_use_id, content } => json!({
+            ContentBlock::ToolResult { tool_use_id, content, is_error } => json!({
                 "type": "tool_result",
                 "tool_use_id": tool_use_id,
                 "content": content,
+                "is_error": is_error,
             }),
         })
         .collect();
@@ -161,7 +173,7 @@ impl LLMProvider for AnthropicProvider {
         Ok(text)
     }
 
-    fn complete_rich(&self, messages: &[RichMessage]) -> Result<LLMEvent> {
+    fn complete_rich(&self, messages: &[RichMessage], tools: &ToolRegistry) -> Result<LLMEvent> {
         debug!("[Anthropic] complete_rich: {} message(s)", messages.len());
 
         let mut system: Option<String> = None;
@@ -191,7 +203,7 @@ impl LLMProvider for AnthropicProvider {
         let mut body = json!({
             "model": self.model,
             "max_tokens": 8096,
-            "tools": all_tools(),
+            "tools": tools.to_anthropic(),
             "messages": msgs,
         });
 
@@ -204,18 +216,12 @@ impl LLMProvider for AnthropicProvider {
         let stop_reason = body["stop_reason"].as_str().unwrap_or("");
         debug!("[Anthropic] complete_rich: stop_reason={}", stop_reason);
         let content = body["content"].as_array().cloned().unwrap_or_default();
+        let usage = body["usage"].as_object().map(|u| Usage {
+            input_tokens: u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+            output_tokens: u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+        });
 
         if stop_reason == "tool_use" {
-            // Find the tool_use block.
-            let tool_use = content
-                .iter()
-                .find(|b| b["type"] == "tool_use")
-                .ok_or_else(|| anyhow::anyhow!("tool_use stop but no tool_use block"))?;
-
-            let id = tool_use["id"].as_str().unwrap_or("").to_string();
-            let name = tool_use["name"].as_str().unwrap_or("").to_string();
-            let input = tool_use["input"].clone();
-
             // Build the content blocks to append to rich history.
             let mut assistant_blocks: Vec<ContentBlock> = vec![];
             for block in &content {
@@ -228,8 +234,7 @@ impl LLMProvider for AnthropicProvider {
                         }
                     }
                     Some("tool_use") => {
-                        // Use each block's own id/name/input — not the outer `id`/`name`/`input`
-                        // — so that multiple tool_use blocks in one response don't share the same id.
+                        // Each block keeps its own id so parallel calls get distinct tool_results.
                         let block_id = block["id"].as_str().unwrap_or("").to_string();
                         let block_name = block["name"].as_str().unwrap_or("").to_string();
                         let block_input = block["input"].clone();
@@ -243,11 +248,19 @@ impl LLMProvider for AnthropicProvider {
                 }
             }
 
-            // Dispatch by tool name via shared sheesh-tools crate.
-            return match dispatch(id, name, &input)? {
-                ToolResult::Local { id, name } => Ok(LLMEvent::LocalTool { id, name, assistant_blocks }),
-                ToolResult::Command { id, command, description } => Ok(LLMEvent::ToolCall { id, command, description, assistant_blocks }),
-            };
+            // Dispatch every tool_use block by tool name via the session's registry.
+            let calls = assistant_blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolUse { id, name, input } => Some(tools.dispatch(id.as_str(), name, input)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if calls.is_empty() {
+                return Err(anyhow::anyhow!("tool_use stop but no tool_use block"));
+            }
+            debug!("[Anthropic] complete_rich: {} tool call(s)", calls.len());
+            return Ok(LLMEvent::ToolCalls { calls, assistant_blocks, usage });
         }
 
         // Normal text response.
@@ -259,11 +272,11 @@ impl LLMProvider for AnthropicProvider {
             .join("");
 
         if text.is_empty() {
-            error!("[Anthropic] complete_rich: empty text in response: {}", body);
+            error!("[Anthropic] complete_rich: empty text in response (stop_reason={})", stop_reason);
             return Err(anyhow::anyhow!("unexpected Anthropic response: {}", body));
         }
 
         debug!("[Anthropic] complete_rich: response {} chars", text.len());
-        Ok(LLMEvent::Response(text))
+        Ok(LLMEvent::Response { text, usage })
     }
 }
@@ -0,0 +1,75 @@
+//! Short-lived cache of provider responses. Sending the same conversation to
+//! the same model twice within the TTL (a double Enter, a re-sent context)
+//! returns the first answer instead of paying and waiting for it again.
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use sheesh_tools::ToolRegistry;
+
+use super::{LLMEvent, LLMProvider, Message, RichMessage};
+
+/// Responses by request hash, shared by every provider so `/model` routes
+/// and new sessions hit it too.
+static RESPONSES: LazyLock<Mutex<HashMap<u64, (Instant, LLMEvent)>>> = LazyLock::new(Default::default);
+
+/// Provider decorator answering repeated requests from [`RESPONSES`].
+pub struct CachedProvider {
+    inner: Arc<dyn LLMProvider>,
+    /// Provider and model, so equal conversations sent to different models
+    /// don't share answers.
+    model: String,
+    ttl: Duration,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Arc<dyn LLMProvider>, model: String, ttl: Duration) -> Self {
+        Self { inner, model, ttl }
+    }
+
+    fn key(&self, messages: &[RichMessage], tools: &ToolRegistry) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.model.hash(&mut hasher);
+        serde_json::to_string(messages).unwrap_or_default().hash(&mut hasher);
+        for tool in tools.tools() {
+            tool.name.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+impl LLMProvider for CachedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn complete(&self, messages: &[Message]) -> Result<String> {
+        self.inner.complete(messages)
+    }
+
+    fn complete_rich(&self, messages: &[RichMessage], tools: &ToolRegistry) -> Result<LLMEvent> {
+        let key = self.key(messages, tools);
+        {
+            let mut responses = RESPONSES.lock().unwrap();
+            responses.retain(|_, (at, _)| at.elapsed() < self.ttl);
+            if let Some((_, event)) = responses.get(&key) {
+                log::info!("[llm] answering from the response cache ({})", self.model);
+                // Nothing was spent this time.
+                let mut event = event.clone();
+                if let LLMEvent::Response { usage, .. } | LLMEvent::ToolCalls { usage, .. } = &mut event {
+                    *usage = None;
+                }
+                return Ok(event);
+            }
+        }
+        let event = self.inner.complete_rich(messages, tools)?;
+        if !matches!(event, LLMEvent::Error(_)) {
+            RESPONSES.lock().unwrap().insert(key, (Instant::now(), event.clone()));
+        }
+        Ok(event)
+    }
+}
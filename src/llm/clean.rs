@@ -0,0 +1,85 @@
+//! Tidying of terminal text before it is sent to the model, so the context
+//! budget goes to output rather than noise: spinner and clock frames, runs
+//! of repeated lines and bare shell prompts.
+
+/// Runs of this many or more lines that differ only in numbers or spinner
+/// glyphs (progress counters, clocks) are collapsed to their last line.
+const SIMILAR_RUN: usize = 3;
+
+/// Longest line still taken for a bare shell prompt.
+const MAX_PROMPT_LEN: usize = 80;
+
+/// `text` with spinner frames dropped, repeated and counter-only lines
+/// collapsed to their last one behind a marker, bare prompts removed (but
+/// the final line kept, as it shows where the shell is now) and blank runs
+/// squeezed to one.
+pub fn clean_terminal_text(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let last = lines.len().saturating_sub(1);
+    let kept: Vec<&str> = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| *i == last || !(is_spinner_frame(line) || is_bare_prompt(line)))
+        .map(|(_, line)| line.trim_end())
+        .collect();
+
+    let mut out: Vec<String> = Vec::with_capacity(kept.len());
+    let mut i = 0;
+    while i < kept.len() {
+        let line = kept[i];
+        let key = similarity_key(line);
+        let run = kept[i..].iter().take_while(|l| similarity_key(l) == key).count();
+        let identical = kept[i..i + run].iter().all(|l| *l == line);
+        let last_of_run = kept[i + run - 1];
+        if line.is_empty() {
+            out.push(String::new());
+        } else if run > 1 && identical {
+            out.push(format!("{}  [repeated {} times]", line, run));
+        } else if run >= SIMILAR_RUN {
+            out.push(format!("{}  [last of {} similar lines]", last_of_run, run));
+        } else {
+            out.extend(kept[i..i + run].iter().map(|l| l.to_string()));
+        }
+        i += run;
+    }
+    out.join("\n")
+}
+
+fn is_spinner_glyph(c: char) -> bool {
+    matches!(c, '\u{2800}'..='\u{28ff}' | '◐' | '◓' | '◑' | '◒' | '◴' | '◷' | '◶' | '◵' | '◰' | '◳' | '◲' | '◱')
+}
+
+/// A line that is nothing but a spinner animation frame.
+fn is_spinner_frame(line: &str) -> bool {
+    let line = line.trim();
+    matches!(line, "|" | "/" | "-" | "\\") || (!line.is_empty() && line.chars().all(is_spinner_glyph))
+}
+
+/// A shell prompt with nothing typed after it, e.g. `user@host:~$`.
+fn is_bare_prompt(line: &str) -> bool {
+    let line = line.trim();
+    line.len() <= MAX_PROMPT_LEN
+        && line.ends_with(['$', '#', '%', '>'])
+        && (line.contains('@') || line.contains(':'))
+        // `[user@host dir]$` is the one common prompt with a space in it.
+        && (!line.contains(char::is_whitespace) || line.starts_with('[') && line[..line.len() - 1].ends_with(']'))
+}
+
+/// What a line looks like with its numbers and spinner glyphs masked out.
+/// Lines made only of digits stay distinct, so `seq` output is not merged.
+fn similarity_key(line: &str) -> String {
+    if !line.chars().any(char::is_alphabetic) {
+        return line.to_string();
+    }
+    let mut key = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c.is_ascii_digit() {
+            if !key.ends_with('#') {
+                key.push('#');
+            }
+        } else if !is_spinner_glyph(c) {
+            key.push(c);
+        }
+    }
+    key
+}
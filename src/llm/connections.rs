@@ -0,0 +1,126 @@
+//! Local tools about the user's saved connections. `list_connections` shows
+//! the model which hosts exist, so it can say which one runs what; only
+//! aliases, addresses and descriptions are shared — no keys or options.
+//! `open_session` lets it open one of them next to the current session.
+
+use std::sync::mpsc::{self, Sender};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+use sheesh_tools::{LocalPermission, LocalTool, Param, ToolDef, ToolGroup};
+
+use crate::config::{load_connections, ssh_config_path};
+
+pub struct ListConnections {
+    /// Alias of the session the model is talking to, marked in the list.
+    current: String,
+}
+
+impl ListConnections {
+    pub fn new(current: &str) -> Self {
+        Self { current: current.to_string() }
+    }
+}
+
+impl LocalTool for ListConnections {
+    fn def(&self) -> ToolDef {
+        ToolDef::new(
+            "list_connections",
+            ToolGroup::Core,
+            "List the user's saved SSH connections: alias, user@host:port and description. Use it to answer \
+             which host runs a service or to suggest another connection to look at.",
+        )
+        .param(
+            Param::string("query")
+                .describe("Only list connections whose alias, hostname or description contains this text."),
+        )
+    }
+
+    fn permission(&self, _input: &Value) -> LocalPermission {
+        LocalPermission::Allow
+    }
+
+    fn run(&self, input: &Value) -> Result<String> {
+        let query = input["query"].as_str().unwrap_or("").trim().to_lowercase();
+        let connections: Vec<_> = load_connections(&ssh_config_path())?
+            .into_iter()
+            .filter(|c| {
+                query.is_empty()
+                    || [&c.name, &c.hostname, &c.description].iter().any(|f| f.to_lowercase().contains(&query))
+            })
+            .collect();
+        if connections.is_empty() {
+            return Ok(if query.is_empty() {
+                "No saved connections.".to_string()
+            } else {
+                format!("No saved connection matches {:?}.", query)
+            });
+        }
+        let mut out = format!("{} saved connection(s):", connections.len());
+        for c in &connections {
+            match c.transport.describe() {
+                Some(link) => out.push_str(&format!("\n- {}: {}", c.name, link)),
+                None => out.push_str(&format!("\n- {}: {}:{}", c.name, c.destination(), c.effective_port())),
+            }
+            if c.name == self.current {
+                out.push_str(" (current session)");
+            }
+            if !c.description.is_empty() {
+                out.push_str(&format!(" — {}", c.description.replace('\n', " ")));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// An alias for the app to open, and where to say how that went.
+pub type OpenRequest = (String, Sender<Result<(), String>>);
+
+/// `open_session`: asks sheesh to connect to another saved host in a new
+/// session next to the current one. The app does the connecting; this tool
+/// validates the alias, sends it over `requests` and waits for the outcome.
+pub struct OpenSession {
+    requests: Sender<OpenRequest>,
+}
+
+impl OpenSession {
+    pub fn new(requests: Sender<OpenRequest>) -> Self {
+        Self { requests }
+    }
+}
+
+impl LocalTool for OpenSession {
+    fn def(&self) -> ToolDef {
+        ToolDef::new(
+            "open_session",
+            ToolGroup::Core,
+            "Open a new session to another saved connection (see list_connections), e.g. to check a load \
+             balancer or database host too. It opens next to this one and the user switches to it; you \
+             cannot run commands there from this conversation. The user must approve.",
+        )
+        .param(Param::string("alias").describe("Alias of the saved connection.").required())
+    }
+
+    fn permission(&self, input: &Value) -> LocalPermission {
+        match input["alias"].as_str() {
+            Some(alias) => LocalPermission::Ask(format!("Open a new session to {}", alias)),
+            None => LocalPermission::Allow,
+        }
+    }
+
+    fn run(&self, input: &Value) -> Result<String> {
+        let alias = input["alias"].as_str().ok_or_else(|| anyhow!("open_session: missing field `alias`"))?;
+        let Some(conn) = load_connections(&ssh_config_path())?.into_iter().find(|c| c.name == alias) else {
+            bail!("there is no saved connection named {:?}; call list_connections for the aliases", alias);
+        };
+        let (reply, outcome) = mpsc::channel();
+        self.requests.send((conn.name.clone(), reply)).map_err(|_| anyhow!("sheesh is shutting down"))?;
+        let opened = outcome.recv().map_err(|_| anyhow!("sheesh is shutting down"))?;
+        opened.map_err(|e| anyhow!("could not open a session to {}: {}", conn.name, e))?;
+        Ok(format!(
+            "Opened a session to {} ({}) next to this one; the user can switch to it with F3.",
+            conn.name,
+            conn.destination()
+        ))
+    }
+}
@@ -0,0 +1,58 @@
+//! `--debug-llm`: full request and response bodies, one JSON object per
+//! line, in their own file so the normal log stays short. Anything that
+//! looks like an API key is masked before it is written, here and in the
+//! provider messages that go to the normal log.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::{Value, json};
+
+/// The dump file, once `--debug-llm` has opened it.
+static DUMP: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Key-shaped strings and the values of auth headers or key fields.
+static SECRET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)\b(sk-[a-z0-9_-]{8,})|((?:x-api-key|authorization|api[_-]?key)["']?\s*[:=]\s*["']?(?:bearer\s+)?)([^\s"',}]+)"#,
+    )
+    .expect("secret pattern")
+});
+
+/// Start dumping request and response bodies to `path`, appending.
+pub fn enable(path: &Path) -> Result<()> {
+    let file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let _ = DUMP.set(Mutex::new(file));
+    log::info!("[llm] writing request and response bodies to {}", path.display());
+    Ok(())
+}
+
+/// Record one `kind` ("request" or "response") body exchanged with `provider`.
+pub fn dump(provider: &str, kind: &str, body: &Value) {
+    let Some(file) = DUMP.get() else { return };
+    let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let line = json!({ "at": at, "provider": provider, "kind": kind, "body": body }).to_string();
+    let mut file = file.lock().unwrap();
+    if let Err(e) = writeln!(file, "{}", redact(&line)) {
+        log::warn!("[llm] could not write debug dump: {}", e);
+    }
+}
+
+/// `text` with API keys and auth header values replaced by `[redacted]`.
+pub fn redact(text: &str) -> String {
+    SECRET
+        .replace_all(text, |c: &regex::Captures| match c.get(2) {
+            Some(name) => format!("{}[redacted]", name.as_str()),
+            None => "[redacted]".to_string(),
+        })
+        .into_owned()
+}
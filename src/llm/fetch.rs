@@ -0,0 +1,262 @@
+//! `fetch_url`: a local tool that GETs a web page for the model (a man page,
+//! vendor docs for an error message) from hosts on the configured allowlist,
+//! and hands back its text.
+
+use std::io::Read;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::Url;
+use serde_json::Value;
+use sheesh_tools::{LocalPermission, LocalTool, Param, ToolDef, ToolGroup};
+
+const REDIRECT_LIMIT: usize = 5;
+
+pub struct FetchUrl {
+    /// Hosts that may be fetched; each also allows its subdomains.
+    allowlist: Vec<String>,
+    /// Bytes of the response body read before the rest is dropped.
+    max_bytes: usize,
+}
+
+impl FetchUrl {
+    pub fn new(allowlist: &[String], max_bytes: usize) -> Self {
+        let allowlist = allowlist
+            .iter()
+            .map(|d| d.trim().trim_start_matches("*.").trim_end_matches('.').to_ascii_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+        Self { allowlist, max_bytes }
+    }
+
+    /// Parse `input["url"]` and check it against the allowlist.
+    fn url(&self, input: &Value) -> Result<Url> {
+        let raw = input["url"].as_str().ok_or_else(|| anyhow!("fetch_url: missing field `url`"))?;
+        let url = Url::parse(raw.trim()).with_context(|| format!("invalid URL {:?}", raw))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("only http and https URLs can be fetched");
+        }
+        let host = url.host_str().unwrap_or_default();
+        if !host_allowed(&self.allowlist, host) {
+            bail!("{} is not on the fetch allowlist (allowed: {})", host, self.allowlist.join(", "));
+        }
+        Ok(url)
+    }
+}
+
+/// True if `host` is an allowlisted domain or a subdomain of one.
+fn host_allowed(allowlist: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowlist
+        .iter()
+        .any(|d| host == *d || host.strip_suffix(d.as_str()).is_some_and(|sub| sub.ends_with('.')))
+}
+
+impl LocalTool for FetchUrl {
+    fn def(&self) -> ToolDef {
+        ToolDef::new(
+            "fetch_url",
+            ToolGroup::Net,
+            format!(
+                "Fetch a web page from the user's machine and return its text, e.g. a man page or vendor \
+                 documentation for an error message. Only these hosts (and their subdomains) can be \
+                 fetched: {}. The user must approve each request.",
+                self.allowlist.join(", ")
+            ),
+        )
+        .param(Param::string("url").describe("http(s) URL of the page.").required())
+    }
+
+    fn permission(&self, input: &Value) -> LocalPermission {
+        // Refused URLs fail in `run` without touching the network.
+        match self.url(input) {
+            Ok(url) => LocalPermission::Ask(format!("GET {}", url)),
+            Err(_) => LocalPermission::Allow,
+        }
+    }
+
+    fn run(&self, input: &Value) -> Result<String> {
+        let url = self.url(input)?;
+        let allowlist = self.allowlist.clone();
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .user_agent(concat!("sheesh/", env!("CARGO_PKG_VERSION")))
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                if attempt.previous().len() >= REDIRECT_LIMIT {
+                    attempt.error("too many redirects")
+                } else if !host_allowed(&allowlist, &host) {
+                    attempt.error(format!("redirected to {}, which is not on the fetch allowlist", host))
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()?;
+        let resp = client.get(url.clone()).send().with_context(|| format!("fetching {}", url))?;
+        let status = resp.status();
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("text/plain")
+            .to_ascii_lowercase();
+        let textual = content_type.starts_with("text/")
+            || ["json", "xml", "yaml", "javascript"].iter().any(|t| content_type.contains(t));
+        if !textual {
+            bail!("{} is {}, not a text document", url, content_type);
+        }
+
+        let mut body = Vec::new();
+        resp.take(self.max_bytes as u64 + 1).read_to_end(&mut body).context("reading the response")?;
+        let truncated = body.len() > self.max_bytes;
+        body.truncate(self.max_bytes);
+        let body = String::from_utf8_lossy(&body);
+        let text = if content_type.contains("html") { html_to_text(&body) } else { body.trim().to_string() };
+
+        let mut out = format!("Fetched {} ({}, {})\n\n{}", url, status, content_type, text);
+        if truncated {
+            out.push_str(&format!("\n\n[response cut at {} KB]", self.max_bytes / 1024));
+        }
+        Ok(out)
+    }
+}
+
+/// Readable text of an HTML page: scripts, styles and comments dropped,
+/// block elements on their own lines, `<pre>` kept as is, entities decoded.
+fn html_to_text(html: &str) -> String {
+    const BLOCKS: &[&str] = &[
+        "p", "br", "div", "li", "ul", "ol", "tr", "table", "pre", "h1", "h2", "h3", "h4", "h5", "h6", "dt", "dd",
+        "dl", "section", "article", "header", "footer", "blockquote", "hr", "title",
+    ];
+    let mut out = String::new();
+    let mut rest = html;
+    let mut pre = 0usize;
+    while let Some(lt) = rest.find('<') {
+        push_text(&mut out, &rest[..lt], pre > 0);
+        rest = &rest[lt..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        let Some(gt) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..gt];
+        rest = &rest[gt + 1..];
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if !closing && matches!(name.as_str(), "script" | "style" | "noscript" | "svg") {
+            let end = format!("</{}", name);
+            rest = rest.to_ascii_lowercase().find(&end).map_or("", |i| &rest[i..]);
+            rest = rest.find('>').map_or("", |i| &rest[i + 1..]);
+            continue;
+        }
+        if name == "pre" {
+            pre = if closing { pre.saturating_sub(1) } else { pre + 1 };
+        }
+        if BLOCKS.contains(&name.as_str()) && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    push_text(&mut out, rest, pre > 0);
+
+    let mut text = String::new();
+    let mut blank = false;
+    for line in out.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank = !text.is_empty();
+            continue;
+        }
+        if blank {
+            text.push('\n');
+            blank = false;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.trim_end().to_string()
+}
+
+/// Append decoded `raw` text, collapsing whitespace outside `<pre>`.
+fn push_text(out: &mut String, raw: &str, pre: bool) {
+    let decoded = decode_entities(raw);
+    if pre {
+        out.push_str(&decoded);
+        return;
+    }
+    for c in decoded.chars() {
+        if !c.is_whitespace() {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with([' ', '\n']) {
+            out.push(' ');
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..].find(';').filter(|&i| i <= 10).map(|i| &rest[1..i + 1]);
+        let decoded = entity.and_then(|e| match e {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => e
+                .strip_prefix("#x")
+                .or_else(|| e.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| e.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (entity, decoded) {
+            (Some(e), Some(c)) => {
+                out.push(c);
+                rest = &rest[e.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_to_text_keeps_the_readable_part() {
+        let html = "<html><head><title>Docs</title><style>p { color: red }</style></head><body><!-- nav -->\
+                    <h1>Install</h1><p>Run   <code>make &amp;&amp; make install</code>.</p>\
+                    <pre>  indented\n    more</pre><script>var p = '<p>';</script><p>&lt;done&gt; &#x41;&#66;</p>";
+        assert_eq!(html_to_text(html), "Docs\nInstall\nRun make && make install.\n  indented\n    more\n<done> AB");
+    }
+
+    #[test]
+    fn html_to_text_collapses_blank_lines() {
+        assert_eq!(html_to_text("<pre>a\n\n\n\nb</pre>"), "a\n\nb");
+        assert_eq!(html_to_text("<p>\n\n</p><p>text</p>"), "text");
+    }
+
+    #[test]
+    fn html_to_text_survives_broken_markup() {
+        assert_eq!(html_to_text("before <!-- never closed"), "before");
+        assert_eq!(html_to_text("a &bogus; b <unclosed"), "a &bogus; b");
+    }
+}
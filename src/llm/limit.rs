@@ -0,0 +1,134 @@
+//! Process-wide limiter for provider requests. Every session shares it, so
+//! several open sessions can't fire unbounded simultaneous calls: at most
+//! `max_concurrent_requests` are in flight, optional per-provider
+//! requests-per-minute budgets apply, and the rest wait in FIFO order.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use super::LLMConfig;
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// How often a waiting request checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+struct State {
+    max_concurrent: usize,
+    /// Requests per minute by provider (`anthropic`, `openai`, …).
+    per_minute: HashMap<String, u32>,
+    next_ticket: u64,
+    queue: VecDeque<u64>,
+    running: usize,
+    /// Start times of the last minute's requests, by provider.
+    sent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl State {
+    /// How long `provider` must wait before its per-minute budget allows
+    /// another request, if at all.
+    fn budget_wait(&mut self, provider: &str) -> Option<Duration> {
+        let limit = *self.per_minute.get(provider).filter(|l| **l > 0)? as usize;
+        let sent = self.sent.entry(provider.to_string()).or_default();
+        while sent.front().is_some_and(|at| at.elapsed() >= WINDOW) {
+            sent.pop_front();
+        }
+        if sent.len() < limit {
+            return None;
+        }
+        sent.front().map(|oldest| WINDOW.saturating_sub(oldest.elapsed()))
+    }
+}
+
+static LIMITER: LazyLock<(Mutex<State>, Condvar)> = LazyLock::new(|| {
+    let state = State {
+        max_concurrent: 2,
+        per_minute: HashMap::new(),
+        next_ticket: 0,
+        queue: VecDeque::new(),
+        running: 0,
+        sent: HashMap::new(),
+    };
+    (Mutex::new(state), Condvar::new())
+});
+
+/// Apply the limits from `[llm]`.
+pub fn configure(cfg: &LLMConfig) {
+    let (lock, ready) = &*LIMITER;
+    let mut state = lock.lock().unwrap();
+    state.max_concurrent = cfg.max_concurrent_requests.max(1);
+    state.per_minute = cfg.requests_per_minute.clone();
+    ready.notify_all();
+}
+
+/// Why a request is still waiting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Queued {
+    /// Behind other requests; 1 means next in line.
+    Position(usize),
+    /// First in line, but the provider's per-minute budget is spent.
+    RateLimited(Duration),
+}
+
+/// A request in flight. Dropping it frees the slot.
+pub struct Slot(());
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let (lock, ready) = &*LIMITER;
+        lock.lock().unwrap().running -= 1;
+        ready.notify_all();
+    }
+}
+
+/// Block until a request to `provider` may be sent. `on_queued` is called
+/// whenever the reason for waiting changes; it is not called at all if the
+/// request can go right away. `None` if `cancelled` is set while waiting;
+/// the request then leaves the queue.
+pub fn acquire(provider: &str, cancelled: &AtomicBool, mut on_queued: impl FnMut(Queued)) -> Option<Slot> {
+    let (lock, ready) = &*LIMITER;
+    let mut state = lock.lock().unwrap();
+    let ticket = state.next_ticket;
+    state.next_ticket += 1;
+    state.queue.push_back(ticket);
+    let mut reported = None;
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            state.queue.retain(|t| *t != ticket);
+            ready.notify_all();
+            return None;
+        }
+        let position = state.queue.iter().position(|t| *t == ticket).unwrap_or(0);
+        let wait = if position == 0 && state.running < state.max_concurrent {
+            match state.budget_wait(provider) {
+                None => break,
+                Some(wait) => Some(wait),
+            }
+        } else {
+            None
+        };
+        let queued = match wait {
+            Some(wait) => Queued::RateLimited(wait),
+            None => Queued::Position(position + 1),
+        };
+        // The remaining time changes constantly; only a new reason is news.
+        let changed = match reported {
+            Some(Queued::RateLimited(_)) => !matches!(queued, Queued::RateLimited(_)),
+            Some(before) => before != queued,
+            None => true,
+        };
+        if changed {
+            on_queued(queued);
+            reported = Some(queued);
+        }
+        let wait = wait.map_or(CANCEL_POLL, |wait| wait.min(CANCEL_POLL));
+        state = ready.wait_timeout(state, wait).unwrap().0;
+    }
+    state.queue.pop_front();
+    state.running += 1;
+    state.sent.entry(provider.to_string()).or_default().push_back(Instant::now());
+    // The next in line may fit too.
+    ready.notify_all();
+    Some(Slot(()))
+}
@@ -0,0 +1,75 @@
+//! Presets for local OpenAI-compatible servers (llama.cpp's `llama-server`
+//! and LM Studio), and a startup check that the server is up and has a model
+//! loaded, so a stopped server shows up as a plain explanation instead of a
+//! failed request later on.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::LLMConfig;
+
+pub const LLAMACPP_URL: &str = "http://localhost:8080/v1";
+pub const LMSTUDIO_URL: &str = "http://localhost:1234/v1";
+
+/// Display name and API root of `cfg`'s local server, or `None` for the
+/// other providers.
+pub fn server(cfg: &LLMConfig) -> Option<(&'static str, String)> {
+    let (name, default) = match cfg.provider.as_str() {
+        "llamacpp" => ("llama.cpp", LLAMACPP_URL),
+        "lmstudio" => ("LM Studio", LMSTUDIO_URL),
+        _ => return None,
+    };
+    let url = cfg.base_url.as_deref().filter(|u| !u.trim().is_empty()).unwrap_or(default);
+    Some((name, url.trim_end_matches('/').to_string()))
+}
+
+/// What is wrong with the configured local server, if anything.
+pub fn health_check(cfg: &LLMConfig) -> Option<String> {
+    let (name, base_url) = server(cfg)?;
+    let client = reqwest::blocking::Client::builder().timeout(Duration::from_secs(3)).build().ok()?;
+    let start_hint = match cfg.provider.as_str() {
+        "llamacpp" => "start it with `llama-server -m <model>.gguf`",
+        _ => "start the server from LM Studio's Developer tab or with `lms server start`",
+    };
+    let unreachable = || format!("{} is not reachable at {} — {}.", name, base_url, start_hint);
+
+    // llama-server answers /health with 503 while the model is loading.
+    if cfg.provider == "llamacpp" {
+        let root = base_url.trim_end_matches("/v1");
+        return match client.get(format!("{}/health", root)).send() {
+            Err(_) => Some(unreachable()),
+            Ok(resp) if resp.status().as_u16() == 503 => {
+                Some(format!("{} at {} is still loading its model; try again in a moment.", name, root))
+            }
+            Ok(resp) if !resp.status().is_success() => {
+                Some(format!("{} at {} is unhealthy (HTTP {}).", name, root, resp.status()))
+            }
+            Ok(_) => None,
+        };
+    }
+
+    let models: Vec<String> = match client.get(format!("{}/models", base_url)).send() {
+        Err(_) => return Some(unreachable()),
+        Ok(resp) => resp
+            .json::<Value>()
+            .ok()
+            .and_then(|body| body["data"].as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|m| m["id"].as_str().map(str::to_string))
+            .collect(),
+    };
+    if models.is_empty() {
+        return Some(format!("{} has no model loaded — load one (e.g. `lms load <model>`).", name));
+    }
+    if !models.contains(&cfg.model) {
+        return Some(format!(
+            "{} has no model {:?} loaded. Loaded: {}. Set `model` in [llm] to one of them.",
+            name,
+            cfg.model,
+            models.join(", ")
+        ));
+    }
+    None
+}
@@ -0,0 +1,44 @@
+//! Where requests go. Every request carries the session header and the
+//! terminal output the conversation holds, so with a cloud provider they
+//! leave the machine: the first one of a session says so in the panel,
+//! until the user asks not to be told again about that vendor, and with
+//! `confirm_context_share` each one waits for a yes.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+/// The company whose servers `provider` sends requests to; `None` for the
+/// ones that run where the user points them.
+pub fn cloud_vendor(provider: &str) -> Option<&'static str> {
+    match provider {
+        "anthropic" => Some("Anthropic"),
+        "openai" => Some("OpenAI"),
+        _ => None,
+    }
+}
+
+/// The vendors the user was told about and asked not to be again, one per line.
+fn acknowledged_path() -> PathBuf {
+    dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("sheesh").join("context-notice-seen")
+}
+
+/// Whether the user asked not to be told again that requests go to `vendor`.
+pub fn acknowledged(vendor: &str) -> bool {
+    std::fs::read_to_string(acknowledged_path()).is_ok_and(|text| text.lines().any(|line| line.trim() == vendor))
+}
+
+/// Don't tell the user again that requests go to `vendor`.
+pub fn acknowledge(vendor: &str) -> Result<()> {
+    if acknowledged(vendor) {
+        return Ok(());
+    }
+    let path = acknowledged_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let mut text = std::fs::read_to_string(&path).unwrap_or_default();
+    text.push_str(vendor);
+    text.push('\n');
+    std::fs::write(&path, text).with_context(|| format!("writing {}", path.display()))
+}
@@ -0,0 +1,135 @@
+//! `/man <cmd> [question]`: read the command's man page (or its `--help`)
+//! on the host itself and have the model answer from that, so the answer
+//! fits the version actually installed rather than the one it remembers.
+
+use anyhow::{Result, bail};
+
+use crate::ssh::{self, SSHConnection};
+
+/// Most of the page sent along; long pages (bash's is 300k) are cut down to
+/// the top and the parts the question is about.
+const BUDGET: usize = 12_000;
+/// The start of the page kept whatever the question: name, synopsis and
+/// the first of the description.
+const HEAD: usize = 2_000;
+/// Longest run of lines kept or dropped as one piece.
+const BLOCK_LINES: usize = 20;
+
+/// Words too common to say what a question is about.
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "how", "what", "does", "with", "that", "this", "which", "when", "can", "are", "from", "use",
+    "into", "its", "not", "only", "all", "way", "get", "you", "there", "should", "would", "option", "flag",
+];
+
+/// Asked of the model when no question comes with the command.
+const DEFAULT_QUESTION: &str = "Summarize what this command does and its most useful options on this host.";
+
+/// A page as read from the host.
+pub struct ManPage {
+    pub command: String,
+    /// `man` or `--help`.
+    pub source: &'static str,
+    pub text: String,
+}
+
+impl ManPage {
+    /// Read the page for `command` over a separate ssh session; falls back
+    /// to `--help` where there is no man page (or no `man`, as on busybox).
+    pub fn fetch(conn: &SSHConnection, command: &str) -> Result<ManPage> {
+        if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphanumeric() || "._+-".contains(c)) {
+            bail!("'{}' is not a command name", command);
+        }
+        // The first line says which of the two answered.
+        let script = format!(
+            "if man -w {c} >/dev/null 2>&1; then echo man; MANPAGER=cat MANWIDTH=100 man {c} 2>/dev/null | \
+             (col -b 2>/dev/null || cat); else echo help; {c} --help 2>&1 | head -n 2000; fi",
+            c = command
+        );
+        let output = ssh::exec(conn, &script)?;
+        let (source, text) = output.split_once('\n').unwrap_or((output.as_str(), ""));
+        let source = if source.trim() == "man" { "man" } else { "--help" };
+        if text.trim().is_empty() {
+            bail!("{} has no man page or --help on this host", command);
+        }
+        Ok(ManPage { command: command.to_string(), source, text: text.to_string() })
+    }
+
+    /// The request sent for `question`, with the page cut down to fit.
+    pub fn prompt(&self, question: &str) -> String {
+        let question = if question.trim().is_empty() { DEFAULT_QUESTION } else { question.trim() };
+        let source = if self.source == "man" { format!("man {}", self.command) } else { format!("{} --help", self.command) };
+        format!(
+            "This is `{}` as printed on the host:\n```\n{}\n```\nAnswer from this text, which matches the version \
+             installed there; say so where it differs from what you'd expect elsewhere. Question: {}",
+            source,
+            shorten(&self.text, question),
+            question
+        )
+    }
+}
+
+/// Cut `text` down to [`BUDGET`]: its head, then the blocks that mention
+/// the question's words most, then whatever else fits, in page order.
+/// Dropped stretches are marked `[…]`.
+fn shorten(text: &str, question: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in text.lines().map(str::trim_end) {
+        // Man pages pad with runs of blank lines.
+        if !(line.is_empty() && lines.last().is_none_or(|l| l.is_empty())) {
+            lines.push(line);
+        }
+    }
+    let text = lines.join("\n");
+    if text.len() <= BUDGET {
+        return text;
+    }
+
+    // Blank-line separated paragraphs, long ones split.
+    let blocks: Vec<String> = text
+        .split("\n\n")
+        .flat_map(|para| {
+            let para: Vec<&str> = para.lines().collect();
+            para.chunks(BLOCK_LINES).map(|chunk| chunk.join("\n")).collect::<Vec<_>>()
+        })
+        .collect();
+
+    let words: Vec<String> = question
+        .split(|c: char| c.is_whitespace() || ",.?!;:'\"()`".contains(c))
+        .map(str::to_lowercase)
+        .filter(|w| (w.len() >= 3 || w.starts_with('-')) && !STOP_WORDS.contains(&w.as_str()))
+        .collect();
+    let score = |block: &str| {
+        let block = block.to_lowercase();
+        words.iter().map(|w| block.matches(w.as_str()).count()).sum::<usize>()
+    };
+
+    let mut keep = vec![false; blocks.len()];
+    let mut used = 0;
+    for (i, block) in blocks.iter().enumerate() {
+        if used + block.len() > HEAD {
+            break;
+        }
+        keep[i] = true;
+        used += block.len() + 2;
+    }
+    let mut ranked: Vec<(usize, usize)> =
+        blocks.iter().enumerate().filter(|(i, _)| !keep[*i]).map(|(i, b)| (score(b), i)).collect();
+    // Best match first; among equals, earlier in the page.
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for (_, i) in ranked {
+        if used + blocks[i].len() <= BUDGET {
+            keep[i] = true;
+            used += blocks[i].len() + 2;
+        }
+    }
+
+    let mut out: Vec<&str> = vec![];
+    for (i, block) in blocks.iter().enumerate() {
+        if keep[i] {
+            out.push(block);
+        } else if out.last() != Some(&"[…]") {
+            out.push("[…]");
+        }
+    }
+    out.join("\n\n")
+}
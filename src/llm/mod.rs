@@ -1,10 +1,24 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, mpsc::Sender};
+use sheesh_tools::{OutputLimits, ToolCallEnd, ToolGroup, ToolObserver, ToolRegistry, ToolResult};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, atomic::AtomicBool, mpsc::Sender};
 
 pub mod anthropic;
+pub mod cache;
+pub mod clean;
+pub mod connections;
+pub mod debug;
+pub mod fetch;
+pub mod limit;
+pub mod local;
+pub mod locality;
+pub mod man;
 pub mod ollama;
 pub mod openai;
+pub mod quickfix;
+pub mod runbook;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Role {
@@ -18,17 +32,69 @@ pub enum Role {
 pub struct Message {
     pub role: Role,
     pub content: String,
+    #[serde(default)]
+    pub meta: TurnMeta,
 }
 
 impl Message {
     pub fn user(content: impl Into<String>) -> Self {
-        Self { role: Role::User, content: content.into() }
+        Self { role: Role::User, content: content.into(), meta: TurnMeta::now() }
     }
 
     pub fn assistant(content: impl Into<String>) -> Self {
-        Self { role: Role::Assistant, content: content.into() }
+        Self { role: Role::Assistant, content: content.into(), meta: TurnMeta::now() }
     }
 
+    /// Attach the model that answered and what the turn cost.
+    pub fn answered_by(mut self, model: impl Into<String>, usage: Option<Usage>) -> Self {
+        self.meta.model = Some(model.into());
+        self.meta.usage = usage;
+        self
+    }
+
+}
+
+/// Tokens a request (or a whole turn of requests) consumed, as reported by
+/// the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// When a message was added and, for answers, which model gave it and at
+/// what cost. Kept with the transcript so incident timelines can be rebuilt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnMeta {
+    /// Unix time in seconds; 0 when unknown.
+    pub at: u64,
+    pub model: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+impl TurnMeta {
+    fn now() -> Self {
+        let at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Self { at, ..Self::default() }
+    }
+
+    /// Short summary such as `14:02:31Z · claude-sonnet-4-6 · 1200→85 tokens`.
+    pub fn summary(&self) -> String {
+        let secs = self.at % 86_400;
+        let mut parts = vec![format!("{:02}:{:02}:{:02}Z", secs / 3600, secs / 60 % 60, secs % 60)];
+        parts.extend(self.model.clone());
+        if let Some(usage) = self.usage {
+            parts.push(format!("{}→{} tokens", usage.input_tokens, usage.output_tokens));
+        }
+        parts.join(" · ")
+    }
 }
 
 // ── Rich content (Anthropic tool-use format) ──────────────────────────────────
@@ -39,7 +105,13 @@ impl Message {
 pub enum ContentBlock {
     Text { text: String },
     ToolUse { id: String, name: String, input: serde_json::Value },
-    ToolResult { tool_use_id: String, content: String },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        /// The call failed or couldn't be made; `content` says why.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
 }
 
 /// Full API message that supports multi-part content (text + tool calls).
@@ -70,41 +142,31 @@ impl RichMessage {
             content: vec![ContentBlock::Text { text: text.into() }],
         }
     }
-
-    pub fn tool_result(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
-        Self {
-            role: Role::User,
-            content: vec![ContentBlock::ToolResult {
-                tool_use_id: tool_use_id.into(),
-                content: content.into(),
-            }],
-        }
-    }
 }
 
 // ── Events sent back from the background LLM thread ──────────────────────────
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LLMEvent {
     /// Full text response — conversation continues normally.
-    Response(String),
-    /// Claude wants to run a shell command on the PTY; user must confirm before it runs.
-    ToolCall {
-        /// Tool-use block id — echoed back in the tool_result.
-        id: String,
-        /// The command Claude wants to execute.
-        command: String,
-        /// Optional one-line description Claude provided.
-        description: Option<String>,
-        /// Full assistant content blocks (text + tool_use) for rich history.
-        assistant_blocks: Vec<ContentBlock>,
+    Response {
+        text: String,
+        /// Tokens used, when the provider reports them.
+        usage: Option<Usage>,
     },
-    /// Claude invoked a tool that is resolved locally (no PTY needed).
-    LocalTool {
-        id: String,
-        name: String,
+    /// Claude issued one or more tool calls in a single turn. Commands must be
+    /// confirmed by the user before they run on the PTY; local tools resolve
+    /// immediately. All results go back together in one user message.
+    ToolCalls {
+        /// Dispatched calls, in the order the model issued them.
+        calls: Vec<ToolResult>,
+        /// Full assistant content blocks (text + tool_use) for rich history.
         assistant_blocks: Vec<ContentBlock>,
+        usage: Option<Usage>,
     },
+    /// The request is waiting for the shared limiter; sent before the
+    /// final event, possibly several times.
+    Queued(limit::Queued),
     /// An error occurred.
     Error(String),
 }
@@ -117,32 +179,37 @@ pub trait LLMProvider: Send + Sync {
     /// Plain completion — used by providers without tool support.
     fn complete(&self, messages: &[Message]) -> Result<String>;
 
-    /// Rich completion with tool definitions included in the request.
+    /// Rich completion offering the tools in `tools`.
     /// Default implementation strips tool content and falls back to `complete`.
-    fn complete_rich(&self, messages: &[RichMessage]) -> Result<LLMEvent> {
-        let simple: Vec<Message> = messages
-            .iter()
-            .filter_map(|m| {
-                let text: String = m
-                    .content
-                    .iter()
-                    .filter_map(|c| match c {
-                        ContentBlock::Text { text } => Some(text.as_str()),
-                        ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
-                        ContentBlock::ToolUse { .. } => None,
-                    })
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                if text.trim().is_empty() {
-                    return None;
-                }
-                Some(Message { role: m.role.clone(), content: text })
-            })
-            .collect();
-        self.complete(&simple).map(LLMEvent::Response)
+    fn complete_rich(&self, messages: &[RichMessage], _tools: &ToolRegistry) -> Result<LLMEvent> {
+        self.complete(&plain(messages)).map(|text| LLMEvent::Response { text, usage: None })
     }
 }
 
+/// `messages` without their tool calls, for a plain completion: tool
+/// results are kept as text.
+pub fn plain(messages: &[RichMessage]) -> Vec<Message> {
+    messages
+        .iter()
+        .filter_map(|m| {
+            let text: String = m
+                .content
+                .iter()
+                .filter_map(|c| match c {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                    ContentBlock::ToolUse { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            if text.trim().is_empty() {
+                return None;
+            }
+            Some(Message { role: m.role.clone(), content: text, meta: TurnMeta::default() })
+        })
+        .collect()
+}
+
 // ── Configuration ─────────────────────────────────────────────────────────────
 
 /// Default system prompt injected at the start of every LLM session.
@@ -155,14 +222,37 @@ You have the following tools available:\n\
 - read_terminal: Read recent output from the user's terminal. \
   Call this proactively whenever the user asks about what is on the screen, \
   what is happening, or any question that requires knowing the current terminal state. \
-  Do NOT ask the user to share output — just call the tool.\n\
+  Do NOT ask the user to share output — just call the tool. After the first call it returns only \
+  output printed since your previous read; pass full=true if you need the recent lines again.\n\
 - run_command: Execute a shell command on the remote SSH session. \
-  Always explain what a command does before proposing to run it.\n\
+  Always explain what a command does before proposing to run it. \
+  Only run non-interactive commands that terminate on their own: no editors, pagers, \
+  REPLs or follow modes (use cat, top -bn1, --no-pager, tail -n).\n\
 - system_information: Get SSH connection details for the current session.\n\
-- read_file, list_dir, make_dir, touch_file: File operations on the remote host.";
+- list_connections: The user's other saved hosts with their descriptions, for questions like \
+  \"which of my hosts runs the staging DB?\".\n\
+- open_session: Open another saved host in a new session next to this one, when the problem \
+  also needs looking at there (\"check the load balancer too\").\n\
+- read_file, list_dir, make_dir, touch_file: File operations on the remote host.\n\
+- package_info: Check whether a package is installed, its version and available updates \
+  on any common distro; use it instead of guessing the package manager.\n\
+- disk_usage: Filesystem usage and the largest directories under a path, for \"what is filling the disk\".\n\
+- list_scheduled: All cron jobs and systemd timers on the host, for tracking down jobs that run on their own.\n\
+- git_info: Branch, HEAD, upstream drift, uncommitted changes and recent commits of a remote checkout.\n\
+- write_file, append_file, edit_file: Change files on the remote host. The user reviews a diff \
+  before anything is written; prefer edit_file for small changes to existing files.\n\
+- download_file: Copy a remote file to the user's machine and report where it was saved.\n\
+- fetch_url: Read a documentation page (man pages, vendor docs) from an allowlisted site, \
+  e.g. to look up an unfamiliar error message or option.\n\
+- rollback_file: Restore the backup taken before this session's latest write to a file.\n\
+- read_more: Page through a long tool output that was shortened; its marker gives the token and line.\n\
+- update_plan: Show the user a checklist of the steps of a multi-step task and keep it updated. \
+  Before running more than one command for a request, call update_plan with the whole plan \
+  first, giving each step's exact command: the user approves it once, after which those commands \
+  run without further prompts; any other command still asks.";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct LLMConfig {
     pub provider: String,
     pub model: String,
@@ -172,7 +262,66 @@ pub struct LLMConfig {
     pub api_key_env: String,
     pub ollama_host: String,
     pub ollama_model: String,
+    /// API root for the llamacpp and lmstudio providers (default: their
+    /// standard local ports).
+    pub base_url: Option<String>,
     pub system_prompt: Option<String>,
+    /// File to read the system prompt from instead, e.g. `~/.config/sheesh/prompt.md`.
+    pub system_prompt_file: Option<String>,
+    /// Per-provider prompts, e.g. a shorter one for small local models.
+    pub prompts: HashMap<String, PromptOverride>,
+    /// Seconds an identical request is answered from cache; 0 disables it.
+    pub cache_ttl_secs: u64,
+    /// Strip spinner frames, repeated lines and bare prompts from terminal
+    /// text before it is sent.
+    pub clean_context: bool,
+    /// Ask before each request to a cloud provider (Anthropic, OpenAI), as
+    /// every one carries the session's context and terminal output.
+    pub confirm_context_share: bool,
+    /// Requests in flight at once across all sessions; more wait their turn.
+    pub max_concurrent_requests: usize,
+    /// Per-provider cap on requests started per minute, e.g. `anthropic = 50`.
+    pub requests_per_minute: HashMap<String, u32>,
+    /// Named models for `/model <name>`, e.g. a cheap local one.
+    pub models: HashMap<String, ModelRoute>,
+    /// Session header sent with every request; see [`render_context`].
+    pub context_template: String,
+    /// Per-connection overrides keyed by Host alias; `*` and `?` wildcards match.
+    pub connections: HashMap<String, ConnectionLLM>,
+    /// The model a `[llm.connections]` entry holds the session to; set by
+    /// [`LLMConfig::for_connection`], never read from the file.
+    #[serde(skip)]
+    pub pinned: Option<String>,
+}
+
+/// `[llm.prompts.<provider>]` — the system prompt used with one provider.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptOverride {
+    pub system_prompt: Option<String>,
+    pub system_prompt_file: Option<String>,
+}
+
+/// `[llm.connections."<alias>"]` — the assistant on matching connections,
+/// e.g. none on air-gapped hosts, or only a local model for a client's.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionLLM {
+    /// No assistant panel, and nothing from the session sent to a provider.
+    pub disabled: bool,
+    /// The only model the session uses, `/model` included when it would go
+    /// to another provider: a `[llm.models]` name or `provider:model`.
+    pub model: Option<String>,
+}
+
+/// `[llm.models.<name>]` — a model a single message can be routed to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelRoute {
+    pub provider: String,
+    pub model: String,
+    /// Environment variable with the API key, when it differs from `[llm]`.
+    pub api_key_env: Option<String>,
 }
 
 impl Default for LLMConfig {
@@ -184,11 +333,349 @@ impl Default for LLMConfig {
             api_key_env: "ANTHROPIC_API_KEY".into(),
             ollama_host: "http://localhost:11434".into(),
             ollama_model: "llama3".into(),
+            base_url: None,
             system_prompt: Some(DEFAULT_SYSTEM_PROMPT.into()),
+            system_prompt_file: None,
+            prompts: HashMap::new(),
+            cache_ttl_secs: 60,
+            clean_context: true,
+            confirm_context_share: false,
+            max_concurrent_requests: 2,
+            requests_per_minute: HashMap::new(),
+            models: HashMap::new(),
+            context_template: DEFAULT_CONTEXT_TEMPLATE.into(),
+            connections: HashMap::new(),
+            pinned: None,
+        }
+    }
+}
+
+/// Values accepted for `provider`.
+pub const PROVIDERS: [&str; 5] = ["anthropic", "openai", "ollama", "llamacpp", "lmstudio"];
+
+/// Header describing the live session, sent with every request.
+pub const DEFAULT_CONTEXT_TEMPLATE: &str = "\
+Session context (write commands for this environment rather than assuming bash on Ubuntu):
+- host: {host} (Host alias {alias})
+- connection: {link}, not ssh, so scp and downloads do not reach it
+- user: {user}
+- shell: {shell}
+- {shell_syntax}
+- OS: {os}
+- package manager: {package_manager}, for installing anything missing
+- working directory: {cwd}; commands you run execute there unless they cd elsewhere
+- last command in the terminal: `{last_command}`, exit status {exit_status}";
+
+/// Fill `{name}` placeholders in `template` from `vars`. Lines with a
+/// placeholder that has no value (or is not a known name) are dropped, so a
+/// header only states what is known.
+pub fn render_context(template: &str, vars: &[(&str, Option<String>)]) -> String {
+    let mut lines = vec![];
+    'lines: for line in template.lines() {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else { break };
+            let name = &rest[start + 1..start + len];
+            match vars.iter().find(|(n, _)| *n == name) {
+                Some((_, Some(value))) => {
+                    out.push_str(&rest[..start]);
+                    out.push_str(value);
+                }
+                _ => continue 'lines,
+            }
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        lines.push(out);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+impl LLMConfig {
+    /// The model requests go to, for the configured provider.
+    pub fn model_name(&self) -> &str {
+        match self.provider.as_str() {
+            "ollama" => &self.ollama_model,
+            _ => &self.model,
+        }
+    }
+
+    /// The system prompt for the configured provider: its `[llm.prompts]`
+    /// entry if it has one, else `system_prompt_file`, else `system_prompt`.
+    /// Files are read on every call, so edits apply to the next session.
+    pub fn system_prompt(&self) -> Result<Option<String>> {
+        let mut levels = vec![];
+        if let Some(prompt) = self.prompts.get(&self.provider) {
+            levels.push((&prompt.system_prompt_file, &prompt.system_prompt));
+        }
+        levels.push((&self.system_prompt_file, &self.system_prompt));
+        for (file, text) in levels {
+            if let Some(file) = file {
+                let path = crate::config::expand_home(file);
+                let prompt = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading system prompt {}", path.display()))?;
+                return Ok(Some(prompt.trim_end().to_string()));
+            }
+            if text.is_some() {
+                return Ok(text.clone());
+            }
+        }
+        Ok(None)
+    }
+
+    /// The config for a session on the connection named `alias`, or `None`
+    /// when a matching `[llm.connections]` entry turns the assistant off.
+    /// Of the matching entries that name a model, the longest pattern wins.
+    pub fn for_connection(&self, alias: &str) -> Option<LLMConfig> {
+        let mut matching: Vec<(&String, &ConnectionLLM)> =
+            self.connections.iter().filter(|(pattern, _)| wildcard_match(pattern, alias)).collect();
+        if matching.iter().any(|(_, c)| c.disabled) {
+            return None;
+        }
+        matching.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let Some(model) = matching.iter().find_map(|(_, c)| c.model.as_deref()) else {
+            return Some(self.clone());
+        };
+        let mut cfg = self.route(model);
+        cfg.pinned = Some(model.to_string());
+        Some(cfg)
+    }
+
+    /// This config with the model replaced by `name`, for `/model <name>`:
+    /// a key of `[llm.models]`, `provider:model`, or another model of the
+    /// configured provider.
+    pub fn route(&self, name: &str) -> LLMConfig {
+        let route = match self.models.get(name) {
+            Some(route) => route.clone(),
+            None => match name.split_once(':') {
+                Some((provider, model)) if PROVIDERS.contains(&provider) => ModelRoute {
+                    provider: provider.to_string(),
+                    model: model.to_string(),
+                    api_key_env: None,
+                },
+                _ => ModelRoute { provider: self.provider.clone(), model: name.to_string(), api_key_env: None },
+            },
+        };
+        let mut cfg = self.clone();
+        if route.provider != self.provider {
+            // The `[llm]` key belongs to the other provider.
+            cfg.api_key = None;
+            cfg.api_key_env = match route.provider.as_str() {
+                "openai" => "OPENAI_API_KEY".into(),
+                _ => "ANTHROPIC_API_KEY".into(),
+            };
+        }
+        if let Some(env) = route.api_key_env {
+            cfg.api_key_env = env;
+        }
+        match route.provider.as_str() {
+            "ollama" => cfg.ollama_model = route.model,
+            _ => cfg.model = route.model,
+        }
+        cfg.provider = route.provider;
+        cfg
+    }
+}
+
+/// `[tools]` section of config.toml — how tool-call commands are executed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolConfig {
+    /// Seconds an approved command may run before it is interrupted and
+    /// whatever was captured is sent back to the model.
+    pub capture_timeout_secs: u64,
+    /// What an approved plan lets commands skip.
+    pub plan_policy: PlanPolicy,
+    /// Where `download_file` puts files (default: the OS downloads directory).
+    pub download_dir: Option<String>,
+    /// Where each connection's notes (F11) and `/runbook`s are kept, a
+    /// directory per connection (default: the OS data directory's
+    /// `sheesh/notes`).
+    pub notes_dir: Option<String>,
+    /// Tool results longer than this many lines are cut to head and tail.
+    pub max_output_lines: usize,
+    /// Tool results larger than this many bytes are cut to head and tail.
+    pub max_output_bytes: usize,
+    /// Tool groups (core, fs, write, net, inspect) disabled on every connection.
+    pub disabled_groups: Vec<String>,
+    /// Per-connection overrides keyed by Host alias; `*` and `?` wildcards match.
+    pub connections: HashMap<String, ConnectionTools>,
+    /// Hosts `fetch_url` may fetch from, subdomains included. Empty turns
+    /// the tool off.
+    pub fetch_allowlist: Vec<String>,
+    /// Bytes of a fetched page read before the rest is dropped.
+    pub fetch_max_bytes: usize,
+    /// Ask a host its shell and OS over a second ssh session the first
+    /// time sheesh meets it. Never done on guarded hosts.
+    pub detect_platform: bool,
+}
+
+/// `[tools.connections."<alias>"]` — tool settings for matching connections.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionTools {
+    /// Groups disabled in addition to the global `disabled_groups`.
+    pub disabled_groups: Vec<String>,
+    /// Production guard: no auto-approval of any kind, commands and writes
+    /// confirmed by typing the host's alias, and a warning strip over the
+    /// terminal for the whole session.
+    pub guard: bool,
+}
+
+impl Default for ToolConfig {
+    fn default() -> Self {
+        let limits = OutputLimits::default();
+        Self {
+            capture_timeout_secs: 120,
+            plan_policy: PlanPolicy::Safe,
+            download_dir: None,
+            notes_dir: None,
+            max_output_lines: limits.max_lines,
+            max_output_bytes: limits.max_bytes,
+            disabled_groups: vec![],
+            connections: HashMap::new(),
+            fetch_allowlist: ["man7.org", "manpages.debian.org", "manpages.ubuntu.com", "wiki.archlinux.org"]
+                .map(String::from)
+                .to_vec(),
+            fetch_max_bytes: 512 * 1024,
+            detect_platform: false,
         }
     }
 }
 
+impl ToolConfig {
+    /// True if a `[tools.connections]` entry matching `alias` sets `guard`.
+    pub fn guarded(&self, alias: &str) -> bool {
+        self.connections.iter().any(|(pattern, c)| c.guard && wildcard_match(pattern, alias))
+    }
+
+    /// Tools offered on the connection named `alias`: every built-in tool
+    /// except the groups disabled globally or by a matching override. Calls
+    /// are recorded in the log file by a [`ToolAuditLog`]; `open_session`
+    /// sends the aliases it opens to `sessions`.
+    pub fn registry_for(&self, alias: &str, sessions: &Sender<connections::OpenRequest>) -> ToolRegistry {
+        let names = self.disabled_groups.iter().chain(
+            self.connections
+                .iter()
+                .filter(|(pattern, _)| wildcard_match(pattern, alias))
+                .flat_map(|(_, c)| &c.disabled_groups),
+        );
+        let groups: Vec<ToolGroup> = names
+            .filter_map(|name| {
+                let group = ToolGroup::parse(name);
+                if group.is_none() {
+                    log::warn!("[tools] unknown tool group {:?} in config", name);
+                }
+                group
+            })
+            .collect();
+        let mut registry = ToolRegistry::builtin()
+            .with_local(Arc::new(connections::ListConnections::new(alias)))
+            .with_local(Arc::new(connections::OpenSession::new(sessions.clone())));
+        if !self.fetch_allowlist.is_empty() {
+            registry = registry.with_local(Arc::new(fetch::FetchUrl::new(&self.fetch_allowlist, self.fetch_max_bytes)));
+        }
+        registry
+            .without_groups(&groups)
+            .observe(Arc::new(ToolAuditLog { connection: alias.to_string() }))
+    }
+
+    /// Per-result output budget, with floors that keep head + tail useful.
+    pub fn output_limits(&self) -> OutputLimits {
+        OutputLimits { max_lines: self.max_output_lines.max(20), max_bytes: self.max_output_bytes.max(1024) }
+    }
+
+    /// Resolved download directory, expanding a leading `~`.
+    pub fn download_dir(&self) -> PathBuf {
+        match self.download_dir.as_deref() {
+            Some(dir) => crate::config::expand_home(dir),
+            None => dirs::download_dir()
+                .or_else(dirs::home_dir)
+                .unwrap_or_else(|| PathBuf::from(".")),
+        }
+    }
+
+    /// Resolved notes directory, expanding a leading `~`.
+    pub fn notes_dir(&self) -> PathBuf {
+        match self.notes_dir.as_deref() {
+            Some(dir) => crate::config::expand_home(dir),
+            None => dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("sheesh").join("notes"),
+        }
+    }
+}
+
+/// Writes a line to the log file for every tool call that runs.
+struct ToolAuditLog {
+    connection: String,
+}
+
+impl ToolObserver for ToolAuditLog {
+    fn on_call_start(&self, id: &str, name: &str) {
+        log::info!("[audit] {}: {} started ({})", self.connection, name, id);
+    }
+
+    fn on_call_end(&self, call: &ToolCallEnd) {
+        log::info!(
+            "[audit] {}: {} finished ({}) in {}ms, {} bytes of output",
+            self.connection,
+            call.name,
+            call.id,
+            call.duration.as_millis(),
+            call.result_bytes
+        );
+    }
+}
+
+/// Permission policy for commands that belong to a plan the user approved.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanPolicy {
+    /// Plans are informational; every command is confirmed.
+    Off,
+    /// Safe commands run without asking; destructive ones still confirm.
+    Safe,
+    /// Every command runs without asking once the plan is approved.
+    All,
+}
+
+/// Glob match supporting `*` (any run of characters) and `?` (one character).
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Stands in for the provider of a session whose assistant is turned off,
+/// so that whatever still asks for a completion gets an error instead.
+pub struct Disabled;
+
+impl LLMProvider for Disabled {
+    fn name(&self) -> &str {
+        "off"
+    }
+
+    fn complete(&self, _messages: &[Message]) -> Result<String> {
+        anyhow::bail!("the assistant is turned off for this connection")
+    }
+}
+
 pub fn build_provider(cfg: &LLMConfig) -> Arc<dyn LLMProvider> {
     let resolve_key = |cfg: &LLMConfig| -> String {
         if let Some(k) = cfg.api_key.as_deref().filter(|k| !k.is_empty()) {
@@ -210,7 +697,7 @@ pub fn build_provider(cfg: &LLMConfig) -> Arc<dyn LLMProvider> {
         }
     };
 
-    match cfg.provider.as_str() {
+    let provider: Arc<dyn LLMProvider> = match cfg.provider.as_str() {
         "openai" => {
             Arc::new(openai::OpenAIProvider::new(resolve_key(cfg), cfg.model.clone()))
         }
@@ -218,24 +705,69 @@ pub fn build_provider(cfg: &LLMConfig) -> Arc<dyn LLMProvider> {
             cfg.ollama_host.clone(),
             cfg.ollama_model.clone(),
         )),
+        "llamacpp" | "lmstudio" => {
+            let (name, base_url) = local::server(cfg).expect("local provider");
+            Arc::new(openai::OpenAIProvider::compatible(name, &base_url, String::new(), cfg.model.clone()))
+        }
         _ => {
             Arc::new(anthropic::AnthropicProvider::new(resolve_key(cfg), cfg.model.clone()))
         }
+    };
+    if cfg.cache_ttl_secs == 0 {
+        return provider;
     }
+    Arc::new(cache::CachedProvider::new(
+        provider,
+        format!("{}:{}", cfg.provider, cfg.model_name()),
+        std::time::Duration::from_secs(cfg.cache_ttl_secs),
+    ))
 }
 
 // ── Background thread helpers ─────────────────────────────────────────────────
 
 /// Spawns a background thread to get a rich LLM completion with tool support.
+/// The request first waits for the shared [`limit`]er, under the budget of
+/// `provider_id` (the `provider` setting it was built from), and reports
+/// its queue position on `tx` while it does. Setting `cancelled` before it
+/// leaves the queue drops it without a word.
 pub fn spawn_completion_rich(
     provider: Arc<dyn LLMProvider>,
+    provider_id: String,
     messages: Vec<RichMessage>,
+    tools: ToolRegistry,
     tx: Sender<LLMEvent>,
+    cancelled: Arc<AtomicBool>,
 ) {
     std::thread::spawn(move || {
-        match provider.complete_rich(&messages) {
+        let queued = |queued| {
+            let _ = tx.send(LLMEvent::Queued(queued));
+        };
+        let Some(_slot) = limit::acquire(&provider_id, &cancelled, queued) else {
+            return;
+        };
+        match provider.complete_rich(&messages, &tools) {
             Ok(event) => { let _ = tx.send(event); }
             Err(e) => { let _ = tx.send(LLMEvent::Error(e.to_string())); }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_match_stars_and_question_marks() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("web*", "web"));
+        assert!(wildcard_match("web?", "web1"));
+        assert!(!wildcard_match("web?", "web"));
+        assert!(wildcard_match("*.example.com", "db.eu.example.com"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+        // A star backs off as far as it needs to.
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+        assert!(wildcard_match("systemctl status *", "systemctl status nginx"));
+        assert!(!wildcard_match("systemctl status *", "systemctl restart nginx"));
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::{Context, Result};
 use serde_json::{json, Value};
 
-use super::{LLMProvider, Message, Role};
+use super::{LLMProvider, Message, Role, debug::dump};
 
 pub struct OllamaProvider {
     host: String,
@@ -37,17 +37,20 @@ impl LLMProvider for OllamaProvider {
         let url = format!("{}/api/chat", self.host.trim_end_matches('/'));
 
         let client = reqwest::blocking::Client::new();
+        let body = json!({
+            "model": self.model,
+            "messages": msgs,
+            "stream": false,
+        });
+        dump("Ollama", "request", &body);
         let resp = client
             .post(&url)
-            .json(&json!({
-                "model": self.model,
-                "messages": msgs,
-                "stream": false,
-            }))
+            .json(&body)
             .send()
             .context("sending request to Ollama")?;
 
         let body: Value = resp.json().context("parsing Ollama response")?;
+        dump("Ollama", "response", &body);
 
         body["message"]["content"]
             .as_str()
@@ -1,55 +1,184 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow, bail};
 use serde_json::{json, Value};
+use sheesh_tools::{ToolRegistry, ToolResult};
 
-use super::{LLMProvider, Message, Role};
+use super::{ContentBlock, LLMEvent, LLMProvider, Message, RichMessage, Role, Usage, debug::dump, plain};
 
 pub struct OpenAIProvider {
     api_key: String,
     model: String,
+    /// Display name, e.g. "OpenAI" or "LM Studio".
+    name: String,
+    /// API root the `/chat/completions` path is appended to.
+    base_url: String,
+    /// Whether the session's tools are offered. Local servers only take
+    /// them with some models and settings, so they get plain completions.
+    tools: bool,
 }
 
 impl OpenAIProvider {
     pub fn new(api_key: String, model: String) -> Self {
-        Self { api_key, model }
+        Self { tools: true, ..Self::compatible("OpenAI", "https://api.openai.com/v1", api_key, model) }
+    }
+
+    /// A server speaking the OpenAI API at `base_url` (llama.cpp, LM Studio, …).
+    /// An empty `api_key` sends no Authorization header.
+    pub fn compatible(name: &str, base_url: &str, api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            model,
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            tools: false,
+        }
+    }
+
+    fn post(&self, body: Value) -> Result<Value> {
+        let client = reqwest::blocking::Client::new();
+        let mut request = client.post(format!("{}/chat/completions", self.base_url));
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+        dump(&self.name, "request", &body);
+        let resp = request
+            .json(&body)
+            .send()
+            .map_err(|e| {
+                if e.is_connect() {
+                    anyhow!("cannot reach {} at {} — is the server running?", self.name, self.base_url)
+                } else {
+                    anyhow!("sending request to {}: {}", self.name, e)
+                }
+            })?;
+
+        let body: Value = resp.json().with_context(|| format!("parsing {} response", self.name))?;
+        dump(&self.name, "response", &body);
+        Ok(body)
     }
 }
 
-impl LLMProvider for OpenAIProvider {
-    fn name(&self) -> &str {
-        "OpenAI"
+fn role(role: &Role) -> &'static str {
+    match role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::System => "system",
     }
+}
 
-    fn complete(&self, messages: &[Message]) -> Result<String> {
-        let msgs: Vec<Value> = messages
+/// A `RichMessage` as Chat Completions messages: tool calls go in the
+/// assistant message's `tool_calls`, and each tool result is a `tool`
+/// message of its own, ahead of any text the user message also carries.
+fn rich_to_json(m: &RichMessage) -> Vec<Value> {
+    let text = m
+        .content
+        .iter()
+        .filter_map(|c| if let ContentBlock::Text { text } = c { Some(text.as_str()) } else { None })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if m.role == Role::Assistant {
+        let calls: Vec<Value> = m
+            .content
             .iter()
-            .map(|m| {
-                json!({
-                    "role": match m.role {
-                        Role::User => "user",
-                        Role::Assistant => "assistant",
-                        Role::System => "system",
-                    },
-                    "content": m.content,
-                })
+            .filter_map(|c| match c {
+                ContentBlock::ToolUse { id, name, input } => Some(json!({
+                    "id": id,
+                    "type": "function",
+                    "function": { "name": name, "arguments": input.to_string() },
+                })),
+                _ => None,
             })
             .collect();
+        if calls.is_empty() {
+            return vec![json!({ "role": "assistant", "content": text })];
+        }
+        let content = if text.is_empty() { Value::Null } else { json!(text) };
+        return vec![json!({ "role": "assistant", "content": content, "tool_calls": calls })];
+    }
+    let mut msgs: Vec<Value> = m
+        .content
+        .iter()
+        .filter_map(|c| match c {
+            ContentBlock::ToolResult { tool_use_id, content, .. } => {
+                Some(json!({ "role": "tool", "tool_call_id": tool_use_id, "content": content }))
+            }
+            _ => None,
+        })
+        .collect();
+    if !text.is_empty() || msgs.is_empty() {
+        msgs.push(json!({ "role": role(&m.role), "content": text }));
+    }
+    msgs
+}
 
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .bearer_auth(&self.api_key)
-            .json(&json!({
-                "model": self.model,
-                "messages": msgs,
-            }))
-            .send()
-            .context("sending request to OpenAI")?;
+impl LLMProvider for OpenAIProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-        let body: Value = resp.json().context("parsing OpenAI response")?;
+    fn complete(&self, messages: &[Message]) -> Result<String> {
+        let msgs: Vec<Value> = messages.iter().map(|m| json!({ "role": role(&m.role), "content": m.content })).collect();
+        let body = self.post(json!({
+            "model": self.model,
+            "messages": msgs,
+        }))?;
 
         body["choices"][0]["message"]["content"]
             .as_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| anyhow::anyhow!("unexpected OpenAI response: {}", body))
+            .ok_or_else(|| anyhow!("unexpected {} response: {}", self.name, body))
+    }
+
+    fn complete_rich(&self, messages: &[RichMessage], tools: &ToolRegistry) -> Result<LLMEvent> {
+        if !self.tools {
+            return self.complete(&plain(messages)).map(|text| LLMEvent::Response { text, usage: None });
+        }
+        let msgs: Vec<Value> = messages.iter().flat_map(rich_to_json).collect();
+        let body = self.post(json!({
+            "model": self.model,
+            "tools": tools.to_openai(),
+            "messages": msgs,
+        }))?;
+        if let Some(message) = body["error"]["message"].as_str() {
+            bail!("{} error: {}", self.name, message);
+        }
+
+        let message = &body["choices"][0]["message"];
+        let usage = body["usage"].as_object().map(|u| Usage {
+            input_tokens: u.get("prompt_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+            output_tokens: u.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+        });
+        let text = message["content"].as_str().unwrap_or("").to_string();
+        let tool_calls = message["tool_calls"].as_array().cloned().unwrap_or_default();
+        if tool_calls.is_empty() {
+            if text.is_empty() {
+                bail!("unexpected {} response: {}", self.name, body);
+            }
+            return Ok(LLMEvent::Response { text, usage });
+        }
+
+        let mut assistant_blocks = vec![];
+        if !text.is_empty() {
+            assistant_blocks.push(ContentBlock::Text { text });
+        }
+        let mut calls = vec![];
+        for call in &tool_calls {
+            let name = call["function"]["name"].as_str().unwrap_or("").to_string();
+            let id = call["id"].as_str().unwrap_or("").to_string();
+            // Arguments come as a JSON string; a call without any sends "".
+            let arguments = call["function"]["arguments"].as_str().filter(|a| !a.trim().is_empty()).unwrap_or("{}");
+            let (input, result) = match serde_json::from_str::<Value>(arguments) {
+                Ok(input) => {
+                    let result = tools.dispatch(id.as_str(), &name, &input);
+                    (input, result)
+                }
+                Err(e) => {
+                    let error = format!("the arguments are not valid JSON ({}): {}", e, arguments);
+                    (json!({}), ToolResult::Rejected { id: id.clone(), name: name.clone(), error })
+                }
+            };
+            calls.push(result);
+            assistant_blocks.push(ContentBlock::ToolUse { id, name, input });
+        }
+        Ok(LLMEvent::ToolCalls { calls, assistant_blocks, usage })
     }
 }
@@ -0,0 +1,130 @@
+//! Errors in the terminal's recent output that sheesh offers to fix with
+//! one key (F5 with nothing selected): a missing command, a permission
+//! problem, a port already taken. Spotting them is a few patterns; the fix
+//! itself comes from the LLM, best command first, into the suggestion bar.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Non-blank output lines looked at, newest last. Errors further up have
+/// most likely been dealt with or scrolled past.
+const RECENT_LINES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProblemKind {
+    CommandNotFound,
+    PermissionDenied,
+    PortInUse,
+}
+
+impl ProblemKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ProblemKind::CommandNotFound => "command not found",
+            ProblemKind::PermissionDenied => "permission denied",
+            ProblemKind::PortInUse => "port in use",
+        }
+    }
+
+    /// What F5 offers, for the key hints.
+    pub fn hint(self) -> &'static str {
+        match self {
+            ProblemKind::CommandNotFound => "fix: command not found",
+            ProblemKind::PermissionDenied => "fix: permission denied",
+            ProblemKind::PortInUse => "fix: port in use",
+        }
+    }
+}
+
+static PATTERNS: LazyLock<Vec<(ProblemKind, Regex)>> = LazyLock::new(|| {
+    [
+        // bash and zsh, dash's `sh: 1: foo: not found`, and cmd.exe.
+        (
+            ProblemKind::CommandNotFound,
+            r"(?i)command not found|^\S+: \d+: \S+: not found$|is not recognized as an internal or external command",
+        ),
+        (ProblemKind::PermissionDenied, r"(?i)permission denied|operation not permitted|\bEACCES\b|\bEPERM\b"),
+        (
+            ProblemKind::PortInUse,
+            r"(?i)address already in use|\bEADDRINUSE\b|port \d+ is already (in use|allocated)|bind\(\) to \S+ failed",
+        ),
+    ]
+    .into_iter()
+    .map(|(kind, re)| (kind, Regex::new(re).expect("valid pattern")))
+    .collect()
+});
+
+/// An error worth a quick fix and the output around it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    /// The line that matched.
+    pub line: String,
+    /// The recent lines it was found in, for the prompt.
+    pub context: String,
+}
+
+impl Problem {
+    /// The newest problem in the last few lines of `text`, if any.
+    pub fn detect(text: &str) -> Option<Problem> {
+        let lines: Vec<&str> = text.lines().map(str::trim_end).filter(|l| !l.trim().is_empty()).collect();
+        let recent = &lines[lines.len().saturating_sub(RECENT_LINES)..];
+        recent.iter().rev().find_map(|line| {
+            let (kind, _) = PATTERNS.iter().find(|(_, re)| re.is_match(line))?;
+            Some(Problem { kind: *kind, line: line.trim().to_string(), context: recent.join("\n") })
+        })
+    }
+
+    /// The request sent for a fix. The best command comes first in a block
+    /// of its own, so it is the first suggestion.
+    pub fn prompt(&self) -> String {
+        format!(
+            "This just went wrong in my terminal ({}):\n```\n{}\n```\nSuggest the most likely fix. Give the single \
+             best command to run first, alone in a ```bash block, then say briefly why; alternatives after that, \
+             if any. Don't run anything yourself.",
+            self.kind.label(),
+            self.context
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(text: &str) -> Option<ProblemKind> {
+        Problem::detect(text).map(|p| p.kind)
+    }
+
+    #[test]
+    fn detects_each_kind() {
+        assert_eq!(kind("bash: htop: command not found"), Some(ProblemKind::CommandNotFound));
+        assert_eq!(kind("sh: 1: htop: not found"), Some(ProblemKind::CommandNotFound));
+        assert_eq!(
+            kind("'htop' is not recognized as an internal or external command,"),
+            Some(ProblemKind::CommandNotFound)
+        );
+        assert_eq!(kind("mkdir: cannot create directory '/x': Permission denied"), Some(ProblemKind::PermissionDenied));
+        assert_eq!(kind("Error: listen EADDRINUSE: :::3000"), Some(ProblemKind::PortInUse));
+        assert_eq!(kind("nginx: [emerg] bind() to 0.0.0.0:80 failed (98: ...)"), Some(ProblemKind::PortInUse));
+        assert_eq!(kind("Bind for 0.0.0.0:5432 failed: port 5432 is already allocated"), Some(ProblemKind::PortInUse));
+        assert_eq!(kind("all good\n$"), None);
+    }
+
+    #[test]
+    fn the_newest_problem_in_the_recent_lines_wins() {
+        let text = "$ ls /root\nls: /root: Permission denied\n$ htop\nbash: htop: command not found\n$ ";
+        let problem = Problem::detect(text).unwrap();
+        assert_eq!(problem.kind, ProblemKind::CommandNotFound);
+        assert_eq!(problem.line, "bash: htop: command not found");
+        assert_eq!(problem.context.lines().count(), 5);
+    }
+
+    #[test]
+    fn problems_scrolled_past_are_ignored() {
+        let mut text = "bash: htop: command not found\n".to_string();
+        text.push_str(&"ok\n\n".repeat(RECENT_LINES));
+        assert_eq!(kind(&text), None);
+    }
+}
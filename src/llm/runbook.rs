@@ -0,0 +1,61 @@
+//! `/runbook [title]`: have the model turn the session (the terminal as it
+//! was printed and the conversation) into a markdown checklist, saved in the
+//! notes directory, so the next time the same thing breaks there is
+//! something to follow instead of a shell history to dig through.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::{notes, store};
+
+/// Most of the terminal sent along; longer sessions keep their end, where
+/// the fix most likely is.
+const TRANSCRIPT_BUDGET: usize = 16_000;
+
+/// The request sent for a runbook about `title` (what the session was
+/// about, if the user said), with the terminal's text.
+pub fn prompt(title: &str, transcript: &str) -> String {
+    let transcript = transcript.trim();
+    let start = transcript.len().saturating_sub(TRANSCRIPT_BUDGET);
+    let start = (start..transcript.len()).find(|i| transcript.is_char_boundary(*i)).unwrap_or(0);
+    let cut = if start > 0 { "[… earlier output dropped]\n" } else { "" };
+    let about = if title.trim().is_empty() { String::new() } else { format!(" about \"{}\"", title.trim()) };
+    format!(
+        "Turn this session into a runbook{} that I can follow the next time this comes up. Our conversation \
+         above is part of the session; this is the terminal as it was printed:\n```\n{}{}\n```\nReply with the \
+         markdown only: a `# ` title, one line on when to use it, prerequisites, then the steps that worked as a \
+         `- [ ]` checklist, each with its command in a ```bash block and what to expect from it, then how to check \
+         it worked. Leave out dead ends and typos, but keep what to do when a step fails the way it did here. \
+         Put placeholders in place of passwords, tokens and keys.",
+        about,
+        cut,
+        &transcript[start..]
+    )
+}
+
+/// Write `reply` to `<dir>/<alias>/runbook-<time>-<title>.md` (`.md.age`
+/// in the encrypted store), named after its `# ` heading, and return the
+/// path.
+pub fn save(dir: &Path, alias: &str, reply: &str) -> Result<PathBuf> {
+    let text = unwrap_fence(reply);
+    let heading = text.lines().find_map(|l| l.strip_prefix("# ")).unwrap_or("session");
+    let dir = notes::host_dir(dir, alias);
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path = dir.join(format!("runbook-{}-{}.md", stamp, notes::file_name(&heading.to_lowercase())));
+    store::write(&path, &format!("{}\n", text)).with_context(|| format!("writing {}", path.display()))
+}
+
+/// `text` without the ```markdown fence models sometimes wrap it in.
+fn unwrap_fence(text: &str) -> &str {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix("```")
+        && let Some((_, body)) = rest.split_once('\n')
+        && let Some(body) = body.trim_end().strip_suffix("```")
+    {
+        return body.trim();
+    }
+    text
+}
@@ -1,17 +1,22 @@
-mod app;
-mod config;
-mod event;
-mod llm;
-mod ssh;
-mod tabs;
-mod ui;
+#[cfg(unix)]
+use sheesh_rs::daemon;
+use sheesh_rs::{
+    agent, app, bundle, chats, config, event, export, hooks, inventory, known_hosts, llm, metrics, notes, route, ssh,
+    store, tabs, ui,
+};
 
-use std::{path::Path, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
 
 use crossterm::event::{
-    DisableMouseCapture, EnableMouseCapture, MouseButton, MouseEventKind, poll, read,
+    DisableMouseCapture, EnableMouseCapture, KeyEventKind, MouseButton, MouseEventKind, poll, read,
 };
 use crossterm::execute;
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode};
 use ftail::Ftail;
 use log::LevelFilter;
 use ratatui::{
@@ -19,15 +24,32 @@ use ratatui::{
     layout::{Constraint, Layout, Rect},
     prelude::CrosstermBackend,
     text::{Line, Span},
-    widgets::{Block, BorderType, Clear, Paragraph},
+    widgets::Paragraph,
 };
 
 use app::{AppState, ConnectedFocus};
-use config::{load_connections, save_connections, ssh_config_path};
+use config::{default_ssh_config_path, load_connections, save_connections};
 use event::Action;
-use llm::{LLMConfig, build_provider};
+use hooks::{HookEvent, Hooks};
+use llm::connections::OpenRequest;
+use llm::{Disabled, LLMConfig, LLMProvider, ToolConfig, build_provider};
+use route::RouteStatus;
+use ssh::Backend;
 use tabs::{Tab, listing::ListingTab, llm::LLMTab, terminal::TerminalTab};
-use ui::{keybindings::render_keybindings, theme::Theme};
+use ui::{
+    UiConfig,
+    clipboard::{self, SnippetKind},
+    color::ColorDepth,
+    idle::IdleLock,
+    keybindings::render_keybindings,
+    menu::{ContextMenu, MenuOutcome},
+    notes::NotesEditor,
+    notice::Notices,
+    popover::Popover,
+    prompt::{AgentChoice, AgentPrompt, PromptKey, QuitPrompt, SavePrompt},
+    snippets::{SnippetAction, SnippetPicker},
+    theme::Theme,
+};
 
 /// Captures terminal output produced by a tool-call command and forwards it
 /// to the LLM once the output has been stable (no new lines) for a short period.
@@ -38,6 +60,43 @@ struct PendingCapture {
     last_line_count: usize,
     /// When the line count last changed (used to detect output stability).
     last_change: std::time::Instant,
+    /// The command is waiting for a password typed by the user in the terminal.
+    /// Capture is paused and the terminal is unlocked until they press enter.
+    awaiting_password: bool,
+    /// Output captured before the password prompt (the prompt itself is dropped).
+    before_password: String,
+    /// When the command was sent — used for the capture timeout.
+    started: std::time::Instant,
+    /// When progress was last reported to the LLM panel.
+    last_progress: std::time::Instant,
+    /// The terminal's count of finished commands when this one was sent;
+    /// with OSC 133 the next one marks the end of the output exactly.
+    finished: usize,
+}
+
+impl PendingCapture {
+    fn new(snapshot: usize, finished: usize) -> Self {
+        let now = std::time::Instant::now();
+        Self {
+            snapshot,
+            last_line_count: snapshot,
+            last_change: now,
+            awaiting_password: false,
+            before_password: String::new(),
+            started: now,
+            last_progress: now,
+            finished,
+        }
+    }
+}
+
+/// An open connection that is not on screen. Its terminal keeps running
+/// and its assistant keeps polling; F3 brings it back.
+struct Session {
+    name: String,
+    terminal: TerminalTab,
+    llm: LLMTab,
+    focus: ConnectedFocus,
 }
 
 struct Sheesh {
@@ -45,31 +104,235 @@ struct Sheesh {
     listing: ListingTab,
     terminal: Option<TerminalTab>,
     llm: Option<LLMTab>,
+    /// Names of the config file's profiles, and the one in use.
+    profiles: Vec<String>,
+    profile: Option<String>,
+    /// SSH config the listing reads and writes.
+    ssh_path: PathBuf,
     llm_config: LLMConfig,
-    error: Option<String>,
+    tool_config: ToolConfig,
+    ui_config: UiConfig,
+    hooks: Hooks,
+    /// Errors waiting to be shown.
+    notices: Notices,
+    /// Row the error banners cover (the footer).
+    banner_area: Rect,
     /// Last known areas for the two connected panels — used for mouse click focus.
     terminal_area: Rect,
     llm_area: Rect,
     /// Pending terminal output capture for an in-flight tool call.
     pending_capture: Option<PendingCapture>,
+    /// Other open sessions, in the order F3 cycles through them. `terminal`
+    /// and `llm` hold the session on screen.
+    background: Vec<Session>,
+    /// Aliases the assistant's `open_session` tool asked to open.
+    open_tx: mpsc::Sender<OpenRequest>,
+    open_rx: mpsc::Receiver<OpenRequest>,
+    /// F6 was pressed: write the focused panel to a file after the next draw.
+    export_requested: bool,
+    /// Show the focused panel as plain text (F9).
+    plain_view: bool,
+    /// Colors the host terminal shows, from `[ui] colors` or detected.
+    color_depth: ColorDepth,
+    /// Right-click menu of the focused panel, while open.
+    menu: Option<ContextMenu<MenuAction>>,
+    /// Where to save the terminal's scrollback (F7), while asking.
+    save_prompt: Option<SavePrompt>,
+    /// Quit confirmation, while it is up.
+    quit_prompt: Option<QuitPrompt>,
+    /// Asks about a key ssh-agent doesn't hold before connecting.
+    agent_prompt: Option<AgentPrompt>,
+    /// The screen was left for a program of its own (`ssh-add`) and is
+    /// drawn again from scratch.
+    redraw_requested: bool,
+    /// The F5 explanation next to the terminal selection, while it is up.
+    explanation: Option<Popover>,
+    /// The clipboard history (F12), while it is open.
+    snippets: Option<SnippetPicker>,
+    /// The session's notes (F11), while they are open.
+    notes: Option<NotesEditor>,
+    /// Last key, click or paste, for the idle lock.
+    last_input: Instant,
+    /// Hides the sessions after `idle_lock_minutes` without input.
+    idle_lock: Option<IdleLock>,
+}
+
+/// Entries of the right-click menu.
+#[derive(Debug, Clone, Copy)]
+enum MenuAction {
+    Copy,
+    Paste,
+    /// Quote the terminal selection into the LLM input.
+    SendToLlm,
+    /// Ask the LLM to explain the terminal selection.
+    Explain,
+    ClearBuffer,
+    ClearInput,
+    Find,
+    SaveScrollback,
+    CopyLastOutput,
+    /// Quote the last command and its output into the LLM input.
+    SendLastOutput,
+    ClipboardHistory,
+    Notes,
+    /// Have the LLM write up the session as a runbook (`/runbook`).
+    Runbook,
 }
 
 impl Sheesh {
-    fn new(connections: Vec<ssh::SSHConnection>, llm_config: LLMConfig) -> Self {
+    fn new(config: ConfigFile, profile: Option<String>) -> Self {
+        let (open_tx, open_rx) = mpsc::channel();
+        let (ssh_path, listing) = config.listing();
+        llm::limit::configure(&config.llm);
         Self {
             state: AppState::Listing,
-            listing: ListingTab::new(connections),
+            listing,
             terminal: None,
             llm: None,
-            llm_config,
+            profiles: config.profile_names(),
+            profile,
+            ssh_path,
+            llm_config: config.llm,
+            tool_config: config.tools,
+            plain_view: config.ui.screen_reader,
+            color_depth: config.ui.colors.resolve(),
+            ui_config: config.ui,
+            hooks: config.hooks,
             terminal_area: Rect::default(),
             llm_area: Rect::default(),
-            error: None,
+            notices: Notices::default(),
+            banner_area: Rect::default(),
             pending_capture: None,
+            background: vec![],
+            open_tx,
+            open_rx,
+            export_requested: false,
+            agent_prompt: None,
+            redraw_requested: false,
+            menu: None,
+            save_prompt: None,
+            quit_prompt: None,
+            explanation: None,
+            snippets: None,
+            notes: None,
+            last_input: Instant::now(),
+            idle_lock: None,
         }
     }
 
+    /// Reload the config with the next profile (after the last one, none).
+    /// Sessions already open keep the settings they were opened with.
+    fn switch_profile(&mut self) {
+        if self.profiles.is_empty() {
+            self.notices.error("No [profile.<name>] sections in config.toml.");
+            return;
+        }
+        let next = match &self.profile {
+            None => self.profiles.first(),
+            Some(current) => self.profiles.iter().skip_while(|p| *p != current).nth(1),
+        };
+        let profile = next.cloned();
+        // A broken file leaves the current settings in place.
+        let (config, error) = match load_config(profile.as_deref()) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.notices.fatal(format!("{:#}", e));
+                return;
+            }
+        };
+        llm::limit::configure(&config.llm);
+        (self.ssh_path, self.listing) = config.listing();
+        self.profiles = config.profile_names();
+        self.llm_config = config.llm;
+        self.tool_config = config.tools;
+        if config.ui.mouse != self.ui_config.mouse {
+            set_mouse_capture(config.ui.mouse);
+        }
+        self.plain_view = config.ui.screen_reader;
+        self.color_depth = config.ui.colors.resolve();
+        self.ui_config = config.ui;
+        self.hooks = config.hooks;
+        self.profile = profile;
+        self.report_config(error);
+    }
+
+    /// Show a config diagnostic as a popup, or if there is none, whatever
+    /// is wrong with a local LLM server as a banner.
+    fn report_config(&mut self, error: Option<String>) {
+        match error {
+            Some(error) => self.notices.fatal(error),
+            None => {
+                if let Some(error) = llm::local::health_check(&self.llm_config) {
+                    self.notices.error(error);
+                }
+            }
+        }
+    }
+
+    /// Connect to the listing's selection, first offering to add its key
+    /// to ssh-agent when the agent runs without it.
+    fn connect_selected(&mut self) {
+        let Some(conn) = self.listing.selected_connection() else {
+            return;
+        };
+        let (name, identity) = (conn.name.clone(), conn.effective_identity_file().map(String::from));
+        let ssh = conn.transport.is_ssh();
+        self.listing.refresh_agent();
+        match identity {
+            Some(identity) if ssh && self.listing.agent.holds(&identity) == Some(false) => {
+                self.agent_prompt = Some(AgentPrompt { name, identity });
+            }
+            _ => self.connect(name),
+        }
+    }
+
+    /// Run `ssh-add` for the prompt's key on the terminal sheesh runs in,
+    /// then connect if it worked.
+    fn add_key_and_connect(&mut self, prompt: AgentPrompt) {
+        let _ = disable_raw_mode();
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        println!("Adding {} to ssh-agent for {}.", prompt.identity, prompt.name);
+        let result = agent::add(&prompt.identity);
+        let _ = execute!(std::io::stdout(), EnterAlternateScreen);
+        let _ = enable_raw_mode();
+        set_mouse_capture(self.ui_config.mouse);
+        self.redraw_requested = true;
+        self.listing.refresh_agent();
+        match result {
+            Ok(()) => self.connect(prompt.name),
+            Err(e) => self.notices.error(format!("{:#}", e)),
+        }
+    }
+
+    /// Connect to `name` and put it on screen; the current session, if any,
+    /// moves to the background.
     fn connect(&mut self, name: String) {
+        let Ok((terminal, llm)) = self.open(&name) else {
+            return;
+        };
+        self.park_active();
+        self.activate(Session { name, terminal, llm, focus: ConnectedFocus::Terminal });
+    }
+
+    /// Connect to `name` next to the session on screen, for the assistant's
+    /// `open_session` tool. The user switches to it with F3. The error is
+    /// the one shown to the user.
+    fn open_background(&mut self, name: String) -> Result<(), String> {
+        let (terminal, llm) = self.open(&name)?;
+        let session = Session { name, terminal, llm, focus: ConnectedFocus::Terminal };
+        if self.state == AppState::Listing {
+            self.activate(session);
+            return Ok(());
+        }
+        if let Some(active) = &mut self.llm {
+            active.status = format!("Opened a session to {} — F3 to switch.", session.name);
+        }
+        self.background.push(session);
+        Ok(())
+    }
+
+    /// Start the terminal and assistant for connection `name`.
+    fn open(&mut self, name: &str) -> Result<(TerminalTab, LLMTab), String> {
         let conn = self
             .listing
             .connections
@@ -78,42 +341,486 @@ impl Sheesh {
             .cloned();
 
         let Some(conn) = conn else {
-            self.error = Some(format!("Connection '{}' not found", name));
-            return;
+            let error = format!("Connection '{}' not found", name);
+            self.notices.fatal(error.clone());
+            return Err(error);
         };
 
-        let terminal = match TerminalTab::connect(&conn) {
+        let mut terminal = match TerminalTab::connect(&conn) {
             Ok(t) => t,
             Err(e) => {
-                // PTY could not be opened at the OS level — show a terse error
-                self.error = Some(format!("PTY error: {}", e));
-                return;
+                // The PTY or serial device could not be opened — show a terse error
+                self.notices.fatal(format!("Could not open '{}': {:#}", name, e));
+                self.hooks.run(HookEvent::Error, &conn, &[("SHEESH_ERROR", format!("{:#}", e))]);
+                return Err(format!("{:#}", e));
             }
         };
 
-        let provider = build_provider(&self.llm_config);
+        // `[llm.connections]` may turn the assistant off or hold it to one model.
+        let llm_config = self.llm_config.for_connection(&conn.name);
+        let disabled = llm_config.is_none();
+        let llm_config = llm_config.unwrap_or_else(|| self.llm_config.clone());
+        // Not built without an assistant, so nothing looks for its key.
+        let provider: Arc<dyn LLMProvider> = if disabled { Arc::new(Disabled) } else { build_provider(&llm_config) };
+        let system_prompt = llm_config.system_prompt().unwrap_or_else(|e| {
+            log::warn!("[llm] {:#}", e);
+            self.notices.error(format!("{:#}", e));
+            llm_config.system_prompt.clone()
+        });
         let output_log = terminal.output_log_arc();
-        self.terminal = Some(terminal);
-        let mut llm = LLMTab::new(
-            provider,
-            self.llm_config.system_prompt.clone(),
-            conn.clone(),
-        );
+        let mut llm = LLMTab::new(provider, system_prompt, conn.clone());
+        if disabled {
+            llm.disable();
+        }
         llm.set_terminal_output(output_log);
-        self.llm = Some(llm);
+        llm.set_plan_policy(self.tool_config.plan_policy);
+        llm.set_download_dir(self.tool_config.download_dir());
+        llm.set_notes_dir(self.tool_config.notes_dir());
+        llm.set_output_limits(self.tool_config.output_limits());
+        let tools = self.tool_config.registry_for(&conn.name, &self.open_tx);
+        llm.set_tools(tools.observe(self.hooks.tool_observer(&conn)).observe(metrics::tool_observer()));
+        llm.set_hooks(self.hooks.clone());
+        if self.tool_config.guarded(&conn.name) {
+            llm.set_guard(true);
+            terminal.add_warning(format!(
+                "⚠ Guarded host {}: nothing runs without a keypress, and commands and writes need its name typed.",
+                conn.name
+            ));
+        }
+        llm.set_host_facts(inventory::load(&conn.name));
+        match notes::load(&self.tool_config.notes_dir(), &conn.name) {
+            Ok(notes) => llm.set_notes(notes.for_assistant()),
+            Err(e) => log::warn!("[notes] {:#}", e),
+        }
+        if !disabled {
+            match chats::load(&conn.name) {
+                Ok(chat) => llm.restore_chat(chat),
+                Err(e) => log::warn!("[chats] {:#}", e),
+            }
+        }
+        if self.tool_config.detect_platform && !self.tool_config.guarded(&conn.name) {
+            llm.detect_platform();
+        }
+        llm.set_llm_config(llm_config);
+        llm.set_ui_config(&self.ui_config);
+        terminal.set_ui_config(&self.ui_config);
+        if let Some(route) = RouteStatus::new(&conn, &self.listing.connections)
+            && conn.backend == Backend::System
+            && !terminal.reattached()
+        {
+            terminal.set_route(route);
+        }
+        if terminal.reattached() {
+            llm.status = "Reattached to the session left running; its recent output is restored.".into();
+        }
+        metrics::session_opened();
+        llm.run_hook(HookEvent::Connect, &[("SHEESH_REATTACHED", terminal.reattached().to_string())]);
+        Ok((terminal, llm))
+    }
+
+    /// `sheesh attach`: reopen every session left running when sheesh quit.
+    #[cfg(unix)]
+    fn attach_detached(&mut self) {
+        let names = daemon::detached(&self.listing.connections);
+        if names.is_empty() {
+            self.notices.error("No sessions were left running.");
+        }
+        // Failures are already on screen.
+        for name in names {
+            let _ = self.open_background(name);
+        }
+    }
+
+    /// End every open session, their holders included.
+    fn close_sessions(&mut self) {
+        let others = self.background.iter_mut().map(|s| &mut s.terminal);
+        self.terminal.iter_mut().chain(others).for_each(TerminalTab::close);
+        let others = self.background.iter().map(|s| &s.llm);
+        for llm in self.llm.iter().chain(others) {
+            llm.run_hook(HookEvent::Disconnect, &[]);
+        }
+    }
+
+    /// Move the session on screen to the back of the background list.
+    fn park_active(&mut self) {
+        if let AppState::Connected { connection_name, focus } = self.state.clone()
+            && let (Some(terminal), Some(llm)) = (self.terminal.take(), self.llm.take())
+        {
+            self.background.push(Session { name: connection_name, terminal, llm, focus });
+        }
+        self.explanation = None;
+    }
+
+    fn activate(&mut self, session: Session) {
+        self.terminal = Some(session.terminal);
+        self.llm = Some(session.llm);
         self.state = AppState::Connected {
-            connection_name: name,
-            focus: ConnectedFocus::Terminal,
+            connection_name: session.name,
+            focus: session.focus,
+        };
+    }
+
+    /// Put the next background session on screen (F3). Not while a tool
+    /// command's output is being captured, which needs its terminal visible.
+    fn next_session(&mut self) {
+        if self.background.is_empty() {
+            return;
+        }
+        if self.pending_capture.is_some() {
+            if let Some(llm) = &mut self.llm {
+                llm.status = "Finish or cancel the running command before switching sessions.".into();
+            }
+            return;
+        }
+        self.park_active();
+        let next = self.background.remove(0);
+        self.activate(next);
+    }
+
+    /// Write what the focused panel shows in `buffer` (the frame just drawn)
+    /// to the download directory.
+    fn export_view(&mut self, buffer: &ratatui::buffer::Buffer) {
+        let AppState::Connected { connection_name, focus } = &self.state else {
+            return;
+        };
+        let (area, panel) = match focus {
+            ConnectedFocus::Terminal => (self.terminal_area, "terminal"),
+            ConnectedFocus::LLM => (self.llm_area, "chat"),
+        };
+        let label = format!("{}-{}", connection_name, panel);
+        match export::export_view(buffer, area, &self.tool_config.download_dir(), &label) {
+            Ok((ansi, html)) => {
+                if let Some(llm) = &mut self.llm {
+                    llm.status = format!("Exported the {} view to {} and {}", panel, ansi.display(), html.display());
+                }
+            }
+            Err(e) => self.notices.error(format!("Export failed: {:#}", e)),
+        }
+    }
+
+    /// Ask where to save the terminal's scrollback.
+    fn prompt_save_scrollback(&mut self) {
+        let AppState::Connected { connection_name, .. } = &self.state else {
+            return;
+        };
+        let stamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let file = format!("sheesh-{}-scrollback-{}.txt", connection_name, stamp);
+        let path = self.tool_config.download_dir().join(file);
+        self.save_prompt = Some(SavePrompt::new(path.display().to_string()));
+    }
+
+    /// F5: ask the LLM about the terminal selection and show the answer in
+    /// a popover next to it, as well as in the chat. With nothing selected,
+    /// ask for a fix for the error the terminal shows, if any, and switch
+    /// to the assistant, where F4 runs the suggested command.
+    fn explain_selection(&mut self) {
+        let (Some(terminal), Some(llm)) = (&self.terminal, &mut self.llm) else {
+            return;
+        };
+        if llm.is_disabled() {
+            self.notices.error("The assistant is turned off for this connection.");
+            return;
+        }
+        match (terminal.selected_text(), terminal.selection_anchor()) {
+            (Some(text), Some(anchor)) => {
+                if llm.explain(&text) {
+                    self.explanation = Some(Popover::new("Explanation", anchor));
+                }
+            }
+            _ if llm.problem().is_some() => {
+                if llm.quick_fix()
+                    && let AppState::Connected { focus, .. } = &mut self.state
+                {
+                    *focus = ConnectedFocus::LLM;
+                }
+            }
+            _ => llm.status = "Select text in the terminal first (drag, or F8) to have it explained.".into(),
+        }
+    }
+
+    fn save_scrollback(&mut self, prompt: SavePrompt) {
+        let Some(terminal) = &self.terminal else {
+            return;
         };
+        let (lines, width) = terminal.all_rows();
+        let path = config::expand_home(prompt.path.trim());
+        match export::write_lines(&lines, width, &path, prompt.ansi) {
+            Ok(()) => {
+                if let Some(llm) = &mut self.llm {
+                    llm.status = format!("Saved {} lines of scrollback to {}", lines.len(), path.display());
+                }
+            }
+            Err(e) => self.notices.error(format!("Saving the scrollback failed: {:#}", e)),
+        }
     }
 
+    /// Open the right-click menu of the focused panel at (`col`, `row`).
+    fn open_menu(&mut self, col: u16, row: u16) {
+        let AppState::Connected { focus, .. } = &self.state else {
+            return;
+        };
+        let mut items = vec![];
+        match focus {
+            ConnectedFocus::Terminal => {
+                let Some(terminal) = &self.terminal else {
+                    return;
+                };
+                let assistant = self.llm.as_ref().is_some_and(|l| !l.is_disabled());
+                if terminal.has_selection() {
+                    items.push(("Copy", MenuAction::Copy));
+                }
+                if terminal.has_selection() && assistant {
+                    items.push(("Send selection to LLM", MenuAction::SendToLlm));
+                    items.push(("Explain selection", MenuAction::Explain));
+                }
+                if !terminal.is_locked() {
+                    items.push(("Paste", MenuAction::Paste));
+                    items.push(("Clear buffer", MenuAction::ClearBuffer));
+                }
+                if terminal.last_command_output().is_some() {
+                    items.push(("Copy last command output", MenuAction::CopyLastOutput));
+                }
+                if terminal.last_command_output().is_some() && assistant {
+                    items.push(("Send last command output to LLM", MenuAction::SendLastOutput));
+                }
+                items.push(("Find…", MenuAction::Find));
+                items.push(("Save scrollback…", MenuAction::SaveScrollback));
+                items.push(("Clipboard history…", MenuAction::ClipboardHistory));
+                items.push(("Notes…", MenuAction::Notes));
+            }
+            ConnectedFocus::LLM => {
+                let Some(llm) = &self.llm else {
+                    return;
+                };
+                if llm.has_selection() {
+                    items.push(("Copy", MenuAction::Copy));
+                }
+                items.push(("Paste", MenuAction::Paste));
+                if !llm.input.is_empty() {
+                    items.push(("Clear input", MenuAction::ClearInput));
+                }
+                items.push(("Find…", MenuAction::Find));
+                if !llm.waiting {
+                    items.push(("Write a runbook of this session", MenuAction::Runbook));
+                }
+                items.push(("Clipboard history…", MenuAction::ClipboardHistory));
+                items.push(("Notes…", MenuAction::Notes));
+            }
+        }
+        if !items.is_empty() {
+            self.menu = Some(ContextMenu::new(items, col, row, self.terminal_area.union(self.llm_area)));
+        }
+    }
+
+    /// Show what was copied and applied so far, if anything was.
+    fn open_snippets(&mut self) {
+        match SnippetPicker::new() {
+            Some(picker) => self.snippets = Some(picker),
+            None => self.notices.error("Nothing has been copied or applied yet."),
+        }
+    }
+
+    /// Hide the sessions once nothing has been typed or clicked for
+    /// `idle_lock_minutes`, pausing every auto-approval until unlocked.
+    fn check_idle(&mut self) {
+        let minutes = self.ui_config.idle_lock_minutes;
+        if minutes == 0
+            || self.idle_lock.is_some()
+            || !matches!(self.state, AppState::Connected { .. })
+            || self.last_input.elapsed() < Duration::from_secs(minutes * 60)
+        {
+            return;
+        }
+        let llms = self.llm.iter_mut().chain(self.background.iter_mut().map(|s| &mut s.llm));
+        let paused = llms.map(|l| l.pause_auto_approve()).filter(|paused| *paused).count();
+        let passphrase = self.ui_config.idle_passphrase_env.as_deref().and_then(|name| {
+            let value = std::env::var(name).ok().filter(|v| !v.is_empty());
+            if value.is_none() {
+                log::warn!("[ui] idle_passphrase_env: {} is not set; enter unlocks", name);
+            }
+            value
+        });
+        self.idle_lock = Some(IdleLock::new(minutes, passphrase, paused));
+    }
+
+    fn unlock(&mut self) {
+        self.idle_lock = None;
+        self.last_input = Instant::now();
+        let llms = self.llm.iter_mut().chain(self.background.iter_mut().map(|s| &mut s.llm));
+        llms.for_each(LLMTab::resume_auto_approve);
+    }
+
+    /// Open the notes of the session on screen.
+    fn open_notes(&mut self) {
+        if let AppState::Connected { connection_name, .. } = &self.state {
+            match notes::load(&self.tool_config.notes_dir(), connection_name) {
+                Ok(notes) => self.notes = Some(NotesEditor::new(connection_name, notes)),
+                Err(e) => self.notices.error(format!("Could not open the notes: {:#}", e)),
+            }
+        }
+    }
+
+    /// Keep the notes as written and tell the session's assistant, which
+    /// sees them if they are shared.
+    fn close_notes(&mut self) {
+        let Some(editor) = self.notes.take() else {
+            return;
+        };
+        let notes = editor.notes();
+        if let Err(e) = notes::save(&self.tool_config.notes_dir(), editor.alias(), &notes) {
+            self.notices.error(format!("Could not save the notes: {:#}", e));
+        }
+        if let Some(llm) = &mut self.llm {
+            llm.set_notes(notes.for_assistant());
+        }
+    }
+
+    /// Copy a snippet from the history again, or send it to the terminal.
+    fn use_snippet(&mut self, action: SnippetAction) {
+        match action {
+            SnippetAction::Copy(text) => {
+                if let Some(terminal) = &mut self.terminal {
+                    terminal.copy_text(text);
+                }
+            }
+            SnippetAction::Apply(text) => {
+                clipboard::remember(&text, SnippetKind::Applied);
+                self.type_in_terminal(&text);
+            }
+        }
+    }
+
+    fn open_menu_by_key(&mut self) {
+        let area = match &self.state {
+            AppState::Connected { focus: ConnectedFocus::Terminal, .. } => self.terminal_area,
+            AppState::Connected { focus: ConnectedFocus::LLM, .. } => self.llm_area,
+            AppState::Listing => return,
+        };
+        self.open_menu(area.x + 2, area.y + 1);
+    }
+
+    fn run_menu_action(&mut self, action: MenuAction) {
+        let AppState::Connected { focus, .. } = &self.state else {
+            return;
+        };
+        match (*focus, action) {
+            (ConnectedFocus::Terminal, MenuAction::Copy) => self.terminal.as_mut().map(|t| t.copy_selection()),
+            (ConnectedFocus::Terminal, MenuAction::Paste) => self.terminal.as_mut().map(|t| t.paste_from_clipboard()),
+            (ConnectedFocus::Terminal, MenuAction::ClearBuffer) => self.terminal.as_mut().map(|t| t.clear_buffer()),
+            (ConnectedFocus::Terminal, MenuAction::SendToLlm) => {
+                if let (Some(terminal), Some(llm)) = (&self.terminal, &mut self.llm)
+                    && let Some(text) = terminal.selected_text()
+                {
+                    llm.quote_into_input(&text);
+                    self.cycle_focus();
+                }
+                None
+            }
+            (ConnectedFocus::Terminal, MenuAction::Explain) => {
+                self.explain_selection();
+                None
+            }
+            (ConnectedFocus::Terminal, MenuAction::CopyLastOutput) => {
+                self.terminal.as_mut().map(|t| t.copy_last_command_output())
+            }
+            (ConnectedFocus::Terminal, MenuAction::SendLastOutput) => {
+                if let (Some(terminal), Some(llm)) = (&self.terminal, &mut self.llm)
+                    && let Some(done) = terminal.last_command_output()
+                {
+                    let status = done.exit_status.map(|s| format!("\n[exit status {}]", s)).unwrap_or_default();
+                    llm.quote_into_input(&format!("$ {}\n{}{}", done.command, done.output, status));
+                    self.cycle_focus();
+                }
+                None
+            }
+            (ConnectedFocus::Terminal, MenuAction::Find) => self.terminal.as_mut().map(|t| t.open_find()),
+            (ConnectedFocus::LLM, MenuAction::Copy) => self.llm.as_mut().map(|l| l.copy_selection()),
+            (ConnectedFocus::LLM, MenuAction::Paste) => self.llm.as_mut().map(|l| l.paste_into_input()),
+            (ConnectedFocus::LLM, MenuAction::ClearInput) => self.llm.as_mut().map(|l| l.input.clear()),
+            (ConnectedFocus::LLM, MenuAction::Find) => self.llm.as_mut().map(|l| l.open_find()),
+            (ConnectedFocus::LLM, MenuAction::Runbook) => self.llm.as_mut().map(|l| l.send_message("/runbook".into())),
+            (_, MenuAction::SaveScrollback) => {
+                self.prompt_save_scrollback();
+                None
+            }
+            (_, MenuAction::ClipboardHistory) => {
+                self.open_snippets();
+                None
+            }
+            (_, MenuAction::Notes) => {
+                self.open_notes();
+                None
+            }
+            _ => None,
+        };
+    }
+
+    /// Close the session on screen and show the next one, or the connection
+    /// list when it was the last.
     fn disconnect(&mut self) {
+        // ssh refused the host over a changed key: go to the recorded keys
+        // rather than leave the user with its warning.
+        let mut key_changed = None;
+        if let Some(terminal) = &mut self.terminal {
+            if let AppState::Connected { connection_name, .. } = &self.state
+                && !terminal.is_alive()
+                && terminal.plain_text().iter().any(|line| line.contains(known_hosts::CHANGED))
+            {
+                key_changed = Some(connection_name.clone());
+            }
+            terminal.close();
+        }
+        if let Some(llm) = self.llm.take() {
+            llm.save_chat();
+            llm.run_hook(HookEvent::Disconnect, &[]);
+            if let Some(name) = &key_changed {
+                llm.run_hook(HookEvent::Error, &[("SHEESH_ERROR", format!("{}'s host key has changed", name))]);
+            }
+        }
         self.terminal = None;
-        self.llm = None;
-        self.state = AppState::Listing;
+        self.pending_capture = None;
+        if self.background.is_empty() {
+            self.state = AppState::Listing;
+            self.listing.refresh_agent();
+            if let Some(name) = key_changed {
+                self.listing.select(&name);
+                self.listing.open_known_hosts();
+            }
+        } else {
+            if let Some(name) = key_changed {
+                self.notices.error(format!(
+                    "{}'s host key has changed. Press h on it in the connection list to see the recorded keys.",
+                    name
+                ));
+            }
+            let next = self.background.remove(0);
+            self.activate(next);
+        }
+    }
+
+    /// Ask before quitting with sessions open. Returns false to quit now.
+    fn confirm_quit(&mut self) -> bool {
+        let mut sessions = vec![];
+        if let (AppState::Connected { connection_name, .. }, Some(terminal), Some(llm)) =
+            (&self.state, &self.terminal, &self.llm)
+        {
+            sessions.push(session_summary(connection_name, terminal, llm, self.pending_capture.is_some()));
+        }
+        for s in &self.background {
+            sessions.push(session_summary(&s.name, &s.terminal, &s.llm, false));
+        }
+        if sessions.is_empty() {
+            return false;
+        }
+        let detachable = self.terminal.iter().chain(self.background.iter().map(|s| &s.terminal)).any(|t| t.is_detachable());
+        self.quit_prompt = Some(QuitPrompt { sessions, detachable });
+        true
     }
 
     fn cycle_focus(&mut self) {
+        if self.llm.as_ref().is_some_and(LLMTab::is_disabled) {
+            return;
+        }
         if let AppState::Connected { ref mut focus, .. } = self.state {
             *focus = match focus {
                 ConnectedFocus::Terminal => ConnectedFocus::LLM,
@@ -125,14 +832,150 @@ impl Sheesh {
     fn handle_event(&mut self, event: &crossterm::event::Event) -> bool {
         use crossterm::event::{KeyCode, KeyEvent};
 
-        // Dismiss error on any key
-        if self.error.is_some() {
-            self.error = None;
+        if let crossterm::event::Event::Resize(cols, rows) = *event {
+            self.resize(cols, rows);
+            return true;
+        }
+
+        if let Some(lock) = &mut self.idle_lock {
+            if let crossterm::event::Event::Key(key) = event
+                && lock.handle_key(key)
+            {
+                self.unlock();
+            }
+            return true;
+        }
+        if matches!(
+            event,
+            crossterm::event::Event::Key(_) | crossterm::event::Event::Mouse(_) | crossterm::event::Event::Paste(_)
+        ) {
+            self.last_input = Instant::now();
+        }
+
+        if self.notices.handle_event(event, self.banner_area) {
+            return true;
+        }
+
+        if let Some(prompt) = &self.quit_prompt {
+            if let crossterm::event::Event::Key(key) = event {
+                match prompt.handle_key(key) {
+                    Some(PromptKey::Confirm) => {
+                        self.close_sessions();
+                        return false;
+                    }
+                    Some(PromptKey::Detach) => return false,
+                    Some(PromptKey::Cancel) => self.quit_prompt = None,
+                    None => {}
+                }
+            }
+            return true;
+        }
+
+        if let Some(menu) = &mut self.menu {
+            match menu.handle_event(event) {
+                MenuOutcome::Open => {}
+                MenuOutcome::Chosen(action) => {
+                    self.menu = None;
+                    self.run_menu_action(action);
+                }
+                MenuOutcome::Closed => self.menu = None,
+            }
+            return true;
+        }
+
+        if let Some(picker) = &mut self.snippets {
+            match picker.handle_event(event) {
+                MenuOutcome::Open => {}
+                MenuOutcome::Chosen(action) => {
+                    self.snippets = None;
+                    self.use_snippet(action);
+                }
+                MenuOutcome::Closed => self.snippets = None,
+            }
+            return true;
+        }
+
+        if let Some(editor) = &mut self.notes {
+            if !editor.handle_event(event) {
+                self.close_notes();
+            }
+            return true;
+        }
+
+        if let Some(prompt) = &self.agent_prompt {
+            if let crossterm::event::Event::Key(key) = event {
+                match prompt.handle_key(key) {
+                    Some(AgentChoice::Add) => {
+                        let prompt = self.agent_prompt.take().unwrap();
+                        self.add_key_and_connect(prompt);
+                    }
+                    Some(AgentChoice::Connect) => {
+                        let prompt = self.agent_prompt.take().unwrap();
+                        self.connect(prompt.name);
+                    }
+                    Some(AgentChoice::Cancel) => self.agent_prompt = None,
+                    None => {}
+                }
+            }
             return true;
         }
 
+        if let Some(prompt) = &mut self.save_prompt {
+            if let crossterm::event::Event::Key(key) = event {
+                match prompt.handle_key(key) {
+                    Some(PromptKey::Confirm) => {
+                        let prompt = self.save_prompt.take().unwrap();
+                        self.save_scrollback(prompt);
+                    }
+                    Some(PromptKey::Cancel) => self.save_prompt = None,
+                    Some(PromptKey::Detach) | None => {}
+                }
+            }
+            return true;
+        }
+
+        // The explanation popover closes on the next key or click; Esc
+        // goes no further, anything else still does what it does.
+        if self.explanation.is_some() {
+            match event {
+                crossterm::event::Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
+                    self.explanation = None;
+                    return true;
+                }
+                crossterm::event::Event::Key(_) => self.explanation = None,
+                crossterm::event::Event::Mouse(me) if matches!(me.kind, MouseEventKind::Down(_)) => {
+                    self.explanation = None
+                }
+                _ => {}
+            }
+        }
+
         if let AppState::Connected { .. } = &self.state {
             match event {
+                // Right click — panel menu (copy, paste, …)
+                crossterm::event::Event::Mouse(me) if me.kind == MouseEventKind::Down(MouseButton::Right) => {
+                    if let AppState::Connected { ref mut focus, .. } = self.state {
+                        if contains(self.terminal_area, me.column, me.row) {
+                            *focus = ConnectedFocus::Terminal;
+                        } else if contains(self.llm_area, me.column, me.row) {
+                            *focus = ConnectedFocus::LLM;
+                        }
+                    }
+                    self.open_menu(me.column, me.row);
+                    return true;
+                }
+                // Shift+F10 or the Menu key — the right-click menu, at the
+                // focused panel's top-left corner.
+                crossterm::event::Event::Key(KeyEvent { code: KeyCode::F(10), modifiers, .. })
+                    if modifiers.contains(crossterm::event::KeyModifiers::SHIFT) =>
+                {
+                    self.open_menu_by_key();
+                    return true;
+                }
+                crossterm::event::Event::Key(KeyEvent { code: KeyCode::Menu, .. }) => {
+                    self.open_menu_by_key();
+                    return true;
+                }
                 // F2 — toggle between terminal and LLM
                 crossterm::event::Event::Key(KeyEvent {
                     code: KeyCode::F(2),
@@ -141,11 +984,95 @@ impl Sheesh {
                     self.cycle_focus();
                     return true;
                 }
+                // F3 — next open session
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(3),
+                    ..
+                }) => {
+                    self.next_session();
+                    return true;
+                }
+                // F5 — explain the terminal selection
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(5),
+                    ..
+                }) => {
+                    self.explain_selection();
+                    return true;
+                }
+                // F6 — export the focused panel as ANSI and HTML
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(6),
+                    ..
+                }) => {
+                    self.export_requested = true;
+                    return true;
+                }
+                // F9 — switch between the panels and the plain view
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(9),
+                    ..
+                }) => {
+                    self.plain_view = !self.plain_view;
+                    return true;
+                }
+                // F7 — save the terminal's whole scrollback to a file
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(7),
+                    ..
+                }) => {
+                    self.prompt_save_scrollback();
+                    return true;
+                }
+                // F12 — clipboard history
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(12),
+                    ..
+                }) => {
+                    self.open_snippets();
+                    return true;
+                }
+                // F11 — the connection's notes
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::F(11),
+                    ..
+                }) => {
+                    self.open_notes();
+                    return true;
+                }
+                // While a tool command's output is being captured:
+                // ctrl+f sends what has been captured so far, and ctrl+c in the
+                // (locked) terminal interrupts the command and cancels the call.
+                crossterm::event::Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ ('f' | 'c')),
+                    modifiers,
+                    ..
+                }) if self.pending_capture.is_some()
+                    && modifiers.contains(crossterm::event::KeyModifiers::CONTROL) =>
+                {
+                    let terminal_focused = matches!(
+                        self.state,
+                        AppState::Connected { focus: ConnectedFocus::Terminal, .. }
+                    );
+                    match c {
+                        'f' => {
+                            self.finish_capture(Some(
+                                "The user ended output capture early; the command may still be running.".into(),
+                            ));
+                            return true;
+                        }
+                        _ if terminal_focused => {
+                            self.cancel_capture();
+                            return true;
+                        }
+                        _ => {}
+                    }
+                }
                 // Mouse click — focus the panel that was clicked.
                 // Do NOT return early for the terminal panel so the click also
                 // reaches the terminal handler to start a text selection.
                 crossterm::event::Event::Mouse(me)
-                    if me.kind == MouseEventKind::Down(MouseButton::Left) =>
+                    if matches!(me.kind, MouseEventKind::Down(MouseButton::Left | MouseButton::Middle)) =>
                 {
                     let col = me.column;
                     let row = me.row;
@@ -166,29 +1093,38 @@ impl Sheesh {
             }
         }
 
-        match &self.state.clone() {
-            AppState::Listing => {
+        let focus = match &self.state {
+            AppState::Listing => None,
+            AppState::Connected { focus, .. } => Some(*focus),
+        };
+        match focus {
+            None => {
                 let action = self.listing.handle_event(event);
                 match action {
                     Action::Quit => return false,
-                    Action::Confirm => {
-                        if let Some(conn) = self.listing.selected_connection() {
-                            let name = conn.name.clone();
-                            self.connect(name);
-                        }
+                    Action::Confirm => self.connect_selected(),
+                    Action::SwitchProfile => {
+                        self.switch_profile();
+                        return true;
                     }
                     _ => {}
                 }
-                let _ = save_connections(&ssh_config_path(), &self.listing.connections);
+                if !self.listing.read_only {
+                    let _ = save_connections(&self.ssh_path, &self.listing.connections);
+                }
             }
 
-            AppState::Connected { focus, .. } => {
+            Some(focus) => {
                 let action = match focus {
-                    ConnectedFocus::Terminal => self
-                        .terminal
-                        .as_mut()
-                        .map(|t| t.handle_event(event))
-                        .unwrap_or(Action::None),
+                    ConnectedFocus::Terminal => {
+                        let action = self
+                            .terminal
+                            .as_mut()
+                            .map(|t| t.handle_event(event))
+                            .unwrap_or(Action::None);
+                        self.finish_password_entry(event);
+                        action
+                    }
                     ConnectedFocus::LLM => self
                         .llm
                         .as_mut()
@@ -197,36 +1133,11 @@ impl Sheesh {
                 };
 
                 match action {
-                    Action::Quit => return false,
+                    Action::Quit => return self.confirm_quit(),
                     Action::Disconnect => self.disconnect(),
-                    Action::CancelToolCall => {
-                        self.pending_capture = None;
-                        if let Some(llm) = &mut self.llm {
-                            llm.cancel_tool_call();
-                        }
-                        if let Some(terminal) = &mut self.terminal {
-                            terminal.set_tool_locked(false);
-                        }
-                    }
-                    Action::SendToTerminal(cmd) => {
-                        if let Some(t) = &mut self.terminal {
-                            let snapshot = t.line_count();
-                            t.send_string(&cmd);
-                            t.send_string("\r");
-                            t.set_tool_locked(true);
-                            // Wait for output to stabilise (300 ms of silence) then
-                            // forward it to Claude. The user can press ctrl+c to cancel.
-                            let now = std::time::Instant::now();
-                            self.pending_capture = Some(PendingCapture {
-                                snapshot,
-                                last_line_count: snapshot,
-                                last_change: now,
-                            });
-                        }
-                        if let AppState::Connected { ref mut focus, .. } = self.state {
-                            *focus = ConnectedFocus::Terminal;
-                        }
-                    }
+                    Action::CancelToolCall => self.cancel_capture(),
+                    Action::SendToTerminal(cmd) => self.send_to_terminal(&cmd),
+                    Action::TypeInTerminal(text) => self.type_in_terminal(&text),
                     _ => {}
                 }
             }
@@ -235,52 +1146,255 @@ impl Sheesh {
         true
     }
 
+    /// Type `cmd` into the terminal and start capturing its output.
+    fn send_to_terminal(&mut self, cmd: &str) {
+        if let Some(t) = &mut self.terminal {
+            let snapshot = t.line_count();
+            let finished = t.finished_commands();
+            t.send_string(cmd);
+            t.send_string("\r");
+            t.set_tool_locked(true);
+            // Wait for the shell to report the command finished (OSC 133),
+            // or for output to stabilise, then forward it to Claude. The
+            // user can press ctrl+c to cancel.
+            self.pending_capture = Some(PendingCapture::new(snapshot, finished));
+        }
+        if let AppState::Connected { ref mut focus, .. } = self.state {
+            *focus = ConnectedFocus::Terminal;
+        }
+    }
+
+    /// Type `text` at the terminal's prompt and focus the terminal, so the
+    /// user can look it over and press Enter.
+    fn type_in_terminal(&mut self, text: &str) {
+        let Some(t) = &mut self.terminal else {
+            return;
+        };
+        if t.is_locked() {
+            self.notices.error("The terminal is locked; try again once it is unlocked.");
+            return;
+        }
+        // A trailing newline would run it after all.
+        t.paste_text(text.trim_end_matches('\n'));
+        if let AppState::Connected { ref mut focus, .. } = self.state {
+            *focus = ConnectedFocus::Terminal;
+        }
+    }
+
+    /// Stop capturing and send the output collected so far as the tool_result,
+    /// resuming the LLM. `note` explains why capture ended early, if it did.
+    fn finish_capture(&mut self, note: Option<String>) {
+        let Some(cap) = self.pending_capture.take() else {
+            return;
+        };
+        if let (Some(terminal), Some(llm)) = (&self.terminal, &mut self.llm)
+            && llm.awaiting_output()
+        {
+            let output = cap.before_password + &terminal.capture_since(cap.snapshot);
+            let status = terminal
+                .last_command_output()
+                .filter(|_| terminal.finished_commands() > cap.finished)
+                .and_then(|done| done.exit_status)
+                .map(|status| format!("Exit status: {}.", status));
+            let note = match (note, status) {
+                (Some(note), Some(status)) => Some(format!("{} {}", note, status)),
+                (note, status) => note.or(status),
+            };
+            llm.resume_with_output(output, note);
+        }
+    }
+
+    /// Interrupt the running tool command with ctrl+c and end the tool call
+    /// without resuming the LLM; the partial output is kept in the tool_result.
+    fn cancel_capture(&mut self) {
+        let partial = match (self.pending_capture.take(), &mut self.terminal) {
+            (Some(cap), Some(terminal)) => {
+                terminal.send_string("\x03");
+                cap.before_password + &terminal.capture_since(cap.snapshot)
+            }
+            _ => String::new(),
+        };
+        if let Some(llm) = &mut self.llm {
+            llm.cancel_tool_call(partial);
+        }
+        if let Some(terminal) = &mut self.terminal {
+            terminal.set_tool_locked(false);
+        }
+    }
+
+    /// Called when a tool command prints a password prompt: pause capture and
+    /// hand the terminal to the user. The password is typed straight into the
+    /// PTY (with echo off), so it never reaches the captured output.
+    fn begin_password_entry(&mut self) {
+        let (Some(cap), Some(terminal)) = (&mut self.pending_capture, &mut self.terminal) else {
+            return;
+        };
+        let output = terminal.capture_since(cap.snapshot);
+        cap.before_password.push_str(before_prompt(&output));
+        cap.awaiting_password = true;
+        terminal.set_tool_locked(false);
+        if let Some(llm) = &mut self.llm {
+            llm.status = "Password requested — type it in the terminal and press enter…".into();
+        }
+        if let AppState::Connected { ref mut focus, .. } = self.state {
+            *focus = ConnectedFocus::Terminal;
+        }
+    }
+
+    /// Resume output capture once the user submits the password with enter.
+    fn finish_password_entry(&mut self, event: &crossterm::event::Event) {
+        use crossterm::event::{Event, KeyCode, KeyEvent};
+        let Event::Key(KeyEvent { code: KeyCode::Enter, .. }) = event else {
+            return;
+        };
+        let (Some(cap), Some(terminal)) = (&mut self.pending_capture, &mut self.terminal) else {
+            return;
+        };
+        if !cap.awaiting_password {
+            return;
+        }
+        let before = std::mem::take(&mut cap.before_password);
+        let started = cap.started;
+        *cap = PendingCapture::new(terminal.line_count(), cap.finished);
+        cap.before_password = before;
+        cap.started = started;
+        terminal.set_tool_locked(true);
+        if let Some(llm) = &mut self.llm {
+            llm.status = "Command sent — capturing output…".into();
+        }
+    }
+
+    /// The window is now `cols` × `rows`. The PTY is sized straight away
+    /// rather than on the next draw, so the remote program starts redrawing
+    /// at once, and an open menu is moved back on screen; prompts and
+    /// notices are centred as they are drawn.
+    /// The terminal's and the assistant's areas of the session on screen.
+    /// Without an assistant the terminal has the whole width.
+    fn session_layout(&self, area: Rect) -> [Rect; 2] {
+        match &self.llm {
+            Some(l) if l.is_disabled() => [area, Rect::default()],
+            _ => connected_layout(area),
+        }
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) {
+        let [_, main_area, _] = screen_layout(Rect::new(0, 0, cols, rows));
+        if matches!(self.state, AppState::Connected { .. }) {
+            let [left_area, _] = self.session_layout(main_area);
+            if let Some(t) = &mut self.terminal {
+                t.fit(left_area);
+            }
+            if let Some(l) = &mut self.llm {
+                l.resized();
+            }
+        }
+        if let Some(menu) = &mut self.menu {
+            menu.keep_within(main_area);
+        }
+    }
+
     fn draw(&mut self, frame: &mut Frame) {
         let area = frame.area();
 
-        // Split into header, main, footer
-        let [header_area, main_area, footer_area] = Layout::vertical([
-            Constraint::Length(1),
-            Constraint::Min(0),
-            Constraint::Length(1),
-        ])
-        .areas(area);
+        let [header_area, main_area, footer_area] = screen_layout(area);
 
         self.render_header(frame, header_area);
         self.render_main(frame, main_area);
         self.render_footer(frame, footer_area);
 
-        if let Some(ref err) = self.error {
-            render_error_popup(frame, area, err);
+        if let Some(popover) = &mut self.explanation {
+            if popover.is_waiting()
+                && let Some(text) = self.llm.as_mut().and_then(LLMTab::take_explanation)
+            {
+                popover.set_text(text);
+            }
+            popover.render(frame, self.terminal_area);
+        }
+        if let Some(menu) = &self.menu {
+            menu.render(frame);
+        }
+        if let Some(picker) = &self.snippets {
+            picker.render(frame, area);
+        }
+        if let Some(editor) = &mut self.notes {
+            editor.render(frame, area);
+        }
+        if let Some(prompt) = &self.save_prompt {
+            prompt.render(frame, area);
+        }
+        if let Some(prompt) = &self.quit_prompt {
+            prompt.render(frame, area);
+        }
+        if let Some(prompt) = &self.agent_prompt {
+            prompt.render(frame, area);
+        }
+        if let Some(lock) = &self.idle_lock {
+            lock.render(frame, main_area);
         }
+
+        self.banner_area = footer_area;
+        self.notices.render(frame, area, footer_area);
+        self.color_depth.downgrade(frame.buffer_mut());
     }
 
     fn render_header(&self, frame: &mut Frame, area: Rect) {
         let title = match &self.state {
-            AppState::Listing => " sheesh ".to_string(),
+            AppState::Listing => match &self.profile {
+                Some(profile) => format!(" sheesh [{}] ", profile),
+                None => " sheesh ".to_string(),
+            },
             AppState::Connected {
                 connection_name, ..
             } => {
-                format!(" sheesh > {} ", connection_name)
+                match &self.terminal {
+                    Some(t) => format!(" sheesh > {} · {} ", connection_name, format_open_for(t.open_for())),
+                    None => format!(" sheesh > {} ", connection_name),
+                }
             }
         };
 
-        let line = Line::from(vec![
-            Span::styled(title, Theme::title()),
-            Span::styled(" [?] help", Theme::key_hint_desc()),
-        ]);
+        let mut spans = vec![Span::styled(title, Theme::title())];
+        for session in &self.background {
+            spans.push(Span::styled(format!(" {} ", session.name), Theme::dimmed()));
+        }
+        spans.push(Span::styled(" [?] help", Theme::key_hint_desc()));
+        let line = Line::from(spans);
         frame.render_widget(Paragraph::new(line), area);
     }
 
     fn render_main(&mut self, frame: &mut Frame, area: Rect) {
-        match &self.state.clone() {
+        match &self.state {
             AppState::Listing => {
                 self.listing.render(frame, area, true);
             }
+            AppState::Connected { focus, connection_name } if self.plain_view => {
+                // The assistant's replies arrive when it is drawn; keep them
+                // coming while only the terminal is.
+                if let Some(l) = &mut self.llm {
+                    l.poll();
+                }
+                // Only the focused panel takes clicks in the plain view.
+                let (heading, lines, scroll) = match focus {
+                    ConnectedFocus::Terminal => {
+                        (self.terminal_area, self.llm_area) = (area, Rect::default());
+                        let Some(t) = &self.terminal else { return };
+                        let state = if t.is_alive() { "connected" } else { "disconnected" };
+                        let heading = match t.warnings().as_slice() {
+                            [] => format!("Terminal, {}, {}:", connection_name, state),
+                            warnings => format!("Terminal, {}, {}. {}", connection_name, state, warnings.join(" ")),
+                        };
+                        (heading, t.plain_text(), t.scroll_offset())
+                    }
+                    ConnectedFocus::LLM => {
+                        (self.terminal_area, self.llm_area) = (Rect::default(), area);
+                        let Some(l) = &self.llm else { return };
+                        (format!("Assistant, {}:", connection_name), l.plain_text(), l.scroll_offset())
+                    }
+                };
+                ui::plain::render(frame, area, &heading, &lines, scroll);
+            }
             AppState::Connected { focus, .. } => {
-                let [left_area, right_area] =
-                    Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
-                        .areas(area);
+                let [left_area, right_area] = self.session_layout(area);
 
                 self.terminal_area = left_area;
                 self.llm_area = right_area;
@@ -288,8 +1402,10 @@ impl Sheesh {
                 if let Some(t) = &mut self.terminal {
                     t.render(frame, left_area, *focus == ConnectedFocus::Terminal);
                 }
-                if let Some(l) = &mut self.llm {
-                    l.render(frame, right_area, *focus == ConnectedFocus::LLM);
+                match &mut self.llm {
+                    Some(l) if l.is_disabled() => l.poll(),
+                    Some(l) => l.render(frame, right_area, *focus == ConnectedFocus::LLM),
+                    None => {}
                 }
             }
         }
@@ -297,9 +1413,24 @@ impl Sheesh {
 
     fn render_footer(&self, frame: &mut Frame, area: Rect) {
         let hints: Vec<(&str, &str)> = match &self.state {
-            AppState::Listing => self.listing.key_hints(),
+            AppState::Listing => {
+                let mut hints = self.listing.key_hints();
+                if !self.profiles.is_empty() && hints.iter().any(|(key, _)| *key == "ctrl+q") {
+                    hints.insert(hints.len() - 1, ("p", "switch profile"));
+                }
+                hints
+            }
             AppState::Connected { focus, .. } => {
-                let mut hints = vec![("F2", "switch panel")];
+                let mut hints = vec![];
+                if self.llm.as_ref().is_some_and(|l| !l.is_disabled()) {
+                    hints.push(("F2", "switch panel"));
+                }
+                if self.plain_view || self.ui_config.screen_reader {
+                    hints.push(("F9", if self.plain_view { "panels" } else { "plain view" }));
+                }
+                if !self.background.is_empty() {
+                    hints.push(("F3", "next session"));
+                }
                 let panel_hints: Vec<(&str, &str)> = match focus {
                     ConnectedFocus::Terminal => self
                         .terminal
@@ -311,6 +1442,15 @@ impl Sheesh {
                     }
                 };
                 hints.extend(panel_hints);
+                if let (Some(terminal), Some(problem)) = (&self.terminal, self.llm.as_ref().and_then(LLMTab::problem))
+                    && !terminal.has_selection()
+                {
+                    hints.push(("F5", problem.kind.hint()));
+                }
+                if self.pending_capture.is_some() {
+                    hints.push(("ctrl+f", "finish capture"));
+                    hints.push(("ctrl+c", "cancel tool call"));
+                }
                 hints.push(("ctrl+q", "quit"));
                 hints
             }
@@ -319,42 +1459,28 @@ impl Sheesh {
     }
 }
 
-fn render_error_popup(frame: &mut Frame, area: Rect, msg: &str) {
-    let popup_area = centered_rect(60, 20, area);
-    frame.render_widget(Clear, popup_area);
-
-    let para = Paragraph::new(vec![
-        Line::default(),
-        Line::from(Span::styled(format!("  {}", msg), Theme::error())),
-        Line::default(),
-        Line::from(Span::styled("  Press any key to continue", Theme::dimmed())),
-    ])
-    .block(
-        Block::bordered()
-            .border_type(BorderType::Rounded)
-            .border_style(Theme::error())
-            .title(Span::styled(" Error ", Theme::error())),
-    );
-
-    frame.render_widget(para, popup_area);
+/// `name` and what its session is busy with, for the quit prompt.
+fn session_summary(name: &str, terminal: &TerminalTab, llm: &LLMTab, capturing: bool) -> String {
+    let mut busy = vec![];
+    if !terminal.is_alive() {
+        busy.push("disconnected");
+    }
+    if capturing {
+        busy.push("running a tool command");
+    } else if llm.is_executing_tool() {
+        busy.push("tool call in progress");
+    }
+    if llm.waiting {
+        busy.push("waiting for the assistant");
+    }
+    if busy.is_empty() { name.to_string() } else { format!("{} — {}", name, busy.join(", ")) }
 }
 
-fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
-    let [_, mid_v, _] = Layout::vertical([
-        Constraint::Percentage((100 - percent_y) / 2),
-        Constraint::Percentage(percent_y),
-        Constraint::Percentage((100 - percent_y) / 2),
-    ])
-    .areas(area);
-
-    let [_, center, _] = Layout::horizontal([
-        Constraint::Percentage((100 - percent_x) / 2),
-        Constraint::Percentage(percent_x),
-        Constraint::Percentage((100 - percent_x) / 2),
-    ])
-    .areas(mid_v);
-
-    center
+/// True if the last line of `output` is a password/passphrase prompt
+/// (sudo, su, ssh key unlock) waiting for input.
+fn is_password_prompt(output: &str) -> bool {
+    let last = output.rsplit('\n').next().unwrap_or("").trim().to_lowercase();
+    (last.contains("password") || last.contains("passphrase")) && last.ends_with(':')
 }
 
 fn contains(rect: Rect, col: u16, row: u16) -> bool {
@@ -362,28 +1488,101 @@ fn contains(rect: Rect, col: u16, row: u16) -> bool {
 }
 
 fn main() -> anyhow::Result<()> {
-    Ftail::new()
-        .single_file(Path::new("logs"), true, LevelFilter::Debug)
-        .init()
-        .unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    #[cfg(unix)]
+    if let [_, hold, socket, ssh_args @ ..] = args.as_slice()
+        && hold == "hold"
+    {
+        return daemon::hold(Path::new(socket), ssh_args);
+    }
 
-    let ssh_path = ssh_config_path();
-    let connections = load_connections(&ssh_path).unwrap_or_default();
+    // Request and response details are only logged with --debug-llm.
+    let debug_llm = args.iter().any(|a| a == "--debug-llm");
+    let level = if debug_llm { LevelFilter::Debug } else { LevelFilter::Info };
+    Ftail::new().single_file(Path::new("logs"), true, level).init().unwrap();
+    if debug_llm && let Err(e) = llm::debug::enable(Path::new("llm-debug.jsonl")) {
+        log::warn!("[llm] --debug-llm: {:#}", e);
+    }
 
-    let llm_config = load_llm_config();
-    let mut app = Sheesh::new(connections, llm_config);
+    let profile = cli_value("--profile");
+    let (config, config_error) = load_config(profile.as_deref())?;
+    if let Some(at) = args.iter().position(|a| a == "export" || a == "import") {
+        return export_or_import(&config, &args[at..]);
+    }
+    if config.encrypt_store {
+        open_store(&config)?;
+    }
+    let metrics_error = metrics::start(&config.metrics).err();
+    let mut app = Sheesh::new(config, profile);
+    app.report_config(config_error);
+    if let Some(e) = metrics_error {
+        app.notices.error(format!("Metrics: {:#}", e));
+    }
+    if args.iter().skip(1).any(|a| a == "attach") {
+        #[cfg(unix)]
+        app.attach_detached();
+        #[cfg(not(unix))]
+        app.notices.error("Detached sessions are not available on this platform.");
+    }
 
-    // Enable mouse before entering the TUI
-    execute!(std::io::stdout(), EnableMouseCapture)?;
+    // Enable mouse before entering the TUI, unless the config leaves the
+    // mouse to the host terminal (its own selection and scrolling).
+    set_mouse_capture(app.ui_config.mouse);
 
     let result = ratatui::run(
         |terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>| -> std::io::Result<()> {
             loop {
-                terminal.draw(|f| app.draw(f))?;
+                if let (Some(terminal), Some(llm)) = (&app.terminal, &mut app.llm) {
+                    llm.set_cwd(terminal.cwd());
+                    llm.set_last_exit(terminal.last_exit());
+                }
+
+                // Sessions the assistant asked to open, and the assistants of
+                // sessions not on screen (the visible one polls as it renders).
+                while let Ok((name, reply)) = app.open_rx.try_recv() {
+                    let _ = reply.send(app.open_background(name));
+                }
+                for session in &mut app.background {
+                    session.llm.poll();
+                }
+
+                if std::mem::take(&mut app.redraw_requested) {
+                    terminal.clear()?;
+                }
+                let frame = terminal.draw(|f| app.draw(f))?;
+                if std::mem::take(&mut app.export_requested) {
+                    app.export_view(frame.buffer);
+                }
+
+                app.check_idle();
+
+                // Tool calls approved without a keypress (auto-approve, or the
+                // rest of a batch after `Y`) run as soon as nothing is capturing
+                // and the session isn't locked.
+                if app.pending_capture.is_none()
+                    && app.idle_lock.is_none()
+                    && let Some(cmd) = app.llm.as_mut().and_then(|l| l.take_approved_command())
+                {
+                    app.send_to_terminal(&cmd);
+                }
+
+                // A tool command asking for a password pauses capture until the
+                // user has typed it into the terminal.
+                let password_prompt = match (&app.pending_capture, &app.terminal) {
+                    (Some(cap), Some(terminal)) if !cap.awaiting_password => {
+                        is_password_prompt(&terminal.capture_since(cap.snapshot))
+                    }
+                    _ => false,
+                };
+                if password_prompt {
+                    app.begin_password_entry();
+                }
 
                 // Forward captured terminal output to Claude once output has been
                 // stable (no new PTY lines) for 300 ms.
-                let should_fire = if let Some(ref mut cap) = app.pending_capture {
+                let should_fire = if let Some(ref mut cap) = app.pending_capture
+                    && !cap.awaiting_password
+                {
                     let now = std::time::Instant::now();
                     let current = app.terminal.as_ref().map_or(0, |t| t.line_count());
                     if current > cap.last_line_count {
@@ -392,21 +1591,48 @@ fn main() -> anyhow::Result<()> {
                     }
                     let silence = now.duration_since(cap.last_change);
                     let has_output = cap.last_line_count > cap.snapshot;
-                    // Wait for output to appear, then stabilise for 1100 ms.
-                    // If the command produces no output at all, fire after 5 s.
-                    (has_output && silence >= Duration::from_millis(1100))
-                        || (!has_output && silence >= Duration::from_secs(5))
+                    match &app.terminal {
+                        // The shell says when the command is done.
+                        Some(terminal) if terminal.has_shell_integration() => {
+                            terminal.finished_commands() > cap.finished
+                        }
+                        // Wait for output to appear, then stabilise for 1100 ms.
+                        // If the command produces no output at all, fire after 5 s.
+                        _ => {
+                            (has_output && silence >= Duration::from_millis(1100))
+                                || (!has_output && silence >= Duration::from_secs(5))
+                        }
+                    }
                 } else {
                     false
                 };
                 if should_fire {
-                    let snapshot = app.pending_capture.take().unwrap().snapshot;
-                    if let (Some(terminal), Some(llm)) = (&app.terminal, &mut app.llm)
-                        && llm.awaiting_output_id.is_some()
-                    {
-                        let output = terminal.capture_since(snapshot);
-                        llm.resume_with_output(output);
+                    app.finish_capture(None);
+                }
+
+                // Interrupt commands that run past the configured timeout
+                // (interactive prompts, hangs) and report what was captured.
+                let timeout = Duration::from_secs(app.tool_config.capture_timeout_secs);
+                if let Some(ref cap) = app.pending_capture
+                    && !cap.awaiting_password
+                    && cap.started.elapsed() >= timeout
+                {
+                    if let Some(terminal) = &mut app.terminal {
+                        terminal.send_string("\x03");
                     }
+                    app.finish_capture(Some(format!(
+                        "The command did not finish within {}s and was interrupted with ctrl+c.",
+                        timeout.as_secs()
+                    )));
+                }
+
+                // Keep the LLM panel's status line moving while a command runs.
+                if let (Some(cap), Some(terminal), Some(llm)) = (&mut app.pending_capture, &app.terminal, &mut app.llm)
+                    && cap.last_progress.elapsed() >= Duration::from_millis(500)
+                {
+                    cap.last_progress = std::time::Instant::now();
+                    let bytes = cap.before_password.len() + terminal.capture_since(cap.snapshot).len();
+                    llm.report_progress(bytes, cap.awaiting_password.then_some("waiting for a password"));
                 }
 
                 // Release the tool lock once the LLM finishes the tool-execution cycle.
@@ -420,6 +1646,12 @@ fn main() -> anyhow::Result<()> {
 
                 if poll(Duration::from_millis(5))? {
                     let ev = read()?;
+                    // Windows reports key releases too; only presses act.
+                    if let crossterm::event::Event::Key(key) = &ev
+                        && key.kind == KeyEventKind::Release
+                    {
+                        continue;
+                    }
                     if !app.handle_event(&ev) {
                         break;
                     }
@@ -429,50 +1661,286 @@ fn main() -> anyhow::Result<()> {
         },
     );
 
-    execute!(std::io::stdout(), DisableMouseCapture)?;
+    set_mouse_capture(false);
+    // Sessions still open, whether ended or left running.
+    for llm in app.llm.iter().chain(app.background.iter().map(|s| &s.llm)) {
+        llm.save_chat();
+    }
+    metrics::flush();
     result?;
     Ok(())
 }
 
-fn load_llm_config() -> LLMConfig {
+/// How long a session has been open, e.g. `12m` or `1h 05m`.
+fn format_open_for(d: Duration) -> String {
+    let minutes = d.as_secs() / 60;
+    match minutes {
+        0..60 => format!("{}m", minutes),
+        _ => format!("{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+/// Header, main area and footer of the screen.
+fn screen_layout(area: Rect) -> [Rect; 3] {
+    Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(area)
+}
+
+/// Terminal and assistant panels of the connected view.
+fn connected_layout(area: Rect) -> [Rect; 2] {
+    Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(area)
+}
+
+fn set_mouse_capture(on: bool) {
+    let result = if on {
+        execute!(std::io::stdout(), EnableMouseCapture)
+    } else {
+        execute!(std::io::stdout(), DisableMouseCapture)
+    };
+    if let Err(e) = result {
+        log::warn!("could not {} mouse capture: {}", if on { "enable" } else { "disable" }, e);
+    }
+}
+
+/// Contents of `~/.config/sheesh/config.toml`.
+#[derive(serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    llm: LLMConfig,
+    #[serde(default)]
+    tools: ToolConfig,
+    #[serde(default)]
+    ui: UiConfig,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default)]
+    metrics: metrics::MetricsConfig,
+    /// SSH config to list and edit instead of `~/.ssh/config`.
+    ssh_config: Option<String>,
+    /// Only list the SSH config's hosts, never write it (e.g. a team's
+    /// shared file).
+    #[serde(default)]
+    ssh_config_read_only: bool,
+    /// Keep notes, runbooks, the host memory and chats encrypted (read at
+    /// startup).
+    #[serde(default)]
+    encrypt_store: bool,
+    /// Environment variable holding the store's passphrase; without one it
+    /// is asked for before the TUI starts.
+    store_passphrase_env: Option<String>,
+    /// `[profile.<name>]` — settings layered over the rest of the file when
+    /// the profile is selected (`--profile <name>` or `p` in the listing).
+    #[serde(default)]
+    profile: HashMap<String, ConfigFile>,
+}
+
+impl ConfigFile {
+    /// The SSH config to use: `--ssh-config`, else `$SSH_CONFIG`, else the
+    /// `ssh_config` key, else `~/.ssh/config`.
+    fn ssh_config_path(&self) -> PathBuf {
+        let path = cli_value("--ssh-config")
+            .or_else(|| std::env::var("SSH_CONFIG").ok().filter(|p| !p.is_empty()))
+            .or_else(|| self.ssh_config.clone());
+        path.map_or_else(default_ssh_config_path, |p| config::expand_home(&p))
+    }
+
+    /// The SSH config and its hosts, read-only when configured so, asked
+    /// for with `--read-only`, or when the file can't be written.
+    fn listing(&self) -> (PathBuf, ListingTab) {
+        let path = self.ssh_config_path();
+        config::use_ssh_config(path.clone());
+        let mut listing = ListingTab::new(load_connections(&path).unwrap_or_default());
+        listing.read_only = self.ssh_config_read_only
+            || std::env::args().any(|a| a == "--read-only")
+            || std::fs::metadata(&path).is_ok_and(|m| m.permissions().readonly());
+        listing.refresh_agent();
+        (path, listing)
+    }
+
+    /// Profile names, sorted.
+    fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profile.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// `overlay` merged into `base`, table by table.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge_tables(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Unlock the encrypted store before the TUI starts, with the passphrase
+/// from `store_passphrase_env` or typed on the terminal (twice while it is
+/// being chosen), then encrypt whatever is still in plain text.
+fn open_store(config: &ConfigFile) -> anyhow::Result<()> {
+    let from_env = config.store_passphrase_env.as_deref().and_then(|name| {
+        let value = std::env::var(name).ok().filter(|v| !v.is_empty());
+        if value.is_none() {
+            log::warn!("[store] store_passphrase_env: {} is not set; asking", name);
+        }
+        value
+    });
+    match from_env {
+        Some(passphrase) => store::unlock(&passphrase)?,
+        None if !store::has_key() => {
+            let key = store::key_path();
+            println!("Notes, runbooks and the host memory will be encrypted; the key goes to {}.", key.display());
+            let passphrase = rpassword::prompt_password("Choose a passphrase: ")?;
+            if passphrase.is_empty() {
+                anyhow::bail!("an empty passphrase would protect nothing; nothing was encrypted");
+            }
+            if rpassword::prompt_password("Again: ")? != passphrase {
+                anyhow::bail!("the passphrases differ; nothing was encrypted");
+            }
+            store::unlock(&passphrase)?;
+        }
+        None => {
+            for attempt in 1.. {
+                let passphrase = rpassword::prompt_password("Passphrase for sheesh's encrypted store: ")?;
+                match store::unlock(&passphrase) {
+                    Ok(()) => break,
+                    Err(e) if attempt < 3 => eprintln!("{:#}", e),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    let sealed = store::seal_existing(&[config.tools.notes_dir(), inventory::inventory_path(), chats::chats_dir()])?;
+    if sealed > 0 {
+        log::info!("[store] encrypted {} files written before encrypt_store was on", sealed);
+    }
+    Ok(())
+}
+
+/// `sheesh export <file>` / `sheesh import <file> [--on-conflict keep|replace|rename]`,
+/// on the profile's SSH config.
+fn export_or_import(config: &ConfigFile, args: &[String]) -> anyhow::Result<()> {
+    let [command, file, ..] = args else {
+        anyhow::bail!("usage: sheesh {} <file.json|file.yaml>", args[0]);
+    };
+    let (ssh_path, listing) = config.listing();
+    let file = Path::new(file);
+    if command == "export" {
+        bundle::export(&listing.connections, file)?;
+        let count = listing.connections.len();
+        println!("Exported {} connections from {} to {}.", count, ssh_path.display(), file.display());
+        return Ok(());
+    }
+    let on_conflict = bundle::OnConflict::parse(cli_value("--on-conflict").as_deref().unwrap_or("keep"))?;
+    let imported = bundle::read(file)?;
+    if listing.read_only {
+        anyhow::bail!("{} is read-only; nothing imported", ssh_path.display());
+    }
+    let mut connections = listing.connections;
+    let report = bundle::merge(&mut connections, imported, on_conflict);
+    save_connections(&ssh_path, &connections)?;
+    println!("{}", report.summary());
+    Ok(())
+}
+
+/// The value of command-line option `flag`, given as `--flag <value>` or
+/// `--flag=<value>`.
+fn cli_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(flag).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// The config file with `profile` applied, or the defaults when there is
+/// none, with a note if `profile` isn't in it. An invalid file is an error
+/// naming the offending line and key; sheesh won't start on one.
+fn load_config(profile: Option<&str>) -> anyhow::Result<(ConfigFile, Option<String>)> {
     let path = dirs::config_dir()
         .unwrap_or_else(|| std::path::PathBuf::from("."))
         .join("sheesh")
         .join("config.toml");
 
-    log::info!("[config] loading LLM config from {}", path.display());
+    log::info!("[config] loading config from {}", path.display());
 
-    match std::fs::read_to_string(&path) {
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
         Err(e) => {
             log::warn!(
                 "[config] could not read config file: {} — using defaults",
                 e
             );
+            return Ok((ConfigFile::default(), None));
         }
-        Ok(content) => {
-            #[derive(serde::Deserialize, Default)]
-            struct ConfigFile {
-                #[serde(default)]
-                llm: LLMConfig,
-            }
-            match toml::from_str::<ConfigFile>(&content) {
-                Err(e) => {
-                    log::error!(
-                        "[config] failed to parse config.toml: {} — using defaults",
-                        e
-                    );
-                }
-                Ok(cfg) => {
-                    log::info!(
-                        "[config] loaded: provider={} model={}",
-                        cfg.llm.provider,
-                        cfg.llm.model
-                    );
-                    return cfg.llm;
+    };
+    // Validate the file as written, so errors point at its lines; then
+    // expand `${VAR}` and `~` in the values.
+    let mut missing_profile = None;
+    let parsed = toml::from_str::<ConfigFile>(&content).and_then(|_| {
+        let mut table = toml::from_str::<toml::Table>(&content)?;
+        if let Some(name) = profile {
+            let overlay = table
+                .get("profile")
+                .and_then(|p| p.get(name))
+                .and_then(|p| p.as_table())
+                .cloned();
+            match overlay {
+                Some(mut overlay) => {
+                    overlay.remove("profile");
+                    merge_tables(&mut table, overlay);
                 }
+                None => missing_profile = Some(format!("No [profile.{}] in {}", name, path.display())),
             }
         }
+        let mut value = toml::Value::Table(table);
+        config::interpolate(&mut value);
+        value.try_into::<ConfigFile>()
+    });
+    match parsed {
+        Err(e) => {
+            log::error!("[config] failed to parse config.toml: {}", e);
+            anyhow::bail!("{} is invalid; fix it to start sheesh\n{}", path.display(), e.to_string().trim_end())
+        }
+        Ok(cfg) => {
+            log::info!(
+                "[config] loaded: profile={} provider={} model={}",
+                profile.unwrap_or("-"),
+                cfg.llm.provider,
+                cfg.llm.model
+            );
+            Ok((cfg, missing_profile))
+        }
     }
+}
 
-    LLMConfig::default()
+/// What a capture keeps when a password prompt interrupts it: the lines
+/// before the prompt's, still ending in a newline, so the output captured
+/// after the password doesn't run on from the last of them.
+fn before_prompt(output: &str) -> &str {
+    match output.rfind('\n') {
+        Some(end) if end > 0 => &output[..=end],
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_around_a_password_prompt_keeps_its_lines_apart() {
+        let before = before_prompt("Reading package lists...\nDone\n[sudo] password for ops: ");
+        assert_eq!(before.to_string() + "Setting up nginx\n", "Reading package lists...\nDone\nSetting up nginx\n");
+        assert_eq!(before_prompt("[sudo] password for ops: "), "");
+        assert_eq!(before_prompt("\nPassword: "), "");
+    }
 }
@@ -0,0 +1,191 @@
+//! `[metrics]` — usage counters for the whole process (sessions opened,
+//! commands run, LLM tokens, tool calls and failures) in the Prometheus
+//! text format, for teams that want to see how sheesh is used. They can be
+//! served on a local port, rewritten into a file (e.g. for node_exporter's
+//! textfile collector), or both; with neither set they are only counted.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sheesh_tools::{ToolCallEnd, ToolObserver};
+
+use crate::llm::Usage;
+
+/// `[metrics]` section of config.toml (read at startup).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address to serve the counters on, e.g. `127.0.0.1:9464`.
+    pub listen: Option<String>,
+    /// File the counters are rewritten into.
+    pub file: Option<String>,
+    /// Seconds between rewrites of `file`.
+    pub interval_secs: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { listen: None, file: None, interval_secs: 15 }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    sessions_opened: u64,
+    commands_executed: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    /// Finished tool calls by tool name.
+    tool_calls: BTreeMap<String, u64>,
+    tool_failures: u64,
+}
+
+static COUNTERS: LazyLock<Mutex<Counters>> = LazyLock::new(Mutex::default);
+
+/// The file being kept, for [`flush`].
+static FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A session's terminal and assistant were started.
+pub fn session_opened() {
+    COUNTERS.lock().unwrap().sessions_opened += 1;
+}
+
+/// A command the assistant proposed was approved and typed into a terminal.
+pub fn command_executed() {
+    COUNTERS.lock().unwrap().commands_executed += 1;
+}
+
+/// Tokens spent by one provider request.
+pub fn tokens(usage: &Usage) {
+    let mut counters = COUNTERS.lock().unwrap();
+    counters.input_tokens += usage.input_tokens;
+    counters.output_tokens += usage.output_tokens;
+}
+
+/// A tool call ran but didn't do what was asked (a local tool's error, a
+/// failed download or write).
+pub fn tool_failed() {
+    COUNTERS.lock().unwrap().tool_failures += 1;
+}
+
+/// Counts the calls of the tool registry it is added to.
+pub fn tool_observer() -> Arc<dyn ToolObserver> {
+    Arc::new(ToolCounter)
+}
+
+struct ToolCounter;
+
+impl ToolObserver for ToolCounter {
+    fn on_call_end(&self, call: &ToolCallEnd) {
+        *COUNTERS.lock().unwrap().tool_calls.entry(call.name.clone()).or_default() += 1;
+    }
+}
+
+/// The counters in the Prometheus text exposition format.
+pub fn render() -> String {
+    let counters = COUNTERS.lock().unwrap();
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, samples: &[(String, u64)]| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    counter("sheesh_sessions_opened_total", "Sessions opened.", &[(String::new(), counters.sessions_opened)]);
+    counter(
+        "sheesh_commands_executed_total",
+        "Assistant commands approved and run in a terminal.",
+        &[(String::new(), counters.commands_executed)],
+    );
+    counter(
+        "sheesh_llm_tokens_total",
+        "Tokens sent to and received from LLM providers.",
+        &[
+            ("{kind=\"input\"}".to_string(), counters.input_tokens),
+            ("{kind=\"output\"}".to_string(), counters.output_tokens),
+        ],
+    );
+    let calls: Vec<(String, u64)> =
+        counters.tool_calls.iter().map(|(tool, n)| (format!("{{tool=\"{}\"}}", tool), *n)).collect();
+    counter("sheesh_tool_calls_total", "Tool calls run, by tool.", &calls);
+    counter("sheesh_tool_failures_total", "Tool calls that failed.", &[(String::new(), counters.tool_failures)]);
+    out
+}
+
+/// Start serving and writing the counters as `cfg` asks. Errors (e.g. the
+/// port is taken) leave the counters unpublished that way.
+pub fn start(cfg: &MetricsConfig) -> Result<()> {
+    if let Some(file) = &cfg.file {
+        let path = PathBuf::from(file);
+        let interval = Duration::from_secs(cfg.interval_secs.max(1));
+        *FILE.lock().unwrap() = Some(path.clone());
+        std::thread::spawn(move || {
+            loop {
+                if let Err(e) = write_file(&path) {
+                    log::warn!("[metrics] {:#}", e);
+                }
+                std::thread::sleep(interval);
+            }
+        });
+    }
+    if let Some(addr) = &cfg.listen {
+        let listener = TcpListener::bind(addr).with_context(|| format!("serving metrics on {}", addr))?;
+        log::info!("[metrics] serving on http://{}/metrics", addr);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream) {
+                    log::warn!("[metrics] {}", e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Write the file one last time, so it holds the final counts on exit.
+pub fn flush() {
+    if let Some(path) = FILE.lock().unwrap().as_deref()
+        && let Err(e) = write_file(path)
+    {
+        log::warn!("[metrics] {:#}", e);
+    }
+}
+
+/// Replace `path` whole, so a collector never reads half a file.
+fn write_file(path: &Path) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, render()).with_context(|| format!("writing {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Answer one HTTP request: the counters for `/metrics` (or `/`), 404 otherwise.
+fn respond(stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers; nothing in them matters here.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = match path {
+        "/metrics" | "/" => ("200 OK", render()),
+        _ => ("404 Not Found", "Not found; try /metrics\n".to_string()),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
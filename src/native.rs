@@ -0,0 +1,611 @@
+//! The native ssh client (`# sheesh: backend native`): sessions made with
+//! libssh2 in-process rather than by running the ssh binary in a PTY. The
+//! host-key question and the password, passphrase and keyboard-interactive
+//! prompts are asked by sheesh itself — in the terminal for a shell, not at
+//! all for the commands and downloads run beside it, which fail instead, as
+//! they do with BatchMode. Of the connection's options only what names the
+//! host, user, port, key, known_hosts file and environment is used; the
+//! rest (ProxyJump, forwarding, …) is listed as ignored when a shell opens.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use sheesh_tools::{ExecOutput, SshContext};
+use ssh2::{Channel, ErrorCode, HashType, HostKeyType, KeyboardInteractivePrompt, Prompt, Session};
+
+use crate::config::expand_home;
+use crate::known_hosts::{CHANGED, KnownHosts};
+use crate::llm::wildcard_match;
+use crate::ssh::SSHConnection;
+use crate::telnet;
+
+/// Options the native client acts on; any others are reported as ignored.
+const USED_OPTIONS: [&str; 6] = ["hostname", "user", "port", "identityfile", "hostkeyalias", "userknownhostsfile"];
+
+/// Keys ssh tries when the connection names none.
+const DEFAULT_IDENTITIES: [&str; 3] = ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+/// libssh2's `LIBSSH2_ERROR_FILE`: a key file it couldn't read, which for
+/// a key that exists means a passphrase is needed (or was wrong).
+const KEY_FILE_ERROR: ErrorCode = ErrorCode::Session(-16);
+
+/// libssh2's `LIBSSH2_ERROR_EAGAIN`, from requests on a non-blocking
+/// session.
+const WOULD_BLOCK: ErrorCode = ErrorCode::Session(-37);
+
+/// How long the shell's pump sleeps when nothing came or went.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Asks for what authenticating needs.
+trait Ask {
+    /// Show `text`, which ends its own lines.
+    fn say(&mut self, text: &str);
+    /// Ask for a line after `prompt`; `None` when they gave up.
+    fn ask(&mut self, prompt: &str, echo: bool) -> Option<String>;
+    /// Whether there is anyone to ask.
+    fn can_ask(&self) -> bool {
+        true
+    }
+}
+
+/// For sessions with no terminal: nothing is asked.
+struct NoPrompts;
+
+impl Ask for NoPrompts {
+    fn say(&mut self, text: &str) {
+        log::info!("[native] {}", text.trim());
+    }
+
+    fn ask(&mut self, _prompt: &str, _echo: bool) -> Option<String> {
+        None
+    }
+
+    fn can_ask(&self) -> bool {
+        false
+    }
+}
+
+/// An authenticated connection to a host.
+pub struct NativeSession {
+    session: Session,
+    /// Variables asked of the server for each channel.
+    env: Vec<(String, String)>,
+}
+
+impl NativeSession {
+    /// Connect to `conn` and log in, asking through `ask` for anything
+    /// the host key check or authentication needs.
+    fn open(conn: &SSHConnection, ask: &mut dyn Ask) -> Result<NativeSession> {
+        let host = conn.effective_hostname();
+        let stream = telnet::connect(host, conn.effective_port())?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(stream);
+        session.handshake().with_context(|| format!("ssh handshake with {}", host))?;
+        check_host_key(&session, conn, ask)?;
+        authenticate(&session, conn, ask)?;
+        Ok(NativeSession { session, env: environment(conn) })
+    }
+
+    /// Connect without prompting: a host not yet trusted, or one that
+    /// wants a password or a key's passphrase, fails.
+    pub fn batch(conn: &SSHConnection) -> Result<NativeSession> {
+        NativeSession::open(conn, &mut NoPrompts)
+    }
+
+    /// Run `command` and wait for it to finish. Both streams are read as
+    /// output comes, so a command filling one while the other is read
+    /// first can't stall.
+    pub fn exec(&self, command: &str) -> Result<ExecOutput> {
+        let mut channel = self.session.channel_session()?;
+        set_env(&mut channel, &self.env);
+        channel.exec(command).context("starting the command")?;
+        self.session.set_blocking(false);
+        let read = drain(&mut channel);
+        self.session.set_blocking(true);
+        let (stdout, stderr) = read?;
+        channel.wait_close()?;
+        // No status when a signal ended it.
+        let status = channel.exit_signal()?.exit_signal.is_none().then_some(channel.exit_status()?);
+        Ok(ExecOutput { stdout, stderr, status })
+    }
+
+    /// Copy `remote` to `local` over sftp, keeping its modification time
+    /// and mode as `scp -p` does.
+    pub fn download(&self, remote: &str, local: &Path) -> Result<()> {
+        let sftp = self.session.sftp().context("starting sftp")?;
+        let path = sftp_path(remote);
+        let stat = sftp.stat(&path).map_err(|e| anyhow!("{}: {}", remote, e.message()))?;
+        if stat.is_dir() {
+            bail!("{} is a directory", remote);
+        }
+        let mut file = sftp.open(&path).map_err(|e| anyhow!("{}: {}", remote, e.message()))?;
+        let mut out = std::fs::File::create(local).with_context(|| format!("creating {}", local.display()))?;
+        if let Err(e) = io::copy(&mut file, &mut out) {
+            let _ = std::fs::remove_file(local);
+            return Err(e).with_context(|| format!("downloading {}", remote));
+        }
+        if let Some(mtime) = stat.mtime {
+            out.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = stat.perm {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(local, std::fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+        Ok(())
+    }
+
+    /// Pass what the shell prints to the terminal, and what is typed and
+    /// the window's size to the shell, until either side closes.
+    fn pump(&self, mut channel: Channel, term: &mut Terminal) -> Result<()> {
+        self.session.set_blocking(false);
+        let mut buf = [0u8; 8192];
+        let mut typed: Vec<u8> = term.typed.drain(..).collect();
+        let mut resize = None;
+        loop {
+            let mut idle = true;
+            for mut stream in [channel.stream(0), channel.stderr()] {
+                match stream.read(&mut buf) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        idle = false;
+                        if term.output.send(buf[..n].to_vec()).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if channel.eof() {
+                return Ok(());
+            }
+            loop {
+                match term.input.try_recv() {
+                    Ok(Input::Data(data)) => typed.extend(data),
+                    Ok(Input::Resize(rows, cols)) => resize = Some((rows, cols)),
+                    Err(TryRecvError::Empty) => break,
+                    // The tab is gone.
+                    Err(TryRecvError::Disconnected) => {
+                        let _ = channel.close();
+                        return Ok(());
+                    }
+                }
+            }
+            if !typed.is_empty() {
+                match channel.write(&typed) {
+                    Ok(n) => {
+                        typed.drain(..n);
+                        idle = false;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if let Some((rows, cols)) = resize {
+                match channel.request_pty_size(cols.into(), rows.into(), None, None) {
+                    Ok(()) => resize = None,
+                    Err(e) if e.code() == WOULD_BLOCK => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if idle {
+                thread::sleep(POLL);
+            }
+        }
+    }
+}
+
+/// Everything `channel` prints, stdout and stderr, read side by side on a
+/// non-blocking session until it ends.
+fn drain(channel: &mut Channel) -> Result<(String, String)> {
+    let (mut stdout, mut stderr) = (vec![], vec![]);
+    let mut buf = [0u8; 8192];
+    loop {
+        let mut idle = true;
+        for (mut stream, out) in [(channel.stream(0), &mut stdout), (channel.stderr(), &mut stderr)] {
+            match stream.read(&mut buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    idle = false;
+                    out.extend_from_slice(&buf[..n]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // Only once both are empty, so nothing left in a buffer is lost.
+        if idle && channel.eof() {
+            let text = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).into_owned();
+            return Ok((text(stdout), text(stderr)));
+        }
+        if idle {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+/// `remote` as sftp takes it: relative to the home directory already,
+/// with no `~` to expand.
+fn sftp_path(remote: &str) -> PathBuf {
+    PathBuf::from(remote.strip_prefix("~/").unwrap_or(remote))
+}
+
+/// The user to log in as: the connection's, or the local one as with ssh.
+fn user(conn: &SSHConnection) -> String {
+    match conn.effective_user() {
+        "" => std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default(),
+        user => user.to_string(),
+    }
+}
+
+/// Check the server's key against known_hosts as ssh does, asking before
+/// trusting a host seen for the first time.
+fn check_host_key(session: &Session, conn: &SSHConnection, ask: &mut dyn Ask) -> Result<()> {
+    let (blob, kind) = session.host_key().context("the server sent no host key")?;
+    // The name known_hosts lines use, and the one ssh-keygen lists.
+    let (name, label) = match kind {
+        HostKeyType::Rsa => ("ssh-rsa", "RSA"),
+        HostKeyType::Dss => ("ssh-dss", "DSA"),
+        HostKeyType::Ecdsa256 => ("ecdsa-sha2-nistp256", "ECDSA"),
+        HostKeyType::Ecdsa384 => ("ecdsa-sha2-nistp384", "ECDSA"),
+        HostKeyType::Ecdsa521 => ("ecdsa-sha2-nistp521", "ECDSA"),
+        HostKeyType::Ed25519 => ("ssh-ed25519", "ED25519"),
+        HostKeyType::Unknown => bail!("the server's host key is of a kind libssh2 doesn't know"),
+    };
+    let hash = session.host_key_hash(HashType::Sha256).context("hashing the host key")?;
+    let fingerprint = format!("SHA256:{}", STANDARD_NO_PAD.encode(hash));
+    let mut known = KnownHosts::lookup(conn)?;
+    if known.keys.iter().any(|k| k.fingerprint == fingerprint) {
+        return Ok(());
+    }
+    if let Some(old) = known.keys.iter().find(|k| k.kind == label) {
+        bail!(
+            "WARNING: {}!\nThe {} key {} sends is {},\nnot the {} in {} (line {}).\n\
+             If the host was reinstalled, remove the old key with h in the listing.",
+            CHANGED,
+            label,
+            known.host,
+            fingerprint,
+            old.fingerprint,
+            known.file.display(),
+            old.line
+        );
+    }
+    if !ask.can_ask() {
+        bail!("{} is not in {} yet; open its terminal once to check its key", known.host, known.file.display());
+    }
+    ask.say(&format!(
+        "The authenticity of host '{}' can't be established.\r\n{} key fingerprint is {}.\r\n",
+        known.host, label, fingerprint
+    ));
+    let mut prompt = "Are you sure you want to continue connecting (yes/no)? ";
+    loop {
+        let answer = ask.ask(prompt, true);
+        match answer.as_deref().map(str::trim) {
+            Some("yes") => break,
+            Some("no") | None => bail!("Host key verification failed."),
+            _ => prompt = "Please type 'yes' or 'no': ",
+        }
+    }
+    known.trust(&format!("{} {}", name, STANDARD.encode(blob)))?;
+    ask.say(&format!("Warning: Permanently added '{}' ({}) to the list of known hosts.\r\n", known.host, label));
+    Ok(())
+}
+
+/// Log in as ssh would: with the agent's keys, the key files, and then
+/// keyboard-interactive and the password, as far as the server allows.
+fn authenticate(session: &Session, conn: &SSHConnection, ask: &mut dyn Ask) -> Result<()> {
+    let user = user(conn);
+    let methods = session.auth_methods(&user).context("asking how to log in")?.to_string();
+    // Let in with no authentication at all.
+    if session.authenticated() {
+        return Ok(());
+    }
+    let offers = |method: &str| methods.split(',').any(|m| m == method);
+    if offers("publickey") {
+        if std::env::var_os("SSH_AUTH_SOCK").is_some() && session.userauth_agent(&user).is_ok() {
+            return Ok(());
+        }
+        for key in identities(conn) {
+            if key_login(session, &user, &key, ask) {
+                return Ok(());
+            }
+        }
+    }
+    if !ask.can_ask() {
+        bail!(
+            "could not authenticate without a prompt (permission denied: {}); this needs key-based auth or ssh-agent",
+            methods
+        );
+    }
+    if offers("keyboard-interactive")
+        && session.userauth_keyboard_interactive(&user, &mut Interactive(&mut *ask)).is_ok()
+    {
+        return Ok(());
+    }
+    if offers("password") {
+        let prompt = format!("{}@{}'s password: ", user, conn.effective_hostname());
+        for _ in 0..3 {
+            let Some(password) = ask.ask(&prompt, false) else {
+                break;
+            };
+            if session.userauth_password(&user, &password).is_ok() {
+                return Ok(());
+            }
+            ask.say("Permission denied, please try again.\r\n");
+        }
+    }
+    bail!("{}@{}: Permission denied ({}).", user, conn.effective_hostname(), methods)
+}
+
+/// The key files to try: the connection's, or those ssh looks for.
+fn identities(conn: &SSHConnection) -> Vec<PathBuf> {
+    match conn.effective_identity_file() {
+        Some(key) => vec![expand_home(key)],
+        None => DEFAULT_IDENTITIES.iter().map(|key| expand_home(key)).filter(|key| key.exists()).collect(),
+    }
+}
+
+/// Log in with the key in `path`, asking for its passphrase if it has
+/// one. False if the server won't take it or no passphrase was given.
+fn key_login(session: &Session, user: &str, path: &Path, ask: &mut dyn Ask) -> bool {
+    let public = PathBuf::from(format!("{}.pub", path.display()));
+    let public = public.exists().then_some(public.as_path());
+    match session.userauth_pubkey_file(user, public, path, None) {
+        Ok(()) => return true,
+        Err(e) if e.code() != KEY_FILE_ERROR => return false,
+        Err(_) => {}
+    }
+    let prompt = format!("Enter passphrase for key '{}': ", path.display());
+    for _ in 0..3 {
+        let Some(passphrase) = ask.ask(&prompt, false).filter(|p| !p.is_empty()) else {
+            return false;
+        };
+        match session.userauth_pubkey_file(user, public, path, Some(&passphrase)) {
+            Ok(()) => return true,
+            Err(e) if e.code() != KEY_FILE_ERROR => return false,
+            Err(_) => ask.say("Bad passphrase, try again.\r\n"),
+        }
+    }
+    false
+}
+
+/// Keyboard-interactive prompts, such as for a one-time code, asked in turn.
+struct Interactive<'a>(&'a mut dyn Ask);
+
+impl KeyboardInteractivePrompt for Interactive<'_> {
+    fn prompt<'a>(&mut self, _username: &str, instructions: &str, prompts: &[Prompt<'a>]) -> Vec<String> {
+        if !instructions.is_empty() {
+            self.0.say(&format!("{}\r\n", instructions.trim_end()));
+        }
+        prompts.iter().map(|p| self.0.ask(&p.text, p.echo).unwrap_or_default()).collect()
+    }
+}
+
+/// The `SetEnv` pairs, and the local variables `SendEnv` names. Servers
+/// refuse what their `AcceptEnv` doesn't allow, which ssh passes over
+/// silently too.
+fn environment(conn: &SSHConnection) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> =
+        conn.set_env.iter().filter_map(|pair| pair.split_once('=')).map(|(n, v)| (n.into(), v.into())).collect();
+    env.extend(std::env::vars().filter(|(name, _)| conn.send_env.iter().any(|p| wildcard_match(p, name))));
+    env
+}
+
+fn set_env(channel: &mut Channel, env: &[(String, String)]) {
+    for (name, value) in env {
+        let _ = channel.setenv(name, value);
+    }
+}
+
+/// The options of `conn` the native client leaves out, as written.
+fn ignored_options(conn: &SSHConnection) -> Vec<String> {
+    conn.effective_options()
+        .filter_map(|opt| opt.split([' ', '=', '\t']).next())
+        .filter(|key| !USED_OPTIONS.iter().any(|used| used.eq_ignore_ascii_case(key)))
+        .map(String::from)
+        .collect()
+}
+
+/// [`SshContext`] over one native connection, opened on first use. One
+/// that fails is dropped, so the next call starts afresh. Sheesh only runs
+/// commands through it (reading a file for a write's diff preview); the
+/// trait's file helpers work over `exec` like everywhere else.
+pub struct NativeContext {
+    conn: SSHConnection,
+    session: Mutex<Option<NativeSession>>,
+}
+
+impl NativeContext {
+    pub fn new(conn: SSHConnection) -> Self {
+        Self { conn, session: Mutex::new(None) }
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&NativeSession) -> Result<T>) -> Result<T> {
+        let mut kept = self.session.lock().unwrap();
+        let session = match kept.take() {
+            Some(session) => session,
+            None => NativeSession::batch(&self.conn)?,
+        };
+        let result = f(&session);
+        if result.is_ok() {
+            *kept = Some(session);
+        }
+        result
+    }
+}
+
+impl SshContext for NativeContext {
+    fn exec(&self, command: &str) -> Result<ExecOutput> {
+        self.with(|session| session.exec(command))
+    }
+}
+
+// ── Shell ─────────────────────────────────────────────────────────────────────
+
+/// From the terminal tab to the session's thread.
+enum Input {
+    Data(Vec<u8>),
+    /// Rows and columns.
+    Resize(u16, u16),
+}
+
+/// The terminal a shell is opened in, at the other end of two channels.
+struct Terminal {
+    input: Receiver<Input>,
+    output: Sender<Vec<u8>>,
+    /// Typed and not yet used.
+    typed: VecDeque<u8>,
+    /// Rows and columns, as last told.
+    size: (u16, u16),
+}
+
+impl Terminal {
+    fn receive(&mut self, input: Input) {
+        match input {
+            Input::Data(data) => self.typed.extend(data),
+            Input::Resize(rows, cols) => self.size = (rows, cols),
+        }
+    }
+}
+
+impl Ask for Terminal {
+    fn say(&mut self, text: &str) {
+        if !text.is_empty() {
+            let _ = self.output.send(text.as_bytes().to_vec());
+        }
+    }
+
+    /// Read a line as the terminal's line discipline would: Enter ends it,
+    /// Backspace takes a character back, Ctrl-C or Ctrl-D gives up.
+    fn ask(&mut self, prompt: &str, echo: bool) -> Option<String> {
+        self.say(prompt);
+        let mut line: Vec<u8> = vec![];
+        loop {
+            while self.typed.is_empty() {
+                let input = self.input.recv().ok()?;
+                self.receive(input);
+            }
+            match self.typed.pop_front()? {
+                b'\r' | b'\n' => {
+                    self.say("\r\n");
+                    return Some(String::from_utf8_lossy(&line).into_owned());
+                }
+                0x03 | 0x04 => {
+                    self.say("^C\r\n");
+                    return None;
+                }
+                0x7f | 0x08 => {
+                    // A whole character, however many bytes.
+                    while line.pop().is_some_and(|b| b & 0xc0 == 0x80) {}
+                    if echo {
+                        self.say("\x08 \x08");
+                    }
+                }
+                // An escape sequence (an arrow key, say) arrives in one
+                // piece; none of it belongs in the line.
+                0x1b => self.typed.clear(),
+                b if b < 0x20 => {}
+                b => {
+                    line.push(b);
+                    if echo {
+                        let _ = self.output.send(vec![b]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the session prints, for the terminal tab to read; it ends when
+/// the session does.
+pub struct ShellOutput {
+    output: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    at: usize,
+}
+
+impl Read for ShellOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.at == self.chunk.len() {
+            match self.output.recv() {
+                Ok(chunk) => (self.chunk, self.at) = (chunk, 0),
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.at);
+        buf[..n].copy_from_slice(&self.chunk[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+/// What is typed into the session, and its window size. Clones feed the
+/// same session, which closes once they are all dropped.
+#[derive(Clone)]
+pub struct ShellInput {
+    input: Sender<Input>,
+}
+
+impl ShellInput {
+    pub fn resize(&self, rows: u16, cols: u16) {
+        let _ = self.input.send(Input::Resize(rows, cols));
+    }
+}
+
+impl Write for ShellInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.send(Input::Data(buf.to_vec())).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Open a login shell on `conn` for a terminal tab. Connecting, and the
+/// prompts on the way, happen on a thread of the session's own, and what
+/// they print arrives on the output like the shell's.
+pub fn shell(conn: &SSHConnection) -> (ShellOutput, ShellInput) {
+    let (output_tx, output_rx) = mpsc::channel();
+    let (input_tx, input_rx) = mpsc::channel();
+    let conn = conn.clone();
+    thread::spawn(move || {
+        let mut term = Terminal { input: input_rx, output: output_tx, typed: VecDeque::new(), size: (40, 120) };
+        if let Err(e) = run_shell(&conn, &mut term) {
+            term.say(&format!("\r\n{:#}\r\n", e).replace("\r\n", "\n").replace('\n', "\r\n"));
+        }
+    });
+    (ShellOutput { output: output_rx, chunk: vec![], at: 0 }, ShellInput { input: input_tx })
+}
+
+fn run_shell(conn: &SSHConnection, term: &mut Terminal) -> Result<()> {
+    let ignored = ignored_options(conn);
+    if !ignored.is_empty() {
+        term.say(&format!("sheesh's ssh client leaves out {} here.\r\n", ignored.join(", ")));
+    }
+    let native = NativeSession::open(conn, term)?;
+    let mut channel = native.session.channel_session()?;
+    // The window's size may have come while connecting.
+    while let Ok(input) = term.input.try_recv() {
+        term.receive(input);
+    }
+    let (rows, cols) = term.size;
+    channel.request_pty("xterm-256color", None, Some((cols.into(), rows.into(), 0, 0)))?;
+    set_env(&mut channel, &native.env);
+    match conn.export_command() {
+        Some(command) => channel.exec(&command)?,
+        None => channel.shell()?,
+    }
+    native.pump(channel, term)
+}
@@ -0,0 +1,67 @@
+//! Notes kept per connection (F11): a markdown file in the notes directory
+//! for the things worth knowing next time — addresses, where credentials
+//! live (not the credentials), quirks of the host. Shared with the
+//! assistant only when asked to; a `<!-- sheesh: share -->` first line
+//! records that, the same way `# sheesh:` lines do in the ssh config.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::store;
+
+const FILE: &str = "notes.md";
+const SHARE: &str = "<!-- sheesh: share -->";
+
+/// One connection's notes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notes {
+    pub text: String,
+    /// Sent to the assistant with every request.
+    pub shared: bool,
+}
+
+impl Notes {
+    /// The notes for the assistant, if shared and not empty.
+    pub fn for_assistant(&self) -> Option<String> {
+        (self.shared && !self.text.trim().is_empty()).then(|| self.text.trim().to_string())
+    }
+}
+
+/// The directory of `alias`'s notes and runbooks under `dir`.
+pub fn host_dir(dir: &Path, alias: &str) -> PathBuf {
+    dir.join(file_name(alias))
+}
+
+/// `alias`'s notes (empty if none yet).
+pub fn load(dir: &Path, alias: &str) -> Result<Notes> {
+    let path = host_dir(dir, alias).join(FILE);
+    let text = match store::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Notes::default()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    Ok(match text.strip_prefix(SHARE) {
+        Some(rest) => Notes { text: rest.strip_prefix('\n').unwrap_or(rest).to_string(), shared: true },
+        None => Notes { text, shared: false },
+    })
+}
+
+/// Keep `notes` for `alias`.
+pub fn save(dir: &Path, alias: &str, notes: &Notes) -> Result<()> {
+    let dir = host_dir(dir, alias);
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = dir.join(FILE);
+    let text = if notes.shared { format!("{}\n{}", SHARE, notes.text) } else { notes.text.clone() };
+    store::write(&path, &text).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+/// `name` as a file name: words of letters, digits and `.` joined by `-`,
+/// at most 48 characters.
+pub fn file_name(name: &str) -> String {
+    let name = name.split(|c: char| !c.is_alphanumeric() && c != '.').filter(|w| !w.is_empty()).collect::<Vec<_>>();
+    let name: String = name.join("-").chars().take(48).collect();
+    let name = name.trim_matches(|c| c == '-' || c == '.');
+    if name.is_empty() { "untitled".to_string() } else { name.to_string() }
+}
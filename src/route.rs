@@ -0,0 +1,298 @@
+//! Connections made through jump hosts (`ProxyJump bastion,db-gw`): the
+//! chain as the edit form shows it, and how far a connection got, read
+//! from what ssh prints while it connects. ssh says nothing about a hop it
+//! logs in to with a key, so a hop counts as reached once ssh prints
+//! something naming it or a later one (a password or host key prompt), and
+//! when the session ends before the far end said anything, the first error
+//! points at the hop that broke.
+
+use crate::ssh::SSHConnection;
+
+/// The separator the edit form and details use between hops.
+pub const ARROW: &str = " → ";
+
+/// Hops as typed in the edit form: separated by arrows (`→` or `->`),
+/// commas or spaces.
+pub fn parse_chain(text: &str) -> Vec<String> {
+    text.replace("->", ",")
+        .split(|c: char| c == ',' || c == '→' || c.is_whitespace())
+        .filter(|hop| !hop.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// One hop of a route and the names ssh may call it by.
+#[derive(Debug, Clone)]
+struct Hop {
+    name: String,
+    /// The alias and host name of a saved connection, or the host of a
+    /// `[user@]host[:port]` hop.
+    names: Vec<String>,
+}
+
+impl Hop {
+    fn new(hop: &str, connections: &[SSHConnection]) -> Self {
+        let names = match connections.iter().find(|c| c.name == hop) {
+            Some(conn) => vec![conn.name.clone(), conn.effective_hostname().to_string()],
+            None => {
+                let host = hop.rsplit_once('@').map_or(hop, |(_, host)| host);
+                let host = host.split_once(':').map_or(host, |(host, _)| host);
+                vec![host.to_string()]
+            }
+        };
+        Self { name: hop.to_string(), names }
+    }
+
+    fn named_in(&self, line: &str) -> bool {
+        self.names.iter().filter(|n| !n.is_empty()).any(|name| mentions(line, name))
+    }
+}
+
+/// Whether `line` has `name` as a whole host name, not as part of a longer
+/// one (`web1` in `web10` or `web1.example.com`).
+fn mentions(line: &str, name: &str) -> bool {
+    let name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    line.match_indices(name).any(|(at, _)| {
+        let before = line[..at].chars().next_back();
+        let mut after = line[at + name.len()..].chars();
+        let next = after.next();
+        // A dot ends a sentence, but goes on to a domain.
+        let longer = next.is_some_and(name_char) || (next == Some('.') && after.next().is_some_and(name_char));
+        !before.is_some_and(name_char) && !longer
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// The first `reached` hops answered; the rest are still ahead.
+    Connecting { reached: usize },
+    /// The far end is talking.
+    Up,
+    /// The session ended at hop `at`.
+    Broken { at: usize, reason: String },
+}
+
+/// A connection's hops, the host itself last, and how far it got.
+#[derive(Debug, Clone)]
+pub struct RouteStatus {
+    hops: Vec<Hop>,
+    pub progress: Progress,
+}
+
+impl RouteStatus {
+    /// For ssh connections that go through jump hosts; `connections` are
+    /// the saved ones, whose host names ssh's messages may use.
+    pub fn new(conn: &SSHConnection, connections: &[SSHConnection]) -> Option<Self> {
+        let jumps = conn.jump_hosts();
+        if jumps.is_empty() || !conn.transport.is_ssh() {
+            return None;
+        }
+        let mut hops: Vec<Hop> = jumps.iter().map(|hop| Hop::new(hop, connections)).collect();
+        let names = vec![conn.name.clone(), conn.effective_hostname().to_string()];
+        hops.push(Hop { name: conn.name.clone(), names });
+        Some(Self { hops, progress: Progress::Connecting { reached: 0 } })
+    }
+
+    /// Whether there is nothing left to learn.
+    pub fn settled(&self) -> bool {
+        !matches!(self.progress, Progress::Connecting { .. })
+    }
+
+    /// Read the session's output so far; `alive` is false once ssh exited.
+    pub fn update(&mut self, output: &str, alive: bool) {
+        if self.settled() {
+            return;
+        }
+        let mut reached = 0;
+        let mut failure: Option<(usize, String)> = None;
+        for line in output.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let named = self.hops.iter().rposition(|hop| hop.named_in(line));
+            let lower = line.to_lowercase();
+            let fatal = FAILURES.iter().any(|f| lower.contains(f));
+            let aftermath = AFTERMATH.iter().any(|f| lower.contains(f));
+            if named.is_none() && !fatal && !aftermath && !SSH_MESSAGES.iter().any(|m| lower.contains(m)) {
+                self.progress = Progress::Up;
+                return;
+            }
+            match named {
+                // "connect to host db-gw port 22: Connection refused"
+                Some(i) if fatal || aftermath => reached = reached.max(i),
+                // A prompt or warning from its sshd.
+                Some(i) => reached = reached.max(i + 1),
+                None => {}
+            }
+            if failure.is_some() || !(fatal || aftermath) {
+                continue;
+            }
+            let reason = line.strip_prefix("ssh: ").unwrap_or(line);
+            failure = Some(match (named, line.find(OPEN_FAILED)) {
+                (Some(i), _) => (i, reason.to_string()),
+                // A hop that was logged in to couldn't open the way on.
+                (None, Some(at)) => (reached.max(1), line[at + OPEN_FAILED.len()..].trim().to_string()),
+                (None, None) => (reached, reason.to_string()),
+            });
+        }
+        let last = self.hops.len() - 1;
+        self.progress = match failure {
+            _ if alive => Progress::Connecting { reached: reached.min(last) },
+            Some((at, reason)) => Progress::Broken { at: at.min(last), reason },
+            // Nothing went wrong that ssh said: stopped at the last hop heard from.
+            None => Progress::Broken { at: reached.saturating_sub(1).min(last), reason: "the session ended".into() },
+        };
+    }
+
+    /// The strip shown over the terminal, while there is something to say.
+    pub fn summary(&self) -> Option<String> {
+        match &self.progress {
+            Progress::Up => None,
+            Progress::Connecting { reached } => {
+                let hops: Vec<String> = self
+                    .hops
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hop)| match i.cmp(reached) {
+                        std::cmp::Ordering::Less => format!("{} ✓", hop.name),
+                        std::cmp::Ordering::Equal => format!("{} …", hop.name),
+                        std::cmp::Ordering::Greater => hop.name.clone(),
+                    })
+                    .collect();
+                Some(format!("Connecting: {}", hops.join(ARROW)))
+            }
+            Progress::Broken { at, reason } => Some(match at.checked_sub(1).map(|i| &self.hops[i].name) {
+                Some(from) => format!("✗ Route broke at {} (from {}): {}", self.hops[*at].name, from, reason),
+                None => format!("✗ Route broke at {}: {}", self.hops[*at].name, reason),
+            }),
+        }
+    }
+}
+
+/// How ssh reports that a jump host couldn't open the next connection.
+const OPEN_FAILED: &str = "open failed:";
+
+/// Lowercased parts of the messages that end a connection at a hop.
+const FAILURES: [&str; 12] = [
+    "could not resolve hostname",
+    "connection refused",
+    "connection timed out",
+    "operation timed out",
+    "no route to host",
+    "network is unreachable",
+    "permission denied (",
+    "host key verification failed",
+    "too many authentication failures",
+    OPEN_FAILED,
+    "received disconnect",
+    "remote host identification has changed",
+];
+
+/// What follows a failure further along the chain; the cause only when
+/// nothing came before it.
+const AFTERMATH: [&str; 5] =
+    ["stdio forwarding failed", "kex_exchange_identification", "connection closed by", "connection reset", "banner exchange"];
+
+/// Lowercased parts of the other things ssh prints while connecting.
+const SSH_MESSAGES: [&str; 24] = [
+    "ssh:",
+    "warning:",
+    "the authenticity of host",
+    "key fingerprint is",
+    "this key is not known",
+    "this host key is known",
+    "are you sure you want to continue",
+    "please type 'yes'",
+    "enter passphrase",
+    "password:",
+    "verification code",
+    "permission denied",
+    "@@@",
+    "it is possible that",
+    "someone could be",
+    "it is also possible",
+    "the fingerprint for",
+    "please contact",
+    "add correct host key",
+    "offending",
+    "host key for",
+    "load key",
+    "bad owner or permissions",
+    "pseudo-terminal will not",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::Transport;
+
+    fn saved(name: &str, hostname: &str, jump: Option<&str>) -> SSHConnection {
+        SSHConnection {
+            name: name.into(),
+            hostname: hostname.into(),
+            extra_options: jump.map(|hops| format!("ProxyJump {}", hops)).into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn route() -> RouteStatus {
+        let bastion = saved("bastion", "bastion.example.com", None);
+        RouteStatus::new(&saved("db", "10.0.0.9", Some("bastion")), &[bastion]).unwrap()
+    }
+
+    #[test]
+    fn parse_chain_takes_any_separator() {
+        assert_eq!(parse_chain("bastion → db-gw"), ["bastion", "db-gw"]);
+        assert_eq!(parse_chain("bastion->db-gw, core"), ["bastion", "db-gw", "core"]);
+        assert!(parse_chain("  ").is_empty());
+    }
+
+    #[test]
+    fn mentions_whole_host_names_only() {
+        assert!(mentions("connect to host web1 port 22", "web1"));
+        assert!(mentions("Connection closed by web1.", "web1"));
+        assert!(!mentions("connect to host web10 port 22", "web1"));
+        assert!(!mentions("connect to host web1.example.com", "web1"));
+        assert!(!mentions("connect to host my-web1", "web1"));
+    }
+
+    #[test]
+    fn only_jumping_ssh_connections_have_a_route() {
+        assert!(RouteStatus::new(&saved("db", "10.0.0.9", None), &[]).is_none());
+        let serial = SSHConnection { transport: Transport::parse("serial COM3").unwrap(), ..saved("db", "", Some("b")) };
+        assert!(RouteStatus::new(&serial, &[]).is_none());
+    }
+
+    #[test]
+    fn a_prompt_from_the_far_end_means_the_hops_before_answered() {
+        let mut route = route();
+        route.update("user@10.0.0.9's password: ", true);
+        assert_eq!(route.progress, Progress::Connecting { reached: 1 });
+        assert_eq!(route.summary().as_deref(), Some("Connecting: bastion ✓ → db …"));
+        route.update("user@10.0.0.9's password: \nWelcome to Ubuntu", true);
+        assert_eq!(route.progress, Progress::Up);
+        assert!(route.settled() && route.summary().is_none());
+    }
+
+    #[test]
+    fn a_failure_naming_a_hop_breaks_there() {
+        let mut route = route();
+        route.update("ssh: connect to host bastion.example.com port 22: Connection refused", false);
+        let reason = "connect to host bastion.example.com port 22: Connection refused".to_string();
+        assert_eq!(route.progress, Progress::Broken { at: 0, reason });
+    }
+
+    #[test]
+    fn a_jump_host_that_cannot_open_the_way_on_breaks_at_the_next_hop() {
+        let mut route = route();
+        route.update("channel 0: open failed: connect failed: Connection refused\nstdio forwarding failed", false);
+        assert_eq!(
+            route.summary().as_deref(),
+            Some("✗ Route broke at db (from bastion): connect failed: Connection refused")
+        );
+    }
+
+    #[test]
+    fn a_session_that_ends_quietly_breaks_at_the_last_hop_heard_from() {
+        let mut route = route();
+        route.update("", false);
+        assert_eq!(route.progress, Progress::Broken { at: 0, reason: "the session ended".into() });
+    }
+}
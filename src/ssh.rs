@@ -1,6 +1,15 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+use sheesh_tools::{ExecOutput, SshContext};
+
+use crate::native::{NativeContext, NativeSession};
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
 pub struct SSHConnection {
     /// Matches the `Host` alias in ~/.ssh/config
     pub name: String,
@@ -11,28 +20,555 @@ pub struct SSHConnection {
     pub identity_file: Option<String>,
     /// Extra SSH options as key=value pairs (e.g. "ForwardAgent yes")
     pub extra_options: Vec<String>,
+    /// `NAME=value` pairs ssh asks the server to set (`SetEnv`); the server
+    /// only takes the names its `AcceptEnv` allows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub set_env: Vec<String>,
+    /// Local variables ssh passes on by name (`SendEnv`), patterns allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub send_env: Vec<String>,
+    /// `NAME=value` pairs exported in the remote shell before it starts, for
+    /// servers that accept none over ssh. The remote shell expands `~` and
+    /// `$VAR` in values. Kept in `# sheesh: export` lines above the `Host`
+    /// line, which ssh ignores.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub export_env: Vec<String>,
+    /// How sheesh reaches this connection; anything but ssh is kept in a
+    /// `# sheesh:` line above the `Host` line.
+    #[serde(default, skip_serializing_if = "Transport::is_ssh")]
+    pub transport: Transport,
+    /// The remote shell and OS, when declared rather than left to
+    /// detection; kept in a `# sheesh: platform` line.
+    #[serde(default, skip_serializing_if = "Platform::is_auto")]
+    pub platform: Platform,
+    /// The ssh client sessions are made with; the native one is kept in a
+    /// `# sheesh: backend native` line.
+    #[serde(default, skip_serializing_if = "Backend::is_system")]
+    pub backend: Backend,
+    /// The whole `Host` line, when it names more than this alias (e.g.
+    /// `web1 web2 web-*`), so saving writes the aliases back together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_patterns: Option<String>,
+    /// What `Host *` and other matching pattern blocks add to this alias.
+    /// Worked out on load and never written back.
+    #[serde(skip)]
+    pub inherited: Inherited,
+}
+
+/// Settings a connection picks up from blocks other than its own. ssh uses
+/// the first value it finds for each setting, so blocks above the alias's
+/// own override it and blocks below only fill in what it leaves unset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inherited {
+    pub overrides: HostSettings,
+    pub defaults: HostSettings,
+}
+
+/// What a connection opens in its terminal panel.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Transport {
+    #[default]
+    Ssh,
+    /// A local serial device, such as the console cable of a switch or board.
+    Serial { device: String, baud: u32 },
+    /// Telnet, for legacy gear without ssh. Unencrypted.
+    Telnet { host: String, port: u16 },
+    /// A bare TCP connection, e.g. to a terminal server's console port.
+    /// Unencrypted.
+    Tcp { host: String, port: u16 },
+}
+
+/// The speed most console ports default to.
+pub const DEFAULT_BAUD: u32 = 9600;
+
+pub const TELNET_PORT: u16 = 23;
+
+impl Transport {
+    pub fn is_ssh(&self) -> bool {
+        *self == Transport::Ssh
+    }
+
+    /// Read a transport as written after `# sheesh:` or in the edit form,
+    /// e.g. `serial /dev/ttyUSB0 115200`, `telnet core-sw1` or
+    /// `tcp 10.0.0.5 2003`. Empty or `ssh` is ssh.
+    pub fn parse(text: &str) -> Option<Transport> {
+        let mut words = text.split_whitespace();
+        match words.next() {
+            None | Some("ssh") => Some(Transport::Ssh),
+            Some("serial") => {
+                let device = words.next()?.to_string();
+                let baud = match words.next() {
+                    Some(baud) => baud.parse().ok()?,
+                    None => DEFAULT_BAUD,
+                };
+                Some(Transport::Serial { device, baud })
+            }
+            Some("telnet") => {
+                let host = words.next()?.to_string();
+                let port = match words.next() {
+                    Some(port) => port.parse().ok()?,
+                    None => TELNET_PORT,
+                };
+                Some(Transport::Telnet { host, port })
+            }
+            Some("tcp") => {
+                let host = words.next()?.to_string();
+                let port = words.next()?.parse().ok()?;
+                Some(Transport::Tcp { host, port })
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// The inverse of [`Transport::parse`]; empty for ssh.
+    pub fn directive(&self) -> String {
+        match self {
+            Transport::Ssh => String::new(),
+            Transport::Serial { device, baud } => format!("serial {} {}", device, baud),
+            Transport::Telnet { host, port } => format!("telnet {} {}", host, port),
+            Transport::Tcp { host, port } => format!("tcp {} {}", host, port),
+        }
+    }
+
+    /// How the connection is made, in words, for everything but ssh.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Transport::Ssh => None,
+            Transport::Serial { device, baud } => Some(format!("serial console {} at {} baud", device, baud)),
+            Transport::Telnet { host, port } => Some(format!("telnet to {}:{}, unencrypted", host, port)),
+            Transport::Tcp { host, port } => Some(format!("raw TCP to {}:{}, unencrypted", host, port)),
+        }
+    }
+
+    /// The warning shown over a session that crosses the network in the
+    /// clear.
+    pub fn insecure_warning(&self) -> Option<&'static str> {
+        match self {
+            Transport::Telnet { .. } => Some(
+                "⚠ Insecure protocol: telnet is unencrypted. What you type, passwords included, crosses the network in the clear.",
+            ),
+            Transport::Tcp { .. } => Some(
+                "⚠ Insecure protocol: raw TCP is unencrypted. What you type, passwords included, crosses the network in the clear.",
+            ),
+            Transport::Ssh | Transport::Serial { .. } => None,
+        }
+    }
+}
+
+/// Which ssh client an ssh connection's sessions are made with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Backend {
+    /// The ssh binary, in a PTY or under a session holder.
+    #[default]
+    System,
+    /// sheesh's own client ([`crate::native`]), which asks for host keys,
+    /// passwords and passphrases itself and runs commands and downloads
+    /// over channels rather than ssh and scp processes.
+    Native,
+}
+
+impl Backend {
+    pub fn is_system(&self) -> bool {
+        *self == Backend::System
+    }
+
+    /// Read a backend as written after `# sheesh: backend` or in the edit
+    /// form: empty or `system`, or `native`.
+    pub fn parse(text: &str) -> Option<Backend> {
+        match text.trim() {
+            "" | "system" => Some(Backend::System),
+            "native" => Some(Backend::Native),
+            _ => None,
+        }
+    }
+
+    /// The inverse of [`Backend::parse`]; empty for the ssh binary.
+    pub fn text(&self) -> &'static str {
+        match self {
+            Backend::System => "",
+            Backend::Native => "native",
+        }
+    }
+}
+
+/// The settings of one or more pattern blocks, first value wins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostSettings {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<String>,
+    /// Any other settings, as "key value".
+    pub options: Vec<String>,
+    /// The `Host` lines these came from.
+    pub sources: Vec<String>,
+}
+
+impl HostSettings {
+    /// Take `key value` unless an earlier block already set `key`. Keys
+    /// match case-insensitively and are kept as written.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let value = value.to_string();
+        match key.to_lowercase().as_str() {
+            "hostname" => self.hostname = self.hostname.take().or(Some(value)),
+            "user" => self.user = self.user.take().or(Some(value)),
+            "port" => self.port = self.port.or(Some(value.parse().unwrap_or(22))),
+            "identityfile" => self.identity_file = self.identity_file.take().or(Some(value)),
+            _ => {
+                let same = |o: &String| o.split_whitespace().next().is_some_and(|k| k.eq_ignore_ascii_case(key));
+                if !self.options.iter().any(same) {
+                    self.options.push(format!("{} {}", key, value));
+                }
+            }
+        }
+    }
+}
+
+/// Login shells told apart from the OS words of a `platform` line.
+const SHELLS: [&str; 11] = ["bash", "zsh", "fish", "sh", "dash", "ash", "ksh", "mksh", "tcsh", "csh", "busybox"];
+
+/// The remote environment commands are written for, e.g. `fish` on
+/// `Alpine`. Either half left out is detected instead.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Platform {
+    pub shell: Option<String>,
+    pub os: Option<String>,
+}
+
+impl Platform {
+    pub fn is_auto(&self) -> bool {
+        self.shell.is_none() && self.os.is_none()
+    }
+
+    /// Read a platform as written after `# sheesh: platform` or in the edit
+    /// form: a shell name, an OS, or both, e.g. `zsh FreeBSD 14` or
+    /// `busybox`. Words that aren't a known shell are the OS.
+    pub fn parse(text: &str) -> Platform {
+        let mut shell = None;
+        let mut os = vec![];
+        for word in text.split_whitespace() {
+            match SHELLS.iter().find(|s| s.eq_ignore_ascii_case(word)) {
+                Some(name) if shell.is_none() => shell = Some(name.to_string()),
+                _ => os.push(word),
+            }
+        }
+        Platform { shell, os: (!os.is_empty()).then(|| os.join(" ")) }
+    }
+
+    /// The inverse of [`Platform::parse`]; empty when nothing is declared.
+    pub fn text(&self) -> String {
+        self.shell.iter().chain(&self.os).map(String::as_str).collect::<Vec<_>>().join(" ")
+    }
 }
 
 impl SSHConnection {
+    /// The host ssh connects to: the `HostName`, or else the alias itself.
+    pub fn effective_hostname(&self) -> &str {
+        let inherited = &self.inherited;
+        inherited.overrides.hostname.as_deref()
+            .or(Some(self.hostname.as_str()).filter(|h| !h.is_empty()))
+            .or(inherited.defaults.hostname.as_deref())
+            .unwrap_or(&self.name)
+    }
+
+    /// The user ssh logs in as, or "" to leave it to ssh (the local user).
+    pub fn effective_user(&self) -> &str {
+        let inherited = &self.inherited;
+        inherited.overrides.user.as_deref()
+            .or(Some(self.user.as_str()).filter(|u| !u.is_empty()))
+            .or(inherited.defaults.user.as_deref())
+            .unwrap_or("")
+    }
+
+    pub fn effective_port(&self) -> u16 {
+        let inherited = &self.inherited;
+        inherited.overrides.port.or(Some(self.port).filter(|p| *p != 0)).or(inherited.defaults.port).unwrap_or(22)
+    }
+
+    pub fn effective_identity_file(&self) -> Option<&str> {
+        let inherited = &self.inherited;
+        inherited.overrides.identity_file.as_deref()
+            .or(self.identity_file.as_deref())
+            .or(inherited.defaults.identity_file.as_deref())
+    }
+
+    /// Extra options in the order ssh should see them; ssh keeps the first
+    /// value of each, so overrides go before the connection's own.
+    pub fn effective_options(&self) -> impl Iterator<Item = &String> {
+        let inherited = &self.inherited;
+        inherited.overrides.options.iter().chain(&self.extra_options).chain(&inherited.defaults.options)
+    }
+
+    /// The value of option `key` (case-insensitive, e.g. `hostkeyalias`)
+    /// as ssh will use it.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.effective_options().find_map(|opt| {
+            let (name, value) = opt.trim().split_once([' ', '=', '\t'])?;
+            name.eq_ignore_ascii_case(key).then(|| value.trim_start_matches([' ', '=', '\t']))
+        })
+    }
+
+    /// The jump hosts ssh goes through first, in order (`ProxyJump`).
+    pub fn jump_hosts(&self) -> Vec<String> {
+        match self.option("proxyjump") {
+            None | Some("none") => vec![],
+            Some(hops) => hops.split(',').map(str::trim).filter(|h| !h.is_empty()).map(String::from).collect(),
+        }
+    }
+
+    /// Arguments for an interactive session. With variables to export, ssh
+    /// runs the export and then the user's login shell in their place.
     pub fn ssh_args(&self) -> Vec<String> {
+        let mut args = self.common_args("-p");
+        let Some(command) = self.export_command() else {
+            args.push(self.destination());
+            return args;
+        };
+        args.push("-t".into());
+        args.push(self.destination());
+        args.push(command);
+        args
+    }
+
+    /// The command run in place of the login shell to export `export_env`
+    /// first, when there is anything to export.
+    pub fn export_command(&self) -> Option<String> {
+        if self.export_env.is_empty() {
+            return None;
+        }
+        let exports: Vec<String> = self.export_env.iter().map(|pair| env_word(pair)).collect();
+        Some(format!("export {}; exec \"$SHELL\" -l", exports.join(" ")))
+    }
+
+    /// Arguments for `scp` reaching this connection, up to (not including)
+    /// the source and destination. scp spells the port flag `-P`.
+    pub fn scp_args(&self) -> Vec<String> {
+        self.common_args("-P")
+    }
+
+    /// Port, key and options, with inherited settings applied: ssh is given
+    /// the host name rather than the alias, so it wouldn't match pattern
+    /// blocks like `web-*` on its own.
+    fn common_args(&self, port_flag: &str) -> Vec<String> {
         let mut args = vec![];
 
-        if self.port != 0 && self.port != 22 {
-            args.push("-p".into());
-            args.push(self.port.to_string());
+        let port = self.effective_port();
+        if port != 22 {
+            args.push(port_flag.into());
+            args.push(port.to_string());
         }
 
-        if let Some(ref key) = self.identity_file {
+        if let Some(key) = self.effective_identity_file() {
             args.push("-i".into());
-            args.push(key.clone());
+            args.push(key.to_string());
         }
 
-        for opt in &self.extra_options {
+        for opt in self.effective_options() {
             args.push("-o".into());
             args.push(opt.clone());
         }
 
-        args.push(format!("{}@{}", self.user, self.hostname));
+        if !self.set_env.is_empty() {
+            let pairs: Vec<String> = self.set_env.iter().map(|pair| env_word(pair)).collect();
+            args.push("-o".into());
+            args.push(format!("SetEnv {}", pairs.join(" ")));
+        }
+        if !self.send_env.is_empty() {
+            args.push("-o".into());
+            args.push(format!("SendEnv {}", self.send_env.join(" ")));
+        }
+
         args
     }
+
+    /// `user@host` (or just `host` when no user is configured), or the
+    /// device or host of a connection that isn't ssh.
+    pub fn destination(&self) -> String {
+        match &self.transport {
+            Transport::Ssh => {}
+            Transport::Serial { device, .. } => return device.clone(),
+            Transport::Telnet { host, .. } | Transport::Tcp { host, .. } => return host.clone(),
+        }
+        match self.effective_user() {
+            "" => self.effective_hostname().to_string(),
+            user => format!("{}@{}", user, self.effective_hostname()),
+        }
+    }
+}
+
+/// Split the value of a `SetEnv` line into its `NAME=value` pairs; a value
+/// with spaces is written in double quotes, which are dropped.
+pub fn split_env(value: &str) -> Vec<String> {
+    let mut pairs = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    pairs.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        pairs.push(word);
+    }
+    pairs
+}
+
+/// A `NAME=value` pair as ssh config (or a shell) reads it: quoted when the
+/// value has spaces.
+pub fn env_word(pair: &str) -> String {
+    match pair.split_once('=') {
+        Some((name, value)) if value.contains(char::is_whitespace) => format!("{}=\"{}\"", name, value),
+        _ => pair.to_string(),
+    }
+}
+
+/// Copy `remote` from the connection's host into `dir` with scp and return
+/// the local path. Existing files are not overwritten; a numeric suffix is
+/// added instead. Runs non-interactively, so password-only hosts fail with
+/// an explanation rather than prompting over the TUI. scp is made to use
+/// the SFTP protocol (`-s`), so the path reaches the host as written instead
+/// of through its shell; the native backend uses sftp itself.
+pub fn download(conn: &SSHConnection, remote: &str, dir: &Path) -> Result<PathBuf> {
+    let name = remote.trim_end_matches('/').rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("download");
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let mut local = dir.join(name);
+    let mut n = 1;
+    while local.exists() {
+        local = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    if conn.backend == Backend::Native {
+        NativeSession::batch(conn)?.download(remote, &local)?;
+        return Ok(local);
+    }
+
+    let output = Command::new("scp")
+        .args(conn.scp_args())
+        .args(["-q", "-p", "-s", "-o", "BatchMode=yes"])
+        .arg(format!("{}:{}", conn.destination(), remote))
+        .arg(&local)
+        .output()
+        .context("running scp")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if stderr.contains("Permission denied") || stderr.contains("passphrase") {
+            bail!("scp could not authenticate without a prompt ({}); downloads need key-based auth or ssh-agent", stderr);
+        }
+        bail!("scp failed: {}", stderr);
+    }
+    Ok(local)
+}
+
+/// Run `command` on the connection's host in a session of its own, beside
+/// the terminal's, and return what it printed. It runs under `sh` whatever
+/// the login shell is. Non-interactive like [`download`]: hosts that would
+/// prompt for a password fail instead.
+pub fn exec(conn: &SSHConnection, command: &str) -> Result<String> {
+    let script = format!("sh -c '{}'", command.replace('\'', "'\\''"));
+    let output = context(conn).exec(&script)?;
+    if !output.success() && output.stdout.trim().is_empty() {
+        bail!("ssh failed: {}", output.stderr.trim());
+    }
+    Ok(output.stdout)
+}
+
+/// The connection's host as tools reach it outside the terminal: commands
+/// run in non-interactive sessions beside the terminal's, as with [`exec`]
+/// but through the login shell and with their exit status. The native
+/// client keeps one connection for all of them.
+pub fn context(conn: &SSHConnection) -> Box<dyn SshContext + Send + Sync> {
+    match conn.backend {
+        Backend::System => Box::new(SshCommand(conn.clone())),
+        Backend::Native => Box::new(NativeContext::new(conn.clone())),
+    }
+}
+
+/// A run of the ssh binary per command.
+struct SshCommand(SSHConnection);
+
+impl SshContext for SshCommand {
+    fn exec(&self, command: &str) -> Result<ExecOutput> {
+        let output = Command::new("ssh")
+            .args(self.0.common_args("-p"))
+            .args(["-T", "-o", "BatchMode=yes"])
+            .arg(self.0.destination())
+            .arg(command)
+            .output()
+            .context("running ssh")?;
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        // 255 is ssh's own failure; anything else is the command's business.
+        if output.status.code() == Some(255) {
+            let stderr = stderr.trim();
+            if stderr.contains("Permission denied") || stderr.contains("passphrase") {
+                bail!("ssh could not authenticate without a prompt ({}); this needs key-based auth or ssh-agent", stderr);
+            }
+            bail!("ssh failed: {}", stderr);
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        Ok(ExecOutput { stdout, stderr, status: output.status.code() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_parse_reads_each_kind() {
+        assert_eq!(Transport::parse(""), Some(Transport::Ssh));
+        assert_eq!(Transport::parse("  ssh "), Some(Transport::Ssh));
+        assert_eq!(
+            Transport::parse("serial /dev/ttyUSB0 115200"),
+            Some(Transport::Serial { device: "/dev/ttyUSB0".into(), baud: 115200 })
+        );
+        assert_eq!(
+            Transport::parse("serial COM3"),
+            Some(Transport::Serial { device: "COM3".into(), baud: DEFAULT_BAUD })
+        );
+        assert_eq!(
+            Transport::parse("telnet core-sw1"),
+            Some(Transport::Telnet { host: "core-sw1".into(), port: TELNET_PORT })
+        );
+        assert_eq!(Transport::parse("tcp 10.0.0.5 2003"), Some(Transport::Tcp { host: "10.0.0.5".into(), port: 2003 }));
+    }
+
+    #[test]
+    fn transport_parse_refuses_what_it_cannot_read() {
+        assert_eq!(Transport::parse("serail /dev/ttyS0"), None);
+        assert_eq!(Transport::parse("serial"), None);
+        assert_eq!(Transport::parse("serial /dev/ttyS0 fast"), None);
+        assert_eq!(Transport::parse("telnet core-sw1 70000"), None);
+        // tcp has no default port.
+        assert_eq!(Transport::parse("tcp 10.0.0.5"), None);
+    }
+
+    #[test]
+    fn transport_directive_reads_back() {
+        for text in ["serial /dev/ttyUSB0 115200", "telnet core-sw1 2323", "tcp 10.0.0.5 2003"] {
+            let transport = Transport::parse(text).unwrap();
+            assert_eq!(Transport::parse(&transport.directive()), Some(transport));
+        }
+        assert_eq!(Transport::Ssh.directive(), "");
+    }
+
+    #[test]
+    fn split_env_keeps_quoted_spaces() {
+        assert_eq!(split_env(r#"A=1  B="two words" C="""#), ["A=1", "B=two words", "C="]);
+        assert!(split_env("   ").is_empty());
+    }
+
+    #[test]
+    fn env_word_quotes_what_split_env_unquotes() {
+        let pairs = ["LANG=C", "GREETING=hello there"];
+        let line = pairs.map(env_word).join(" ");
+        assert_eq!(line, r#"LANG=C GREETING="hello there""#);
+        assert_eq!(split_env(&line), pairs);
+    }
 }
@@ -0,0 +1,136 @@
+//! The encrypted store (`encrypt_store = true`): what sheesh keeps about
+//! hosts in files of its own — notes, runbooks and the host memory — is
+//! written as age files (`notes.md.age`) rather than plain text. The files
+//! are encrypted to a key generated on first use, which is kept in
+//! `store.key` in the data directory, itself encrypted with the passphrase;
+//! so the passphrase is asked for once at startup, not for every file.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use age::secrecy::{ExposeSecret, SecretString};
+use age::{scrypt, x25519};
+use anyhow::{Context, Result, anyhow};
+
+/// The unlocked key, while the store is encrypted.
+static KEY: Mutex<Option<x25519::Identity>> = Mutex::new(None);
+
+const SEALED: &str = "age";
+
+/// Where the passphrase-protected key is kept.
+pub fn key_path() -> PathBuf {
+    dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("sheesh").join("store.key")
+}
+
+/// Whether the key exists; until it does, unlocking chooses the passphrase.
+pub fn has_key() -> bool {
+    key_path().exists()
+}
+
+/// Open the key with `passphrase`, generating it on first use, and encrypt
+/// files from now on.
+pub fn unlock(passphrase: &str) -> Result<()> {
+    let path = key_path();
+    let passphrase = SecretString::from(passphrase.to_string());
+    let key = if path.exists() {
+        let sealed = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        let text = age::decrypt(&scrypt::Identity::new(passphrase), &sealed).map_err(|e| match e {
+            age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys => {
+                anyhow!("wrong passphrase for {}", path.display())
+            }
+            e => anyhow!("{}: {}", path.display(), e),
+        })?;
+        let text = String::from_utf8_lossy(&text);
+        text.trim().parse::<x25519::Identity>().map_err(|e| anyhow!("{}: {}", path.display(), e))?
+    } else {
+        let key = x25519::Identity::generate();
+        let sealed = age::encrypt(&scrypt::Recipient::new(passphrase), key.to_string().expose_secret().as_bytes())?;
+        write_private(&path, &sealed)?;
+        log::info!("[store] created {}", path.display());
+        key
+    };
+    *KEY.lock().unwrap() = Some(key);
+    Ok(())
+}
+
+/// `path` as it is kept encrypted: `notes.md` → `notes.md.age`.
+fn sealed(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", SEALED));
+    PathBuf::from(name)
+}
+
+/// Read `path`, from its `.age` file when there is one. Without the key,
+/// an encrypted file reads as an error rather than as missing, so nothing
+/// is written over it.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let sealed_path = sealed(path);
+    if !sealed_path.exists() {
+        return std::fs::read_to_string(path);
+    }
+    let key = KEY.lock().unwrap();
+    let Some(key) = key.as_ref() else {
+        return Err(io::Error::other(format!("{} is encrypted; set encrypt_store to read it", sealed_path.display())));
+    };
+    let text = age::decrypt(key, &std::fs::read(&sealed_path)?).map_err(io::Error::other)?;
+    String::from_utf8(text).map_err(io::Error::other)
+}
+
+/// Write `text` to `path`: encrypted to `<path>.age`, replacing a plain
+/// copy, while the store is unlocked, and as it is otherwise. Without the
+/// key, a file that has an encrypted copy is an error rather than written
+/// plain, since reading would keep finding the stale `.age` file. Returns
+/// the file written.
+pub fn write(path: &Path, text: &str) -> io::Result<PathBuf> {
+    let key = KEY.lock().unwrap();
+    let sealed_path = sealed(path);
+    let Some(key) = key.as_ref() else {
+        if sealed_path.exists() {
+            return Err(io::Error::other(format!(
+                "{} is encrypted; set encrypt_store to change it",
+                sealed_path.display()
+            )));
+        }
+        std::fs::write(path, text)?;
+        return Ok(path.to_path_buf());
+    };
+    let sealed_text = age::encrypt(&key.to_public(), text.as_bytes()).map_err(io::Error::other)?;
+    write_private(&sealed_path, &sealed_text).map_err(io::Error::other)?;
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(sealed_path),
+    }
+}
+
+/// Encrypt the plain files under each of `paths` (files or directories),
+/// e.g. those written before `encrypt_store` was turned on. Returns how
+/// many there were.
+pub fn seal_existing(paths: &[PathBuf]) -> Result<usize> {
+    let mut count = 0;
+    for path in paths {
+        if path.is_dir() {
+            let entries = std::fs::read_dir(path).with_context(|| format!("reading {}", path.display()))?;
+            let children: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+            count += seal_existing(&children)?;
+        } else if path.is_file() && path.extension().is_none_or(|ext| ext != SEALED) {
+            let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+            write(path, &text).with_context(|| format!("encrypting {}", path.display()))?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Write `bytes` to `path`, readable only by the user where that can be said.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).with_context(|| format!("writing {}", path.display()))?;
+    io::Write::write_all(&mut file, bytes).with_context(|| format!("writing {}", path.display()))
+}
@@ -7,8 +7,11 @@ use ratatui::{
 };
 
 use crate::{
+    agent::AgentStatus,
     event::Action,
-    ssh::SSHConnection,
+    known_hosts::KnownHosts,
+    route::{self, ARROW},
+    ssh::{Backend, Inherited, Platform, SSHConnection, Transport, env_word, split_env},
     ui::theme::Theme,
 };
 
@@ -24,6 +27,16 @@ pub enum ListingMode {
     Editing { is_new: bool },
     /// Confirm delete
     ConfirmDelete,
+    /// The host keys ssh has recorded for the selection
+    KnownHosts,
+}
+
+/// The known_hosts view of one connection.
+pub struct KnownHostsView {
+    pub known: KnownHosts,
+    pub selected: usize,
+    /// The last removal, or why it failed.
+    pub message: Option<(String, bool)>,
 }
 
 /// Form state for add/edit.
@@ -31,22 +44,44 @@ pub enum ListingMode {
 pub struct EditForm {
     pub name: String,
     pub description: String,
+    /// Blank for ssh, or a transport as `Transport::parse` reads it.
+    pub transport: String,
     pub hostname: String,
     pub user: String,
     pub port: String,
+    /// Jump hosts in order, as `route::parse_chain` reads them; kept in a
+    /// `ProxyJump` line.
+    pub jump: String,
     pub identity_file: String,
+    /// `NAME=value` pairs separated by spaces, double quotes around values
+    /// with spaces; the same for `export_env`.
+    pub set_env: String,
+    /// Variable names separated by spaces.
+    pub send_env: String,
+    pub export_env: String,
+    /// Blank to detect, or a platform as `Platform::parse` reads it.
+    pub platform: String,
+    /// Blank for the ssh binary, or `native`.
+    pub backend: String,
     pub extra_options: String,
+    /// Carried over from the connection being edited.
+    pub host_patterns: Option<String>,
+    pub inherited: Inherited,
     /// Which field is focused (0-based index)
     pub field: usize,
+    /// Why the last enter did not save, until the form is next typed in.
+    pub error: Option<String>,
 }
 
 impl EditForm {
-    const FIELD_COUNT: usize = 7;
+    const FIELD_COUNT: usize = 14;
 
     pub fn from_connection(conn: &SSHConnection) -> Self {
+        let (jump, extra_options) = split_jump(&conn.extra_options);
         Self {
             name: conn.name.clone(),
             description: conn.description.clone(),
+            transport: conn.transport.directive(),
             hostname: conn.hostname.clone(),
             user: conn.user.clone(),
             port: if conn.port == 0 || conn.port == 22 {
@@ -54,48 +89,84 @@ impl EditForm {
             } else {
                 conn.port.to_string()
             },
+            jump: jump.join(ARROW),
             identity_file: conn.identity_file.clone().unwrap_or_default(),
-            extra_options: conn.extra_options.join(", "),
+            set_env: conn.set_env.iter().map(|pair| env_word(pair)).collect::<Vec<_>>().join(" "),
+            send_env: conn.send_env.join(" "),
+            export_env: conn.export_env.iter().map(|pair| env_word(pair)).collect::<Vec<_>>().join(" "),
+            platform: conn.platform.text(),
+            backend: conn.backend.text().to_string(),
+            extra_options: extra_options.join(", "),
+            host_patterns: conn.host_patterns.clone(),
+            inherited: conn.inherited.clone(),
             field: 0,
+            error: None,
         }
     }
 
-    pub fn to_connection(&self) -> SSHConnection {
-        SSHConnection {
+    /// The connection the form describes, or why it can't be saved.
+    pub fn to_connection(&self) -> Result<SSHConnection, String> {
+        let transport = Transport::parse(&self.transport).ok_or_else(|| {
+            format!(
+                "Connect via: \"{}\" is not serial DEVICE [BAUD], telnet HOST [PORT] or tcp HOST PORT",
+                self.transport.trim()
+            )
+        })?;
+        Ok(SSHConnection {
             name: self.name.trim().to_string(),
             description: self.description.trim().to_string(),
+            transport,
             hostname: self.hostname.trim().to_string(),
             user: self.user.trim().to_string(),
-            port: self.port.parse().unwrap_or(22),
+            // Left unset when blank, so an inherited port still applies.
+            port: self.port.parse().unwrap_or(0),
             identity_file: {
                 let s = self.identity_file.trim().to_string();
                 if s.is_empty() { None } else { Some(s) }
             },
-            extra_options: self.extra_options
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect(),
-        }
+            set_env: split_env(&self.set_env).into_iter().filter(|pair| pair.contains('=')).collect(),
+            send_env: self.send_env.split_whitespace().map(String::from).collect(),
+            export_env: split_env(&self.export_env).into_iter().filter(|pair| pair.contains('=')).collect(),
+            platform: Platform::parse(&self.platform),
+            backend: Backend::parse(&self.backend).unwrap_or_default(),
+            extra_options: {
+                let hops = route::parse_chain(&self.jump);
+                let jump = (!hops.is_empty()).then(|| format!("ProxyJump {}", hops.join(",")));
+                jump.into_iter()
+                    .chain(self.extra_options.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()))
+                    .collect()
+            },
+            host_patterns: self.host_patterns.clone(),
+            inherited: self.inherited.clone(),
+        })
     }
 
     fn active_field_mut(&mut self) -> &mut String {
         match self.field {
             0 => &mut self.name,
             1 => &mut self.description,
-            2 => &mut self.hostname,
-            3 => &mut self.user,
-            4 => &mut self.port,
-            5 => &mut self.identity_file,
+            2 => &mut self.transport,
+            3 => &mut self.hostname,
+            4 => &mut self.user,
+            5 => &mut self.port,
+            6 => &mut self.jump,
+            7 => &mut self.identity_file,
+            8 => &mut self.set_env,
+            9 => &mut self.send_env,
+            10 => &mut self.export_env,
+            11 => &mut self.platform,
+            12 => &mut self.backend,
             _ => &mut self.extra_options,
         }
     }
 
     pub fn push_char(&mut self, ch: char) {
+        self.error = None;
         self.active_field_mut().push(ch);
     }
 
     pub fn pop_char(&mut self) {
+        self.error = None;
         self.active_field_mut().pop();
     }
 
@@ -108,6 +179,21 @@ impl EditForm {
     }
 }
 
+/// A connection's own `ProxyJump` hops, and the rest of its extra options.
+fn split_jump(options: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut hops = vec![];
+    let mut rest = vec![];
+    for opt in options {
+        match opt.trim().split_once([' ', '=', '\t']) {
+            Some((key, value)) if key.eq_ignore_ascii_case("proxyjump") && value.trim() != "none" => {
+                hops.extend(value.split(',').map(str::trim).filter(|h| !h.is_empty()).map(String::from));
+            }
+            _ => rest.push(opt.clone()),
+        }
+    }
+    (hops, rest)
+}
+
 pub struct ListingTab {
     pub connections: Vec<SSHConnection>,
     pub list_state: ListState,
@@ -116,6 +202,11 @@ pub struct ListingTab {
     pub form: EditForm,
     /// Index of the connection being edited (None = add)
     pub edit_index: Option<usize>,
+    /// The SSH config is shared or not writable: no add, edit or delete.
+    pub read_only: bool,
+    /// What ssh-agent held when last asked.
+    pub agent: AgentStatus,
+    pub known_hosts: Option<KnownHostsView>,
 }
 
 impl ListingTab {
@@ -131,9 +222,17 @@ impl ListingTab {
             filter: String::new(),
             form: EditForm::default(),
             edit_index: None,
+            read_only: false,
+            agent: AgentStatus::NotRunning,
+            known_hosts: None,
         }
     }
 
+    /// Ask ssh-agent again what it holds.
+    pub fn refresh_agent(&mut self) {
+        self.agent = AgentStatus::query();
+    }
+
     pub fn filtered_indices(&self) -> Vec<usize> {
         if self.filter.is_empty() {
             (0..self.connections.len()).collect()
@@ -210,8 +309,56 @@ impl ListingTab {
         self.mode = ListingMode::Browse;
     }
 
+    /// Select the connection called `name`, clearing the filter.
+    pub fn select(&mut self, name: &str) {
+        self.filter.clear();
+        if let Some(i) = self.connections.iter().position(|c| c.name == name) {
+            self.list_state.select(Some(i));
+        }
+    }
+
+    /// Show the host keys recorded for the selected ssh connection.
+    pub fn open_known_hosts(&mut self) {
+        let Some(conn) = self.selected_connection().filter(|c| c.transport.is_ssh()) else {
+            return;
+        };
+        let (known, message) = match KnownHosts::lookup(conn) {
+            Ok(known) => (known, None),
+            Err(e) => {
+                let host = conn.effective_hostname().to_string();
+                (KnownHosts { file: Default::default(), host, keys: vec![] }, Some((format!("{:#}", e), true)))
+            }
+        };
+        self.known_hosts = Some(KnownHostsView { known, selected: 0, message });
+        self.mode = ListingMode::KnownHosts;
+    }
+
+    fn remove_host_key(&mut self) {
+        let Some(view) = &mut self.known_hosts else {
+            return;
+        };
+        let Some(key) = view.known.keys.get(view.selected).cloned() else {
+            return;
+        };
+        view.message = Some(match view.known.remove(view.selected) {
+            Ok(()) => (
+                format!("Removed the {} key; ssh asks to trust the host's new one on the next connection.", key.kind),
+                false,
+            ),
+            Err(e) => (format!("{:#}", e), true),
+        });
+        view.selected = view.selected.min(view.known.keys.len().saturating_sub(1));
+    }
+
     fn save_form(&mut self) {
-        let conn = self.form.to_connection();
+        let conn = match self.form.to_connection() {
+            Ok(conn) => conn,
+            Err(e) => {
+                self.form.field = 2;
+                self.form.error = Some(e);
+                return;
+            }
+        };
         if let Some(idx) = self.edit_index {
             self.connections[idx] = conn;
         } else {
@@ -227,12 +374,16 @@ impl ListingTab {
 impl Tab for ListingTab {
     fn key_hints(&self) -> Vec<(&str, &str)> {
         match self.mode {
+            ListingMode::Browse if self.read_only => {
+                vec![("enter", "connect"), ("/", "filter"), ("h", "host keys"), ("ctrl+q", "quit")]
+            }
             ListingMode::Browse => vec![
                 ("enter", "connect"),
                 ("a", "add"),
                 ("e", "edit"),
                 ("d", "delete"),
                 ("/", "filter"),
+                ("h", "host keys"),
                 ("ctrl+q", "quit"),
             ],
             ListingMode::Filtering => vec![
@@ -249,6 +400,10 @@ impl Tab for ListingTab {
                 ("y", "confirm delete"),
                 ("n / esc", "cancel"),
             ],
+            ListingMode::KnownHosts => vec![
+                ("d", "remove key"),
+                ("esc", "close"),
+            ],
         }
     }
 
@@ -268,6 +423,7 @@ impl Tab for ListingTab {
                     Action::None
                 }
                 KeyCode::Enter => Action::Confirm,
+                KeyCode::Char('a' | 'e' | 'd') if self.read_only => Action::None,
                 KeyCode::Char('a') => {
                     self.start_add();
                     Action::None
@@ -285,6 +441,11 @@ impl Tab for ListingTab {
                     self.mode = ListingMode::Filtering;
                     Action::None
                 }
+                KeyCode::Char('h') => {
+                    self.open_known_hosts();
+                    Action::None
+                }
+                KeyCode::Char('p') => Action::SwitchProfile,
                 KeyCode::Char('q') if modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
                 _ => Action::None,
             },
@@ -349,6 +510,28 @@ impl Tab for ListingTab {
                 }
                 _ => Action::None,
             },
+
+            ListingMode::KnownHosts => {
+                match code {
+                    KeyCode::Esc | KeyCode::Char('q' | 'h') => {
+                        self.known_hosts = None;
+                        self.mode = ListingMode::Browse;
+                    }
+                    KeyCode::Char('d') | KeyCode::Delete => self.remove_host_key(),
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        if let Some(view) = &mut self.known_hosts {
+                            view.selected = (view.selected + 1).min(view.known.keys.len().saturating_sub(1));
+                        }
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => {
+                        if let Some(view) = &mut self.known_hosts {
+                            view.selected = view.selected.saturating_sub(1);
+                        }
+                    }
+                    _ => {}
+                }
+                Action::None
+            }
         }
     }
 
@@ -367,6 +550,9 @@ impl Tab for ListingTab {
         if self.mode == ListingMode::ConfirmDelete {
             self.render_confirm_delete(frame, area);
         }
+        if self.mode == ListingMode::KnownHosts {
+            self.render_known_hosts(frame, area);
+        }
     }
 }
 
@@ -378,10 +564,11 @@ impl ListingTab {
             Theme::normal_border()
         };
 
+        let read_only = if self.read_only { " (read-only)" } else { "" };
         let filter_title = if !self.filter.is_empty() {
-            format!(" Connections [/{}] ", self.filter)
+            format!(" Connections{} [/{}] ", read_only, self.filter)
         } else {
-            " Connections ".to_string()
+            format!(" Connections{} ", read_only)
         };
 
         let block = Block::bordered()
@@ -394,7 +581,9 @@ impl ListingTab {
             .iter()
             .map(|&i| {
                 let c = &self.connections[i];
-                let host_display = if c.hostname.is_empty() {
+                let host_display = if !c.transport.is_ssh() {
+                    format!("{} ({})", c.name, c.destination())
+                } else if c.hostname.is_empty() {
                     c.name.clone()
                 } else {
                     format!("{} ({})", c.name, c.hostname)
@@ -421,21 +610,70 @@ impl ListingTab {
             .title(Span::styled(" Detail ", Theme::title()));
 
         if let Some(conn) = self.selected_connection() {
-            let port_str = if conn.port == 0 || conn.port == 22 {
+            // Values that don't come from the connection's own block say so.
+            let shown = |effective: &str, own: &str| {
+                if effective == own { effective.to_string() } else { format!("{} (inherited)", effective) }
+            };
+            let port = conn.effective_port();
+            let port_str = if port == 22 && matches!(conn.port, 0 | 22) {
                 "22 (default)".to_string()
             } else {
-                conn.port.to_string()
+                shown(&port.to_string(), &conn.port.to_string())
+            };
+            let key_str = match conn.effective_identity_file() {
+                Some(key) => shown(key, conn.identity_file.as_deref().unwrap_or("")),
+                None => "(none)".to_string(),
             };
-            let key_str = conn.identity_file.as_deref().unwrap_or("(none)").to_string();
-            let lines: Vec<Line> = vec![
-                detail_line("Name", &conn.name),
-                detail_line("Host", &conn.hostname),
-                detail_line("User", &conn.user),
-                detail_line("Port", &port_str),
-                detail_line("Key", &key_str),
-                Line::default(),
-                detail_line("Desc", &conn.description),
-            ];
+            let host_str = if conn.hostname.is_empty() && conn.effective_hostname() == conn.name {
+                conn.name.clone()
+            } else {
+                shown(conn.effective_hostname(), &conn.hostname)
+            };
+            let user_str = shown(conn.effective_user(), &conn.user);
+            let inherited = &conn.inherited;
+            let sources: Vec<&str> =
+                inherited.overrides.sources.iter().chain(&inherited.defaults.sources).map(String::as_str).collect();
+            let sources = sources.join(", ");
+            let link = conn.transport.describe();
+            let agent = self.agent.describe(conn.effective_identity_file());
+            let mut lines: Vec<Line> = match &link {
+                // Nothing ssh reads applies to the other transports.
+                Some(link) => vec![detail_line("Name", &conn.name), detail_line("Via", link)],
+                None => vec![
+                    detail_line("Name", &conn.name),
+                    detail_line("Host", &host_str),
+                    detail_line("User", &user_str),
+                    detail_line("Port", &port_str),
+                    detail_line("Key", &key_str),
+                    detail_line("Agent", &agent),
+                ],
+            };
+            let jumps = conn.jump_hosts();
+            let route = jumps.iter().chain([&conn.name]).map(String::as_str).collect::<Vec<_>>().join(ARROW);
+            if link.is_none() && !jumps.is_empty() {
+                lines.push(detail_line("Route", &route));
+            }
+            if !sources.is_empty() {
+                lines.push(detail_line("Inherits", &sources));
+            }
+            let set_env = conn.set_env.join(" ");
+            let send_env = conn.send_env.join(" ");
+            let export_env = conn.export_env.join(" ");
+            let platform = conn.platform.text();
+            let backend = conn.backend.text().to_string();
+            for (label, value) in [
+                ("SetEnv", &set_env),
+                ("SendEnv", &send_env),
+                ("Export", &export_env),
+                ("Shell/OS", &platform),
+                ("Client", &backend),
+            ] {
+                if !value.is_empty() {
+                    lines.push(detail_line(label, value));
+                }
+            }
+            lines.push(Line::default());
+            lines.push(detail_line("Desc", &conn.description));
 
             let para = Paragraph::new(lines)
                 .block(block)
@@ -460,10 +698,17 @@ impl ListingTab {
         let fields = [
             ("Name", &self.form.name),
             ("Description", &self.form.description),
+            ("Connect via", &self.form.transport),
             ("Hostname", &self.form.hostname),
             ("User", &self.form.user),
             ("Port", &self.form.port),
+            ("Jump via", &self.form.jump),
             ("Identity File", &self.form.identity_file),
+            ("SetEnv", &self.form.set_env),
+            ("SendEnv", &self.form.send_env),
+            ("Export", &self.form.export_env),
+            ("Shell / OS", &self.form.platform),
+            ("SSH Client", &self.form.backend),
             ("Extra Options", &self.form.extra_options),
         ];
 
@@ -479,6 +724,25 @@ impl ListingTab {
                 Span::styled(format!("{}{}", value, cursor), value_style),
             ]));
         }
+        let jump_hint = self.jump_hint();
+        let hint = match self.form.field {
+            2 => "blank for ssh, or serial DEVICE [BAUD], telnet HOST [PORT] or tcp HOST PORT",
+            6 => &jump_hint,
+            8 => "NAME=value pairs the server sets, if its AcceptEnv allows them",
+            9 => "local variables passed on by name, e.g. LANG LC_*",
+            10 => "NAME=value pairs exported in the remote shell before it starts",
+            11 => "blank to detect, or the shell and/or OS commands are written for, e.g. fish Alpine, busybox",
+            12 => "blank for the ssh binary, or native for sheesh's own, which asks for passwords itself",
+            _ => "",
+        };
+        if !hint.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled(format!("  {}", hint), Theme::dimmed())));
+        }
+        if let Some(error) = &self.form.error {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled(format!("  {}", error), Theme::error())));
+        }
 
         let para = Paragraph::new(lines)
             .block(
@@ -492,6 +756,21 @@ impl ListingTab {
         frame.render_widget(para, popup_area);
     }
 
+    /// The form's hint for the jump hosts, with what is wrong with them.
+    fn jump_hint(&self) -> String {
+        let hint = "saved hosts to go through first, in order, e.g. bastion → db-gw";
+        let hops = route::parse_chain(&self.form.jump);
+        if hops.iter().any(|hop| *hop == self.form.name.trim()) {
+            return format!("{} — not the connection itself", hint);
+        }
+        let unknown: Vec<&str> =
+            hops.iter().filter(|hop| !self.connections.iter().any(|c| c.name == **hop)).map(String::as_str).collect();
+        match unknown.as_slice() {
+            [] => hint.to_string(),
+            _ => format!("{} — not saved, so only ssh's config knows: {}", hint, unknown.join(", ")),
+        }
+    }
+
     fn render_confirm_delete(&self, frame: &mut Frame, area: Rect) {
         let popup_area = centered_rect(40, 20, area);
         frame.render_widget(Clear, popup_area);
@@ -523,6 +802,58 @@ impl ListingTab {
         );
         frame.render_widget(para, popup_area);
     }
+
+    fn render_known_hosts(&self, frame: &mut Frame, area: Rect) {
+        let Some(view) = &self.known_hosts else {
+            return;
+        };
+        let popup_area = centered_rect(70, 50, area);
+        frame.render_widget(Clear, popup_area);
+
+        let known = &view.known;
+        let file = known.file.display().to_string();
+        let mut lines = vec![
+            Line::default(),
+            detail_line("Host", &known.host),
+            detail_line("File", &file),
+            Line::default(),
+        ];
+        if known.keys.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "  No keys recorded; ssh asks whether to trust the host on the next connection.",
+                Theme::dimmed(),
+            )));
+        }
+        for (i, key) in known.keys.iter().enumerate() {
+            let style = if i == view.selected { Theme::highlight() } else { Theme::value() };
+            lines.push(Line::from(vec![
+                Span::styled(if i == view.selected { "> " } else { "  " }, style),
+                Span::styled(format!("{:8} {}", key.kind, key.fingerprint), style),
+                Span::styled(format!("  line {}", key.line), Theme::dimmed()),
+            ]));
+        }
+        lines.push(Line::default());
+        match &view.message {
+            Some((message, true)) => lines.push(Line::from(Span::styled(format!("  {}", message), Theme::error()))),
+            Some((message, false)) => lines.push(Line::from(Span::styled(format!("  {}", message), Theme::value()))),
+            None if !known.keys.is_empty() => lines.push(Line::from(Span::styled(
+                "  After a host is reinstalled its old keys are stale: remove them and ssh asks to trust the new one.",
+                Theme::dimmed(),
+            ))),
+            None => {}
+        }
+
+        let para = Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(" Known host keys ", Theme::title()))
+                    .title_bottom(Span::styled(" d: remove key · esc: close ", Theme::dimmed())),
+            )
+            .wrap(Wrap { trim: false });
+        frame.render_widget(para, popup_area);
+    }
 }
 
 fn detail_line<'a>(label: &'a str, value: &'a str) -> Line<'a> {
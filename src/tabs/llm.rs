@@ -1,4 +1,9 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 use ratatui::{
@@ -6,18 +11,40 @@ use ratatui::{
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, BorderType, Paragraph, Wrap},
+    widgets::{Block, BorderType, Clear, Paragraph, Wrap},
 };
 
 use crate::{
+    chats::{self, Chat},
     event::Action,
-    llm::{ContentBlock, LLMEvent, LLMProvider, Message, RichMessage, Role, spawn_completion_rich},
+    hooks::{HookEvent, Hooks},
+    inventory::{self, HostFacts},
+    metrics,
+    llm::{
+        ContentBlock, Disabled, LLMConfig, LLMEvent, LLMProvider, Message, PlanPolicy, RichMessage, Role, Usage,
+        build_provider,
+        clean::clean_terminal_text, limit::Queued, locality, man::ManPage, quickfix::Problem, render_context, runbook,
+        spawn_completion_rich,
+    },
     ssh::SSHConnection,
-    tabs::terminal::CONTEXT_LINES,
-    ui::theme::Theme,
+    tabs::terminal::{CONTEXT_LINES, visible_text},
+    ui::{
+        CopyOnSelect, UiConfig,
+        clipboard::{self, SnippetKind},
+        find::{FindBar, FindKey},
+        keybindings::KeyChord,
+        shell,
+        markdown,
+        scroll::Scroller,
+        theme::Theme,
+    },
 };
 
 use super::Tab;
+use sheesh_tools::{
+    FileWrite, LocalPermission, OutputLimits, OutputStore, ReadMoreArgs, SshContext, ToolArgs, ToolCallEnd, ToolGroup,
+    ToolObserver, ToolRegistry, ToolProgress, ToolResult, WriteKind,
+};
 
 /// (line_index, col) in the flattened history line buffer.
 type BufPos = (usize, usize);
@@ -28,8 +55,275 @@ struct PendingToolCall {
     id: String,
     command: String,
     description: Option<String>,
-    /// Assistant content blocks already received (stored in rich_history on confirm/decline).
+    /// In-progress edit of `command` (`e` in the confirmation prompt).
+    edit_buffer: Option<String>,
+    /// True once the user has changed the proposed command.
+    edited: bool,
+    /// Show the whole command instead of the first few rows (`v`).
+    expanded: bool,
+    /// First visible command row when the command overflows the prompt.
+    scroll: usize,
+    /// Set when the command looks interactive and would wedge output capture.
+    warning: Option<String>,
+    /// Set when the command deletes data or is otherwise hard to undo.
+    destructive: Option<String>,
+    /// The alias as typed so far, when a command or write on a guarded host
+    /// is waiting for it.
+    typed_confirm: Option<String>,
+    /// File write behind this call; `command` is empty until the current
+    /// content has been read and the write command built.
+    write: Option<FileWrite>,
+    /// Diff of the write, shown in place of the command.
+    preview: Option<WritePreview>,
+    /// Remote path to copy to this machine (`download_file`); `command`
+    /// only describes the transfer.
+    download: Option<String>,
+    /// Tool to run on this machine; `command` summarises the call.
+    local: Option<LocalCall>,
+}
+
+/// A call to a [`LocalTool`](sheesh_tools::LocalTool).
+struct LocalCall {
+    name: String,
+    input: serde_json::Value,
+    /// The tool asked for the user's approval (`LocalPermission::Ask`).
+    ask: bool,
+}
+
+impl PendingToolCall {
+    /// Plain shell commands; writes are reviewed as a diff, and downloads
+    /// and local tools don't run in the shell, so none of them can be
+    /// edited or scoped by program.
+    fn is_shell_command(&self) -> bool {
+        self.write.is_none() && self.download.is_none() && self.local.is_none()
+    }
+}
+
+/// Outcome of a background scp download: (tool-use id, remote path, local
+/// path or error). The id is `None` for downloads started with `/download`.
+type TransferResult = (Option<String>, String, Result<PathBuf, String>);
+
+/// Outcome of a local tool run on a background thread: (tool-use id, result).
+type LocalResult = (String, String);
+
+/// A file read for a write's diff preview beside the terminal: (tool-use
+/// id, what the read command printed, or the error).
+type ReadResult = (String, Result<String, String>);
+
+/// A line of the chat as built from the history, with its style, if any.
+type ChatLine = (String, Option<Style>);
+
+/// A page read for `/man`: (the question asked, page or error).
+type ManResult = (String, Result<ManPage, String>);
+
+/// Preview of a file write, built once the file's current content is known.
+struct WritePreview {
+    /// Unified diff of the change.
+    diff: String,
+    /// Reported back to the model once the write succeeds.
+    summary: String,
+    /// Remote copy taken before writing (None if the file doesn't exist yet).
+    backup: Option<String>,
+    /// Content of the file before the write.
+    before: String,
+}
+
+/// A file changed by a write tool this session, newest last. Rolling back
+/// restores the backup and drops the record.
+struct FileBackup {
+    path: String,
+    /// Remote backup copy, or None if the write created the file.
+    backup: Option<String>,
+    /// What the backup holds, for the rollback diff.
+    content: String,
+    written: Instant,
+}
+
+impl FileBackup {
+    fn restore(&self) -> FileWrite {
+        FileWrite {
+            path: self.path.clone(),
+            kind: WriteKind::Restore { backup: self.backup.clone(), content: self.content.clone() },
+        }
+    }
+}
+
+/// Tool calls from a single assistant turn. Commands are confirmed one at a
+/// time; once every call has a result, the assistant blocks and all
+/// tool_results are committed to rich history together.
+struct ToolBatch {
+    /// Assistant content blocks (text + every tool_use) for this turn.
     assistant_blocks: Vec<ContentBlock>,
+    /// Commands still waiting behind the current confirmation prompt.
+    queue: VecDeque<PendingToolCall>,
+    /// Number of commands in the batch, for the "2/3" counter.
+    total: usize,
+    /// tool_result blocks collected so far.
+    results: Vec<ContentBlock>,
+    /// Run the remaining commands without asking (`Y`).
+    approve_all: bool,
+    /// `update_plan` call whose new plan waits for the user's approval;
+    /// commands queued behind it are held until then.
+    plan_review: Option<String>,
+    /// Registry the calls came from; told when each one starts and ends.
+    tools: ToolRegistry,
+}
+
+impl ToolBatch {
+    /// Mark call `id` as running, for the registry's observers.
+    fn start(&self, id: &str) {
+        let name = self.assistant_blocks.iter().find_map(|b| match b {
+            ContentBlock::ToolUse { id: use_id, name, .. } if use_id == id => Some(name.as_str()),
+            _ => None,
+        });
+        if let Some(name) = name {
+            self.tools.call_started(id, name);
+        }
+    }
+
+    fn push_result(&mut self, id: &str, content: impl Into<String>) {
+        let content = content.into();
+        self.tools.call_finished(id, &content);
+        self.results.push(ContentBlock::ToolResult { tool_use_id: id.to_string(), content, is_error: false });
+    }
+
+    /// Answer call `id` with why it failed or couldn't be made.
+    fn push_error(&mut self, id: &str, error: impl Into<String>) {
+        let content = error.into();
+        self.tools.call_finished(id, &content);
+        self.results.push(ContentBlock::ToolResult { tool_use_id: id.to_string(), content, is_error: true });
+    }
+
+    /// Assistant message plus one user message carrying every tool_result,
+    /// ordered like the tool_use blocks they answer. Results over `limits`
+    /// are shortened, with the full text kept in `outputs` for `read_more`.
+    fn into_messages(mut self, outputs: &mut OutputStore, limits: &OutputLimits) -> [RichMessage; 2] {
+        for result in &mut self.results {
+            if let ContentBlock::ToolResult { content, .. } = result {
+                *content = outputs.limit(content, limits);
+            }
+        }
+        let position = |id: &str| {
+            self.assistant_blocks
+                .iter()
+                .position(|b| matches!(b, ContentBlock::ToolUse { id: use_id, .. } if use_id == id))
+        };
+        self.results.sort_by_key(|r| match r {
+            ContentBlock::ToolResult { tool_use_id, .. } => position(tool_use_id),
+            _ => None,
+        });
+        [
+            RichMessage { role: Role::Assistant, content: self.assistant_blocks },
+            RichMessage { role: Role::User, content: self.results },
+        ]
+    }
+}
+
+/// Status of one step in the agent's plan, as reported via `update_plan`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepStatus {
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+    Skipped,
+}
+
+impl StepStatus {
+    fn parse(s: &str) -> Self {
+        match s {
+            "in_progress" => StepStatus::InProgress,
+            "done" => StepStatus::Done,
+            "failed" => StepStatus::Failed,
+            "skipped" => StepStatus::Skipped,
+            _ => StepStatus::Pending,
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            StepStatus::Pending => "○",
+            StepStatus::InProgress => "◐",
+            StepStatus::Done => "✓",
+            StepStatus::Failed => "✗",
+            StepStatus::Skipped => "–",
+        }
+    }
+
+    fn style(self) -> Style {
+        match self {
+            StepStatus::Pending => Theme::dimmed(),
+            StepStatus::InProgress => Theme::key_hint_key(),
+            StepStatus::Done => Theme::value(),
+            StepStatus::Failed => Theme::error(),
+            StepStatus::Skipped => Theme::dimmed(),
+        }
+    }
+}
+
+struct PlanStep {
+    title: String,
+    /// The command the step says it runs, which approving the plan lets through.
+    command: Option<String>,
+    status: StepStatus,
+}
+
+/// Maximum checklist rows shown above the status line.
+const PLAN_ROWS: usize = 8;
+
+/// Framing sent to the model for `/task <goal>`.
+const TASK_PROMPT: &str = "\
+Work on this task autonomously. First call update_plan with the steps you intend to take. \
+Then carry them out one at a time with your tools, calling update_plan to mark each step \
+in_progress and then done, failed or skipped. Verify the result at the end. When the task is \
+finished, or cannot be completed, reply with a short summary of what was done and the final \
+state, without calling any more tools.";
+
+/// Asked with the terminal selection by F5.
+const EXPLAIN_PROMPT: &str = "Explain this from my terminal: what it says, and anything in it that looks wrong and \
+what to do about it. Keep it short, a few sentences; it is shown in a small box next to the text.";
+
+/// How long auto-approval lasts once granted from the confirmation prompt.
+#[derive(Debug, Clone)]
+enum AutoApprove {
+    /// Every tool call for the rest of the session (`a`).
+    Session,
+    /// Simple commands running this program (`p`).
+    Program(String),
+    /// The next N tool calls (`5`).
+    Calls(u32),
+    /// Until the deadline passes (`t`).
+    Until(Instant),
+}
+
+impl AutoApprove {
+    /// Number of calls granted by the `5` key.
+    const CALLS: u32 = 5;
+    /// Duration granted by the `t` key.
+    const WINDOW: Duration = Duration::from_secs(5 * 60);
+
+    fn label(&self) -> String {
+        match self {
+            AutoApprove::Session => "auto: this session".into(),
+            AutoApprove::Program(p) => format!("auto: `{} …`", p),
+            AutoApprove::Calls(n) => format!("auto: next {} call{}", n, if *n == 1 { "" } else { "s" }),
+            AutoApprove::Until(t) => {
+                let left = t.saturating_duration_since(Instant::now()).as_secs();
+                format!("auto: {}m{:02}s left", left / 60, left % 60)
+            }
+        }
+    }
+}
+
+/// Tool calls finished since the last `poll`, shown in the chat with how
+/// long they took.
+#[derive(Default)]
+struct ToolTimings(Mutex<Vec<ToolCallEnd>>);
+
+impl ToolObserver for ToolTimings {
+    fn on_call_end(&self, call: &ToolCallEnd) {
+        self.0.lock().unwrap().push(call.clone());
+    }
 }
 
 pub struct LLMTab {
@@ -42,10 +336,27 @@ pub struct LLMTab {
     provider: Arc<dyn LLMProvider>,
     tx: mpsc::Sender<LLMEvent>,
     pub rx: mpsc::Receiver<LLMEvent>,
+    /// Set to drop the latest request while it still waits for the limiter.
+    request_cancel: Arc<AtomicBool>,
     scroll_offset: usize,
     selection: Option<(BufPos, BufPos)>,
+    /// Selecting with the keyboard (F8): arrows move the selection's end.
+    marking: bool,
+    /// Scroll the selection's end into view on the next draw.
+    mark_jump: bool,
+    /// Spell out state that is otherwise only a color (`[ui] screen_reader`).
+    screen_reader: bool,
     last_render_start: usize,
     last_chat_area: Rect,
+    scroller: Scroller,
+    copy_on_select: CopyOnSelect,
+    /// Find in the conversation (ctrl+f), while open.
+    find: Option<FindBar>,
+    /// Scroll the current find match into view on the next draw.
+    find_jump: bool,
+    /// Line to put back at the top on the next draw, after a resize
+    /// rewrapped the history under a scrolled-back view.
+    resize_anchor: Option<usize>,
     /// Rows scrolled up inside the input box (0 = cursor visible at bottom).
     input_scroll: usize,
     /// Saved from last render to hit-test mouse events against the input box.
@@ -54,24 +365,156 @@ pub struct LLMTab {
     suggestions: Vec<String>,
     /// Which suggestion is currently selected (None = no suggestions / cleared).
     suggestion_idx: Option<usize>,
+    /// Index in `history` of the reply the suggestions come from, whose
+    /// code blocks get numbered badges.
+    suggestions_from: Option<usize>,
+    /// The selected suggestion is shown whole, highlighted, over the chat:
+    /// opened with ctrl+e, or while the mouse is over the suggestion bar.
+    preview: bool,
+    hovering_suggestion: bool,
+    last_suggestion_area: Rect,
     /// Tool call from Claude awaiting user confirmation.
     pending_tool_call: Option<PendingToolCall>,
+    /// Tool calls of the current assistant turn, including `pending_tool_call`.
+    tool_batch: Option<ToolBatch>,
+    /// Command approved without a keypress (auto-approve, `Y`), waiting for
+    /// `main.rs` to send it to the terminal.
+    approved_command: Option<String>,
+    /// Write tool call whose target file is being read for the diff preview.
+    reading_file: Option<PendingToolCall>,
+    /// Approved write being run, with the summary reported on success.
+    running_write: Option<(FileWrite, WritePreview)>,
+    /// Files changed by write tools this session, with their backups.
+    changed_files: Vec<FileBackup>,
+    /// Rollback started from the changed-files list: the record's index and
+    /// the restore being run. The record goes once the output confirms it.
+    rolling_back: Option<(usize, FileWrite)>,
+    /// Selected row while the changed-files list is open (`ctrl+b`).
+    changes_view: Option<usize>,
+    /// Local directory `download_file` copies into.
+    download_dir: PathBuf,
+    /// Where `/runbook` saves.
+    notes_dir: PathBuf,
+    /// The reply in flight is a `/runbook`, saved once it arrives.
+    runbook: bool,
+    transfer_tx: mpsc::Sender<TransferResult>,
+    transfer_rx: mpsc::Receiver<TransferResult>,
+    /// Tool-use id of the download in progress.
+    transfer_id: Option<String>,
+    local_tx: mpsc::Sender<LocalResult>,
+    local_rx: mpsc::Receiver<LocalResult>,
+    /// Tool-use id of the local tool in progress.
+    running_local: Option<String>,
+    man_tx: mpsc::Sender<ManResult>,
+    man_rx: mpsc::Receiver<ManResult>,
+    read_tx: mpsc::Sender<ReadResult>,
+    read_rx: mpsc::Receiver<ReadResult>,
     /// Tool-use id waiting for terminal output before resuming Claude.
     pub awaiting_output_id: Option<String>,
-    /// When true, future tool calls execute without asking.
-    auto_approve: bool,
+    /// Command the user edited before approving — reported back in the tool_result.
+    edited_command: Option<String>,
+    /// Active auto-approval scope; matching tool calls execute without asking.
+    auto_approve: Option<AutoApprove>,
+    /// Auto-approval set aside while the idle lock is up.
+    paused_auto_approve: Option<AutoApprove>,
+    /// The reply to the F5 explanation in flight also goes to `explanation`.
+    explaining: bool,
+    /// That reply, until `main.rs` takes it for the popover.
+    explanation: Option<String>,
+    /// An error in the terminal's last lines that F5 would ask a fix for.
+    problem: Option<Problem>,
+    /// The line of the last problem a fix was asked for, not offered again.
+    fixed: Option<String>,
+    /// Observe-and-advise only (ctrl+o): `run_command` and the write tools
+    /// are left out of what the model is offered.
+    read_only: bool,
+    /// Production guard (`guard` under `[tools.connections]`): every call
+    /// needs a keypress, and commands and writes the alias typed out.
+    guard: bool,
+    /// Turned off for this connection (`[llm.connections]`): the panel is
+    /// hidden and its provider refuses every request.
+    disabled: bool,
+    /// Whether a request went out this session, for the notice of where it went.
+    context_sent: bool,
+    /// The cloud vendor the session's first request went to, while the
+    /// strip saying so is up.
+    locality_notice: Option<&'static str>,
+    /// A request waiting for the user's yes (`confirm_context_share`), and
+    /// the vendor it would go to.
+    held_request: Option<&'static str>,
+    /// Length of `rich_history` when the last request was sent.
+    sent_len: usize,
     clipboard: Option<arboard::Clipboard>,
     /// SSH connection info used to resolve the system_information tool locally.
     connection: SSHConnection,
+    /// `[hooks]` to run for this session's events.
+    hooks: Hooks,
+    /// The host outside the terminal, for reading files to preview writes.
+    remote: Arc<dyn SshContext + Send + Sync>,
     /// Maps each visible chat screen row → (build_lines index, byte offset in that string).
     last_visual_row_map: Vec<(usize, usize)>,
+    /// The lines drawn last, which the row map and selections index into;
+    /// mouse hits and copies read these instead of rebuilding the history.
+    last_lines: Rc<Vec<(String, Option<Style>)>>,
     /// Shared reference to the terminal's raw output log (for the read_terminal tool).
     terminal_output: Option<Arc<Mutex<Vec<String>>>>,
+    /// Where the last read_terminal stopped, so the next one sends only
+    /// what was printed since.
+    terminal_mark: Option<TerminalMark>,
+    /// Remote working directory reported by the terminal, if known.
+    cwd: Option<String>,
+    /// Steps of the agent's current plan, shown as a live checklist.
+    plan: Vec<PlanStep>,
+    /// Goal of the running `/task`, if any.
+    task: Option<String>,
+    /// Steps (titles and commands) of the plan the user approved for the
+    /// current request.
+    approved_plan: Option<Vec<(String, Option<String>)>>,
+    /// Which commands an approved plan lets through without a prompt.
+    plan_policy: PlanPolicy,
+    /// Size budget for each tool_result sent to the model.
+    output_limits: OutputLimits,
+    /// Full text of shortened tool results, for the read_more tool.
+    outputs: OutputStore,
+    /// Tools offered to the model on this connection.
+    tools: ToolRegistry,
+    /// Observer on `tools` collecting finished calls for the chat.
+    tool_timings: Arc<ToolTimings>,
+    /// What is known about the remote host, from this and earlier sessions.
+    host_facts: HostFacts,
+    /// The user's notes on this host, when shared (ctrl+t in F11's editor).
+    notes: Option<String>,
+    /// The answer to `detect_platform`, while it is awaited.
+    platform_rx: Option<mpsc::Receiver<String>>,
+    /// Provider settings `/model` derives its overrides from.
+    llm_config: LLMConfig,
+    /// Model picked with `/model` for the current turn, and its name.
+    turn_provider: Option<(String, Arc<dyn LLMProvider>)>,
+    /// Last command in the terminal and its exit status, from OSC 133.
+    last_exit: Option<(String, i32)>,
+    /// Tokens spent by this turn's requests not yet shown on a message.
+    turn_usage: Option<Usage>,
+    /// Show each message's time, model and token counts under it.
+    show_meta: bool,
+    /// Runs the selected suggestion (`[ui] run_suggestion_key`).
+    run_key: KeyChord,
+}
+
+impl Drop for LLMTab {
+    /// A closed session's queued request would otherwise hold its place.
+    fn drop(&mut self) {
+        self.request_cancel.store(true, Ordering::Relaxed);
+    }
 }
 
 impl LLMTab {
     pub fn new(provider: Arc<dyn LLMProvider>, system_prompt: Option<String>, connection: SSHConnection) -> Self {
         let (tx, rx) = mpsc::channel();
+        let (transfer_tx, transfer_rx) = mpsc::channel();
+        let (local_tx, local_rx) = mpsc::channel();
+        let (man_tx, man_rx) = mpsc::channel();
+        let (read_tx, read_rx) = mpsc::channel();
+        let tool_timings = Arc::new(ToolTimings::default());
         let mut rich_history = vec![];
         if let Some(prompt) = system_prompt {
             rich_history.push(RichMessage::system(prompt));
@@ -85,21 +528,89 @@ impl LLMTab {
             provider,
             tx,
             rx,
+            request_cancel: Arc::new(AtomicBool::new(false)),
             scroll_offset: 0,
             selection: None,
+            marking: false,
+            mark_jump: false,
+            screen_reader: false,
             last_render_start: 0,
             last_chat_area: Rect::default(),
+            scroller: Scroller::default(),
+            copy_on_select: CopyOnSelect::Off,
+            find: None,
+            find_jump: false,
+            resize_anchor: None,
             input_scroll: 0,
             last_input_area: Rect::default(),
             suggestions: vec![],
             suggestion_idx: None,
+            suggestions_from: None,
+            preview: false,
+            hovering_suggestion: false,
+            last_suggestion_area: Rect::default(),
             pending_tool_call: None,
+            tool_batch: None,
+            approved_command: None,
+            reading_file: None,
+            running_write: None,
+            changed_files: vec![],
+            rolling_back: None,
+            changes_view: None,
+            download_dir: PathBuf::from("."),
+            notes_dir: PathBuf::from("."),
+            runbook: false,
+            transfer_tx,
+            transfer_rx,
+            transfer_id: None,
+            local_tx,
+            local_rx,
+            man_tx,
+            man_rx,
+            read_tx,
+            read_rx,
+            running_local: None,
             awaiting_output_id: None,
-            auto_approve: false,
+            edited_command: None,
+            auto_approve: None,
+            paused_auto_approve: None,
+            explaining: false,
+            explanation: None,
+            problem: None,
+            fixed: None,
+            read_only: false,
+            guard: false,
+            disabled: false,
+            context_sent: false,
+            locality_notice: None,
+            held_request: None,
+            sent_len: 0,
             clipboard: arboard::Clipboard::new().ok(),
+            hooks: Hooks::default(),
+            remote: crate::ssh::context(&connection).into(),
             connection,
             last_visual_row_map: vec![],
+            last_lines: Rc::default(),
             terminal_output: None,
+            terminal_mark: None,
+            cwd: None,
+            plan: vec![],
+            task: None,
+            approved_plan: None,
+            plan_policy: PlanPolicy::Safe,
+            output_limits: OutputLimits::default(),
+            outputs: OutputStore::default(),
+            tools: ToolRegistry::builtin().observe(tool_timings.clone()),
+            tool_timings,
+            host_facts: HostFacts::default(),
+            notes: None,
+            platform_rx: None,
+            llm_config: LLMConfig::default(),
+            turn_provider: None,
+            last_exit: None,
+            turn_usage: None,
+            show_meta: false,
+            run_key: UiConfig::default().run_suggestion_key,
             rich_history,
         }
     }
@@ -108,237 +619,1523 @@ impl LLMTab {
         self.terminal_output = Some(output);
     }
 
+    pub fn set_plan_policy(&mut self, policy: PlanPolicy) {
+        self.plan_policy = policy;
+    }
+
+    pub fn set_guard(&mut self, on: bool) {
+        self.guard = on;
+        self.auto_approve = None;
+    }
+
+    /// Turn the assistant off for this session; nothing it has is sent.
+    pub fn disable(&mut self) {
+        self.disabled = true;
+        self.provider = Arc::new(Disabled);
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn set_download_dir(&mut self, dir: PathBuf) {
+        self.download_dir = dir;
+    }
+
+    pub fn set_notes_dir(&mut self, dir: PathBuf) {
+        self.notes_dir = dir;
+    }
+
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        self.output_limits = limits;
+    }
+
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// Run the hook for `event` with this session's details and `vars`.
+    pub fn run_hook(&self, event: HookEvent, vars: &[(&str, String)]) {
+        self.hooks.run(event, &self.connection, vars);
+    }
+
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools.observe(self.tool_timings.clone());
+    }
+
+    /// The window was resized: rows no longer wrap where they did, so drop
+    /// the row map mouse hits read until the next draw rebuilds it, keep the
+    /// top line in place if scrolled back, and show the input's cursor.
+    pub fn resized(&mut self) {
+        self.last_visual_row_map.clear();
+        if self.scroll_offset > 0 {
+            self.resize_anchor = Some(self.last_render_start);
+        }
+        self.input_scroll = 0;
+    }
+
+    pub fn set_ui_config(&mut self, cfg: &UiConfig) {
+        self.scroller = Scroller::new(cfg);
+        self.copy_on_select = cfg.copy_on_select;
+        self.show_meta = cfg.show_message_meta;
+        self.screen_reader = cfg.screen_reader;
+        self.run_key = cfg.run_suggestion_key.clone();
+    }
+
+    /// Run the selected suggestion (the run key) as an approved tool call
+    /// would run, unless something would make a tool call ask first: a
+    /// guarded host, a destructive or interactive command, or more than one
+    /// line. Then it is only typed, for Enter to run.
+    fn run_suggestion(&mut self) -> Action {
+        let Some(cmd) = self.suggestion_idx.and_then(|i| self.suggestions.get(i)).cloned() else {
+            return Action::None;
+        };
+        clipboard::remember(&cmd, SnippetKind::Applied);
+        let blocker = if self.guard {
+            Some("guarded host".to_string())
+        } else if cmd.trim().contains('\n') {
+            Some("more than one line".to_string())
+        } else {
+            sheesh_tools::destructive_reason(&cmd)
+                .map(|reason| format!("destructive: {}", reason))
+                .or_else(|| sheesh_tools::interactive_warning(&cmd))
+        };
+        match blocker {
+            None => Action::SendToTerminal(cmd.trim().to_string()),
+            Some(reason) => {
+                self.status = format!("Typed, not run ({}); enter in the terminal runs it.", reason);
+                Action::TypeInTerminal(cmd)
+            }
+        }
+    }
+
+    /// The conversation, the status, any command waiting for approval and
+    /// the input as plain text, one string per line; for the plain view (F9).
+    pub fn plain_text(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.build_lines().0.into_iter().map(|(text, _)| text).collect();
+        if !self.status.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("Status: {}", self.status));
+        }
+        if let Some(ptc) = &self.pending_tool_call {
+            lines.push(String::new());
+            lines.push(match &ptc.typed_confirm {
+                Some(typed) => {
+                    format!("Type {} to run this on a guarded host (esc: back): {}", self.connection.name, typed)
+                }
+                None => "Approve this command? (y: run, e: edit, n: skip)".to_string(),
+            });
+            lines.extend(ptc.command.lines().map(|line| format!("  {}", line)));
+        }
+        lines.push(String::new());
+        lines.extend(format!("Message: {}", self.input).lines().map(str::to_string));
+        lines
+    }
+
+    /// Rows scrolled back from the bottom of the conversation.
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    pub fn set_llm_config(&mut self, config: LLMConfig) {
+        self.llm_config = config;
+    }
+
+    /// Facts remembered about this host; sent with every request and
+    /// updated from the output of commands run this session.
+    pub fn set_host_facts(&mut self, facts: HostFacts) {
+        self.host_facts = facts;
+    }
+
+    pub fn set_notes(&mut self, notes: Option<String>) {
+        self.notes = notes;
+    }
+
+    /// The conversation so far, as [`chats`] keeps it.
+    pub fn chat(&self) -> Chat {
+        let rich_history = self.rich_history.iter().filter(|m| m.role != Role::System).cloned().collect();
+        Chat { history: self.history.clone(), rich_history }
+    }
+
+    /// Go on with the conversation of an earlier session on this host.
+    pub fn restore_chat(&mut self, chat: Chat) {
+        if chat.is_empty() {
+            return;
+        }
+        self.history = chat.history;
+        self.rich_history.extend(chat.rich_history);
+        self.sent_len = self.rich_history.len();
+        self.status = "Continuing the last conversation with this host; ctrl+n starts a new one.".into();
+    }
+
+    /// Keep the conversation for the next session on this host.
+    pub fn save_chat(&self) {
+        if self.disabled {
+            return;
+        }
+        if let Err(e) = chats::save(&self.connection.name, &self.chat()) {
+            log::warn!("[chats] {:#}", e);
+        }
+    }
+
+    /// Forget the conversation (ctrl+n): the next message starts afresh,
+    /// with only the system prompt, and the next session with nothing.
+    fn new_conversation(&mut self) {
+        if self.waiting || self.is_executing_tool() {
+            self.status = "Wait for the current request to finish before starting a new conversation.".into();
+            return;
+        }
+        self.history.clear();
+        self.rich_history.retain(|m| m.role == Role::System);
+        self.sent_len = self.rich_history.len();
+        self.outputs = OutputStore::default();
+        self.terminal_mark = None;
+        (self.plan, self.task, self.approved_plan) = (vec![], None, None);
+        (self.suggestions, self.suggestion_idx, self.suggestions_from, self.preview) = (vec![], None, None, false);
+        (self.selection, self.find, self.scroll_offset) = (None, None, 0);
+        self.abandon_turn("a new conversation was started");
+        self.status = "New conversation.".into();
+    }
+
+    /// Forget what the turn that ended without a reply was for (`/runbook`,
+    /// F5's explanation), so the next reply isn't taken for it.
+    fn abandon_turn(&mut self, why: &str) {
+        self.runbook = false;
+        if std::mem::take(&mut self.explaining) {
+            self.explanation = Some(format!("No explanation: {}", why));
+        }
+    }
+
+    /// Ask the host for its login shell, OS, kernel and services over a
+    /// second ssh session, when neither the connection nor an earlier
+    /// session says what shell and OS it has. This is the only source of
+    /// `host_facts`; a host that won't answer without a prompt stays unknown.
+    pub fn detect_platform(&mut self) {
+        let (c, facts) = (&self.connection, &self.host_facts);
+        let known =
+            (c.platform.shell.is_some() || facts.shell.is_some()) && (c.platform.os.is_some() || facts.os.is_some());
+        if known || !c.transport.is_ssh() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        self.platform_rx = Some(rx);
+        let conn = self.connection.clone();
+        std::thread::spawn(move || {
+            if let Ok(output) = crate::ssh::exec(&conn, inventory::DETECT_COMMAND) {
+                let _ = tx.send(output);
+            }
+        });
+    }
+
+    /// Update the remote working directory (called by `main.rs` every tick).
+    pub fn set_cwd(&mut self, cwd: Option<String>) {
+        self.cwd = cwd;
+    }
+
+    /// The terminal's last command and its exit status, when the shell
+    /// reports them with OSC 133 (called by `main.rs` every tick).
+    pub fn set_last_exit(&mut self, last_exit: Option<(String, i32)>) {
+        self.last_exit = last_exit;
+    }
+
+    /// Name of the model answering the current turn.
+    fn turn_model(&self) -> String {
+        match &self.turn_provider {
+            Some((name, _)) => self.llm_config.route(name).model_name().to_string(),
+            None => self.llm_config.model_name().to_string(),
+        }
+    }
+
+    /// `usage` plus whatever earlier requests of the turn spent, clearing the latter.
+    fn take_turn_usage(&mut self, usage: Option<Usage>) -> Option<Usage> {
+        match (self.turn_usage.take(), usage) {
+            (Some(mut total), Some(usage)) => {
+                total += usage;
+                Some(total)
+            }
+            (total, usage) => total.or(usage),
+        }
+    }
+
+    /// The `context_template` header filled in with what is known of the session.
+    fn session_context(&self) -> String {
+        let c = &self.connection;
+        let known = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        let (last_command, exit_status) = match &self.last_exit {
+            Some((command, status)) => (known(command), Some(status.to_string())),
+            None => (None, None),
+        };
+        // What the connection declares wins over what was detected.
+        let facts = &self.host_facts;
+        let shell = c.platform.shell.clone().or_else(|| facts.shell.clone());
+        let package_manager = match &c.platform.os {
+            Some(os) => inventory::package_manager_for(os).map(String::from),
+            None => facts.os.as_deref().and_then(inventory::package_manager_for).map(String::from),
+        };
+        let os = c.platform.os.clone().or_else(|| facts.os.clone());
+        render_context(
+            &self.llm_config.context_template,
+            &[
+                ("host", known(c.effective_hostname())),
+                ("alias", known(&c.name)),
+                ("link", c.transport.describe()),
+                ("user", known(c.effective_user())),
+                ("port", Some(c.effective_port().to_string())),
+                ("cwd", self.cwd.clone()),
+                ("shell", shell.clone()),
+                ("shell_syntax", shell.as_deref().and_then(inventory::shell_syntax).map(String::from)),
+                ("os", os.clone()),
+                ("package_manager", package_manager),
+                ("last_command", last_command),
+                ("exit_status", exit_status),
+            ],
+        )
+    }
+
+    /// Send the conversation to the provider, once the user knows where it
+    /// goes: the session's first request to a cloud provider puts up a
+    /// notice, and with `confirm_context_share` each one waits for a yes.
+    fn request_completion(&mut self) {
+        let provider_id = match &self.turn_provider {
+            Some((name, _)) => self.llm_config.route(name).provider,
+            None => self.llm_config.provider.clone(),
+        };
+        if let Some(vendor) = locality::cloud_vendor(&provider_id).filter(|_| !self.disabled) {
+            if !std::mem::replace(&mut self.context_sent, true) && !locality::acknowledged(vendor) {
+                self.locality_notice = Some(vendor);
+            }
+            if self.llm_config.confirm_context_share {
+                self.held_request = Some(vendor);
+                return;
+            }
+        }
+        self.send_request();
+    }
+
+    /// Answer the held request: send it, or keep what it would add to the
+    /// conversation from leaving: tool results since the last request are
+    /// replaced by a note, and the turn ends.
+    fn release_request(&mut self, send: bool) {
+        self.held_request = None;
+        if send {
+            self.send_request();
+            return;
+        }
+        let from = self.sent_len.min(self.rich_history.len());
+        for block in self.rich_history[from..].iter_mut().flat_map(|m| &mut m.content) {
+            if let ContentBlock::ToolResult { content, .. } = block {
+                *content = "[not shared: the user declined to send this output]".into();
+            }
+        }
+        self.sent_len = self.rich_history.len();
+        self.waiting = false;
+        self.turn_provider = None;
+        self.status = "Not sent.".into();
+        self.finish_task("stopped");
+        self.abandon_turn("the request was not sent");
+    }
+
+    /// Don't tell the user again that requests go to the vendor on the notice.
+    fn acknowledge_locality(&mut self) {
+        if let Some(vendor) = self.locality_notice.take()
+            && let Err(e) = locality::acknowledge(vendor)
+        {
+            log::warn!("[llm] {:#}", e);
+        }
+    }
+
+    /// Send the conversation to the provider on a background thread.
+    /// Live session details (the context header, changed files) are appended as a trailing system message
+    /// so they are current for every request without piling up in history.
+    fn send_request(&mut self) {
+        self.sent_len = self.rich_history.len();
+        let mut messages = self.rich_history.clone();
+        if let Some(facts) = self.host_facts.prompt() {
+            messages.push(RichMessage::system(facts));
+        }
+        if let Some(notes) = &self.notes {
+            messages.push(RichMessage::system(format!("The user's notes on this host:\n{}", notes)));
+        }
+        let header = self.session_context();
+        if !header.is_empty() {
+            messages.push(RichMessage::system(header));
+        }
+        if !self.changed_files.is_empty() {
+            let files: Vec<String> = self
+                .changed_files
+                .iter()
+                .map(|c| match &c.backup {
+                    Some(b) => format!("{} (backup: {})", c.path, b),
+                    None => format!("{} (created this session)", c.path),
+                })
+                .collect();
+            messages.push(RichMessage::system(format!(
+                "Files changed this session, newest last; rollback_file undoes the latest change to a file:\n{}",
+                files.join("\n")
+            )));
+        }
+        if self.read_only {
+            messages.push(RichMessage::system(
+                "Read-only mode: the user wants analysis and advice only. You cannot run commands or change \
+                 files; suggest any commands in code blocks for the user to run themselves."
+                    .to_string(),
+            ));
+        }
+        let disabled: Vec<&str> = ToolGroup::ALL
+            .into_iter()
+            .filter(|g| !self.tools.tools().iter().any(|t| t.group == *g))
+            .map(ToolGroup::name)
+            .collect();
+        if !disabled.is_empty() {
+            messages.push(RichMessage::system(format!(
+                "Tool groups disabled on this connection: {}. Only call the tools provided with this request.",
+                disabled.join(", ")
+            )));
+        }
+        let (provider_id, provider) = match &self.turn_provider {
+            Some((name, provider)) => (self.llm_config.route(name).provider, provider),
+            None => (self.llm_config.provider.clone(), &self.provider),
+        };
+        let tools = if self.read_only { self.tools.clone().read_only() } else { self.tools.clone() };
+        self.request_cancel = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::clone(&self.request_cancel);
+        spawn_completion_rich(Arc::clone(provider), provider_id, messages, tools, self.tx.clone(), cancel);
+    }
+
+    /// Show how far the running tool command has got in the status line.
+    /// Called by `main.rs` while it captures the command's output.
+    pub fn report_progress(&mut self, bytes: usize, note: Option<&str>) {
+        if let Some(id) = &self.awaiting_output_id
+            && let Some(progress) = self.tools.call_progress(id, bytes, note)
+        {
+            self.status = progress_status(&progress);
+        }
+    }
+
     /// Poll the channel for completed LLM responses. Call this each render frame.
     pub fn poll(&mut self) {
+        self.problem = self
+            .terminal_output
+            .as_ref()
+            .and_then(|log| {
+                let log = log.lock().unwrap();
+                // Reads are small; a few cover the lines the check looks at.
+                let start = log.len().saturating_sub(16);
+                Problem::detect(&visible_text(&log[start..].join("")))
+            })
+            .filter(|problem| self.fixed.as_ref() != Some(&problem.line));
+        if let Some(id) = self.transfer_id.as_ref().or(self.running_local.as_ref())
+            && let Some(progress) = self.tools.call_progress(id, 0, None)
+        {
+            self.status = progress_status(&progress);
+        }
+        while let Ok((id, result)) = self.local_rx.try_recv() {
+            // Results of cancelled calls were already recorded.
+            if self.running_local.as_ref() == Some(&id) {
+                self.running_local = None;
+                if let Some(batch) = &mut self.tool_batch {
+                    batch.push_result(&id, result);
+                }
+                self.advance_batch();
+            }
+        }
+        while let Ok((id, result)) = self.read_rx.try_recv() {
+            // Reads of cancelled calls have nowhere to go.
+            if self.reading_file.as_ref().is_none_or(|ptc| ptc.id != id) {
+                continue;
+            }
+            match result {
+                Ok(output) => {
+                    let ptc = self.reading_file.take().expect("checked above");
+                    self.preview_write(ptc, &output);
+                }
+                Err(e) => {
+                    let ptc = self.reading_file.take().expect("checked above");
+                    let path = ptc.write.as_ref().map_or("the file", |w| w.path.as_str());
+                    if let Some(batch) = &mut self.tool_batch {
+                        batch.push_result(
+                            &ptc.id,
+                            format!("The write was not attempted: could not read {} for the diff preview: {}.", path, e),
+                        );
+                    }
+                    self.advance_batch();
+                }
+            }
+        }
+        for call in std::mem::take(&mut *self.tool_timings.0.lock().unwrap()) {
+            self.history.push(Message::assistant(format!(
+                "[{} · {} · {}]",
+                call.name,
+                format_duration(call.duration),
+                format_bytes(call.result_bytes)
+            )));
+        }
+        if let Some(rx) = &self.platform_rx {
+            match rx.try_recv() {
+                Ok(output) => {
+                    self.platform_rx = None;
+                    if self.host_facts.learn(&output)
+                        && let Err(e) = inventory::save(&self.connection.name, &self.host_facts)
+                    {
+                        log::warn!("[inventory] could not save facts for {}: {}", self.connection.name, e);
+                    }
+                }
+                Err(mpsc::TryRecvError::Disconnected) => self.platform_rx = None,
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+        }
+        while let Ok((question, result)) = self.man_rx.try_recv() {
+            match result {
+                Ok(page) => {
+                    self.rich_history.push(RichMessage::user_text(page.prompt(&question)));
+                    self.status = "Waiting for response…".into();
+                    self.request_completion();
+                }
+                Err(e) => {
+                    self.waiting = false;
+                    self.status = e.clone();
+                    self.history.push(Message::assistant(format!("[{}]", e)));
+                }
+            }
+        }
+        while let Ok((id, remote, result)) = self.transfer_rx.try_recv() {
+            let text = match &result {
+                Ok(local) => format!("Downloaded {} to {}.", remote, local.display()),
+                Err(e) => {
+                    metrics::tool_failed();
+                    format!("Downloading {} failed: {}", remote, e)
+                }
+            };
+            match id {
+                Some(id) if self.transfer_id.as_ref() == Some(&id) => {
+                    self.transfer_id = None;
+                    if let Some(batch) = &mut self.tool_batch {
+                        batch.push_result(&id, text);
+                    }
+                    self.advance_batch();
+                }
+                // Cancelled tool call; its result was already recorded.
+                Some(_) => {}
+                None => {
+                    self.status = text.clone();
+                    self.history.push(Message::assistant(format!("[{}]", text)));
+                    self.scroll_offset = 0;
+                }
+            }
+        }
         while let Ok(event) = self.rx.try_recv() {
+            if let LLMEvent::Queued(queued) = event {
+                self.status = match queued {
+                    Queued::Position(1) => "Queued: next in line, other sessions' requests are in flight…".into(),
+                    Queued::Position(n) => format!("Queued: position {}, other sessions' requests are in flight…", n),
+                    Queued::RateLimited(wait) => format!(
+                        "Queued: requests-per-minute limit reached, sending in about {}s…",
+                        wait.as_secs().max(1)
+                    ),
+                };
+                continue;
+            }
             self.waiting = false;
+            if let LLMEvent::Response { usage: Some(usage), .. } | LLMEvent::ToolCalls { usage: Some(usage), .. } =
+                &event
+            {
+                metrics::tokens(usage);
+            }
             match event {
-                LLMEvent::Response(text) => {
-                    self.status = "Response received.".into();
+                LLMEvent::Response { text, usage } => {
+                    let model = self.turn_model();
+                    let usage = self.take_turn_usage(usage);
+                    self.status = match self.turn_provider.take() {
+                        Some((name, _)) => format!("Response received from {}.", name),
+                        None => "Response received.".into(),
+                    };
+                    // A final answer ends the task; the reply is its summary.
+                    self.finish_task("finished");
                     self.suggestions = extract_code_blocks(&text);
                     self.suggestion_idx = if self.suggestions.is_empty() { None } else { Some(0) };
+                    self.preview = false;
+                    self.suggestions_from = Some(self.history.len());
+                    if std::mem::take(&mut self.explaining) {
+                        self.explanation = Some(text.clone());
+                    }
+                    let saved = std::mem::take(&mut self.runbook).then(|| {
+                        runbook::save(&self.notes_dir, &self.connection.name, &text)
+                            .map(|path| format!("Runbook saved to {}.", path.display()))
+                            .unwrap_or_else(|e| format!("Could not save the runbook: {:#}", e))
+                    });
                     self.rich_history.push(RichMessage::assistant_text(&text));
-                    self.history.push(Message::assistant(text));
+                    self.history.push(Message::assistant(text).answered_by(model, usage));
+                    if let Some(saved) = saved {
+                        self.status = saved.clone();
+                        self.history.push(Message::assistant(format!("[{}]", saved)));
+                    }
                     self.scroll_offset = 0;
                 }
-                LLMEvent::ToolCall { id: api_id, command, description, assistant_blocks } => {
-                    self.status = "Awaiting confirmation…".into();
-                    // Replace the API-generated id with a locally unique one.
+                LLMEvent::ToolCalls { calls, assistant_blocks, usage } => {
+                    // Replace the API-generated ids with locally unique ones.
                     // Anthropic occasionally reuses ids across turns, which causes
                     // "tool_use ids must be unique" rejections on subsequent requests.
-                    let local_id = unique_tool_id();
+                    let mut local_ids: Vec<(String, String)> = vec![];
                     let assistant_blocks: Vec<ContentBlock> = assistant_blocks
                         .into_iter()
                         .map(|b| match b {
-                            ContentBlock::ToolUse { id, name, input } if id == api_id => {
-                                ContentBlock::ToolUse { id: local_id.clone(), name, input }
+                            ContentBlock::ToolUse { id, name, input } => {
+                                let local_id = unique_tool_id();
+                                local_ids.push((id, local_id.clone()));
+                                ContentBlock::ToolUse { id: local_id, name, input }
                             }
                             other => other,
                         })
                         .collect();
-
-                    // Show any text the model produced before the tool call.
+                    let local_id = |api_id: &str| {
+                        local_ids
+                            .iter()
+                            .find(|(a, _)| a == api_id)
+                            .map(|(_, l)| l.clone())
+                            .unwrap_or_else(unique_tool_id)
+                    };
+
+                    // Show any text the model produced before the tool calls.
                     let pre_text: String = assistant_blocks
                         .iter()
                         .filter_map(|b| if let ContentBlock::Text { text } = b { Some(text.as_str()) } else { None })
                         .collect::<Vec<_>>()
                         .join("");
-                    if !pre_text.trim().is_empty() {
-                        self.history.push(Message::assistant(pre_text));
+                    if pre_text.trim().is_empty() {
+                        // Billed to the next message the model writes.
+                        self.turn_usage = self.take_turn_usage(usage);
+                    } else {
+                        let usage = self.take_turn_usage(usage);
+                        self.history.push(Message::assistant(pre_text).answered_by(self.turn_model(), usage));
                     }
-                    self.pending_tool_call = Some(PendingToolCall {
-                        id: local_id,
-                        command: command.clone(),
-                        description,
+
+                    // Local tools resolve right away; commands queue for confirmation.
+                    let mut batch = ToolBatch {
                         assistant_blocks,
-                    });
-                    if self.auto_approve {
-                        // Immediately approve without showing the prompt.
-                        self.confirm_tool_call(true);
+                        queue: VecDeque::new(),
+                        total: 0,
+                        results: vec![],
+                        approve_all: false,
+                        plan_review: None,
+                        tools: self.tools.clone(),
+                    };
+                    for call in calls {
+                        match call {
+                            ToolResult::Local { id, name, input } => {
+                                self.status = format!("{}…", name);
+                                let id = local_id(&id);
+                                batch.start(&id);
+                                let result = self.resolve_local_tool(&name, &input);
+                                if name == "update_plan" && batch.plan_review.is_none() && self.plan_needs_review() {
+                                    let mut text = "Proposed plan:".to_string();
+                                    for (i, step) in self.plan.iter().enumerate() {
+                                        text.push_str(&format!("\n  {}. {}", i + 1, step.title));
+                                        if let Some(command) = &step.command {
+                                            text.push_str(&format!("\n     $ {}", command));
+                                        }
+                                    }
+                                    self.history.push(Message::assistant(text));
+                                    batch.plan_review = Some(id);
+                                } else {
+                                    batch.push_result(&id, result);
+                                }
+                            }
+                            ToolResult::Command { id, command, description } => {
+                                batch.queue.push_back(PendingToolCall {
+                                    id: local_id(&id),
+                                    warning: sheesh_tools::interactive_warning(&command),
+                                    destructive: sheesh_tools::destructive_reason(&command),
+                                    command,
+                                    description,
+                                    write: None,
+                                    preview: None,
+                                    download: None,
+                                    local: None,
+                                    edit_buffer: None,
+                                    edited: false,
+                                    expanded: false,
+                                    scroll: 0,
+                                    typed_confirm: None,
+                                });
+                            }
+                            ToolResult::Download { id, path } => {
+                                let path = self.remote_path(&path);
+                                batch.queue.push_back(PendingToolCall {
+                                    id: local_id(&id),
+                                    command: format!(
+                                        "scp -s {}:{} {}/",
+                                        self.connection.destination(),
+                                        sheesh_tools::shell_quote(&path),
+                                        self.download_dir.display()
+                                    ),
+                                    description: Some(format!("Download {} to this machine", path)),
+                                    edit_buffer: None,
+                                    edited: false,
+                                    expanded: false,
+                                    scroll: 0,
+                                    typed_confirm: None,
+                                    warning: None,
+                                    destructive: None,
+                                    write: None,
+                                    preview: None,
+                                    download: Some(path),
+                                    local: None,
+                                });
+                            }
+                            ToolResult::Write { id, mut write, description } => {
+                                let id = local_id(&id);
+                                // Roll back to the backup taken before this session's latest write.
+                                if let WriteKind::Rollback = write.kind {
+                                    match self.changed_files.iter().rev().find(|c| c.path == write.path) {
+                                        Some(change) => write = change.restore(),
+                                        None => {
+                                            batch.push_result(
+                                                &id,
+                                                format!("{} was not changed this session; there is nothing to roll back.", write.path),
+                                            );
+                                            continue;
+                                        }
+                                    }
+                                }
+                                batch.queue.push_back(PendingToolCall {
+                                    id,
+                                    command: String::new(),
+                                    description,
+                                    edit_buffer: None,
+                                    edited: false,
+                                    expanded: false,
+                                    scroll: 0,
+                                    typed_confirm: None,
+                                    warning: None,
+                                    destructive: None,
+                                    write: Some(write),
+                                    preview: None,
+                                    download: None,
+                                    local: None,
+                                });
+                            }
+                            ToolResult::Machine { id, name, input, permission } => {
+                                let (command, ask) = match permission {
+                                    LocalPermission::Allow => (name.clone(), false),
+                                    LocalPermission::Ask(summary) => (summary, true),
+                                };
+                                batch.queue.push_back(PendingToolCall {
+                                    id: local_id(&id),
+                                    command,
+                                    description: Some(format!("Run {} on this machine", name)),
+                                    edit_buffer: None,
+                                    edited: false,
+                                    expanded: false,
+                                    scroll: 0,
+                                    typed_confirm: None,
+                                    warning: None,
+                                    destructive: None,
+                                    write: None,
+                                    preview: None,
+                                    download: None,
+                                    local: Some(LocalCall { name, input, ask }),
+                                });
+                            }
+                            ToolResult::Rejected { id, name, error } => {
+                                log::warn!("[llm] {} call rejected: {}", name, error);
+                                batch.push_error(&local_id(&id), error);
+                            }
+                        }
                     }
+                    batch.total = batch.queue.len();
+                    self.tool_batch = Some(batch);
+                    self.advance_batch();
                     self.scroll_offset = 0;
                 }
-                LLMEvent::LocalTool { id: api_id, name, assistant_blocks } => {
-                    // Replace api id with a locally unique one.
-                    let local_id = unique_tool_id();
-                    let assistant_blocks: Vec<ContentBlock> = assistant_blocks
-                        .into_iter()
-                        .map(|b| match b {
-                            ContentBlock::ToolUse { id, name, input } if id == api_id => {
-                                ContentBlock::ToolUse { id: local_id.clone(), name, input }
-                            }
-                            other => other,
-                        })
-                        .collect();
+                LLMEvent::Queued(_) => {}
+                LLMEvent::Error(err) => {
+                    self.turn_provider = None;
+                    self.turn_usage = None;
+                    self.status = format!("Error: {}", err);
+                    self.run_hook(HookEvent::Error, &[("SHEESH_ERROR", err.clone())]);
+                    self.abandon_turn(&err);
+                    self.history.push(Message::assistant(format!("[error] {}", err)));
+                    self.scroll_offset = 0;
+                }
+            }
+        }
+    }
 
-                    // Show any text produced before the tool call.
-                    let pre_text: String = assistant_blocks
-                        .iter()
-                        .filter_map(|b| if let ContentBlock::Text { text } = b { Some(text.as_str()) } else { None })
-                        .collect::<Vec<_>>()
-                        .join("");
-                    if !pre_text.trim().is_empty() {
-                        self.history.push(Message::assistant(pre_text));
-                    }
+    /// Confirm or decline the pending tool call.
+    /// Returns the command string if confirmed (to be forwarded as `SendToTerminal`).
+    /// On accept the LLM is NOT resumed yet — `resume_with_output` does that
+    /// once `main.rs` has captured the terminal output.
+    fn confirm_tool_call(&mut self, accepted: bool) -> Option<String> {
+        let ptc = self.pending_tool_call.take()?;
+        if accepted && let Some(batch) = &self.tool_batch {
+            batch.start(&ptc.id);
+        }
+
+        if accepted && let Some(remote) = ptc.download {
+            // Runs outside the PTY; `poll` resumes once scp is done.
+            self.start_download(Some(ptc.id), remote);
+            None
+        } else if accepted && let Some(call) = ptc.local {
+            self.start_local_tool(ptc.id, call);
+            None
+        } else if accepted {
+            // Store the tool-use id; resume happens after output capture.
+            self.awaiting_output_id = Some(ptc.id);
+            self.edited_command = ptc.edited.then(|| ptc.command.clone());
+            self.running_write = ptc.write.zip(ptc.preview);
+            self.waiting = true; // block new messages until output is captured
+            self.status = "Command sent — capturing output…".into();
+            metrics::command_executed();
+            Some(ptc.command)
+        } else {
+            if let Some(batch) = &mut self.tool_batch {
+                batch.push_result(&ptc.id, "User declined to execute the command.");
+            }
+            self.advance_batch();
+            None
+        }
+    }
+
+    /// Copy `remote` to the download directory on a background thread. The
+    /// result arrives through `transfer_rx`; `id` ties it to a tool call.
+    fn start_download(&mut self, id: Option<String>, remote: String) {
+        self.status = format!("Downloading {}…", remote);
+        if id.is_some() {
+            self.waiting = true;
+        }
+        self.transfer_id = id.clone();
+        let (conn, dir, tx) = (self.connection.clone(), self.download_dir.clone(), self.transfer_tx.clone());
+        std::thread::spawn(move || {
+            let result = crate::ssh::download(&conn, &remote, &dir).map_err(|e| e.to_string());
+            let _ = tx.send((id, remote, result));
+        });
+    }
 
-                    // Resolve the tool result locally.
-                    let result = self.resolve_local_tool(&name);
+    /// Run a local tool on a background thread; `poll` resumes once it's done.
+    fn start_local_tool(&mut self, id: String, call: LocalCall) {
+        self.status = format!("Running {}…", call.name);
+        self.waiting = true;
+        self.running_local = Some(id.clone());
+        let (tools, tx) = (self.tools.clone(), self.local_tx.clone());
+        std::thread::spawn(move || {
+            let result = tools.run_local(&call.name, &call.input).unwrap_or_else(|e| {
+                metrics::tool_failed();
+                format!("{} failed: {}", call.name, e)
+            });
+            let _ = tx.send((id, result));
+        });
+    }
+
+    /// Resolve a relative remote path against the shell's working directory
+    /// (scp would otherwise resolve it against the remote home).
+    fn remote_path(&self, path: &str) -> String {
+        match &self.cwd {
+            Some(cwd) if !path.starts_with('/') && !path.starts_with('~') => {
+                format!("{}/{}", cwd.trim_end_matches('/'), path)
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    /// Decline the pending tool call and every command queued behind it (`N`).
+    fn decline_batch(&mut self) {
+        if let Some(batch) = &mut self.tool_batch {
+            for ptc in std::mem::take(&mut batch.queue) {
+                batch.push_result(&ptc.id, "User declined to execute the command.");
+            }
+        }
+        self.confirm_tool_call(false);
+    }
+
+    /// Move on to the next queued command of the current batch, or send all
+    /// results back to the LLM once none are left. Commands covered by
+    /// auto-approve or `Y` are approved here and left in `approved_command`.
+    fn advance_batch(&mut self) {
+        let Some(batch) = &mut self.tool_batch else {
+            return;
+        };
+        if batch.plan_review.is_some() {
+            self.waiting = false;
+            self.status = "Review the proposed plan…".into();
+            return;
+        }
+        let Some(next) = batch.queue.pop_front() else {
+            let batch = self.tool_batch.take().expect("checked above");
+            self.rich_history.extend(batch.into_messages(&mut self.outputs, &self.output_limits));
+            self.waiting = true;
+            self.status = "Waiting for Claude…".into();
+            self.request_completion();
+            return;
+        };
+
+        // Writes need the file's current content before they can be previewed.
+        if let Some(write) = &next.write
+            && next.preview.is_none()
+        {
+            // Read over a session of its own, which starts in the home
+            // directory, so nothing is typed into the user's terminal.
+            let path = self.remote_path(&write.path);
+            if !path.starts_with(['/', '~']) {
+                if let Some(batch) = &mut self.tool_batch {
+                    batch.push_result(
+                        &next.id,
+                        format!(
+                            "The write was not attempted: {} is relative and the terminal's working directory \
+                             is unknown; give an absolute path.",
+                            write.path
+                        ),
+                    );
+                }
+                self.advance_batch();
+                return;
+            }
+            self.status = format!("Reading {} for the diff preview…", write.path);
+            let path = path.strip_prefix("~/").map_or_else(|| path.clone(), String::from);
+            let read = FileWrite { path, kind: write.kind.clone() }.read_command();
+            let (id, remote, tx) = (next.id.clone(), self.remote.clone(), self.read_tx.clone());
+            std::thread::spawn(move || {
+                let result = remote.exec(&read).map(|out| out.stdout).map_err(|e| e.to_string());
+                let _ = tx.send((id, result));
+            });
+            self.reading_file = Some(next);
+            self.waiting = true;
+            return;
+        }
+        self.present_tool_call(next);
+    }
+
+    /// Show `ptc` in the confirmation prompt, or approve it straight away if
+    /// auto-approve, `Y` or an approved plan covers it.
+    fn present_tool_call(&mut self, ptc: PendingToolCall) {
+        let (position, total, batch_approved) = self
+            .tool_batch
+            .as_ref()
+            .map_or((1, 1, false), |b| (b.total - b.queue.len(), b.total, b.approve_all));
+        // Only commands the approved plan names; anything else asks.
+        let in_plan = self.approved_plan.as_ref().is_some_and(|steps| {
+            steps.iter().any(|(_, command)| command.as_deref() == Some(ptc.command.trim()))
+        });
+        let plan_approved = in_plan
+            && match self.plan_policy {
+                PlanPolicy::Off => false,
+                PlanPolicy::Safe => ptc.destructive.is_none(),
+                PlanPolicy::All => true,
+            };
+        // Interactive commands and file writes always get a human look,
+        // even when auto-approved. Auto-approval and plans cover remote
+        // commands only; local tools that ask are run by `Y` or a keypress.
+        // On a guarded host nothing that asks runs without one.
+        let approved = match &ptc.local {
+            Some(call) => !call.ask || (batch_approved && !self.guard),
+            None => {
+                !self.guard
+                    && ptc.warning.is_none()
+                    && ptc.write.is_none()
+                    && (batch_approved || plan_approved || self.take_auto_approval(&ptc.command))
+            }
+        };
+        self.pending_tool_call = Some(ptc);
+        self.waiting = false;
+        if approved {
+            self.approved_command = self.confirm_tool_call(true);
+        } else if total > 1 {
+            self.status = format!("Awaiting confirmation ({} of {})…", position, total);
+        } else {
+            self.status = "Awaiting confirmation…".into();
+        }
+    }
 
-                    // Commit to rich history and immediately resume Claude.
-                    self.rich_history.push(RichMessage {
-                        role: Role::Assistant,
-                        content: assistant_blocks,
-                    });
-                    self.rich_history.push(RichMessage::tool_result(&local_id, &result));
-                    self.waiting = true;
-                    self.status = format!("{}… waiting for Claude…", name);
-                    spawn_completion_rich(Arc::clone(&self.provider), self.rich_history.clone(), self.tx.clone());
-                    self.scroll_offset = 0;
-                }
-                LLMEvent::Error(err) => {
-                    self.status = format!("Error: {}", err);
-                    self.history.push(Message::assistant(format!("[error] {}", err)));
-                    self.scroll_offset = 0;
+    /// Build the diff preview of a write from the captured output of its
+    /// read step and put it up for confirmation. Unreadable files, edits
+    /// that don't apply and no-op writes go straight back to the model.
+    fn preview_write(&mut self, mut ptc: PendingToolCall, output: &str) {
+        let Some(write) = ptc.write.clone() else {
+            return;
+        };
+        let prepared = write
+            .parse_read_output(output)
+            .and_then(|before| write.apply(before.as_deref()).map(|after| (before, after)));
+        let skipped = match prepared {
+            Err(e) => Some(format!("The write was not attempted: {}.", e)),
+            Ok((Some(before), after)) if before == after => {
+                Some(format!("{} already has this content; nothing was written.", write.path))
+            }
+            Ok((before, after)) => {
+                // Keep a copy of existing files next to them before writing.
+                let backup = match (&before, &write.kind) {
+                    (_, WriteKind::Restore { .. }) | (None, _) => None,
+                    (Some(_), _) => {
+                        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                        Some(write.backup_path(now))
+                    }
+                };
+                let before = before.unwrap_or_default();
+                let (added, removed) = sheesh_tools::diff_stat(&before, &after);
+                let summary = match (&write.kind, &backup) {
+                    (WriteKind::Restore { backup: Some(from), .. }, _) => {
+                        format!("Restored {} from {}.", write.path, from)
+                    }
+                    (WriteKind::Restore { backup: None, .. }, _) => {
+                        format!("Removed {}; it did not exist before this session's write.", write.path)
+                    }
+                    (_, Some(backup)) => format!(
+                        "Wrote {} (+{} -{} lines). The previous version was saved to {}.",
+                        write.path, added, removed, backup
+                    ),
+                    (_, None) => format!("Created {} ({} lines).", write.path, added),
+                };
+                ptc.command = write.write_command(&after, backup.as_deref());
+                ptc.preview = Some(WritePreview {
+                    diff: sheesh_tools::unified_diff(&write.path, &before, &after),
+                    summary,
+                    backup,
+                    before,
+                });
+                None
+            }
+        };
+        match skipped {
+            Some(result) => {
+                if let Some(batch) = &mut self.tool_batch {
+                    batch.push_result(&ptc.id, result);
                 }
+                self.advance_batch();
             }
+            None => self.present_tool_call(ptc),
         }
     }
 
-    /// Confirm or decline the pending tool call.
-    /// Returns the command string if confirmed (to be forwarded as `SendToTerminal`).
-    /// On accept the LLM is NOT resumed yet — `resume_with_output` does that
-    /// once `main.rs` has captured the terminal output.
-    fn confirm_tool_call(&mut self, accepted: bool) -> Option<String> {
-        let ptc = self.pending_tool_call.take()?;
+    /// True while `main.rs` should capture terminal output for the LLM: an
+    /// approved command is running or a file is being rolled back from the
+    /// changed-files list.
+    pub fn awaiting_output(&self) -> bool {
+        self.awaiting_output_id.is_some() || self.rolling_back.is_some()
+    }
 
-        // Append assistant blocks to rich history.
-        self.rich_history.push(crate::llm::RichMessage {
-            role: Role::Assistant,
-            content: ptc.assistant_blocks,
-        });
+    /// True if the plan just published via `update_plan` differs from the
+    /// one the user already approved (status changes alone don't count).
+    fn plan_needs_review(&self) -> bool {
+        if self.plan_policy == PlanPolicy::Off || self.plan.is_empty() {
+            return false;
+        }
+        self.approved_plan.as_ref().is_none_or(|approved| *approved != self.plan_outline())
+    }
 
-        if accepted {
-            // Store the tool-use id; resume happens after output capture.
-            self.awaiting_output_id = Some(ptc.id);
-            self.waiting = true; // block new messages until output is captured
-            self.status = "Command sent — capturing output…".into();
-            Some(ptc.command)
+    /// The plan's steps without their progress, as approved.
+    fn plan_outline(&self) -> Vec<(String, Option<String>)> {
+        self.plan.iter().map(|s| (s.title.clone(), s.command.clone())).collect()
+    }
+
+    /// Approve or reject the plan awaiting review. Rejecting declines every
+    /// command queued behind it.
+    fn review_plan(&mut self, approved: bool) {
+        let outline = self.plan_outline();
+        let Some(batch) = &mut self.tool_batch else {
+            return;
+        };
+        let Some(id) = batch.plan_review.take() else {
+            return;
+        };
+        if approved {
+            self.approved_plan = Some(outline);
+            let result = match self.plan_policy {
+                _ if self.guard => "The user approved the plan. This host is guarded, so each command will still be \
+                                    confirmed by typing the host's alias.",
+                PlanPolicy::All => "The user approved the plan. The commands it names will run without further \
+                                    confirmation; any other command will be confirmed.",
+                _ => "The user approved the plan. The safe commands it names will run without further \
+                      confirmation; destructive ones and any other command will still be confirmed.",
+            };
+            batch.push_result(&id, result);
+            self.status = "Plan approved.".into();
         } else {
-            self.rich_history.push(RichMessage::tool_result(
-                &ptc.id,
-                "User declined to execute the command.",
-            ));
-            self.waiting = true;
-            self.status = "Declined — waiting for Claude…".into();
-            spawn_completion_rich(
-                Arc::clone(&self.provider),
-                self.rich_history.clone(),
-                self.tx.clone(),
+            batch.push_result(
+                &id,
+                "The user rejected the plan. Do not run its commands; ask the user how they want to proceed.",
             );
-            None
+            for ptc in std::mem::take(&mut batch.queue) {
+                batch.push_result(&ptc.id, "User rejected the plan; the command was not run.");
+            }
+            self.plan.clear();
+            self.history.push(Message::assistant("[plan rejected]".to_string()));
+        }
+        self.advance_batch();
+    }
+
+    /// True while a proposed plan waits for the user's approval.
+    fn plan_review_pending(&self) -> bool {
+        self.tool_batch.as_ref().is_some_and(|b| b.plan_review.is_some())
+    }
+
+    /// Command approved without a keypress since the last call. `main.rs`
+    /// polls this every tick and runs it like a `SendToTerminal` action.
+    pub fn take_approved_command(&mut self) -> Option<String> {
+        self.approved_command.take()
+    }
+
+    /// Set any auto-approval aside until [`resume_auto_approve`](Self::resume_auto_approve)
+    /// (the idle lock); true if there was one.
+    pub fn pause_auto_approve(&mut self) -> bool {
+        self.paused_auto_approve = self.auto_approve.take();
+        self.paused_auto_approve.is_some()
+    }
+
+    pub fn resume_auto_approve(&mut self) {
+        if let Some(scope) = self.paused_auto_approve.take() {
+            self.auto_approve = Some(scope);
+        }
+    }
+
+    /// Returns true if the active auto-approval covers `command`, consuming
+    /// one call from a counted scope and dropping expired scopes.
+    fn take_auto_approval(&mut self, command: &str) -> bool {
+        let allowed = match &mut self.auto_approve {
+            None => return false,
+            Some(AutoApprove::Session) => true,
+            // Only what the scope says, and never a destructive one.
+            Some(AutoApprove::Program(p)) => {
+                command_program(command).as_deref() == Some(p.as_str())
+                    && sheesh_tools::destructive_reason(command).is_none()
+            }
+            Some(AutoApprove::Calls(n)) => {
+                let allowed = *n > 0;
+                *n = n.saturating_sub(1);
+                allowed
+            }
+            Some(AutoApprove::Until(t)) => Instant::now() < *t,
+        };
+        let exhausted = match &self.auto_approve {
+            Some(AutoApprove::Calls(0)) => true,
+            Some(AutoApprove::Until(t)) => Instant::now() >= *t,
+            _ => false,
+        };
+        if exhausted {
+            self.auto_approve = None;
+        }
+        allowed
+    }
+
+    /// Apply the inline edit buffer to the pending tool call and approve it.
+    /// An empty or unchanged buffer runs the original command.
+    fn finish_edit(&mut self) -> Option<String> {
+        let ptc = self.pending_tool_call.as_mut()?;
+        let edited = ptc.edit_buffer.take()?.trim().to_string();
+        if !edited.is_empty() && edited != ptc.command {
+            ptc.warning = sheesh_tools::interactive_warning(&edited);
+            ptc.destructive = sheesh_tools::destructive_reason(&edited);
+            ptc.command = edited;
+            ptc.edited = true;
+        }
+        self.approve_pending()
+    }
+
+    /// Approve the pending call from the prompt, unless it is a command or
+    /// a write on a guarded host: those ask for the alias to be typed first,
+    /// since a harmless-looking command can still do damage there.
+    fn approve_pending(&mut self) -> Option<String> {
+        if self.guard
+            && let Some(ptc) = &mut self.pending_tool_call
+            && (ptc.is_shell_command() || ptc.write.is_some())
+        {
+            ptc.typed_confirm = Some(String::new());
+            let what = if ptc.write.is_some() { "write" } else { "command" };
+            self.status = format!("Type {} to run this {} on a guarded host.", self.connection.name, what);
+            return None;
         }
+        self.confirm_tool_call(true)
     }
 
     /// Called by `main.rs` after the terminal output has been captured.
     /// Returns true while the LLM is in the middle of a tool-execution cycle
     /// (pending confirmation, command sent, or waiting for Claude to respond).
     pub fn is_executing_tool(&self) -> bool {
-        self.pending_tool_call.is_some() || self.awaiting_output_id.is_some()
+        self.pending_tool_call.is_some()
+            || self.awaiting_output()
+            || self.reading_file.is_some()
+            || self.transfer_id.is_some()
+            || self.running_local.is_some()
+            || self.tool_batch.is_some()
     }
 
     /// Cancel any in-progress tool call and return to an idle state so the
     /// user can type a new message.
-    pub fn cancel_tool_call(&mut self) {
-        // If the tool call was already confirmed (assistant blocks pushed to rich_history),
-        // we must add a tool_result to avoid sending an orphaned tool_use to the API.
-        if let Some(id) = self.awaiting_output_id.take() {
-            self.rich_history.push(RichMessage::tool_result(&id, "User cancelled the command before output was captured."));
+    /// `partial_output` is whatever the command printed before it was interrupted.
+    pub fn cancel_tool_call(&mut self, partial_output: String) {
+        // A request still queued behind other sessions' is dropped.
+        self.request_cancel.store(true, Ordering::Relaxed);
+        if self.held_request.is_some() {
+            self.release_request(false);
+            return;
+        }
+        if let Some((_, write)) = self.rolling_back.take() {
+            self.status = format!("Rollback of {} cancelled; it is still listed under ctrl+b.", write.path);
+            return;
+        }
+        // Every tool_use of the turn still needs a tool_result, otherwise the
+        // next request would carry orphaned tool_use blocks.
+        if let Some(mut batch) = self.tool_batch.take() {
+            if let Some(id) = self.awaiting_output_id.take() {
+                let mut result = "User cancelled the command before it finished.".to_string();
+                if !partial_output.trim().is_empty() {
+                    result.push_str(&format!("\nOutput captured so far:\n```\n{}\n```", partial_output));
+                }
+                batch.push_result(&id, result);
+            }
+            if let Some(id) = self.transfer_id.take() {
+                batch.push_result(&id, "User cancelled the download.");
+            }
+            if let Some(id) = self.running_local.take() {
+                batch.push_result(&id, "User cancelled the tool call before it finished.");
+            }
+            let unfinished = self.reading_file.take().into_iter().chain(self.pending_tool_call.take());
+            for ptc in unfinished.chain(std::mem::take(&mut batch.queue)) {
+                batch.push_result(&ptc.id, "User cancelled the tool call; the command was not run.");
+            }
+            self.rich_history.extend(batch.into_messages(&mut self.outputs, &self.output_limits));
         }
         self.pending_tool_call = None;
+        self.awaiting_output_id = None;
+        self.approved_command = None;
+        self.reading_file = None;
+        self.running_write = None;
+        self.turn_provider = None;
+        self.edited_command = None;
         self.waiting = false;
         self.status = "Tool call cancelled.".into();
         self.history.push(Message::assistant("[tool call cancelled by user]".to_string()));
+        self.finish_task("stopped");
+        self.abandon_turn("cancelled");
         self.scroll_offset = 0;
     }
 
+    /// Recent terminal output for read_terminal: only the chunks printed
+    /// since the previous read, behind a marker saying so, or the last
+    /// [`CONTEXT_LINES`] when `full`, on the first read, or when the earlier
+    /// read can no longer be found (screen cleared, scrolled out of the log).
+    /// The text is tidied first unless `clean_context` is off.
+    fn read_terminal(&mut self, full: bool) -> String {
+        let Some(log) = &self.terminal_output else {
+            return "Terminal output not available.".to_string();
+        };
+        let log = log.lock().unwrap();
+        if log.is_empty() {
+            self.terminal_mark = None;
+            return "No terminal output captured yet.".to_string();
+        }
+        let marked = self.terminal_mark.is_some() && !full;
+        let since = self.terminal_mark.as_ref().filter(|_| !full).and_then(|mark| mark.find(&log));
+        self.terminal_mark = Some(TerminalMark::at_end(&log));
+        let clean = self.llm_config.clean_context;
+        let text = |chunks: &[String]| {
+            let text = visible_text(&chunks.join(""));
+            if clean { clean_terminal_text(&text) } else { text }
+        };
+        let Some(since) = since else {
+            let start = log.len().saturating_sub(CONTEXT_LINES);
+            if marked {
+                return format!(
+                    "[--- output since your last read_terminal was lost (buffer cleared, or more than the log \
+                     keeps); the most recent output follows ---]\n{}",
+                    text(&log[start..])
+                );
+            }
+            return text(&log[start..]);
+        };
+        let new = &log[since..];
+        if new.is_empty() {
+            return "[no new terminal output since your last read_terminal; pass full=true to see the recent \
+                    output again]"
+                .to_string();
+        }
+        let start = new.len().saturating_sub(CONTEXT_LINES);
+        let mut out = if start > 0 {
+            format!(
+                "[continues from your last read_terminal; {} older chunks of new output were skipped]\n",
+                start
+            )
+        } else {
+            "[continues from your last read_terminal; only output printed since then follows]\n".to_string()
+        };
+        out.push_str(&text(&new[start..]));
+        out
+    }
+
     /// Resolve a local tool call (no PTY needed) and return its result string.
-    fn resolve_local_tool(&self, name: &str) -> String {
+    fn resolve_local_tool(&mut self, name: &str, input: &serde_json::Value) -> String {
         match name {
             "system_information" => {
                 let c = &self.connection;
                 format!(
-                    "Host: {}\nHostname: {}\nUser: {}\nPort: {}\nDescription: {}\nIdentityFile: {}\nExtraOptions: {}",
+                    "Host: {}\nHostname: {}\nUser: {}\nPort: {}\nDescription: {}\nIdentityFile: {}\nExtraOptions: {}\nWorkingDirectory: {}",
                     c.name,
-                    c.hostname,
-                    c.user,
-                    if c.port == 0 { 22 } else { c.port },
+                    c.effective_hostname(),
+                    c.effective_user(),
+                    c.effective_port(),
                     if c.description.is_empty() { "(none)" } else { &c.description },
-                    c.identity_file.as_deref().unwrap_or("(none)"),
+                    c.effective_identity_file().unwrap_or("(none)"),
                     if c.extra_options.is_empty() { "(none)".to_string() } else { c.extra_options.join(", ") },
+                    self.cwd.as_deref().unwrap_or("(unknown)"),
                 )
             }
-            "read_terminal" => {
-                match &self.terminal_output {
-                    None => "Terminal output not available.".to_string(),
-                    Some(log) => {
-                        let log = log.lock().unwrap();
-                        if log.is_empty() {
-                            "No terminal output captured yet.".to_string()
-                        } else {
-                            let start = log.len().saturating_sub(CONTEXT_LINES);
-                            log[start..].join("")
-                        }
-                    }
-                }
+            "read_terminal" => self.read_terminal(input["full"].as_bool().unwrap_or(false)),
+            "update_plan" => {
+                self.plan = input["steps"]
+                    .as_array()
+                    .map(|steps| {
+                        steps
+                            .iter()
+                            .filter_map(|s| {
+                                Some(PlanStep {
+                                    title: s["step"].as_str()?.to_string(),
+                                    command: s["command"]
+                                        .as_str()
+                                        .map(str::trim)
+                                        .filter(|c| !c.is_empty())
+                                        .map(String::from),
+                                    status: StepStatus::parse(s["status"].as_str().unwrap_or("")),
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let done = self.plan.iter().filter(|s| s.status == StepStatus::Done).count();
+                format!("Plan updated: {} of {} steps done.", done, self.plan.len())
             }
+            "read_more" => ReadMoreArgs::parse(name, input)
+                .and_then(|args| self.outputs.read_more(&args.token, args.start_line, &self.output_limits))
+                .unwrap_or_else(|e| e.to_string()),
             other => format!("Unknown local tool: {}", other),
         }
     }
 
     /// Appends the output as a tool_result and resumes the LLM.
-    pub fn resume_with_output(&mut self, output: String) {
+    /// `note` explains an early end to capture (timeout, user finished it).
+    pub fn resume_with_output(&mut self, output: String, note: Option<String>) {
+        if let Some((index, write)) = self.rolling_back.take() {
+            self.finish_rollback(index, write, &output);
+            return;
+        }
         let id = match self.awaiting_output_id.take() {
             Some(id) => id,
             None => return,
         };
-        let result_text = if output.trim().is_empty() {
+        if let Some((write, preview)) = self.running_write.take() {
+            let result = match write.check_write_output(&output) {
+                Ok(()) => {
+                    if let WriteKind::Restore { .. } = write.kind {
+                        if let Some(i) = self.changed_files.iter().rposition(|c| c.path == write.path) {
+                            self.changed_files.remove(i);
+                        }
+                    } else {
+                        self.changed_files.push(FileBackup {
+                            path: write.path,
+                            backup: preview.backup,
+                            content: preview.before,
+                            written: Instant::now(),
+                        });
+                    }
+                    preview.summary
+                }
+                Err(e) => {
+                    metrics::tool_failed();
+                    e.to_string()
+                }
+            };
+            if let Some(batch) = &mut self.tool_batch {
+                batch.push_result(&id, result);
+            }
+            self.advance_batch();
+            return;
+        }
+        let mut result_text = if output.trim().is_empty() {
             "Command executed. No output was captured.".to_string()
         } else {
             format!("Command output:\n```\n{}\n```", output)
         };
-        self.rich_history.push(RichMessage::tool_result(&id, &result_text));
+        if let Some(note) = note {
+            result_text = format!("{}\n{}", note, result_text);
+        }
+        if let Some(cmd) = self.edited_command.take() {
+            result_text = format!(
+                "The user edited the proposed command before running it. Command actually executed:\n```\n{}\n```\n{}",
+                cmd, result_text
+            );
+        }
+        if let Some(batch) = &mut self.tool_batch {
+            batch.push_result(&id, result_text);
+        }
+        self.advance_batch();
+    }
+
+    /// Drop the changed-files record at `index` once the captured output
+    /// shows its restore went through; keep it if the restore failed.
+    fn finish_rollback(&mut self, index: usize, write: FileWrite, output: &str) {
+        if let Err(e) = write.check_write_output(output) {
+            self.status = format!("Rollback failed: {}", e.to_string().replace('\n', " "));
+            return;
+        }
+        if index < self.changed_files.len() {
+            self.changed_files.remove(index);
+        }
+        self.changes_view = match (self.changes_view, self.changed_files.len()) {
+            (_, 0) | (None, _) => None,
+            (Some(selected), n) => Some(selected.min(n - 1)),
+        };
+        self.history.push(Message::assistant(format!("[rolled back {}]", write.path)));
+        self.status = format!("Rolled back {}.", write.path);
+    }
+
+    /// Start task mode: the agent plans the steps toward `goal`, carries them
+    /// out through the usual tool confirmation, and ends with a summary.
+    pub fn start_task(&mut self, goal: String) {
+        let goal = goal.trim().to_string();
+        if goal.is_empty() || self.waiting {
+            return;
+        }
+        self.history.push(Message::user(format!("Task: {}", goal)));
+        self.rich_history.push(RichMessage::user_text(format!("Task: {}\n\n{}", goal, TASK_PROMPT)));
+        self.plan.clear();
+        self.task = Some(goal);
         self.waiting = true;
-        self.status = "Output captured — waiting for Claude…".into();
-        spawn_completion_rich(
-            Arc::clone(&self.provider),
-            self.rich_history.clone(),
-            self.tx.clone(),
-        );
+        self.scroll_offset = 0;
+        self.status = "Planning task…".into();
+        self.request_completion();
+    }
+
+    /// Move the plan checklist into the chat history once the agent is done
+    /// with it, so the final state stays visible above the summary.
+    fn finish_task(&mut self, outcome: &str) {
+        // An approved plan only covers the request it was made for.
+        self.approved_plan = None;
+        if self.plan.is_empty() && self.task.is_none() {
+            return;
+        }
+        let mut text = match self.task.take() {
+            Some(goal) => format!("[task {}] {}", outcome, goal),
+            None => "[plan]".to_string(),
+        };
+        for step in std::mem::take(&mut self.plan) {
+            text.push_str(&format!("\n  {} {}", step.status.icon(), step.title));
+        }
+        self.history.push(Message::assistant(text));
     }
 
     pub fn send_message(&mut self, content: String) {
         if content.trim().is_empty() || self.waiting {
             return;
         }
+        self.explaining = false;
+        self.locality_notice = None;
+        if let Some(goal) = content.strip_prefix("/task ") {
+            self.start_task(goal.to_string());
+            return;
+        }
+        if let Some(rest) = content.strip_prefix("/model ") {
+            // The override lasts for this message's turn, tool calls included;
+            // the reply joins the same history the main model sees.
+            let (name, message) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+            if message.trim().is_empty() {
+                self.status = "Usage: /model <name> <message>".into();
+                return;
+            }
+            let route = self.llm_config.route(name);
+            if self.disabled {
+                self.status = "The assistant is turned off for this connection ([llm.connections]).".into();
+                return;
+            }
+            if let Some(pinned) = &self.llm_config.pinned
+                && route.provider != self.llm_config.provider
+            {
+                self.status = format!("This connection only uses {} ([llm.connections]).", pinned);
+                return;
+            }
+            let provider = build_provider(&route);
+            self.turn_provider = Some((name.to_string(), provider));
+            self.history.push(Message::user(&content));
+            self.rich_history.push(RichMessage::user_text(message.trim()));
+            self.waiting = true;
+            self.scroll_offset = 0;
+            self.status = format!("Waiting for {}…", name);
+            self.request_completion();
+            return;
+        }
+        if let Some(path) = content.strip_prefix("/download ") {
+            let path = self.remote_path(path.trim());
+            self.history.push(Message::user(format!("/download {}", path)));
+            self.start_download(None, path);
+            return;
+        }
+        if let Some(rest) = content.strip_prefix("/man ") {
+            let (command, question) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+            if command.is_empty() {
+                self.status = "Usage: /man <command> [question]".into();
+                return;
+            }
+            if !self.connection.transport.is_ssh() {
+                self.status = "/man reads the page over ssh, which this connection isn't".into();
+                return;
+            }
+            self.history.push(Message::user(&content));
+            self.waiting = true;
+            self.scroll_offset = 0;
+            self.status = format!("Reading {} on the host…", command);
+            let (conn, tx) = (self.connection.clone(), self.man_tx.clone());
+            let (command, question) = (command.to_string(), question.trim().to_string());
+            std::thread::spawn(move || {
+                let result = ManPage::fetch(&conn, &command).map_err(|e| format!("/man {}: {:#}", command, e));
+                let _ = tx.send((question, result));
+            });
+            return;
+        }
+        if content == "/runbook" || content.starts_with("/runbook ") {
+            let clean = self.llm_config.clean_context;
+            let transcript = self.terminal_output.as_ref().map_or(String::new(), |log| {
+                let text = visible_text(&log.lock().unwrap().join(""));
+                if clean { clean_terminal_text(&text) } else { text }
+            });
+            self.history.push(Message::user(&content));
+            self.rich_history.push(RichMessage::user_text(runbook::prompt(&content["/runbook".len()..], &transcript)));
+            self.runbook = true;
+            self.waiting = true;
+            self.scroll_offset = 0;
+            self.status = "Writing a runbook of this session…".into();
+            self.request_completion();
+            return;
+        }
         self.history.push(Message::user(&content));
         self.rich_history.push(RichMessage::user_text(&content));
         self.waiting = true;
         self.scroll_offset = 0;
         self.status = "Waiting for response…".into();
-        spawn_completion_rich(
-            Arc::clone(&self.provider),
-            self.rich_history.clone(),
-            self.tx.clone(),
-        );
+        self.request_completion();
     }
 
-    /// Build the flat list of rendered lines from the message history.
-    fn build_lines(&self) -> Vec<(String, Option<Style>)> {
-        let mut all: Vec<(String, Option<Style>)> = vec![];
-        for msg in &self.history {
+    /// Build the flat list of rendered lines from the message history,
+    /// with the opening fence line of each current suggestion and its
+    /// number (from 1), for the badges.
+    fn build_lines(&self) -> (Vec<ChatLine>, Vec<(usize, usize)>) {
+        let mut all: Vec<ChatLine> = vec![];
+        let mut badges = vec![];
+        for (index, msg) in self.history.iter().enumerate() {
+            if self.suggestions_from == Some(index) && self.suggestions.len() > 1 {
+                // Numbered as `extract_code_blocks` numbers them: blocks
+                // with nothing in them are not suggestions.
+                let mut open: Option<(usize, bool)> = None;
+                for (i, line) in msg.content.lines().enumerate() {
+                    if line.trim_start().starts_with("```") {
+                        match open.take() {
+                            Some((at, true)) => badges.push((all.len() + at, badges.len() + 1)),
+                            Some((_, false)) => {}
+                            None => open = Some((i, false)),
+                        }
+                    } else if let Some((_, filled)) = &mut open {
+                        *filled |= !line.trim().is_empty();
+                    }
+                }
+            }
             let (prefix, style) = match msg.role {
                 Role::User => ("You: ", Theme::chat_user()),
-                Role::Assistant => ("Claude: ", Style::default().fg(Color::Rgb(205, 115, 80))),
+                Role::Assistant => ("Claude: ", Theme::chat_assistant()),
                 Role::System => ("System: ", Theme::dimmed()),
             };
             for (i, line) in msg.content.lines().enumerate() {
@@ -348,17 +2145,31 @@ impl LLMTab {
                     all.push((format!("      {}", line), None));
                 }
             }
+            if self.show_meta && msg.meta.at != 0 {
+                all.push((format!("{}{}", markdown::META_PREFIX, msg.meta.summary()), Some(Theme::dimmed())));
+            }
             all.push((String::new(), None));
         }
-        all
+        (all, badges)
     }
 
     fn scroll_up(&mut self) {
-        self.scroll_offset += 3;
+        self.scroll_offset = self.scroll_offset.saturating_add(self.scroller.step());
     }
 
     fn scroll_down(&mut self) {
-        self.scroll_offset = self.scroll_offset.saturating_sub(3);
+        self.scroll_offset = self.scroll_offset.saturating_sub(self.scroller.step());
+    }
+
+    /// Apply one mouse-wheel notch.
+    fn scroll_wheel(&mut self, up: bool) {
+        self.scroll_offset = self.scroll_offset.saturating_add_signed(self.scroller.wheel(up));
+    }
+
+    /// Rows moved by PageUp/PageDown: the history's height, less one line
+    /// kept for orientation.
+    fn page_rows(&self) -> usize {
+        (self.last_chat_area.height as usize).saturating_sub(1).max(1)
     }
 
     fn screen_to_buf(&self, col: u16, row: u16) -> Option<BufPos> {
@@ -375,8 +2186,7 @@ impl LLMTab {
         let &(buf_line, row_byte_start) = self.last_visual_row_map.get(screen_row)?;
 
         // Convert screen_col (char index within this pre-split row) to a byte offset.
-        let all = self.build_lines();
-        let text = all.get(buf_line).map(|(t, _)| t.as_str()).unwrap_or("");
+        let text = self.last_lines.get(buf_line).map(|(t, _)| t.as_str()).unwrap_or("");
         let byte_col: usize = text[row_byte_start..]
             .chars()
             .take(screen_col)
@@ -397,7 +2207,7 @@ impl LLMTab {
 
     fn selected_text(&self) -> Option<String> {
         let (start, end) = self.selection_range()?;
-        let lines = self.build_lines();
+        let lines = &self.last_lines;
         if start.0 >= lines.len() {
             return None;
         }
@@ -421,46 +2231,349 @@ impl LLMTab {
         col >= a.x && col < a.x + a.width && row >= a.y && row < a.y + a.height
     }
 
-    fn copy_selection(&mut self) {
+    pub fn copy_selection(&mut self) {
+        self.copy_selection_to(false);
+    }
+
+    /// F8: start a keyboard selection at the start of the last line (or the
+    /// top of the view when scrolled back), or stop moving it and keep what
+    /// is selected.
+    fn toggle_marking(&mut self) {
+        if std::mem::take(&mut self.marking) {
+            return;
+        }
+        let lines = self.last_lines.len();
+        if lines == 0 {
+            return;
+        }
+        let line = if self.scroll_offset == 0 { lines - 1 } else { self.last_render_start.min(lines - 1) };
+        self.selection = Some(((line, 0), (line, 0)));
+        self.marking = true;
+        self.mark_jump = true;
+    }
+
+    /// Apply a key while selecting with the keyboard: arrows, Home/End and
+    /// PageUp/PageDown move the end, Enter (or `y`, Ctrl+C) copies, Esc
+    /// drops the selection. Other keys are ignored.
+    fn mark_key(&mut self, code: KeyCode, ctrl: bool) {
+        let Some((anchor, (mut line, mut byte))) = self.selection else {
+            self.marking = false;
+            return;
+        };
+        let lines = Rc::clone(&self.last_lines);
+        let last = lines.len().saturating_sub(1);
+        let text = |li: usize| lines.get(li).map_or("", |(t, _)| t.as_str());
+        // The nearest char boundary at or before `byte` on line `li`.
+        let floor = |li: usize, byte: usize| {
+            let t = text(li);
+            (0..=byte.min(t.len())).rev().find(|b| t.is_char_boundary(*b)).unwrap_or(0)
+        };
+        match code {
+            KeyCode::Left if byte > 0 => byte = floor(line, byte - 1),
+            KeyCode::Left if line > 0 => (line, byte) = (line - 1, text(line - 1).len()),
+            KeyCode::Right if byte < text(line).len() => {
+                byte += text(line)[byte..].chars().next().map_or(1, char::len_utf8);
+            }
+            KeyCode::Right if line < last => (line, byte) = (line + 1, 0),
+            KeyCode::Up => line = line.saturating_sub(1),
+            KeyCode::Down => line = (line + 1).min(last),
+            KeyCode::PageUp => line = line.saturating_sub(self.page_rows()),
+            KeyCode::PageDown => line = (line + self.page_rows()).min(last),
+            KeyCode::Home => byte = 0,
+            KeyCode::End => byte = text(line).len(),
+            KeyCode::Enter | KeyCode::Char('y') => {
+                self.copy_selection();
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            KeyCode::Char('c') if ctrl => {
+                self.copy_selection();
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            KeyCode::Esc => {
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            _ => return,
+        }
+        self.selection = Some((anchor, (line, floor(line, byte))));
+        self.mark_jump = true;
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selection.is_some_and(|(a, b)| a != b)
+    }
+
+    /// Append the clipboard's text to the input.
+    pub fn paste_into_input(&mut self) {
+        if let Some(ref mut cb) = self.clipboard
+            && let Some(text) = clipboard::get_text(cb, false)
+        {
+            self.input.push_str(&text);
+        }
+    }
+
+    /// Start finding in the conversation.
+    pub fn open_find(&mut self) {
+        self.find.get_or_insert_with(FindBar::default);
+    }
+
+    /// Put `text` (e.g. a terminal selection) into the input as a code
+    /// block, for the user to add a question to.
+    /// Ask what `text` from the terminal means without typing a question
+    /// (F5). The reply lands in the chat and, once, in
+    /// [`take_explanation`](Self::take_explanation). False if busy.
+    pub fn explain(&mut self, text: &str) -> bool {
+        if self.waiting || self.is_executing_tool() {
+            self.status = "Wait for the current request to finish before asking for an explanation.".into();
+            return false;
+        }
+        self.send_message(format!("{}\n```\n{}\n```", EXPLAIN_PROMPT, text.trim_end()));
+        (self.explaining, self.explanation) = (true, None);
+        true
+    }
+
+    pub fn take_explanation(&mut self) -> Option<String> {
+        self.explanation.take()
+    }
+
+    /// The error F5 would ask a fix for, if the terminal shows one.
+    pub fn problem(&self) -> Option<&Problem> {
+        self.problem.as_ref().filter(|_| !self.disabled)
+    }
+
+    /// Ask for a fix for the error in the terminal (F5 with nothing
+    /// selected). The best command is the first suggestion, ready for F4.
+    /// False if there is none or a request is in flight.
+    pub fn quick_fix(&mut self) -> bool {
+        let Some(problem) = &self.problem else {
+            return false;
+        };
+        if self.waiting || self.is_executing_tool() {
+            self.status = "Wait for the current request to finish before asking for a fix.".into();
+            return false;
+        }
+        self.fixed = Some(problem.line.clone());
+        let prompt = problem.prompt();
+        self.problem = None;
+        self.send_message(prompt);
+        true
+    }
+
+    pub fn quote_into_input(&mut self, text: &str) {
+        if !self.input.is_empty() && !self.input.ends_with('\n') {
+            self.input.push('\n');
+        }
+        self.input.push_str(&format!("```\n{}\n```\n", text.trim_end()));
+    }
+
+    /// Copy the selection to the clipboard, or to the primary selection.
+    fn copy_selection_to(&mut self, primary: bool) {
         if let Some(text) = self.selected_text()
             && let Some(ref mut cb) = self.clipboard
         {
-            let _ = cb.set_text(text);
+            clipboard::set_text(cb, text, primary);
         }
     }
 }
 
 impl Tab for LLMTab {
     fn key_hints(&self) -> Vec<(&str, &str)> {
+        if self.marking {
+            return vec![("arrows", "extend selection"), ("enter", "copy"), ("F8", "keep selection"), ("esc", "cancel")];
+        }
+        if self.held_request.is_some() {
+            return vec![("y", "send"), ("n", "don't send")];
+        }
         let mut hints = vec![
             ("enter", "send"),
             ("alt+enter", "newline"),
             ("esc", "clear input"),
             ("ctrl+c", "copy selection"),
+            ("ctrl+f", "find"),
+            ("F8", "select"),
         ];
+        if !self.history.is_empty() {
+            hints.push(("ctrl+n", "new conversation"));
+        }
+        if self.locality_notice.is_some() {
+            hints.push(("ctrl+k", "hide notice for good"));
+        }
         if self.suggestion_idx.is_some() {
             hints.push(("tab", "cycle suggestion"));
-            hints.push(("F4", "apply to terminal"));
+            hints.push(("ctrl+e", "preview"));
+            if self.suggestions.len() > 1 {
+                hints.push(("alt+1-9", "type block [n]"));
+            }
+            hints.push(("F4", "type into terminal"));
+            hints.push((self.run_key.label(), "run it"));
+        }
+        if self.auto_approve.is_some() {
+            hints.push(("ctrl+r", "revoke auto-approve"));
+        }
+        hints.push(("ctrl+o", if self.read_only { "allow actions" } else { "read-only" }));
+        if !self.changed_files.is_empty() {
+            hints.push(("ctrl+b", "changed files"));
         }
         hints
     }
 
     fn handle_event(&mut self, event: &Event) -> Action {
         match event {
-            Event::Key(KeyEvent {
+            Event::Key(key @ KeyEvent {
                 code, modifiers, ..
             }) => {
                 let ctrl = modifiers.contains(KeyModifiers::CONTROL);
 
-                // Ctrl+C — copy selection if any, or cancel an active tool call
-                if ctrl && *code == KeyCode::Char('c') {
-                    if self.selection.is_some() {
-                        self.copy_selection();
-                        self.selection = None;
-                    } else if self.is_executing_tool() || self.waiting {
-                        return Action::CancelToolCall;
+                // F8 — select text with the keyboard; while selecting, the
+                // keys move the selection instead of editing the input.
+                if *code == KeyCode::F(8) {
+                    self.toggle_marking();
+                    return Action::None;
+                }
+                if self.marking {
+                    self.mark_key(*code, ctrl);
+                    return Action::None;
+                }
+
+                // Ctrl+F — find in the conversation; the find bar takes the
+                // keys it knows while open.
+                if ctrl && *code == KeyCode::Char('f') {
+                    self.open_find();
+                    return Action::None;
+                }
+                if let Some(find) = &mut self.find
+                    && let Some(outcome) = find.handle_key(key)
+                {
+                    match outcome {
+                        FindKey::Close => self.find = None,
+                        FindKey::Edited | FindKey::Moved => self.find_jump = true,
+                    }
+                    return Action::None;
+                }
+
+                // Ctrl+C — copy selection if any, or cancel an active tool call
+                if ctrl && *code == KeyCode::Char('c') {
+                    if self.selection.is_some() {
+                        self.copy_selection();
+                        self.selection = None;
+                    } else if self.is_executing_tool() || self.waiting {
+                        return Action::CancelToolCall;
+                    }
+                    return Action::None;
+                }
+
+                // Ctrl+R — revoke any active auto-approval.
+                if ctrl && *code == KeyCode::Char('r') {
+                    if self.auto_approve.take().is_some() {
+                        self.status = "Auto-approve revoked.".into();
+                    }
+                    return Action::None;
+                }
+
+                // Ctrl+O — toggle read-only mode; applies from the next request.
+                if ctrl && *code == KeyCode::Char('o') {
+                    self.read_only = !self.read_only;
+                    self.status = if self.read_only {
+                        "Read-only: the assistant can look and advise but not run commands or change files.".into()
+                    } else {
+                        "Read-only off: the assistant can propose commands and file changes again.".into()
+                    };
+                    return Action::None;
+                }
+
+                // Ctrl+N — start a new conversation.
+                if ctrl && *code == KeyCode::Char('n') {
+                    self.new_conversation();
+                    return Action::None;
+                }
+
+                // Ctrl+K — don't show the notice of where requests go again.
+                if ctrl && *code == KeyCode::Char('k') && self.locality_notice.is_some() {
+                    self.acknowledge_locality();
+                    return Action::None;
+                }
+
+                // Ctrl+T — toggle message times, models and token counts.
+                if ctrl && *code == KeyCode::Char('t') {
+                    self.show_meta = !self.show_meta;
+                    return Action::None;
+                }
+
+                // Ctrl+B — toggle the list of files changed this session.
+                if ctrl && *code == KeyCode::Char('b') {
+                    self.changes_view = match self.changes_view {
+                        Some(_) => None,
+                        None if self.changed_files.is_empty() => {
+                            self.status = "No files changed this session.".into();
+                            None
+                        }
+                        None => Some(self.changed_files.len() - 1),
+                    };
+                    return Action::None;
+                }
+                if let Some(selected) = self.changes_view {
+                    match code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            self.changes_view = Some(selected.saturating_sub(1));
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            self.changes_view = Some((selected + 1).min(self.changed_files.len() - 1));
+                        }
+                        KeyCode::Char('r') => {
+                            if self.is_executing_tool() || self.waiting {
+                                self.status = "Wait for the current request to finish before rolling back.".into();
+                                return Action::None;
+                            }
+                            let write = self.changed_files[selected].restore();
+                            let command = write.write_command("", None);
+                            self.status = format!("Rolling back {}…", write.path);
+                            self.rolling_back = Some((selected, write));
+                            return Action::SendToTerminal(command);
+                        }
+                        KeyCode::Esc | KeyCode::Char('q') => self.changes_view = None,
+                        _ => {}
+                    }
+                    return Action::None;
+                }
+
+                // Page with PageUp/PageDown, jump with Ctrl+Home/End (the
+                // terminal takes Shift+PageUp/PageDown; both work here).
+                match code {
+                    KeyCode::PageUp => {
+                        self.scroll_offset = self.scroll_offset.saturating_add(self.page_rows());
+                        return Action::None;
+                    }
+                    KeyCode::PageDown => {
+                        self.scroll_offset = self.scroll_offset.saturating_sub(self.page_rows());
+                        return Action::None;
+                    }
+                    KeyCode::Home if ctrl => {
+                        // Clamped to the first line when drawn.
+                        self.scroll_offset = usize::MAX;
+                        return Action::None;
+                    }
+                    KeyCode::End if ctrl => {
+                        self.scroll_offset = 0;
+                        return Action::None;
+                    }
+                    _ => {}
+                }
+
+                // Alt+Up/Down scroll a long input, like the wheel over it.
+                if modifiers.contains(KeyModifiers::ALT) {
+                    match code {
+                        KeyCode::Up => {
+                            self.input_scroll += 1;
+                            return Action::None;
+                        }
+                        KeyCode::Down => {
+                            self.input_scroll = self.input_scroll.saturating_sub(1);
+                            return Action::None;
+                        }
+                        _ => {}
                     }
-                    return Action::None;
                 }
 
                 // Scroll with Ctrl+Up/Down (same as terminal)
@@ -473,6 +2586,17 @@ impl Tab for LLMTab {
                     return Action::None;
                 }
 
+                // Ctrl+E — the whole selected suggestion, highlighted; esc
+                // or ctrl+e again closes it, applying it does too.
+                if ctrl && *code == KeyCode::Char('e') && self.suggestion_idx.is_some() {
+                    self.preview = !self.preview;
+                    return Action::None;
+                }
+                if self.preview && *code == KeyCode::Esc {
+                    self.preview = false;
+                    return Action::None;
+                }
+
                 // Suggestion cycling and application
                 if *code == KeyCode::Tab && !self.suggestions.is_empty() {
                     let n = self.suggestions.len();
@@ -488,25 +2612,163 @@ impl Tab for LLMTab {
                     );
                     return Action::None;
                 }
+                if self.run_key.matches(*code, *modifiers) {
+                    self.preview = false;
+                    return self.run_suggestion();
+                }
+                // Alt+number types the block with that badge.
+                if modifiers.contains(KeyModifiers::ALT)
+                    && let KeyCode::Char(c @ '1'..='9') = code
+                    && let Some(cmd) = self.suggestions.get(*c as usize - '1' as usize)
+                {
+                    self.suggestion_idx = Some(*c as usize - '1' as usize);
+                    self.preview = false;
+                    clipboard::remember(cmd, SnippetKind::Applied);
+                    return Action::TypeInTerminal(cmd.clone());
+                }
                 if *code == KeyCode::F(4) {
                     if let Some(idx) = self.suggestion_idx
                         && let Some(cmd) = self.suggestions.get(idx)
                     {
-                        return Action::SendToTerminal(cmd.clone());
+                        self.preview = false;
+                        clipboard::remember(cmd, SnippetKind::Applied);
+                        return Action::TypeInTerminal(cmd.clone());
+                    }
+                    return Action::None;
+                }
+
+                // A request held for confirmation: send it, or don't.
+                if self.held_request.is_some() {
+                    match code {
+                        KeyCode::Enter | KeyCode::Char('y') => self.release_request(true),
+                        KeyCode::Esc | KeyCode::Char('n') => self.release_request(false),
+                        _ => {}
+                    }
+                    return Action::None;
+                }
+
+                // Plan review: approve once, or reject and skip its commands.
+                if self.plan_review_pending() {
+                    match code {
+                        KeyCode::Enter | KeyCode::Char('y') => self.review_plan(true),
+                        KeyCode::Esc | KeyCode::Char('n') => self.review_plan(false),
+                        _ => {}
+                    }
+                    return Action::None;
+                }
+
+                // Editing the proposed command inline (`e` in the confirmation prompt).
+                if let Some(buf) = self
+                    .pending_tool_call
+                    .as_mut()
+                    .and_then(|ptc| ptc.edit_buffer.as_mut())
+                {
+                    match code {
+                        KeyCode::Enter if modifiers.contains(KeyModifiers::ALT) => buf.push('\n'),
+                        KeyCode::Enter => {
+                            if let Some(cmd) = self.finish_edit() {
+                                return Action::SendToTerminal(cmd);
+                            }
+                        }
+                        KeyCode::Esc => {
+                            if let Some(ptc) = &mut self.pending_tool_call {
+                                ptc.edit_buffer = None;
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            buf.pop();
+                        }
+                        KeyCode::Char(ch)
+                            if modifiers.is_empty() || modifiers.contains(KeyModifiers::SHIFT) =>
+                        {
+                            buf.push(*ch);
+                        }
+                        _ => {}
+                    }
+                    return Action::None;
+                }
+
+                // Typing the alias to run a command or write on a guarded host.
+                if let Some(typed) = self
+                    .pending_tool_call
+                    .as_mut()
+                    .and_then(|ptc| ptc.typed_confirm.as_mut())
+                {
+                    match code {
+                        KeyCode::Enter if typed.trim() == self.connection.name => {
+                            if let Some(cmd) = self.confirm_tool_call(true) {
+                                return Action::SendToTerminal(cmd);
+                            }
+                        }
+                        KeyCode::Enter => {
+                            self.status =
+                                format!("That isn't {}; type it exactly, or esc to go back.", self.connection.name);
+                        }
+                        KeyCode::Esc => {
+                            if let Some(ptc) = &mut self.pending_tool_call {
+                                ptc.typed_confirm = None;
+                            }
+                            self.status = "Awaiting confirmation…".into();
+                        }
+                        KeyCode::Backspace => {
+                            typed.pop();
+                        }
+                        KeyCode::Char(ch)
+                            if modifiers.is_empty() || modifiers.contains(KeyModifiers::SHIFT) =>
+                        {
+                            typed.push(*ch);
+                        }
+                        _ => {}
                     }
                     return Action::None;
                 }
 
                 // Confirmation prompt keys (when a tool call is pending).
-                if self.pending_tool_call.is_some() {
+                if let Some(ptc) = &mut self.pending_tool_call {
                     match code {
+                        KeyCode::Char('e') if ptc.is_shell_command() => {
+                            ptc.edit_buffer = Some(ptc.command.clone());
+                        }
+                        KeyCode::Char('v') => {
+                            ptc.expanded = !ptc.expanded;
+                            ptc.scroll = 0;
+                        }
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            ptc.scroll = ptc.scroll.saturating_sub(1);
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            // Clamped against the visible height at render time.
+                            ptc.scroll += 1;
+                        }
                         KeyCode::Enter | KeyCode::Char('y') => {
+                            if let Some(cmd) = self.approve_pending() {
+                                return Action::SendToTerminal(cmd);
+                            }
+                        }
+                        // No auto-approval on a guarded host.
+                        KeyCode::Char('a' | 'p' | '5' | 't' | 'Y') if self.guard => {
+                            self.status = "Auto-approve is off on this guarded host; y runs this one command.".into();
+                        }
+                        KeyCode::Char(c @ ('a' | 'p' | '5' | 't')) => {
+                            self.auto_approve = Some(match c {
+                                'a' => AutoApprove::Session,
+                                'p' => match command_program(&ptc.command).filter(|_| ptc.is_shell_command()) {
+                                    Some(p) => AutoApprove::Program(p),
+                                    // Compound, prefixed or wrapped commands can't be scoped by program.
+                                    None => return Action::None,
+                                },
+                                // The call being approved now counts as the first one.
+                                '5' => AutoApprove::Calls(AutoApprove::CALLS - 1),
+                                _ => AutoApprove::Until(Instant::now() + AutoApprove::WINDOW),
+                            });
                             if let Some(cmd) = self.confirm_tool_call(true) {
                                 return Action::SendToTerminal(cmd);
                             }
                         }
-                        KeyCode::Char('a') => {
-                            self.auto_approve = true;
+                        KeyCode::Char('Y') => {
+                            if let Some(batch) = &mut self.tool_batch {
+                                batch.approve_all = true;
+                            }
                             if let Some(cmd) = self.confirm_tool_call(true) {
                                 return Action::SendToTerminal(cmd);
                             }
@@ -514,6 +2776,7 @@ impl Tab for LLMTab {
                         KeyCode::Esc | KeyCode::Char('n') => {
                             self.confirm_tool_call(false);
                         }
+                        KeyCode::Char('N') => self.decline_batch(),
                         _ => {}
                     }
                     return Action::None;
@@ -552,6 +2815,11 @@ impl Tab for LLMTab {
 
             Event::Mouse(me) => {
                 let over_input = self.is_over_input(me.column, me.row);
+                let bar = self.last_suggestion_area;
+                self.hovering_suggestion = me.column >= bar.x
+                    && me.column < bar.right()
+                    && me.row >= bar.y
+                    && me.row < bar.bottom();
                 match me.kind {
                     MouseEventKind::Down(MouseButton::Left) => {
                         self.selection =
@@ -569,20 +2837,22 @@ impl Tab for LLMTab {
                             && a == b
                         {
                             self.selection = None;
+                        } else if self.selection.is_some() && self.copy_on_select != CopyOnSelect::Off {
+                            self.copy_selection_to(self.copy_on_select == CopyOnSelect::Primary);
                         }
                     }
                     MouseEventKind::ScrollUp => {
                         if over_input {
                             self.input_scroll += 1;
                         } else {
-                            self.scroll_up();
+                            self.scroll_wheel(true);
                         }
                     }
                     MouseEventKind::ScrollDown => {
                         if over_input {
                             self.input_scroll = self.input_scroll.saturating_sub(1);
                         } else {
-                            self.scroll_down();
+                            self.scroll_wheel(false);
                         }
                     }
                     _ => {}
@@ -603,8 +2873,11 @@ impl Tab for LLMTab {
             Theme::normal_border()
         };
 
-        let provider_name = self.provider.name();
-        let title = if self.waiting {
+        let provider_name = match (focused && self.screen_reader, self.provider.name()) {
+            (true, name) => format!("{}, focused", name),
+            (false, name) => name.to_string(),
+        };
+        let mut title = if self.waiting {
             Line::from(vec![
                 Span::styled(format!(" LLM ({}) ", provider_name), Theme::title()),
                 Span::styled(" thinking... ", Theme::dimmed()),
@@ -615,6 +2888,9 @@ impl Tab for LLMTab {
                 Theme::title(),
             ))
         };
+        if self.read_only {
+            title.push_span(Span::styled(" read-only ", Theme::key_hint_key()));
+        }
 
         let outer_block = Block::bordered()
             .border_type(BorderType::Rounded)
@@ -630,24 +2906,50 @@ impl Tab for LLMTab {
         let content_rows = wrapped_line_count(&self.input, input_width).clamp(1, 5);
         let input_height = content_rows as u16 + 2;
         let suggestion_height = if self.suggestion_idx.is_some() { 1u16 } else { 0 };
+        let plan_height = if self.plan.is_empty() { 0 } else { self.plan.len().min(PLAN_ROWS) as u16 + 1 };
+        let notice_height = u16::from(self.locality_notice.is_some());
 
         let areas = Layout::vertical([
             Constraint::Min(1),
+            Constraint::Length(plan_height),
+            Constraint::Length(notice_height),
             Constraint::Length(1),
             Constraint::Length(suggestion_height),
             Constraint::Length(input_height),
         ])
         .split(inner);
 
-        let (chat_area, status_area, suggestion_area, input_area) =
-            (areas[0], areas[1], areas[2], areas[3]);
+        let (chat_area, plan_area, notice_area, status_area, suggestion_area, input_area) =
+            (areas[0], areas[1], areas[2], areas[3], areas[4], areas[5]);
 
         self.last_chat_area = chat_area;
         self.last_input_area = input_area;
-        self.render_history(frame, chat_area);
+        if self.changes_view.is_some() {
+            self.render_changes(frame, chat_area);
+        } else {
+            self.render_history(frame, chat_area);
+        }
+        if plan_height > 0 {
+            self.render_plan(frame, plan_area);
+        }
+        if let Some(vendor) = self.locality_notice {
+            let line = Line::from(vec![
+                Span::styled(
+                    format!(" ⓘ Terminal output and session details are sent to {} ", vendor),
+                    Theme::dimmed(),
+                ),
+                Span::styled("ctrl+k", Theme::key_hint_key()),
+                Span::styled(" don't show again", Theme::dimmed()),
+            ]);
+            frame.render_widget(Paragraph::new(line), notice_area);
+        }
         self.render_status(frame, status_area);
+        self.last_suggestion_area = suggestion_area;
         if suggestion_height > 0 {
             self.render_suggestion(frame, suggestion_area);
+            if self.preview || self.hovering_suggestion {
+                self.render_preview(frame, chat_area);
+            }
         }
         self.render_input(frame, input_area, focused);
     }
@@ -656,19 +2958,36 @@ impl Tab for LLMTab {
 impl LLMTab {
     fn render_history(&mut self, frame: &mut Frame, area: Rect) {
         // Reserve rows at the bottom for the confirmation prompt when pending.
-        const CONFIRM_ROWS: u16 = 4;
-        let (history_area, confirm_area) = if self.pending_tool_call.is_some() {
+        const CONFIRM_ROWS: u16 = 5;
+        let (history_area, confirm_area) = if let Some(ptc) = &self.pending_tool_call {
+            // While editing, grow to show the whole buffer (up to 6 rows).
+            let confirm_rows = match &ptc.edit_buffer {
+                Some(buf) => 3 + buf.split('\n').count().clamp(1, 6) as u16,
+                None => {
+                    let (total, visible) = command_view_rows(ptc, area);
+                    CONFIRM_ROWS - 1
+                        + visible as u16
+                        + u16::from(total > visible)
+                        + u16::from(ptc.warning.is_some())
+                        + u16::from(ptc.destructive.is_some())
+                }
+            };
             let split = Layout::vertical([
                 Constraint::Min(1),
-                Constraint::Length(CONFIRM_ROWS),
+                Constraint::Length(confirm_rows),
             ])
             .split(area);
             (split[0], Some(split[1]))
+        } else if self.plan_review_pending() {
+            let split = Layout::vertical([Constraint::Min(1), Constraint::Length(3)]).split(area);
+            (split[0], Some(split[1]))
         } else {
             (area, None)
         };
 
-        let all = self.build_lines();
+        let (all, badges) = self.build_lines();
+        let all = Rc::new(all);
+        self.last_lines = Rc::clone(&all);
         let h = history_area.height as usize;
         let sel = self.selection_range();
         let width = history_area.width.max(1) as usize;
@@ -676,6 +2995,36 @@ impl LLMTab {
         // Compute total visual rows (accounts for line wrapping).
         let total_visual: usize = all.iter().map(|(text, _)| wrapped_line_count(text, width)).sum();
 
+        // Bring the current find match to the upper third of the view.
+        if let Some(find) = &mut self.find {
+            find.update(all.iter().map(|(text, _)| text.as_str()));
+            if std::mem::take(&mut self.find_jump)
+                && let Some(li) = find.current_line()
+            {
+                let rows_before: usize = all[..li].iter().map(|(text, _)| wrapped_line_count(text, width)).sum();
+                self.scroll_offset = total_visual.saturating_sub(h + rows_before.saturating_sub(h / 3));
+            }
+        }
+        let current_match = self.find.as_ref().and_then(FindBar::current_line);
+
+        // Keep the end of a keyboard selection on screen.
+        if std::mem::take(&mut self.mark_jump)
+            && let Some((_, (li, _))) = self.selection
+        {
+            let rows_before: usize = all[..li.min(all.len())].iter().map(|(text, _)| wrapped_line_count(text, width)).sum();
+            let first_visible = total_visual.saturating_sub(h + self.scroll_offset);
+            if rows_before < first_visible {
+                self.scroll_offset = total_visual.saturating_sub(h + rows_before);
+            } else if rows_before >= first_visible + h {
+                self.scroll_offset = total_visual.saturating_sub(rows_before + 1);
+            }
+        }
+
+        if let Some(li) = self.resize_anchor.take() {
+            let rows_before: usize = all[..li.min(all.len())].iter().map(|(text, _)| wrapped_line_count(text, width)).sum();
+            self.scroll_offset = total_visual.saturating_sub(h + rows_before);
+        }
+
         // scroll_offset and max_scroll are in visual rows.
         let max_scroll = total_visual.saturating_sub(h);
         self.scroll_offset = self.scroll_offset.min(max_scroll);
@@ -700,30 +3049,18 @@ impl LLMTab {
         self.last_render_start = start_li;
 
         // Pre-compute which lines fall inside a markdown code block or are tables.
-        let in_code: Vec<bool> = {
-            let mut flags = Vec::with_capacity(all.len());
-            let mut in_block = false;
-            for (text, _) in &all {
-                let content = line_content(text);
-                let trimmed = content.trim_start();
-                if trimmed.starts_with("```") {
-                    in_block = !in_block;
-                    flags.push(true);
-                } else if trimmed.starts_with('|') {
-                    flags.push(true);
-                } else {
-                    flags.push(in_block);
-                }
-            }
-            flags
-        };
+        let in_code = markdown::code_lines(all.iter().map(|(text, _)| text.as_str()));
 
         let mut visual_map: Vec<(usize, usize)> = Vec::new();
         let mut visible: Vec<Line<'static>> = Vec::new();
 
         'outer: for (li, (text, _)) in all.iter().enumerate().skip(start_li) {
-            let rendered = render_md_line(text, in_code[li]);
-            for (row_i, (chunk_spans, row_byte_start)) in wrap_line_spans(rendered.spans, width).into_iter().enumerate() {
+            let mut rendered = markdown::render_line(text, in_code[li]);
+            if let Some(&(_, n)) = badges.iter().find(|(line, _)| *line == li) {
+                let style = if self.suggestion_idx == Some(n - 1) { Theme::highlight() } else { Theme::key_hint_key() };
+                rendered.spans.push(Span::styled(format!(" [{}]", n), style));
+            }
+            for (row_i, (chunk_spans, row_byte_start)) in markdown::wrap_spans(rendered.spans, width).into_iter().enumerate() {
                 if li == start_li && row_i < start_intra {
                     continue;
                 }
@@ -731,7 +3068,11 @@ impl LLMTab {
                     break 'outer;
                 }
                 visual_map.push((li, row_byte_start));
-                visible.push(apply_sel_to_chunk(chunk_spans, li, row_byte_start, sel));
+                let line = apply_sel_to_chunk(chunk_spans, li, row_byte_start, sel);
+                visible.push(match &self.find {
+                    Some(find) => find.highlight(line, current_match == Some(li)),
+                    None => line,
+                });
             }
         }
 
@@ -739,44 +3080,310 @@ impl LLMTab {
         frame.render_widget(Paragraph::new(visible), history_area);
 
         // ── Confirmation prompt ────────────────────────────────────────────
-        if let (Some(ptc), Some(ca)) = (&self.pending_tool_call, confirm_area) {
-            let approve_label = if self.auto_approve { " always (active)" } else { "" };
-            let cmd = &ptc.command;
-            let first_line = cmd.lines().next().unwrap_or("").to_string();
-            let preview = if cmd.lines().count() > 1 {
-                format!("{} …", first_line)
-            } else {
-                first_line
-            };
+        if let (Some(ptc), Some(ca)) = (&self.pending_tool_call, confirm_area)
+            && let Some(buf) = &ptc.edit_buffer
+        {
+            let mut lines = vec![
+                Line::from(Span::styled("─".repeat(ca.width as usize), Theme::dimmed())),
+                Line::from(vec![
+                    Span::styled(" ◆ ", Theme::key_hint_key()),
+                    Span::styled("Edit command", Style::default().add_modifier(Modifier::BOLD)),
+                ]),
+            ];
+            let buf_lines: Vec<&str> = buf.split('\n').collect();
+            let skip = buf_lines.len().saturating_sub(6);
+            let last = buf_lines.len() - 1;
+            for (i, l) in buf_lines.iter().enumerate().skip(skip) {
+                let prefix = if i == 0 { "   $ " } else { "     " };
+                let cursor = if i == last { "_" } else { "" };
+                lines.push(Line::from(vec![
+                    Span::styled(prefix, Theme::dimmed()),
+                    Span::styled(format!("{}{}", l, cursor), Theme::md_code_inline()),
+                ]));
+            }
+            lines.push(Line::from(vec![
+                Span::styled("   [enter] ", Theme::key_hint_key()),
+                Span::styled("run edited", Theme::key_hint_desc()),
+                Span::styled("   [alt+enter] ", Theme::key_hint_key()),
+                Span::styled("newline", Theme::key_hint_desc()),
+                Span::styled("   [esc] ", Theme::key_hint_key()),
+                Span::styled("back", Theme::key_hint_desc()),
+            ]));
+            frame.render_widget(Paragraph::new(lines), ca);
+        } else if let (Some(ptc), Some(ca)) = (&mut self.pending_tool_call, confirm_area) {
+            let approve_label = self
+                .auto_approve
+                .as_ref()
+                .map(|a| format!("  ({})", a.label()))
+                .unwrap_or_default();
+            let (total, visible) = command_view_rows(ptc, area);
+            ptc.scroll = ptc.scroll.min(total - visible);
+            let overflow = total > visible;
+
+            let [rule_area, header_area, cmd_area, more_area, warn_area, hints_area, auto_area] = Layout::vertical([
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(visible as u16),
+                Constraint::Length(u16::from(overflow)),
+                Constraint::Length(u16::from(ptc.warning.is_some()) + u16::from(ptc.destructive.is_some())),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ])
+            .areas(ca);
 
             let desc_span = ptc.description.as_deref().unwrap_or("Run command?");
-            let lines = vec![
-                Line::from(Span::styled(
+            // Commands still queued behind this one in the same assistant turn.
+            let (position, total) = self
+                .tool_batch
+                .as_ref()
+                .map_or((1, 1), |b| (b.total - b.queue.len(), b.total));
+            let counter = if total > 1 { format!(" [{}/{}]", position, total) } else { String::new() };
+            frame.render_widget(
+                Paragraph::new(Line::from(Span::styled(
                     "─".repeat(ca.width as usize),
                     Theme::dimmed(),
-                )),
-                Line::from(vec![
+                ))),
+                rule_area,
+            );
+            frame.render_widget(
+                Paragraph::new(Line::from(vec![
                     Span::styled(" ◆ ", Theme::key_hint_key()),
                     Span::styled(desc_span.to_string(), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(counter, Theme::dimmed()),
+                    Span::styled(
+                        match (&ptc.local, self.cwd.as_deref()) {
+                            (Some(_), _) => "  runs on this machine".to_string(),
+                            (None, Some(d)) => format!("  will run in {}", d),
+                            (None, None) => String::new(),
+                        },
+                        Theme::md_code_inline(),
+                    ),
                     Span::styled(approve_label, Theme::dimmed()),
-                ]),
+                ])),
+                header_area,
+            );
+
+            // The full command (or a write's diff), wrapped and scrollable within its rows.
+            let cmd_lines: Vec<Line> = match &ptc.preview {
+                Some(preview) => preview
+                    .diff
+                    .lines()
+                    .map(|l| {
+                        let style = if l.starts_with("+++") || l.starts_with("---") || l.starts_with("@@") {
+                            Theme::dimmed()
+                        } else if l.starts_with('+') {
+                            Theme::diff_added()
+                        } else if l.starts_with('-') {
+                            Theme::diff_removed()
+                        } else {
+                            Style::default()
+                        };
+                        Line::from(vec![Span::raw("     "), Span::styled(l.to_string(), style)])
+                    })
+                    .collect(),
+                None => ptc
+                    .command
+                    .lines()
+                    .enumerate()
+                    .map(|(i, l)| {
+                        let prefix = if i == 0 { "   $ " } else { "     " };
+                        Line::from(vec![
+                            Span::styled(prefix, Theme::dimmed()),
+                            Span::styled(l.to_string(), Theme::md_code_inline()),
+                        ])
+                    })
+                    .collect(),
+            };
+            frame.render_widget(
+                Paragraph::new(cmd_lines)
+                    .wrap(Wrap { trim: false })
+                    .scroll((ptc.scroll as u16, 0)),
+                cmd_area,
+            );
+
+            if overflow {
+                let toggle = if ptc.expanded { "collapse" } else { "expand" };
+                frame.render_widget(
+                    Paragraph::new(Line::from(vec![
+                        Span::styled(
+                            format!(
+                                "     rows {}–{} of {}",
+                                ptc.scroll + 1,
+                                ptc.scroll + visible,
+                                total
+                            ),
+                            Theme::dimmed(),
+                        ),
+                        Span::styled("   [v] ", Theme::key_hint_key()),
+                        Span::styled(toggle, Theme::key_hint_desc()),
+                        Span::styled("   [↑/↓] ", Theme::key_hint_key()),
+                        Span::styled("scroll", Theme::key_hint_desc()),
+                    ])),
+                    more_area,
+                );
+            }
+
+            let mut warn_lines = vec![];
+            if let Some(warning) = &ptc.warning {
+                warn_lines.push(Line::from(vec![
+                    Span::styled("   ⚠ ", Theme::error()),
+                    Span::styled(format!("{} — capture may hang", warning), Theme::error()),
+                ]));
+            }
+            if let Some(reason) = &ptc.destructive {
+                warn_lines.push(Line::from(vec![
+                    Span::styled("   ⚠ ", Theme::error()),
+                    Span::styled(format!("destructive: {}", reason), Theme::error()),
+                ]));
+            }
+            frame.render_widget(Paragraph::new(warn_lines), warn_area);
+
+            if let Some(typed) = &ptc.typed_confirm {
+                let hint = Line::from(vec![
+                    Span::styled(format!("   type {} to run: ", self.connection.name), Theme::error()),
+                    Span::styled(format!("{}_", typed), Theme::highlight()),
+                    Span::styled("   [esc] ", Theme::key_hint_key()),
+                    Span::styled("back", Theme::key_hint_desc()),
+                ]);
+                frame.render_widget(Paragraph::new(hint), hints_area);
+                return;
+            }
+
+            let mut hint_spans = vec![
+                Span::styled("   [y/enter] ", Theme::key_hint_key()),
+                Span::styled(if ptc.write.is_some() { "write" } else { "once" }, Theme::key_hint_desc()),
+            ];
+            if ptc.is_shell_command() {
+                hint_spans.push(Span::styled("   [e] ", Theme::key_hint_key()));
+                hint_spans.push(Span::styled("edit", Theme::key_hint_desc()));
+            }
+            hint_spans.extend([
+                Span::styled("   [n/esc] ", Theme::key_hint_key()),
+                Span::styled("skip", Theme::key_hint_desc()),
+            ]);
+            if position < total && !self.guard {
+                let remaining = total - position + 1;
+                hint_spans.extend([
+                    Span::styled("   [Y] ", Theme::key_hint_key()),
+                    Span::styled(format!("run all {}", remaining), Theme::key_hint_desc()),
+                    Span::styled("   [N] ", Theme::key_hint_key()),
+                    Span::styled(format!("skip all {}", remaining), Theme::key_hint_desc()),
+                ]);
+            }
+            frame.render_widget(Paragraph::new(Line::from(hint_spans)), hints_area);
+
+            if self.guard {
+                let line = Line::from(Span::styled("   auto-approve is off on this guarded host", Theme::dimmed()));
+                frame.render_widget(Paragraph::new(line), auto_area);
+                return;
+            }
+            let mut auto_spans = vec![
+                Span::styled("   auto:", Theme::dimmed()),
+                Span::styled(" [a] ", Theme::key_hint_key()),
+                Span::styled("session", Theme::key_hint_desc()),
+            ];
+            if let Some(program) = command_program(&ptc.command).filter(|_| ptc.is_shell_command()) {
+                auto_spans.push(Span::styled("  [p] ", Theme::key_hint_key()));
+                auto_spans.push(Span::styled(format!("`{} …`", program), Theme::key_hint_desc()));
+            }
+            auto_spans.extend([
+                Span::styled("  [5] ", Theme::key_hint_key()),
+                Span::styled(format!("next {}", AutoApprove::CALLS), Theme::key_hint_desc()),
+                Span::styled("  [t] ", Theme::key_hint_key()),
+                Span::styled(format!("{} min", AutoApprove::WINDOW.as_secs() / 60), Theme::key_hint_desc()),
+            ]);
+            frame.render_widget(Paragraph::new(Line::from(auto_spans)), auto_area);
+        } else if let Some(ca) = confirm_area {
+            let follow_up = match self.plan_policy {
+                _ if self.guard => "each command is still confirmed on this guarded host",
+                PlanPolicy::All => "all steps then run without asking",
+                _ => "safe steps then run without asking",
+            };
+            let lines = vec![
+                Line::from(Span::styled("─".repeat(ca.width as usize), Theme::dimmed())),
                 Line::from(vec![
-                    Span::styled("   $ ", Theme::dimmed()),
-                    Span::styled(preview, Theme::md_code_inline()),
+                    Span::styled(" ◆ ", Theme::key_hint_key()),
+                    Span::styled(
+                        format!("Approve this {}-step plan?", self.plan.len()),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(format!("  {}", follow_up), Theme::dimmed()),
                 ]),
                 Line::from(vec![
                     Span::styled("   [y/enter] ", Theme::key_hint_key()),
-                    Span::styled("once", Theme::key_hint_desc()),
-                    Span::styled("   [a] ", Theme::key_hint_key()),
-                    Span::styled("always", Theme::key_hint_desc()),
+                    Span::styled("approve", Theme::key_hint_desc()),
                     Span::styled("   [n/esc] ", Theme::key_hint_key()),
-                    Span::styled("skip", Theme::key_hint_desc()),
+                    Span::styled("reject", Theme::key_hint_desc()),
                 ]),
             ];
             frame.render_widget(Paragraph::new(lines), ca);
         }
     }
 
+    /// Files changed by write tools this session, newest last, with the
+    /// backups a rollback restores.
+    fn render_changes(&self, frame: &mut Frame, area: Rect) {
+        let selected = self.changes_view.unwrap_or(0);
+        let mut lines = vec![Line::from(vec![
+            Span::styled(" ◆ ", Theme::key_hint_key()),
+            Span::styled("Files changed this session", Style::default().add_modifier(Modifier::BOLD)),
+        ])];
+        let rows = area.height.saturating_sub(3) as usize;
+        let start = selected.saturating_sub(rows.saturating_sub(1));
+        for (i, change) in self.changed_files.iter().enumerate().skip(start).take(rows) {
+            let marker = if i == selected { " ▶ " } else { "   " };
+            let backup = match &change.backup {
+                Some(b) => format!("  ← {}", b),
+                None => "  (new file)".to_string(),
+            };
+            let age = change.written.elapsed().as_secs();
+            lines.push(Line::from(vec![
+                Span::styled(marker, Theme::key_hint_key()),
+                Span::styled(change.path.clone(), if i == selected { Theme::md_code_inline() } else { Theme::value() }),
+                Span::styled(backup, Theme::dimmed()),
+                Span::styled(format!("  {}m{:02}s ago", age / 60, age % 60), Theme::dimmed()),
+            ]));
+        }
+        lines.push(Line::default());
+        lines.push(Line::from(vec![
+            Span::styled("   [↑/↓] ", Theme::key_hint_key()),
+            Span::styled("select", Theme::key_hint_desc()),
+            Span::styled("   [r] ", Theme::key_hint_key()),
+            Span::styled("roll back", Theme::key_hint_desc()),
+            Span::styled("   [esc] ", Theme::key_hint_key()),
+            Span::styled("close", Theme::key_hint_desc()),
+        ]));
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// Live checklist of the agent's plan. Long plans scroll to keep the
+    /// first unfinished step in view.
+    fn render_plan(&self, frame: &mut Frame, area: Rect) {
+        let done = self.plan.iter().filter(|s| s.status == StepStatus::Done).count();
+        let title = self.task.as_deref().unwrap_or("Plan");
+        let mut lines = vec![Line::from(vec![
+            Span::styled(" ☰ ", Theme::key_hint_key()),
+            Span::styled(title.to_string(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  {}/{}", done, self.plan.len()), Theme::dimmed()),
+        ])];
+
+        let current = self
+            .plan
+            .iter()
+            .position(|s| matches!(s.status, StepStatus::Pending | StepStatus::InProgress))
+            .unwrap_or(self.plan.len());
+        let rows = area.height.saturating_sub(1) as usize;
+        let start = current.saturating_sub(1).min(self.plan.len().saturating_sub(rows));
+        for (i, step) in self.plan.iter().enumerate().skip(start).take(rows) {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:>2}. ", i + 1), Theme::dimmed()),
+                Span::styled(format!("{} ", step.status.icon()), step.status.style()),
+                Span::styled(step.title.clone(), if step.status == StepStatus::Pending { Theme::dimmed() } else { Style::default() }),
+            ]));
+        }
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
     fn render_suggestion(&self, frame: &mut Frame, area: Rect) {
         let Some(idx) = self.suggestion_idx else {
             return;
@@ -788,7 +3395,7 @@ impl LLMTab {
         // Show first line of the command; truncate with … if it has more.
         let first_line = cmd.lines().next().unwrap_or("").to_string();
         let preview = if cmd.lines().count() > 1 {
-            format!("{} …", first_line)
+            format!("{} … ({} lines, ctrl+e shows them)", first_line, cmd.lines().count())
         } else {
             first_line
         };
@@ -799,7 +3406,57 @@ impl LLMTab {
         frame.render_widget(Paragraph::new(line), area);
     }
 
+    /// The selected suggestion whole and highlighted, along the bottom of
+    /// `area` just above the suggestion bar.
+    fn render_preview(&self, frame: &mut Frame, area: Rect) {
+        let Some(cmd) = self.suggestion_idx.and_then(|i| self.suggestions.get(i)) else {
+            return;
+        };
+        let mut lines = shell::highlight(cmd);
+        let total = lines.len();
+        let max_rows = area.height.saturating_sub(2).max(1) as usize;
+        if total > max_rows {
+            lines.truncate(max_rows - 1);
+            lines.push(Line::from(Span::styled(
+                format!("… {} more lines", total - lines.len()),
+                Theme::dimmed(),
+            )));
+        }
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect { y: area.bottom().saturating_sub(height), height, ..area };
+        let position = self.suggestion_idx.unwrap_or(0) + 1;
+        let title = format!(" Suggestion [{}/{}] · {} lines ", position, self.suggestions.len(), total);
+        let keys = format!(" F4 type · {} run · esc close ", self.run_key.label());
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(title, Theme::title()))
+                    .title_bottom(Span::styled(keys, Theme::dimmed())),
+            ),
+            popup,
+        );
+    }
+
     fn render_status(&self, frame: &mut Frame, area: Rect) {
+        if let Some(find) = &self.find {
+            let line = Line::from(Span::styled(format!(" {}", find.status()), Theme::key_hint_key()));
+            frame.render_widget(Paragraph::new(line), area);
+            return;
+        }
+        if let Some(vendor) = self.held_request {
+            let line = Line::from(vec![
+                Span::styled(format!(" Send this request, with the session's context, to {}? ", vendor), Theme::title()),
+                Span::styled("y", Theme::key_hint_key()),
+                Span::styled(" send  ", Theme::key_hint_desc()),
+                Span::styled("n", Theme::key_hint_key()),
+                Span::styled(" don't", Theme::key_hint_desc()),
+            ]);
+            frame.render_widget(Paragraph::new(line), area);
+            return;
+        }
         let style = if self.waiting {
             Theme::dimmed()
         } else {
@@ -841,6 +3498,112 @@ impl LLMTab {
     }
 }
 
+// ── Confirmation prompt helpers ───────────────────────────────────────────────
+
+/// Rows shown for a pending command before it is expanded with `v`.
+const COLLAPSED_COMMAND_ROWS: usize = 3;
+
+/// Returns `(total, visible)` wrapped rows for the pending command inside the
+/// chat `area`. Expanded commands may take up to half of the chat area.
+fn command_view_rows(ptc: &PendingToolCall, area: Rect) -> (usize, usize) {
+    // Each command row is prefixed with a 5-column "   $ " gutter.
+    let width = area.width.saturating_sub(5).max(1) as usize;
+    let total: usize = shown_command(ptc)
+        .lines()
+        .map(|l| wrapped_line_count(l, width))
+        .sum::<usize>()
+        .max(1);
+    let cap = if ptc.expanded {
+        (area.height as usize / 2).max(COLLAPSED_COMMAND_ROWS)
+    } else {
+        COLLAPSED_COMMAND_ROWS
+    };
+    (total, total.min(cap))
+}
+
+/// What the confirmation prompt shows for a call: the diff for file
+/// writes, the command otherwise.
+fn shown_command(ptc: &PendingToolCall) -> &str {
+    ptc.preview.as_ref().map_or(ptc.command.as_str(), |p| p.diff.as_str())
+}
+
+/// Status line for a running tool, e.g.
+/// "run_command: 12s elapsed, 3.4 KB captured…".
+fn progress_status(progress: &ToolProgress) -> String {
+    let mut parts = vec![format!("{}s elapsed", progress.elapsed.as_secs())];
+    if progress.bytes > 0 {
+        parts.push(format!("{} captured", format_bytes(progress.bytes)));
+    }
+    parts.extend(progress.note.clone());
+    format!("{}: {}…", progress.name, parts.join(", "))
+}
+
+/// "850ms", "2.3s" or "1m 05s".
+fn format_duration(d: Duration) -> String {
+    match d.as_millis() {
+        ms if ms < 1000 => format!("{}ms", ms),
+        ms if ms < 60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        ms => format!("{}m {:02}s", ms / 60_000, ms % 60_000 / 1000),
+    }
+}
+
+/// "512 B", "4.1 KB" or "2.0 MB".
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// The program a simple command runs, with `sudo` in front when it runs as
+/// root, so a scope for `systemctl` doesn't cover `sudo systemctl`. `None`
+/// for compound commands, where scoping by program would also approve
+/// whatever follows a separator; for `VAR=value` prefixes, which can change
+/// what the program does (`LD_PRELOAD=…`); for programs that run another
+/// command (`env`, `xargs`, `bash -c`); and for sudo with options of its own.
+fn command_program(command: &str) -> Option<String> {
+    const SEPARATORS: &[&str] = &[";", "&", "|", "`", "$(", ">", "<", "\n"];
+    const WRAPPERS: &[&str] = &[
+        "env", "nohup", "nice", "ionice", "timeout", "time", "stdbuf", "setsid", "xargs", "exec", "eval", "command",
+        "builtin", "doas", "su", "sh", "bash", "zsh", "dash", "ksh",
+    ];
+    if SEPARATORS.iter().any(|sep| command.contains(sep)) {
+        return None;
+    }
+    let plain = |w: &&str| !w.starts_with('-') && !w.contains('=') && !WRAPPERS.contains(w);
+    let mut words = command.split_whitespace();
+    match words.next()? {
+        "sudo" => words.next().filter(plain).map(|program| format!("sudo {}", program)),
+        program => Some(program).filter(plain).map(str::to_string),
+    }
+}
+
+/// The end of the terminal log at a read_terminal. The log drops old
+/// chunks as it grows, so the position is found again by the last chunks'
+/// contents rather than by index.
+struct TerminalMark {
+    /// Log length at the read.
+    len: usize,
+    /// Last few chunks before that point.
+    tail: Vec<String>,
+}
+
+impl TerminalMark {
+    const TAIL: usize = 3;
+
+    fn at_end(log: &[String]) -> Self {
+        Self { len: log.len(), tail: log[log.len().saturating_sub(Self::TAIL)..].to_vec() }
+    }
+
+    /// Index in `log` just after the marked chunks, searching back from the
+    /// marked length since the log only loses chunks at the front.
+    fn find(&self, log: &[String]) -> Option<usize> {
+        let n = self.tail.len();
+        (n..=self.len.min(log.len())).rev().find(|&end| log[end - n..end] == self.tail[..])
+    }
+}
+
 // ── Tool id generation ────────────────────────────────────────────────────────
 
 /// Generate a session-unique tool-use id so we never accidentally reuse one
@@ -896,56 +3659,6 @@ fn wrapped_line_count(text: &str, width: usize) -> usize {
         .max(1)
 }
 
-// ── Pre-split wrapping helpers ────────────────────────────────────────────────
-
-/// Split a vec of ratatui spans into visual rows of at most `width` chars.
-/// Returns `(chunk_spans, byte_offset_in_original_string)` per row.
-fn wrap_line_spans(spans: Vec<Span<'static>>, width: usize) -> Vec<(Vec<Span<'static>>, usize)> {
-    if width == 0 {
-        return vec![(spans, 0)];
-    }
-    let mut rows: Vec<(Vec<Span<'static>>, usize)> = Vec::new();
-    let mut current: Vec<Span<'static>> = Vec::new();
-    let mut chars_in_row: usize = 0;
-    let mut line_byte_offset: usize = 0;
-    let mut row_byte_start: usize = 0;
-
-    for span in spans {
-        let style = span.style;
-        let mut remaining = span.content.as_ref().to_string();
-
-        while !remaining.is_empty() {
-            let capacity = width - chars_in_row;
-            let char_count = remaining.chars().count();
-
-            if char_count <= capacity {
-                chars_in_row += char_count;
-                line_byte_offset += remaining.len();
-                current.push(Span::styled(remaining, style));
-                remaining = String::new();
-            } else {
-                let split_byte: usize =
-                    remaining.chars().take(capacity).map(|c| c.len_utf8()).sum();
-                let head = remaining[..split_byte].to_string();
-                let tail = remaining[split_byte..].to_string();
-
-                if !head.is_empty() {
-                    current.push(Span::styled(head.clone(), style));
-                }
-                line_byte_offset += head.len();
-
-                rows.push((std::mem::take(&mut current), row_byte_start));
-                row_byte_start = line_byte_offset;
-                chars_in_row = 0;
-                remaining = tail;
-            }
-        }
-    }
-
-    rows.push((current, row_byte_start));
-    rows
-}
-
 /// Apply selection highlight to a pre-split chunk of spans.
 /// `row_byte_start` is where this chunk starts within the original logical line string.
 fn apply_sel_to_chunk(
@@ -1007,198 +3720,225 @@ fn apply_sel_to_chunk(
     Line::from(result)
 }
 
-// ── Markdown rendering helpers ────────────────────────────────────────────────
-
-/// Strip the role prefix / indent from a line to get the raw content.
-fn line_content(text: &str) -> &str {
-    if let Some(rest) = text.strip_prefix("You: ") {
-        rest
-    } else if let Some(rest) = text.strip_prefix("Claude: ") {
-        rest
-    } else if let Some(rest) = text.strip_prefix("System: ") {
-        rest
-    } else if let Some(rest) = text.strip_prefix("      ") {
-        rest
-    } else {
-        text
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-/// Render a single history line with markdown styling applied.
-/// `in_code` means the line falls inside a fenced code block.
-fn render_md_line(full_text: &str, in_code: bool) -> Line<'static> {
-    if full_text.is_empty() {
-        return Line::raw("");
-    }
-
-    // Split prefix (role label / indent) from content.
-    let (prefix_str, prefix_style, content): (&str, Option<Style>, &str) =
-        if let Some(rest) = full_text.strip_prefix("You: ") {
-            ("You: ", Some(Theme::chat_user()), rest)
-        } else if let Some(rest) = full_text.strip_prefix("Claude: ") {
-            (
-                "Claude: ",
-                Some(Style::default().fg(Color::Rgb(205, 115, 80))),
-                rest,
-            )
-        } else if let Some(rest) = full_text.strip_prefix("System: ") {
-            ("System: ", Some(Theme::dimmed()), rest)
-        } else if let Some(rest) = full_text.strip_prefix("      ") {
-            ("      ", None, rest)
-        } else {
-            ("", None, full_text)
-        };
+    fn tab() -> LLMTab {
+        let connection = SSHConnection { name: "prod".into(), ..Default::default() };
+        LLMTab::new(Arc::new(Disabled), None, connection)
+    }
 
-    let mut spans: Vec<Span<'static>> = Vec::new();
-    if !prefix_str.is_empty() {
-        match prefix_style {
-            Some(s) => spans.push(Span::styled(prefix_str.to_string(), s)),
-            None => spans.push(Span::raw(prefix_str.to_string())),
-        }
-    }
-
-    // Code block lines: render as-is with code style.
-    if in_code {
-        spans.push(Span::styled(content.to_string(), Theme::md_code_block()));
-        return Line::from(spans);
-    }
-
-    // Headings (line-level).
-    if let Some(rest) = content.strip_prefix("### ") {
-        spans.push(Span::styled(
-            format!("### {}", rest),
-            Style::default().add_modifier(Modifier::BOLD),
-        ));
-    } else if let Some(rest) = content.strip_prefix("## ") {
-        spans.push(Span::styled(
-            format!("## {}", rest),
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        ));
-    } else if let Some(rest) = content.strip_prefix("# ") {
-        spans.push(Span::styled(
-            format!("# {}", rest),
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        ));
-    } else {
-        spans.extend(parse_inline_md(content));
+    #[test]
+    fn command_program_scopes_plain_commands_only() {
+        let cases = [
+            ("ls -la /srv", Some("ls")),
+            ("  df -h", Some("df")),
+            ("sudo systemctl status nginx", Some("sudo systemctl")),
+            ("sudo -u root ls", None),
+            ("sudo LD_PRELOAD=/tmp/x.so ls", None),
+            ("LD_PRELOAD=/tmp/x.so ls", None),
+            ("LANG=C ls", None),
+            ("env ls", None),
+            ("xargs rm", None),
+            ("bash -c ls", None),
+            ("sudo bash -c ls", None),
+            ("ls; rm -rf /", None),
+            ("ls && rm -rf /", None),
+            ("ls | sh", None),
+            ("ls > /etc/passwd", None),
+            ("echo $(rm -rf /)", None),
+            ("ls\nrm -rf /", None),
+            ("", None),
+        ];
+        for (command, program) in cases {
+            assert_eq!(command_program(command).as_deref(), program, "{:?}", command);
+        }
     }
 
-    Line::from(spans)
-}
+    #[test]
+    fn auto_approve_scopes_cover_only_what_they_say() {
+        let mut tab = tab();
+        tab.auto_approve = Some(AutoApprove::Program("ls".into()));
+        assert!(tab.take_auto_approval("ls -la /srv"));
+        assert!(!tab.take_auto_approval("LD_PRELOAD=/tmp/x.so ls"));
+        assert!(!tab.take_auto_approval("ls; rm -rf /"));
+        assert!(!tab.take_auto_approval("sudo ls"));
+        assert!(!tab.take_auto_approval("rm -rf /srv"));
+        assert!(tab.auto_approve.is_some());
+
+        tab.auto_approve = Some(AutoApprove::Calls(2));
+        assert!(tab.take_auto_approval("rm -rf /srv"));
+        assert!(tab.take_auto_approval("df -h"));
+        assert!(tab.auto_approve.is_none());
+        assert!(!tab.take_auto_approval("df -h"));
+
+        tab.auto_approve = Some(AutoApprove::Until(Instant::now()));
+        assert!(!tab.take_auto_approval("df -h"));
+        assert!(tab.auto_approve.is_none());
+    }
 
-/// Parse inline markdown (`**bold**`, `*italic*`, `` `code` ``) into styled spans.
-fn parse_inline_md(text: &str) -> Vec<Span<'static>> {
-    let chars: Vec<char> = text.chars().collect();
-    let n = chars.len();
-    let mut spans: Vec<Span<'static>> = Vec::new();
-    let mut buf = String::new();
-    let mut i = 0;
-
-    while i < n {
-        match chars[i] {
-            // **bold** or __bold__
-            '*' if i + 1 < n && chars[i + 1] == '*' => {
-                if let Some(end) = find_seq(&chars, i + 2, &['*', '*']) {
-                    flush_buf(&mut buf, &mut spans);
-                    spans.push(Span::styled(
-                        chars[i + 2..end].iter().collect::<String>(),
-                        Style::default().add_modifier(Modifier::BOLD),
-                    ));
-                    i = end + 2;
-                    continue;
-                }
-                buf.push('*');
-            }
-            '_' if i + 1 < n && chars[i + 1] == '_' => {
-                if let Some(end) = find_seq(&chars, i + 2, &['_', '_']) {
-                    flush_buf(&mut buf, &mut spans);
-                    spans.push(Span::styled(
-                        chars[i + 2..end].iter().collect::<String>(),
-                        Style::default().add_modifier(Modifier::BOLD),
-                    ));
-                    i = end + 2;
-                    continue;
-                }
-                buf.push('_');
-            }
-            // *italic* (single star)
-            '*' => {
-                if let Some(end) = find_char_from(&chars, i + 1, '*')
-                    && end > i + 1
-                {
-                    flush_buf(&mut buf, &mut spans);
-                    spans.push(Span::styled(
-                        chars[i + 1..end].iter().collect::<String>(),
-                        Style::default().add_modifier(Modifier::ITALIC),
-                    ));
-                    i = end + 1;
-                    continue;
-                }
-                buf.push('*');
-            }
-            // _italic_ (single underscore)
-            '_' => {
-                if let Some(end) = find_char_from(&chars, i + 1, '_')
-                    && end > i + 1
-                {
-                    flush_buf(&mut buf, &mut spans);
-                    spans.push(Span::styled(
-                        chars[i + 1..end].iter().collect::<String>(),
-                        Style::default().add_modifier(Modifier::ITALIC),
-                    ));
-                    i = end + 1;
-                    continue;
-                }
-                buf.push('_');
-            }
-            // `inline code`
-            '`' => {
-                if let Some(end) = find_char_from(&chars, i + 1, '`') {
-                    flush_buf(&mut buf, &mut spans);
-                    spans.push(Span::styled(
-                        chars[i + 1..end].iter().collect::<String>(),
-                        Theme::md_code_inline(),
-                    ));
-                    i = end + 1;
-                    continue;
-                }
-                buf.push('`');
-            }
-            c => buf.push(c),
+    /// A turn in which the model calls `run_command` once for each of `commands`.
+    fn run_commands(commands: &[&str]) -> LLMEvent {
+        let ids: Vec<String> = (0..commands.len()).map(|i| format!("toolu_{}", i)).collect();
+        LLMEvent::ToolCalls {
+            calls: ids
+                .iter()
+                .zip(commands)
+                .map(|(id, command)| ToolResult::Command {
+                    id: id.clone(),
+                    command: command.to_string(),
+                    description: None,
+                })
+                .collect(),
+            assistant_blocks: ids
+                .iter()
+                .zip(commands)
+                .map(|(id, command)| ContentBlock::ToolUse {
+                    id: id.clone(),
+                    name: "run_command".into(),
+                    input: serde_json::json!({ "command": command }),
+                })
+                .collect(),
+            usage: None,
         }
-        i += 1;
     }
 
-    flush_buf(&mut buf, &mut spans);
-    if spans.is_empty() {
-        spans.push(Span::raw(String::new()));
+    fn pending(tab: &LLMTab) -> Option<&str> {
+        tab.pending_tool_call.as_ref().map(|ptc| ptc.command.as_str())
     }
-    spans
-}
 
-fn flush_buf(buf: &mut String, spans: &mut Vec<Span<'static>>) {
-    if !buf.is_empty() {
-        spans.push(Span::raw(std::mem::take(buf)));
+    /// The tool_results sent back for the last finished batch, in order.
+    fn results(tab: &LLMTab) -> Vec<&str> {
+        let Some(message) = tab.rich_history.last() else { return vec![] };
+        message
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect()
     }
-}
 
-/// Find the first occurrence of `seq` in `chars` at or after `from`.
-fn find_seq(chars: &[char], from: usize, seq: &[char]) -> Option<usize> {
-    let sl = seq.len();
-    if sl == 0 {
-        return Some(from);
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
     }
-    (from..=chars.len().saturating_sub(sl)).find(|&i| chars[i..i + sl] == *seq)
-}
 
-/// Find the first occurrence of `target` in `chars` at or after `from`.
-fn find_char_from(chars: &[char], from: usize, target: char) -> Option<usize> {
-    chars[from..]
-        .iter()
-        .position(|&c| c == target)
-        .map(|p| from + p)
+    #[test]
+    fn a_batch_asks_for_each_command_and_answers_them_together() {
+        let mut tab = tab();
+        tab.tx.send(run_commands(&["df -h", "uptime", "free -m"])).unwrap();
+        tab.poll();
+        assert_eq!(pending(&tab), Some("df -h"));
+        assert_eq!(tab.status, "Awaiting confirmation (1 of 3)…");
+
+        assert_eq!(tab.confirm_tool_call(true).as_deref(), Some("df -h"));
+        tab.resume_with_output("/dev/sda1  40G".into(), None);
+        assert_eq!(pending(&tab), Some("uptime"));
+        assert!(tab.rich_history.is_empty());
+
+        tab.confirm_tool_call(false);
+        assert_eq!(pending(&tab), Some("free -m"));
+        tab.confirm_tool_call(false);
+        assert!(tab.tool_batch.is_none());
+        assert_eq!(tab.rich_history.len(), 2);
+        assert_eq!(tab.rich_history[0].content.len(), 3);
+        assert_eq!(
+            results(&tab),
+            [
+                "Command output:\n```\n/dev/sda1  40G\n```",
+                "User declined to execute the command.",
+                "User declined to execute the command."
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_y_runs_the_rest_of_the_batch_as_each_command_finishes() {
+        let mut tab = tab();
+        tab.tx.send(run_commands(&["uptime", "df -h"])).unwrap();
+        tab.poll();
+        assert!(matches!(tab.handle_event(&key('Y')), Action::SendToTerminal(c) if c == "uptime"));
+        assert_eq!(tab.take_approved_command(), None);
+        tab.resume_with_output(String::new(), None);
+        assert_eq!(tab.take_approved_command().as_deref(), Some("df -h"));
+        tab.resume_with_output(String::new(), None);
+        assert_eq!(results(&tab), ["Command executed. No output was captured."; 2]);
+    }
+
+    #[test]
+    fn an_approved_plan_runs_the_commands_it_names_as_the_policy_allows() {
+        let cases = [
+            (PlanPolicy::Off, "apt-get update", false),
+            (PlanPolicy::Safe, "apt-get update", true),
+            (PlanPolicy::Safe, "systemctl restart nginx", false),
+            (PlanPolicy::All, "systemctl restart nginx", true),
+            (PlanPolicy::All, "df -h", false),
+        ];
+        for (policy, command, runs) in cases {
+            let mut tab = tab();
+            tab.set_plan_policy(policy);
+            tab.approved_plan = Some(vec![
+                ("Refresh the package lists".into(), Some("apt-get update".into())),
+                ("Restart nginx".into(), Some("systemctl restart nginx".into())),
+            ]);
+            tab.tx.send(run_commands(&[command])).unwrap();
+            tab.poll();
+            assert_eq!(tab.take_approved_command().is_some(), runs, "{:?} {}", policy, command);
+            assert_eq!(pending(&tab).is_some(), !runs, "{:?} {}", policy, command);
+        }
+    }
+
+    #[test]
+    fn a_proposed_plan_holds_the_commands_behind_it_until_approved() {
+        let plan = serde_json::json!({ "steps": [
+            { "step": "Refresh the package lists", "command": "apt-get update", "status": "pending" },
+        ] });
+        let turn = || LLMEvent::ToolCalls {
+            calls: vec![
+                ToolResult::Local { id: "toolu_0".into(), name: "update_plan".into(), input: plan.clone() },
+                ToolResult::Command { id: "toolu_1".into(), command: "apt-get update".into(), description: None },
+            ],
+            assistant_blocks: vec![],
+            usage: None,
+        };
+
+        let mut approved = tab();
+        approved.tx.send(turn()).unwrap();
+        approved.poll();
+        assert!(approved.plan_review_pending());
+        assert_eq!(pending(&approved), None);
+        approved.review_plan(true);
+        let outline = vec![("Refresh the package lists".to_string(), Some("apt-get update".to_string()))];
+        assert_eq!(approved.approved_plan, Some(outline));
+        assert_eq!(approved.take_approved_command().as_deref(), Some("apt-get update"));
+
+        let mut rejected = tab();
+        rejected.tx.send(turn()).unwrap();
+        rejected.poll();
+        rejected.review_plan(false);
+        assert!(rejected.plan.is_empty());
+        assert_eq!(rejected.take_approved_command(), None);
+        assert!(rejected.tool_batch.is_none());
+        assert_eq!(results(&rejected).len(), 2);
+    }
+
+    #[test]
+    fn a_guarded_host_wants_its_alias_typed_for_every_command() {
+        let mut tab = tab();
+        tab.set_guard(true);
+        tab.auto_approve = Some(AutoApprove::Session);
+        tab.tx.send(run_commands(&["uptime"])).unwrap();
+        tab.poll();
+        assert_eq!(tab.take_approved_command(), None);
+        assert_eq!(pending(&tab), Some("uptime"));
+
+        assert!(matches!(tab.handle_event(&key('y')), Action::None));
+        for c in "prod".chars() {
+            tab.handle_event(&key(c));
+        }
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(matches!(tab.handle_event(&enter), Action::SendToTerminal(c) if c == "uptime"));
+    }
 }
@@ -1,11 +1,20 @@
 use std::{
     io::{Read, Write},
-    sync::{Arc, Mutex},
+    net::{Shutdown, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
+    time::{Duration, Instant},
 };
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
+use anyhow::Context;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use portable_pty::{CommandBuilder, MasterPty, NativePtySystem, PtySize, PtySystem};
+use regex::Regex;
+use serialport::SerialPort;
 use ratatui::{
     Frame,
     layout::Rect,
@@ -16,21 +25,100 @@ use ratatui::{
 use termwiz::cell::Intensity;
 use termwiz::color::{ColorSpec, SrgbaTuple};
 use termwiz::escape::csi::{
-    CSI, Cursor as TwCursor, DecPrivateMode, DecPrivateModeCode, Edit, EraseInDisplay, EraseInLine,
+    CSI, Cursor as TwCursor, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay, EraseInLine,
     Mode, Sgr,
 };
 use termwiz::escape::parser::Parser as EscapeParser;
-use termwiz::escape::{Action as TwAction, ControlCode};
+use termwiz::escape::osc::FinalTermSemanticPrompt;
+use termwiz::escape::{Action as TwAction, ControlCode, Esc, EscCode, OperatingSystemCommand};
 
 use super::Tab;
-use crate::{event::Action, ssh::SSHConnection, ui::theme::Theme};
+use crate::{
+    event::Action,
+    native::{self, ShellInput},
+    route::{Progress, RouteStatus},
+    ssh::{Backend, SSHConnection, Transport},
+    telnet::{self, TelnetReader, TelnetWriter},
+    ui::{
+        CopyOnSelect, UiConfig, clipboard,
+        find::{FindBar, FindKey},
+        scroll::Scroller,
+        theme::Theme,
+    },
+};
 
 pub const MAX_LINES: usize = 2000;
+
+/// Bytes of the session's first output read for how connecting went.
+const ROUTE_TEXT: usize = 64 * 1024;
+
+/// How long a serial read waits before checking whether the tab is gone.
+const SERIAL_POLL: Duration = Duration::from_millis(200);
 pub const CONTEXT_LINES: usize = 50;
 
 /// Selection position: (abs_row, col) in the combined scrollback+screen space.
 type SelPos = (usize, u16);
 
+/// A serial device's output. A quiet line times out rather than ending, so
+/// reads wait on until the output arrives or the tab's writer is dropped.
+struct SerialReader {
+    port: Box<dyn SerialPort>,
+    open: Arc<AtomicBool>,
+}
+
+impl Read for SerialReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.open.load(Ordering::Relaxed) {
+            match self.port.read(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                result => return result,
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Typing into a serial device; dropping it stops its [`SerialReader`].
+struct SerialWriter {
+    port: Box<dyn SerialPort>,
+    open: Arc<AtomicBool>,
+}
+
+impl Write for SerialWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl Drop for SerialWriter {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Typing into a raw TCP connection; dropping it hangs up.
+struct TcpWriter(TcpStream);
+
+impl Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for TcpWriter {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Both);
+    }
+}
+
 // ── Cell types ────────────────────────────────────────────────────────────────
 
 #[derive(Clone, Copy, PartialEq, Default)]
@@ -44,7 +132,7 @@ struct CellStyle {
     inverse: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 struct TermCell {
     ch: char,
     style: CellStyle,
@@ -61,9 +149,46 @@ impl Default for TermCell {
 
 type TermRow = Vec<TermCell>;
 
+/// A command in the scrollback, starting at its prompt line. Lines are
+/// counted over every row the emulator has shown (see
+/// [`TermEmulator::line_index`]); all but `prompt` come from OSC 133 marks.
+#[derive(Clone, Copy)]
+struct CommandBlock {
+    prompt: usize,
+    /// Where the typed command starts (line, column).
+    input: Option<(usize, usize)>,
+    /// First line of output.
+    output: Option<usize>,
+    /// Line after the output, once the command has finished.
+    end: Option<usize>,
+    exit_status: Option<i32>,
+}
+
+/// A finished command with its output.
+pub struct CommandOutput {
+    pub command: String,
+    pub output: String,
+    /// Known when the shell reports it with OSC 133.
+    pub exit_status: Option<i32>,
+}
+
+/// Mouse events a full-screen program (vim, htop, tmux) has asked for.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default)]
+enum MouseTracking {
+    #[default]
+    Off,
+    /// Presses, releases and the wheel (mode 1000).
+    Clicks,
+    /// Also motion while a button is held (mode 1002).
+    Drags,
+    /// Also motion with no button held (mode 1003).
+    Motion,
+}
+
 // ── Terminal emulator ─────────────────────────────────────────────────────────
 
-struct TermEmulator {
+/// The screen and scrollback that a session's output is parsed into.
+pub struct TermEmulator {
     rows: usize,
     cols: usize,
     /// Visible (or alternate) screen.
@@ -72,20 +197,61 @@ struct TermEmulator {
     normal_screen: Vec<TermRow>,
     normal_cursor: (usize, usize),
     in_alt_screen: bool,
+    /// Cursor keys send `ESC O x` instead of `ESC [ x` (DECCKM).
+    app_cursor: bool,
+    mouse: MouseTracking,
+    /// Report the mouse as `ESC [ < b;x;y M` (mode 1006) instead of X10 bytes.
+    sgr_mouse: bool,
+    /// Wrap pastes in `ESC [ 200~` … `ESC [ 201~` (mode 2004).
+    bracketed_paste: bool,
     cursor_row: usize,
     cursor_col: usize,
+    /// The last column was just written: the next character goes on a new
+    /// line, unless the cursor moves first (xterm's delayed autowrap).
+    wrap_pending: bool,
+    /// Hidden by the program (DECTCEM), e.g. while htop draws.
+    cursor_visible: bool,
     saved_cursor: (usize, usize),
+    /// Style and character set saved with the cursor by `ESC 7`.
+    saved_style: CellStyle,
+    saved_charsets: ([bool; 2], bool),
     cur_style: CellStyle,
+    /// G0 and G1 hold DEC line drawing instead of ASCII (`ESC ( 0`, `ESC ) 0`).
+    line_drawing: [bool; 2],
+    /// G1 is in use (shift out) rather than G0.
+    shifted: bool,
+    /// Last character printed, for REP.
+    last_char: char,
+    /// Answers to the program's queries (cursor position, device
+    /// attributes), for the tab to send back.
+    replies: Vec<u8>,
     /// Scroll region — inclusive, 0-indexed.
     scroll_top: usize,
     scroll_bot: usize,
-    /// Rows that scrolled off the top of the normal screen.
+    /// Rows that scrolled off the top of the normal screen. Once rows have
+    /// been evicted past [`MAX_LINES`], the first one is a marker saying how many.
     scrollback: Vec<TermRow>,
+    /// Rows evicted from the front of `scrollback`.
+    dropped: usize,
+    /// Rows ever pushed to `scrollback`; the screen's first row is line `pushed`.
+    pushed: usize,
+    /// Commands run so far, oldest first.
+    blocks: Vec<CommandBlock>,
+    /// Regex matching shell prompt lines, for shells without OSC 133.
+    prompt_regex: Option<Regex>,
+    /// The shell marks its prompts with OSC 133; the regex is no longer used.
+    semantic_prompts: bool,
+    /// Commands the shell has reported finished (OSC 133 D), ever.
+    finished: usize,
     parser: EscapeParser,
+    /// Remote working directory, from OSC 7 or a `user@host: path` window title.
+    cwd: Option<String>,
+    /// Once the shell reports OSC 7, window titles are no longer trusted for the cwd.
+    cwd_from_osc7: bool,
 }
 
 impl TermEmulator {
-    fn new(rows: usize, cols: usize) -> Self {
+    pub fn new(rows: usize, cols: usize) -> Self {
         let screen = vec![empty_row(cols); rows];
         let normal_screen = screen.clone();
         Self {
@@ -95,29 +261,61 @@ impl TermEmulator {
             normal_screen,
             normal_cursor: (0, 0),
             in_alt_screen: false,
+            app_cursor: false,
+            mouse: MouseTracking::Off,
+            sgr_mouse: false,
+            bracketed_paste: false,
             cursor_row: 0,
             cursor_col: 0,
+            wrap_pending: false,
+            cursor_visible: true,
             saved_cursor: (0, 0),
+            saved_style: CellStyle::default(),
+            saved_charsets: ([false; 2], false),
             cur_style: CellStyle::default(),
+            line_drawing: [false; 2],
+            shifted: false,
+            last_char: ' ',
+            replies: vec![],
             scroll_top: 0,
             scroll_bot: rows.saturating_sub(1),
             scrollback: Vec::new(),
+            dropped: 0,
+            pushed: 0,
+            blocks: vec![],
+            prompt_regex: None,
+            semantic_prompts: false,
+            finished: 0,
             parser: EscapeParser::new(),
+            cwd: None,
+            cwd_from_osc7: false,
         }
     }
 
     fn resize(&mut self, rows: usize, cols: usize) {
+        // Keep the cursor's line on screen: rows above it go to the
+        // scrollback rather than the bottom of the screen being cut off.
+        if rows < self.rows && self.cursor_row >= rows {
+            let excess = self.cursor_row + 1 - rows;
+            self.scroll_top = 0;
+            self.scroll_bot = self.rows - 1;
+            self.scroll_up_region(excess);
+            self.cursor_row -= excess;
+        }
         self.rows = rows;
         self.cols = cols;
         resize_grid(&mut self.screen, rows, cols);
         resize_grid(&mut self.normal_screen, rows, cols);
         self.cursor_row = self.cursor_row.min(rows.saturating_sub(1));
         self.cursor_col = self.cursor_col.min(cols.saturating_sub(1));
+        self.wrap_pending = false;
         self.scroll_top = 0;
         self.scroll_bot = rows.saturating_sub(1);
     }
 
-    fn process(&mut self, data: &[u8]) {
+    /// Apply output from the session: text, control codes and escape
+    /// sequences, which may be split across calls.
+    pub fn process(&mut self, data: &[u8]) {
         let actions = self.parser.parse_as_vec(data);
         for action in actions {
             self.apply_action(action);
@@ -144,15 +342,27 @@ impl TermEmulator {
             for i in 0..count {
                 self.scrollback.push(self.screen[top + i].clone());
             }
+            self.pushed += count;
             let len = self.scrollback.len();
             if len > MAX_LINES {
-                self.scrollback.drain(0..len - MAX_LINES);
+                let marker = usize::from(self.dropped > 0);
+                let excess = len - MAX_LINES;
+                self.scrollback.drain(marker..marker + excess);
+                self.dropped += excess;
+                let row = marker_row(&format!("--- {} lines dropped ---", self.dropped), self.cols);
+                if marker == 1 {
+                    self.scrollback[0] = row;
+                } else {
+                    self.scrollback.insert(0, row);
+                }
+                let dropped = self.dropped;
+                self.blocks.retain(|b| b.prompt >= dropped);
             }
         }
 
         self.screen[top..=bot].rotate_left(count);
         for i in region_size - count..region_size {
-            self.screen[top + i] = empty_row(self.cols);
+            self.screen[top + i] = self.blank_row();
         }
     }
 
@@ -169,7 +379,7 @@ impl TermEmulator {
         let count = count.min(region_size);
         self.screen[top..=bot].rotate_right(count);
         for i in 0..count {
-            self.screen[top + i] = empty_row(self.cols);
+            self.screen[top + i] = self.blank_row();
         }
     }
 
@@ -185,26 +395,208 @@ impl TermEmulator {
             }
             TwAction::Control(cc) => self.apply_control(cc),
             TwAction::CSI(csi) => self.apply_csi(csi),
+            TwAction::Esc(esc) => self.apply_esc(esc),
+            TwAction::OperatingSystemCommand(osc) => self.apply_osc(*osc),
+            _ => {}
+        }
+    }
+
+    fn apply_osc(&mut self, osc: OperatingSystemCommand) {
+        match osc {
+            OperatingSystemCommand::CurrentWorkingDirectory(url) => {
+                if let Some(path) = cwd_from_osc7(&url) {
+                    self.cwd = Some(path);
+                    self.cwd_from_osc7 = true;
+                }
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::FreshLineAndStartPrompt { .. } | FinalTermSemanticPrompt::StartPrompt(_),
+            ) => {
+                if self.cursor_col > 0 {
+                    self.cursor_col = 0;
+                    self.do_linefeed();
+                }
+                self.semantic_prompts = true;
+                self.push_block(self.pushed + self.cursor_row);
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker
+                | FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilEndOfLine,
+            ) => {
+                let at = (self.pushed + self.cursor_row, self.cursor_col);
+                if let Some(block) = self.blocks.last_mut() {
+                    block.input = Some(at);
+                }
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. },
+            ) => {
+                let line = self.pushed + self.cursor_row;
+                if let Some(block) = self.blocks.last_mut() {
+                    block.output = Some(line);
+                }
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::CommandStatus { status, .. }) => {
+                self.end_command(Some(status))
+            }
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfCommandWithFreshLine { .. },
+            ) => self.end_command(None),
+            // Debian/Ubuntu's default bash prompt sets the title to `user@host: cwd`.
+            OperatingSystemCommand::SetIconNameAndWindowTitle(title)
+            | OperatingSystemCommand::SetWindowTitle(title)
+                if !self.cwd_from_osc7 =>
+            {
+                if let Some(path) = cwd_from_title(&title) {
+                    self.cwd = Some(path);
+                }
+            }
             _ => {}
         }
     }
 
+    // ── Command blocks ────────────────────────────────────────────────────────
+
+    /// Whether `row` is a prompt by `prompt_regex`, outside full-screen programs.
+    fn is_prompt_row(&self, row: &TermRow) -> bool {
+        !self.in_alt_screen
+            && self.prompt_regex.as_ref().is_some_and(|re| re.is_match(&row_text(row, 0, row.len())))
+    }
+
+    fn push_block(&mut self, prompt: usize) {
+        if self.in_alt_screen || self.blocks.last().is_some_and(|b| b.prompt >= prompt) {
+            return;
+        }
+        self.blocks.push(CommandBlock { prompt, input: None, output: None, end: None, exit_status: None });
+    }
+
+    /// OSC 133 D: the running command finished. Shells also send it after
+    /// an empty command line, which has no output mark and is ignored.
+    fn end_command(&mut self, status: Option<i32>) {
+        let line = self.pushed + self.cursor_row + usize::from(self.cursor_col > 0);
+        if let Some(block) = self.blocks.last_mut()
+            && block.output.is_some()
+            && block.end.is_none()
+        {
+            block.end = Some(line);
+            block.exit_status = status;
+            self.finished += 1;
+        }
+    }
+
+    /// Index of `line` in scrollback + screen, if still kept.
+    fn line_index(&self, line: usize) -> Option<usize> {
+        if line >= self.pushed {
+            let row = line - self.pushed;
+            return (row < self.screen.len()).then_some(self.scrollback.len() + row);
+        }
+        let marker = usize::from(self.dropped > 0);
+        line.checked_sub(self.dropped).map(|i| i + marker)
+    }
+
+    fn row_at(&self, index: usize) -> Option<&TermRow> {
+        match index.checked_sub(self.scrollback.len()) {
+            None => self.scrollback.get(index),
+            Some(row) => self.screen.get(row),
+        }
+    }
+
+    /// The last command whose output is complete. With OSC 133 marks its
+    /// bounds are exact; with `prompt_regex` the output runs to the next
+    /// prompt, which may be the one the cursor is on.
+    fn last_command_output(&self) -> Option<CommandOutput> {
+        let (block, start, end) = if self.semantic_prompts {
+            let block = self.blocks.iter().rev().find(|b| b.end.is_some())?;
+            (block, block.output?, block.end?)
+        } else {
+            let cursor_line = self.pushed + self.cursor_row;
+            let waiting = self.screen.get(self.cursor_row).is_some_and(|row| self.is_prompt_row(row))
+                && self.blocks.last().is_none_or(|b| b.prompt < cursor_line);
+            let (block, end) = if waiting {
+                (self.blocks.last()?, cursor_line)
+            } else {
+                let [.., block, next] = self.blocks.as_slice() else {
+                    return None;
+                };
+                (block, next.prompt)
+            };
+            (block, block.prompt + 1, end)
+        };
+        let text = |line: usize| {
+            let row = self.line_index(line).and_then(|i| self.row_at(i))?;
+            Some(row_text(row, 0, row.len()))
+        };
+        let output: Vec<String> = (start..end).filter_map(text).collect();
+        Some(CommandOutput {
+            command: self.command_text(block)?,
+            output: output.join("\n").trim_end().to_string(),
+            exit_status: block.exit_status,
+        })
+    }
+
+    /// The command typed at `block`'s prompt.
+    fn command_text(&self, block: &CommandBlock) -> Option<String> {
+        let (line, col) = block.input.unwrap_or((block.prompt, 0));
+        let row = self.line_index(line).and_then(|i| self.row_at(i))?;
+        let text = row_text(row, col.min(row.len()), row.len());
+        let command = match self.prompt_regex.as_ref().filter(|_| block.input.is_none()).and_then(|re| re.find(&text)) {
+            Some(m) => &text[m.end()..],
+            None => &text,
+        };
+        Some(command.trim().to_string())
+    }
+
+    /// The last command the shell reported an exit status for, with it.
+    fn last_exit(&self) -> Option<(String, i32)> {
+        let block = self.blocks.iter().rev().find(|b| b.end.is_some())?;
+        Some((self.command_text(block).unwrap_or_default(), block.exit_status?))
+    }
+
+    /// Every row of the scrollback and the screen, colors included, with
+    /// the blank rows below the last output dropped; and the widest row.
+    pub fn all_rows(&self) -> (Vec<Line<'static>>, u16) {
+        let rows: Vec<&TermRow> = self.scrollback.iter().chain(self.screen.iter()).collect();
+        let used = rows.iter().rposition(|row| row.iter().any(|c| c.ch != ' ')).map_or(0, |i| i + 1);
+        let width = rows[..used].iter().map(|row| row.len()).max().unwrap_or(0);
+        let lines = rows[..used].iter().map(|row| render_term_row(row, usize::MAX, None, &[])).collect();
+        (lines, width.min(u16::MAX as usize) as u16)
+    }
+
     fn print_char(&mut self, c: char) {
         if self.cursor_row >= self.rows || self.cursor_col >= self.cols {
             return;
         }
+        if std::mem::take(&mut self.wrap_pending) {
+            self.cursor_col = 0;
+            self.do_linefeed();
+        }
+        let c = if self.line_drawing[usize::from(self.shifted)] { line_drawing_char(c) } else { c };
         self.screen[self.cursor_row][self.cursor_col] = TermCell {
             ch: c,
             style: self.cur_style,
         };
-        self.cursor_col += 1;
-        if self.cursor_col >= self.cols {
-            self.cursor_col = 0;
-            self.do_linefeed();
+        self.last_char = c;
+        if self.cursor_col + 1 < self.cols {
+            self.cursor_col += 1;
+        } else {
+            self.wrap_pending = true;
         }
     }
 
+    /// A blank cell as erasing leaves it: in the current background color,
+    /// which full-screen programs rely on to paint theirs (xterm's `bce`).
+    fn blank(&self) -> TermCell {
+        TermCell { ch: ' ', style: CellStyle { bg: self.cur_style.bg, ..CellStyle::default() } }
+    }
+
+    fn blank_row(&self) -> TermRow {
+        vec![self.blank(); self.cols]
+    }
+
     fn do_linefeed(&mut self) {
+        if !self.semantic_prompts && self.cursor_row < self.rows && self.is_prompt_row(&self.screen[self.cursor_row]) {
+            self.push_block(self.pushed + self.cursor_row);
+        }
         if self.cursor_row == self.scroll_bot {
             self.scroll_up_region(1);
         } else {
@@ -213,28 +605,105 @@ impl TermEmulator {
     }
 
     fn apply_control(&mut self, cc: ControlCode) {
+        if !matches!(cc, ControlCode::Bell | ControlCode::Null) {
+            self.wrap_pending = false;
+        }
         match cc {
             ControlCode::LineFeed | ControlCode::VerticalTab | ControlCode::FormFeed => {
                 self.do_linefeed()
             }
             ControlCode::CarriageReturn => self.cursor_col = 0,
-            ControlCode::Backspace => {
-                if self.cursor_col > 0 {
-                    self.cursor_col -= 1;
-                }
+            ControlCode::Backspace if self.cursor_col > 0 => {
+                self.cursor_col -= 1;
             }
             ControlCode::HorizontalTab => {
                 let next = (self.cursor_col / 8 + 1) * 8;
                 self.cursor_col = next.min(self.cols.saturating_sub(1));
             }
+            ControlCode::ShiftOut => self.shifted = true,
+            ControlCode::ShiftIn => self.shifted = false,
             _ => {}
         }
     }
 
+    fn apply_esc(&mut self, esc: Esc) {
+        let Esc::Code(code) = esc else {
+            return;
+        };
+        self.wrap_pending = false;
+        match code {
+            EscCode::DecSaveCursorPosition => {
+                self.saved_cursor = (self.cursor_row, self.cursor_col);
+                self.saved_style = self.cur_style;
+                self.saved_charsets = (self.line_drawing, self.shifted);
+            }
+            EscCode::DecRestoreCursorPosition => {
+                self.cursor_row = self.saved_cursor.0.min(self.rows.saturating_sub(1));
+                self.cursor_col = self.saved_cursor.1.min(self.cols.saturating_sub(1));
+                self.cur_style = self.saved_style;
+                (self.line_drawing, self.shifted) = self.saved_charsets;
+            }
+            EscCode::Index => self.do_linefeed(),
+            EscCode::NextLine => {
+                self.cursor_col = 0;
+                self.do_linefeed();
+            }
+            // Up a line, scrolling the region down at its top: how less and
+            // vim scroll back.
+            EscCode::ReverseIndex => {
+                if self.cursor_row == self.scroll_top {
+                    self.scroll_down_region(1);
+                } else {
+                    self.cursor_row = self.cursor_row.saturating_sub(1);
+                }
+            }
+            EscCode::DecLineDrawingG0 => self.line_drawing[0] = true,
+            EscCode::AsciiCharacterSetG0 | EscCode::UkCharacterSetG0 => self.line_drawing[0] = false,
+            EscCode::DecLineDrawingG1 => self.line_drawing[1] = true,
+            EscCode::AsciiCharacterSetG1 | EscCode::UkCharacterSetG1 => self.line_drawing[1] = false,
+            EscCode::FullReset => self.reset(),
+            _ => {}
+        }
+    }
+
+    /// `ESC c` (`reset`, `tput reset`): a blank screen with default modes.
+    /// The scrollback and what is known about earlier commands stay.
+    fn reset(&mut self) {
+        self.in_alt_screen = false;
+        self.screen = vec![empty_row(self.cols); self.rows];
+        (self.cursor_row, self.cursor_col, self.saved_cursor) = (0, 0, (0, 0));
+        (self.cur_style, self.saved_style) = (CellStyle::default(), CellStyle::default());
+        (self.line_drawing, self.shifted, self.saved_charsets) = ([false; 2], false, ([false; 2], false));
+        (self.scroll_top, self.scroll_bot) = (0, self.rows.saturating_sub(1));
+        (self.app_cursor, self.mouse, self.sgr_mouse, self.bracketed_paste) =
+            (false, MouseTracking::Off, false, false);
+        self.cursor_visible = true;
+    }
+
     fn apply_csi(&mut self, csi: CSI) {
         match csi {
-            CSI::Cursor(c) => self.apply_cursor(c),
-            CSI::Edit(e) => self.apply_edit(e),
+            CSI::Cursor(TwCursor::RequestActivePositionReport) => {
+                let report = format!("\x1b[{};{}R", self.cursor_row + 1, self.cursor_col + 1);
+                self.replies.extend(report.as_bytes());
+            }
+            CSI::Cursor(c) => {
+                self.wrap_pending = false;
+                self.apply_cursor(c);
+            }
+            CSI::Edit(e) => {
+                // REP carries on from the last character, wrap and all.
+                if !matches!(e, Edit::Repeat(_)) {
+                    self.wrap_pending = false;
+                }
+                self.apply_edit(e);
+            }
+            CSI::Device(device) => match *device {
+                // A VT100 with advanced video: what programs need to hear to
+                // stop waiting, without promising features that aren't here.
+                Device::RequestPrimaryDeviceAttributes => self.replies.extend(b"\x1b[?1;2c"),
+                Device::StatusReport => self.replies.extend(b"\x1b[0n"),
+                _ => {}
+            },
             CSI::Sgr(sgr) => self.apply_sgr(sgr),
             CSI::Mode(mode) => self.apply_mode(mode),
             _ => {}
@@ -318,28 +787,29 @@ impl TermEmulator {
         let cols = self.cols;
         let cr = self.cursor_row;
         let cc = self.cursor_col;
+        let blank = self.blank();
 
         match e {
             Edit::EraseInDisplay(eid) => match eid {
                 EraseInDisplay::EraseToEndOfDisplay => {
                     for col in cc..cols {
-                        self.screen[cr][col] = TermCell::default();
+                        self.screen[cr][col] = blank;
                     }
                     for row in cr + 1..rows {
-                        self.screen[row] = empty_row(cols);
+                        self.screen[row] = vec![blank; cols];
                     }
                 }
                 EraseInDisplay::EraseToStartOfDisplay => {
                     for col in 0..=cc.min(cols.saturating_sub(1)) {
-                        self.screen[cr][col] = TermCell::default();
+                        self.screen[cr][col] = blank;
                     }
                     for row in 0..cr {
-                        self.screen[row] = empty_row(cols);
+                        self.screen[row] = vec![blank; cols];
                     }
                 }
                 EraseInDisplay::EraseDisplay => {
                     for row in &mut self.screen {
-                        *row = empty_row(cols);
+                        *row = vec![blank; cols];
                     }
                 }
                 _ => {}
@@ -347,16 +817,16 @@ impl TermEmulator {
             Edit::EraseInLine(eil) => match eil {
                 EraseInLine::EraseToEndOfLine => {
                     for col in cc..cols {
-                        self.screen[cr][col] = TermCell::default();
+                        self.screen[cr][col] = blank;
                     }
                 }
                 EraseInLine::EraseToStartOfLine => {
                     for col in 0..=cc.min(cols.saturating_sub(1)) {
-                        self.screen[cr][col] = TermCell::default();
+                        self.screen[cr][col] = blank;
                     }
                 }
                 EraseInLine::EraseLine => {
-                    self.screen[cr] = empty_row(cols);
+                    self.screen[cr] = vec![blank; cols];
                 }
             },
             Edit::DeleteLine(n) => {
@@ -368,7 +838,7 @@ impl TermEmulator {
                     if top < bot {
                         let sz = bot - top + 1;
                         self.screen[top..=bot].rotate_left(1);
-                        self.screen[top + sz - 1] = empty_row(cols);
+                        self.screen[top + sz - 1] = vec![blank; cols];
                     }
                 }
                 self.scroll_top = saved_top;
@@ -383,30 +853,40 @@ impl TermEmulator {
             }
             Edit::ScrollUp(n) => self.scroll_up_region(n as usize),
             Edit::ScrollDown(n) => self.scroll_down_region(n as usize),
-            Edit::DeleteCharacter(n) => {
-                if cr < rows {
-                    let row = &mut self.screen[cr];
-                    let start = cc.min(cols);
-                    let count = (n as usize).min(cols.saturating_sub(start));
-                    if count > 0 {
-                        row.drain(start..start + count);
-                        while row.len() < cols {
-                            row.push(TermCell::default());
-                        }
+            Edit::DeleteCharacter(n) if cr < rows => {
+                let row = &mut self.screen[cr];
+                let start = cc.min(cols);
+                let count = (n as usize).min(cols.saturating_sub(start));
+                if count > 0 {
+                    row.drain(start..start + count);
+                    while row.len() < cols {
+                        row.push(blank);
                     }
                 }
             }
-            Edit::InsertCharacter(n) => {
-                if cr < rows {
-                    let row = &mut self.screen[cr];
-                    let start = cc.min(cols);
-                    let count = (n as usize).min(cols.saturating_sub(start));
-                    for _ in 0..count {
-                        row.insert(start, TermCell::default());
-                    }
-                    row.truncate(cols);
+            Edit::EraseCharacter(n) if cr < rows => {
+                let end = (cc + (n as usize).max(1)).min(cols);
+                for col in cc.min(end)..end {
+                    self.screen[cr][col] = blank;
                 }
             }
+            Edit::Repeat(n) => {
+                // At most the cells left on the screen, as xterm does in
+                // effect: a huge count from the host would hang the reader.
+                let cells = (rows * cols).saturating_sub(cr * cols + cc.min(cols));
+                for _ in 0..(n.max(1) as usize).min(cells.max(1)) {
+                    self.print_char(self.last_char);
+                }
+            }
+            Edit::InsertCharacter(n) if cr < rows => {
+                let row = &mut self.screen[cr];
+                let start = cc.min(cols);
+                let count = (n as usize).min(cols.saturating_sub(start));
+                for _ in 0..count {
+                    row.insert(start, blank);
+                }
+                row.truncate(cols);
+            }
             _ => {}
         }
     }
@@ -455,16 +935,30 @@ impl TermEmulator {
                     self.normal_cursor = (self.cursor_row, self.cursor_col);
                     self.screen = vec![empty_row(self.cols); self.rows];
                     self.in_alt_screen = true;
+                    self.wrap_pending = false;
                 } else if !set && self.in_alt_screen {
                     self.screen = self.normal_screen.clone();
                     self.cursor_row = self.normal_cursor.0.min(self.rows.saturating_sub(1));
                     self.cursor_col = self.normal_cursor.1.min(self.cols.saturating_sub(1));
                     self.in_alt_screen = false;
+                    self.wrap_pending = false;
                 }
             }
+            DecPrivateModeCode::ShowCursor => self.cursor_visible = set,
+            DecPrivateModeCode::ApplicationCursorKeys => self.app_cursor = set,
+            DecPrivateModeCode::MouseTracking => self.set_mouse(set, MouseTracking::Clicks),
+            DecPrivateModeCode::ButtonEventMouse => self.set_mouse(set, MouseTracking::Drags),
+            DecPrivateModeCode::AnyEventMouse => self.set_mouse(set, MouseTracking::Motion),
+            DecPrivateModeCode::SGRMouse => self.sgr_mouse = set,
+            DecPrivateModeCode::BracketedPaste => self.bracketed_paste = set,
             _ => {}
         }
     }
+
+    /// Turning any of the tracking modes off stops mouse reports altogether.
+    fn set_mouse(&mut self, set: bool, tracking: MouseTracking) {
+        self.mouse = if set { tracking } else { MouseTracking::Off };
+    }
 }
 
 // ── TerminalTab ───────────────────────────────────────────────────────────────
@@ -474,19 +968,89 @@ pub struct TerminalTab {
     output_log: Arc<Mutex<Vec<String>>>,
     pty_writer: Option<Box<dyn Write + Send>>,
     pty_master: Option<Box<dyn MasterPty>>,
+    /// The session holder ssh runs under, when it has one.
+    #[cfg(unix)]
+    holder: Option<crate::daemon::Client>,
+    /// The native ssh client's session, for the window size.
+    native: Option<ShellInput>,
+    reattached: bool,
+    /// Reply to the program's queries. Off on reattaching until the user
+    /// types, so the replayed output's old queries go unanswered rather
+    /// than land as garbage at whatever prompt is there now.
+    answer_queries: bool,
     alive: Arc<Mutex<bool>>,
     #[allow(dead_code)]
     connection_name: String,
     scroll_offset: usize,
+    scroller: Scroller,
+    copy_on_select: CopyOnSelect,
     selection: Option<(SelPos, SelPos)>,
+    /// Selecting with the keyboard (F8): arrows move the selection's end.
+    marking: bool,
+    /// Searching the scrollback (`/`), while open.
+    find: Option<FindBar>,
+    /// The query is still being typed; once it is, n and N go through the
+    /// matches.
+    find_typing: bool,
+    /// Scroll the current match into view on the next draw.
+    find_jump: bool,
+    /// Spell out state that is otherwise only a color (`[ui] screen_reader`).
+    screen_reader: bool,
     last_inner: Rect,
+    /// Shown in strips across the top of the panel for as long as the
+    /// session is open (e.g. that it is unencrypted, or a guarded host).
+    warnings: Vec<String>,
+    /// How far a connection through jump hosts got, while it connects or
+    /// once it broke; read from the output by the reader thread.
+    route: Arc<Mutex<Option<RouteStatus>>>,
     clipboard: Option<arboard::Clipboard>,
     pub user_locked: bool,
     pub tool_locked: bool,
+    /// When the session was opened, for the header's timer.
+    started: Instant,
 }
 
 impl TerminalTab {
+    /// Start ssh for `conn`, under a session holder where the platform has
+    /// them (reattaching to one left running), else in a PTY of our own.
+    /// The native client runs in-process instead, ending with sheesh.
     pub fn connect(conn: &SSHConnection) -> anyhow::Result<Self> {
+        match &conn.transport {
+            Transport::Ssh => {}
+            Transport::Serial { device, baud } => return Self::open_serial(conn, device, *baud),
+            // Connected on a thread, as ssh connects in its own process.
+            Transport::Telnet { host, port } => {
+                let (output, input) = telnet::connect_later(host, *port, |stream| {
+                    Ok((Box::new(TelnetReader::new(&stream)?), Box::new(TelnetWriter::new(stream))))
+                });
+                return Ok(Self::start(conn, Box::new(output), Box::new(input), None));
+            }
+            Transport::Tcp { host, port } => {
+                let (output, input) = telnet::connect_later(host, *port, |stream| {
+                    Ok((Box::new(stream.try_clone()?), Box::new(TcpWriter(stream))))
+                });
+                return Ok(Self::start(conn, Box::new(output), Box::new(input), None));
+            }
+        }
+        if conn.backend == Backend::Native {
+            let (output, input) = native::shell(conn);
+            let mut tab = Self::start(conn, Box::new(output), Box::new(input.clone()), None);
+            tab.native = Some(input);
+            return Ok(tab);
+        }
+
+        #[cfg(unix)]
+        match crate::daemon::Client::open(conn) {
+            Ok((client, output, running)) => {
+                let mut tab = Self::start(conn, Box::new(output), Box::new(client.try_clone()?), None);
+                tab.holder = Some(client);
+                tab.reattached = running;
+                tab.answer_queries = !running;
+                return Ok(tab);
+            }
+            Err(e) => log::warn!("[terminal] {:#}; running ssh without a session holder", e),
+        }
+
         let pty_system = NativePtySystem::default();
         let pair = pty_system.openpty(PtySize {
             rows: 40,
@@ -502,28 +1066,60 @@ impl TerminalTab {
         let _child = pair.slave.spawn_command(cmd)?;
 
         let master_writer = pair.master.take_writer()?;
-        let mut master_reader = pair.master.try_clone_reader()?;
-        let pty_master = pair.master;
+        let master_reader = pair.master.try_clone_reader()?;
+        Ok(Self::start(conn, master_reader, master_writer, Some(pair.master)))
+    }
+
+    /// A tab on a local serial device. There is no PTY or holder: the device
+    /// has no window size, and the session ends with the tab.
+    fn open_serial(conn: &SSHConnection, device: &str, baud: u32) -> anyhow::Result<Self> {
+        let port = serialport::new(device, baud)
+            .timeout(SERIAL_POLL)
+            .open()
+            .with_context(|| format!("opening {} at {} baud", device, baud))?;
+        let open = Arc::new(AtomicBool::new(true));
+        let reader = SerialReader { port: port.try_clone()?, open: Arc::clone(&open) };
+        let writer = SerialWriter { port, open };
+        Ok(Self::start(conn, Box::new(reader), Box::new(writer), None))
+    }
 
+    /// A tab showing what arrives on `output` and typing into `input`.
+    fn start(
+        conn: &SSHConnection,
+        mut output: Box<dyn Read + Send>,
+        input: Box<dyn Write + Send>,
+        pty_master: Option<Box<dyn MasterPty + Send>>,
+    ) -> Self {
         let emulator = Arc::new(Mutex::new(TermEmulator::new(40, 120)));
         let output_log: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
         let alive: Arc<Mutex<bool>> = Arc::new(Mutex::new(true));
+        let route: Arc<Mutex<Option<RouteStatus>>> = Arc::default();
 
         let emulator_c = Arc::clone(&emulator);
         let log_c = Arc::clone(&output_log);
         let alive_c = Arc::clone(&alive);
+        let route_c = Arc::clone(&route);
 
         thread::spawn(move || {
             let mut buf = [0u8; 8192];
+            let mut log_text = LogText::new();
+            // The start of the output, where ssh says how connecting went.
+            let mut early = String::new();
             loop {
-                match master_reader.read(&mut buf) {
+                match output.read(&mut buf) {
                     Ok(0) | Err(_) => break,
                     Ok(n) => {
                         let data = &buf[..n];
                         emulator_c.lock().unwrap().process(data);
 
-                        let stripped = strip_ansi(data);
+                        let stripped = log_text.strip(data);
                         if !stripped.is_empty() {
+                            if early.len() < ROUTE_TEXT {
+                                early.push_str(&stripped);
+                                if let Some(route) = &mut *route_c.lock().unwrap() {
+                                    route.update(&visible_text(&early), true);
+                                }
+                            }
                             let mut log = log_c.lock().unwrap();
                             log.push(stripped);
                             let len = log.len();
@@ -535,22 +1131,63 @@ impl TerminalTab {
                 }
             }
             *alive_c.lock().unwrap() = false;
+            if let Some(route) = &mut *route_c.lock().unwrap() {
+                route.update(&visible_text(&early), false);
+            }
         });
 
-        Ok(Self {
+        Self {
             emulator,
             output_log,
-            pty_writer: Some(master_writer),
-            pty_master: Some(pty_master),
+            pty_writer: Some(input),
+            pty_master: pty_master.map(|m| m as Box<dyn MasterPty>),
+            #[cfg(unix)]
+            holder: None,
+            native: None,
+            reattached: false,
+            answer_queries: true,
             alive,
             connection_name: conn.name.clone(),
             scroll_offset: 0,
+            scroller: Scroller::default(),
+            copy_on_select: CopyOnSelect::Off,
             selection: None,
+            marking: false,
+            find: None,
+            find_typing: false,
+            find_jump: false,
+            screen_reader: false,
             last_inner: Rect::default(),
+            warnings: conn.transport.insecure_warning().map(String::from).into_iter().collect(),
+            route,
             clipboard: arboard::Clipboard::new().ok(),
             user_locked: false,
             tool_locked: false,
-        })
+            started: Instant::now(),
+        }
+    }
+
+    /// True if this joined a session left running earlier, whose output
+    /// was replayed.
+    pub fn reattached(&self) -> bool {
+        self.reattached
+    }
+
+    /// True if the session can keep running after sheesh quits.
+    pub fn is_detachable(&self) -> bool {
+        #[cfg(unix)]
+        return self.holder.is_some();
+        #[cfg(not(unix))]
+        false
+    }
+
+    /// End the session for good, including its holder. Dropping the tab
+    /// without this leaves a held session running.
+    pub fn close(&mut self) {
+        #[cfg(unix)]
+        if let Some(holder) = &mut self.holder {
+            holder.close();
+        }
     }
 
     pub fn is_alive(&self) -> bool {
@@ -561,13 +1198,18 @@ impl TerminalTab {
         Arc::clone(&self.output_log)
     }
 
+    /// Remote working directory as last reported by the shell, if known.
+    pub fn cwd(&self) -> Option<String> {
+        self.emulator.lock().unwrap().cwd.clone()
+    }
+
     pub fn line_count(&self) -> usize {
         self.output_log.lock().unwrap().len()
     }
 
     pub fn capture_since(&self, from: usize) -> String {
         let log = self.output_log.lock().unwrap();
-        log[from.min(log.len())..].join("")
+        visible_text(&log[from.min(log.len())..].join(""))
     }
 
     pub fn send_string(&mut self, s: &str) {
@@ -575,6 +1217,7 @@ impl TerminalTab {
     }
 
     fn send_bytes(&mut self, bytes: &[u8]) {
+        self.answer_queries = true;
         if let Some(ref mut w) = self.pty_writer {
             let _ = w.write_all(bytes);
             let _ = w.flush();
@@ -582,11 +1225,95 @@ impl TerminalTab {
     }
 
     fn scroll_up(&mut self) {
-        self.scroll_offset += 3;
+        self.scroll_offset = self.scroll_offset.saturating_add(self.scroller.step());
     }
 
     fn scroll_down(&mut self) {
-        self.scroll_offset = self.scroll_offset.saturating_sub(3);
+        self.scroll_offset = self.scroll_offset.saturating_sub(self.scroller.step());
+    }
+
+    /// Apply one mouse-wheel notch.
+    fn scroll_wheel(&mut self, up: bool) {
+        self.scroll_offset = self.scroll_offset.saturating_add_signed(self.scroller.wheel(up));
+    }
+
+    pub fn set_ui_config(&mut self, cfg: &UiConfig) {
+        self.scroller = Scroller::new(cfg);
+        self.copy_on_select = cfg.copy_on_select;
+        self.screen_reader = cfg.screen_reader;
+        let regex = cfg.prompt_regex.as_deref().and_then(|re| match Regex::new(re) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                log::warn!("[terminal] ignoring prompt_regex: {}", e);
+                None
+            }
+        });
+        self.emulator.lock().unwrap().prompt_regex = regex;
+    }
+
+    /// The last finished command and its output, once prompts are known
+    /// (OSC 133 or `prompt_regex`).
+    pub fn last_command_output(&self) -> Option<CommandOutput> {
+        self.emulator.lock().unwrap().last_command_output()
+    }
+
+    /// Whether the shell marks prompts and command ends with OSC 133.
+    pub fn has_shell_integration(&self) -> bool {
+        self.emulator.lock().unwrap().semantic_prompts
+    }
+
+    /// The last command the shell reported an exit status for (OSC 133), and that status.
+    pub fn last_exit(&self) -> Option<(String, i32)> {
+        self.emulator.lock().unwrap().last_exit()
+    }
+
+    /// Commands the shell has reported finished so far (OSC 133 only).
+    pub fn finished_commands(&self) -> usize {
+        self.emulator.lock().unwrap().finished
+    }
+
+    /// Put `text` on the clipboard, e.g. a snippet from the history.
+    pub fn copy_text(&mut self, text: String) {
+        if let Some(ref mut cb) = self.clipboard {
+            clipboard::set_text(cb, text, false);
+        }
+    }
+
+    /// Copy the last command's output to the clipboard.
+    pub fn copy_last_command_output(&mut self) {
+        if let Some(done) = self.last_command_output()
+            && let Some(ref mut cb) = self.clipboard
+        {
+            clipboard::set_text(cb, done.output, false);
+        }
+    }
+
+    /// Whether Shift+Up/Down have command blocks to move between: the shell
+    /// is showing (not a full-screen program) and has marked some.
+    fn has_command_blocks(&self) -> bool {
+        let emu = self.emulator.lock().unwrap();
+        !emu.in_alt_screen && !emu.blocks.is_empty()
+    }
+
+    /// Scroll so the previous (`back`) or next command's prompt is the top
+    /// line of the view.
+    fn jump_to_command(&mut self, back: bool) {
+        let height = self.last_inner.height as usize;
+        let emu = self.emulator.lock().unwrap();
+        let total = emu.scrollback.len() + emu.rows;
+        let top = total.saturating_sub(height + self.scroll_offset);
+        let mut prompts = emu.blocks.iter().filter_map(|b| emu.line_index(b.prompt));
+        let target = if back { prompts.rfind(|&i| i < top) } else { prompts.find(|&i| i > top) };
+        match target {
+            Some(index) => self.scroll_offset = total.saturating_sub(height + index),
+            None if !back => self.scroll_offset = 0,
+            None => {}
+        }
+    }
+
+    /// Rows moved by Shift+PageUp/PageDown: a screenful less one line.
+    fn page_rows(&self) -> usize {
+        (self.last_inner.height as usize).saturating_sub(1).max(1)
     }
 
     pub fn is_locked(&self) -> bool {
@@ -623,7 +1350,7 @@ impl TerminalTab {
         }
     }
 
-    fn selected_text(&self) -> Option<String> {
+    pub fn selected_text(&self) -> Option<String> {
         let (start, end) = self.selection_range()?;
         let emu = self.emulator.lock().unwrap();
         let sb_len = emu.scrollback.len();
@@ -661,36 +1388,399 @@ impl TerminalTab {
         }
     }
 
-    fn copy_selection(&mut self) {
+    pub fn copy_selection(&mut self) {
+        self.copy_selection_to(false);
+    }
+
+    /// The screen cell under the start of the selection's last row, kept
+    /// within the panel: where to show something about the selection.
+    pub fn selection_anchor(&self) -> Option<(u16, u16)> {
+        let (start, end) = self.selection_range()?;
+        let emu = self.emulator.lock().unwrap();
+        let inner = self.last_inner;
+        let total = emu.scrollback.len() + emu.rows;
+        let first_visible = total.saturating_sub(inner.height as usize + self.scroll_offset);
+        let last_row = inner.height.saturating_sub(1) as usize;
+        let row = end.0.saturating_sub(first_visible).min(last_row) as u16;
+        let col = if start.0 == end.0 { start.1 } else { 0 };
+        Some((inner.x + col.min(inner.width.saturating_sub(1)), inner.y + row + 1))
+    }
+
+    /// F8: start a keyboard selection at the cursor (or the top of the view
+    /// when scrolled back), or stop moving it and keep what is selected.
+    fn toggle_marking(&mut self) {
+        if std::mem::take(&mut self.marking) {
+            return;
+        }
+        let start = if self.scroll_offset == 0 {
+            let emu = self.emulator.lock().unwrap();
+            Some((emu.scrollback.len() + emu.cursor_row, emu.cursor_col as u16))
+        } else {
+            self.screen_to_sel_pos(0, 0)
+        };
+        self.selection = start.map(|pos| (pos, pos));
+        self.marking = start.is_some();
+    }
+
+    /// Apply a key while selecting with the keyboard: arrows, Home/End and
+    /// PageUp/PageDown move the end, Enter (or `y`, Ctrl+C) copies, Esc
+    /// drops the selection. Other keys are ignored.
+    fn mark_key(&mut self, code: KeyCode, ctrl: bool) {
+        let Some((anchor, (mut row, mut col))) = self.selection else {
+            self.marking = false;
+            return;
+        };
+        let (total, cols) = {
+            let emu = self.emulator.lock().unwrap();
+            (emu.scrollback.len() + emu.rows, emu.cols as u16)
+        };
+        let last = total.saturating_sub(1);
+        match code {
+            KeyCode::Left if col > 0 => col -= 1,
+            KeyCode::Left if row > 0 => (row, col) = (row - 1, cols),
+            KeyCode::Right if col < cols => col += 1,
+            KeyCode::Right if row < last => (row, col) = (row + 1, 0),
+            KeyCode::Up => row = row.saturating_sub(1),
+            KeyCode::Down => row = (row + 1).min(last),
+            KeyCode::PageUp => row = row.saturating_sub(self.page_rows()),
+            KeyCode::PageDown => row = (row + self.page_rows()).min(last),
+            KeyCode::Home => col = 0,
+            KeyCode::End => col = cols,
+            KeyCode::Enter | KeyCode::Char('y') => {
+                self.copy_selection();
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            KeyCode::Char('c') if ctrl => {
+                self.copy_selection();
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            KeyCode::Esc => {
+                (self.selection, self.marking) = (None, false);
+                return;
+            }
+            _ => return,
+        }
+        self.selection = Some((anchor, (row, col)));
+
+        // Keep the moving end on screen.
+        let height = self.last_inner.height as usize;
+        let first_visible = total.saturating_sub(height + self.scroll_offset);
+        if row < first_visible {
+            self.scroll_offset = total.saturating_sub(height + row);
+        } else if row >= first_visible + height {
+            self.scroll_offset = total.saturating_sub(row + 1);
+        }
+    }
+
+    /// Start searching the scrollback, or go back to typing the query.
+    pub fn open_find(&mut self) {
+        self.find.get_or_insert_with(FindBar::default);
+        self.find_typing = true;
+    }
+
+    /// Apply a key to the open search; false when it isn't one of its keys.
+    /// While the query is typed, Enter finishes it (Esc drops the search);
+    /// then n goes to the previous (older) match and N to the next one.
+    fn find_key(&mut self, key: &KeyEvent) -> bool {
+        let Some(find) = &mut self.find else {
+            return false;
+        };
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        if self.find_typing {
+            match key.code {
+                KeyCode::Enter if !key.modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.find_typing = false;
+                    if find.query.is_empty() {
+                        self.find = None;
+                    }
+                }
+                _ => match find.handle_key(key) {
+                    Some(FindKey::Close) => self.find = None,
+                    Some(FindKey::Edited | FindKey::Moved) => self.find_jump = true,
+                    None => {}
+                },
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('n') => find.step(false),
+            KeyCode::Char('N') => find.step(true),
+            KeyCode::Char('/') => self.find_typing = true,
+            KeyCode::Esc => self.find = None,
+            _ => return false,
+        }
+        self.find_jump = true;
+        true
+    }
+
+    /// See [`TermEmulator::all_rows`].
+    pub fn all_rows(&self) -> (Vec<Line<'static>>, u16) {
+        self.emulator.lock().unwrap().all_rows()
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selection.is_some_and(|(a, b)| a != b)
+    }
+
+    /// The scrollback and screen as plain text, one string per row, without
+    /// the blank rows below the last output; for the plain view (F9).
+    pub fn plain_text(&self) -> Vec<String> {
+        let emu = self.emulator.lock().unwrap();
+        let mut rows: Vec<String> =
+            emu.scrollback.iter().chain(emu.screen.iter()).map(|row| row_text(row, 0, row.len())).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        rows
+    }
+
+    /// Size the PTY and emulator to a panel at `area`, if that changes
+    /// them. Done on every draw, and straight away when the window is
+    /// resized so the remote program can redraw before the next frame.
+    pub fn fit(&mut self, area: Rect) {
+        let mut inner = Block::bordered().inner(area);
+        // The strips, leaving at least a row for the terminal.
+        let route = self.route.lock().unwrap().as_ref().is_some_and(|r| r.summary().is_some());
+        let strips = ((self.warnings.len() + route as usize) as u16).min(inner.height.saturating_sub(1));
+        inner.y += strips;
+        inner.height -= strips;
+        if inner == self.last_inner {
+            return;
+        }
+        self.last_inner = inner;
+        let rows = inner.height.max(1) as usize;
+        let cols = inner.width.max(1) as usize;
+        if let Some(ref master) = self.pty_master {
+            let _ = master.resize(PtySize {
+                rows: rows as u16,
+                cols: cols as u16,
+                pixel_width: 0,
+                pixel_height: 0,
+            });
+        }
+        #[cfg(unix)]
+        if let Some(holder) = &mut self.holder {
+            holder.resize(rows as u16, cols as u16);
+        }
+        if let Some(native) = &self.native {
+            native.resize(rows as u16, cols as u16);
+        }
+        let mut emu = self.emulator.lock().unwrap();
+        emu.resize(rows, cols);
+        self.scroll_offset = self.scroll_offset.min(emu.scrollback.len());
+    }
+
+    /// How long the session has been open.
+    pub fn open_for(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// What the panel's strips say: the route while connecting, then the
+    /// warnings.
+    pub fn warnings(&self) -> Vec<String> {
+        let route = self.route.lock().unwrap().as_ref().and_then(RouteStatus::summary);
+        route.into_iter().chain(self.warnings.iter().cloned()).collect()
+    }
+
+    /// Follow connecting through jump hosts hop by hop, from what ssh
+    /// prints; over the terminal until the far end answers.
+    pub fn set_route(&mut self, mut route: RouteStatus) {
+        let early = self.output_log.lock().unwrap().concat();
+        let mut shared = self.route.lock().unwrap();
+        route.update(&visible_text(&early), self.is_alive());
+        *shared = Some(route);
+    }
+
+    /// Add a strip to the top of the panel for the rest of the session.
+    pub fn add_warning(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+        // Refit on the next draw.
+        self.last_inner = Rect::default();
+    }
+
+    /// Rows scrolled back from the bottom.
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    /// Drop the scrollback and output log and have the shell redraw its
+    /// prompt (ctrl+l).
+    /// A marker row takes the place of what was cleared, so the loss is
+    /// visible when scrolling back, and in the assistant's terminal reads.
+    pub fn clear_buffer(&mut self) {
+        let marker = {
+            let mut emu = self.emulator.lock().unwrap();
+            let (rows, cols) = (emu.rows, emu.cols);
+            let on_screen = emu.screen.iter().rposition(|row| row.iter().any(|c| c.ch != ' ')).map_or(0, |i| i + 1);
+            let cleared = emu.dropped + emu.scrollback.len() - usize::from(emu.dropped > 0) + on_screen;
+            let (cwd, from_osc7) = (emu.cwd.take(), emu.cwd_from_osc7);
+            let (prompt_regex, semantic_prompts, finished) = (emu.prompt_regex.take(), emu.semantic_prompts, emu.finished);
+            *emu = TermEmulator::new(rows, cols);
+            emu.cwd = cwd;
+            emu.cwd_from_osc7 = from_osc7;
+            emu.prompt_regex = prompt_regex;
+            emu.semantic_prompts = semantic_prompts;
+            emu.finished = finished;
+            let marker = (cleared > 0).then(|| format!("--- {} lines dropped (buffer cleared) ---", cleared));
+            emu.scrollback.extend(marker.iter().map(|m| marker_row(m, cols)));
+            emu.pushed = emu.scrollback.len();
+            marker
+        };
+        let mut log = self.output_log.lock().unwrap();
+        log.clear();
+        log.extend(marker.map(|m| m + "\n"));
+        drop(log);
+        self.scroll_offset = 0;
+        self.selection = None;
+        self.send_bytes(&[0x0c]);
+    }
+
+    /// Copy the selection to the clipboard, or to the primary selection.
+    fn copy_selection_to(&mut self, primary: bool) {
         if let Some(text) = self.selected_text()
             && let Some(ref mut cb) = self.clipboard
         {
-            let _ = cb.set_text(text);
+            clipboard::set_text(cb, text, primary);
         }
     }
 
-    fn paste_from_clipboard(&mut self) {
+    pub fn paste_from_clipboard(&mut self) {
+        self.paste_from(false);
+    }
+
+    /// Paste the clipboard, or the primary selection, into the PTY.
+    /// Bracketed when the remote program asked for it, so a pasted newline
+    /// is not taken as Enter.
+    fn paste_from(&mut self, primary: bool) {
         if let Some(ref mut cb) = self.clipboard
-            && let Ok(text) = cb.get_text()
+            && let Some(text) = clipboard::get_text(cb, primary)
         {
+            self.paste_text(&text);
+        }
+    }
+
+    /// Put `text` into the PTY as a paste, bracketed when the remote program
+    /// asked for it.
+    pub fn paste_text(&mut self, text: &str) {
+        if self.emulator.lock().unwrap().bracketed_paste {
+            // The end marker inside the text would end the paste early.
+            let text = text.replace("\x1b[201~", "");
+            self.send_bytes(format!("\x1b[200~{}\x1b[201~", text).as_bytes());
+        } else {
             self.send_bytes(text.as_bytes());
         }
     }
+
+    /// An arrow, Home or End key, ending in `last`, in the form the remote
+    /// program has asked for.
+    fn send_cursor_key(&mut self, last: u8) {
+        let app = self.emulator.lock().unwrap().app_cursor;
+        self.send_bytes(&[0x1b, if app { b'O' } else { b'[' }, last]);
+    }
+
+    /// Send `me` to the remote program if it has turned mouse reporting on;
+    /// returns true if the program took it. Holding Shift keeps the mouse
+    /// for selecting text, as in xterm.
+    fn report_mouse(&mut self, me: &MouseEvent) -> bool {
+        let inner = self.last_inner;
+        if me.modifiers.contains(KeyModifiers::SHIFT)
+            || self.scroll_offset > 0
+            || self.is_locked()
+            || !inner.contains((me.column, me.row).into())
+        {
+            return false;
+        }
+        let (tracking, sgr) = {
+            let emu = self.emulator.lock().unwrap();
+            (emu.mouse, emu.sgr_mouse)
+        };
+        let button_code = |b: MouseButton| match b {
+            MouseButton::Left => 0,
+            MouseButton::Middle => 1,
+            MouseButton::Right => 2,
+        };
+        let (button, release) = match me.kind {
+            _ if tracking == MouseTracking::Off => return false,
+            MouseEventKind::Down(b) => (button_code(b), false),
+            MouseEventKind::Up(b) => (button_code(b), true),
+            MouseEventKind::Drag(b) if tracking >= MouseTracking::Drags => (button_code(b) + 32, false),
+            MouseEventKind::Moved if tracking == MouseTracking::Motion => (35, false),
+            MouseEventKind::ScrollUp => (64, false),
+            MouseEventKind::ScrollDown => (65, false),
+            // Not asked for, but not ours either: a drag would select
+            // text under the program.
+            _ => return true,
+        };
+        let mut mods = 0;
+        if me.modifiers.contains(KeyModifiers::ALT) {
+            mods += 8;
+        }
+        if me.modifiers.contains(KeyModifiers::CONTROL) {
+            mods += 16;
+        }
+        let (x, y) = (me.column - inner.x + 1, me.row - inner.y + 1);
+        if sgr {
+            let end = if release { 'm' } else { 'M' };
+            self.send_bytes(format!("\x1b[<{};{};{}{}", button + mods, x, y, end).as_bytes());
+        } else {
+            // X10 bytes: a release doesn't say which button, and positions
+            // past 223 can't be encoded.
+            let button = if release { 3 } else { button } + mods;
+            let (x, y) = (x.min(223) as u8, y.min(223) as u8);
+            self.send_bytes(&[0x1b, b'[', b'M', 32 + button, 32 + x, 32 + y]);
+        }
+        true
+    }
 }
 
 impl Tab for TerminalTab {
     fn key_hints(&self) -> Vec<(&str, &str)> {
-        vec![("ctrl+d", "disconnect")]
+        if self.marking {
+            return vec![("arrows", "extend selection"), ("enter", "copy"), ("F8", "keep selection"), ("esc", "cancel")];
+        }
+        if self.find.is_some() && self.find_typing {
+            return vec![("enter", "done"), ("↑/↓", "previous/next"), ("esc", "close")];
+        }
+        if self.find.is_some() {
+            return vec![("n", "previous match"), ("N", "next match"), ("/", "edit search"), ("esc", "close")];
+        }
+        let mut hints = vec![("ctrl+d", "disconnect"), ("F8", "select")];
+        if self.scroll_offset > 0 {
+            hints.push(("/", "search"));
+        }
+        if self.has_selection() {
+            hints.push(("F5", "explain selection"));
+        }
+        hints
     }
 
     fn handle_event(&mut self, event: &Event) -> Action {
         match event {
-            Event::Key(KeyEvent {
+            Event::Key(key @ KeyEvent {
                 code, modifiers, ..
             }) => {
                 let ctrl = modifiers.contains(KeyModifiers::CONTROL);
                 let shift = modifiers.contains(KeyModifiers::SHIFT);
 
+                // F8 — select text with the keyboard; while selecting, the
+                // keys move the selection instead of reaching the PTY.
+                if *code == KeyCode::F(8) {
+                    self.toggle_marking();
+                    return Action::None;
+                }
+                if self.marking {
+                    self.mark_key(*code, ctrl);
+                    return Action::None;
+                }
+                // While searching, its keys go to the search.
+                if self.find_key(key) {
+                    return Action::None;
+                }
+
                 match code {
                     // ── Always-active keys ──────────────────────────────────
                     KeyCode::Char('d') if ctrl => return Action::Disconnect,
@@ -703,6 +1793,40 @@ impl Tab for TerminalTab {
                         self.scroll_down();
                         return Action::None;
                     }
+                    // Plain PageUp/PageDown/Home/End belong to the remote
+                    // program; with Shift or Ctrl they move the scrollback.
+                    KeyCode::PageUp if shift => {
+                        self.scroll_offset = self.scroll_offset.saturating_add(self.page_rows());
+                        return Action::None;
+                    }
+                    KeyCode::PageDown if shift => {
+                        self.scroll_offset = self.scroll_offset.saturating_sub(self.page_rows());
+                        return Action::None;
+                    }
+                    // Shift+Up/Down walk the command blocks of the shell;
+                    // full-screen programs and block-less sessions get them.
+                    KeyCode::Up if shift && self.has_command_blocks() => {
+                        self.jump_to_command(true);
+                        return Action::None;
+                    }
+                    KeyCode::Down if shift && self.has_command_blocks() => {
+                        self.jump_to_command(false);
+                        return Action::None;
+                    }
+                    KeyCode::Home if ctrl => {
+                        self.scroll_offset = self.emulator.lock().unwrap().scrollback.len();
+                        return Action::None;
+                    }
+                    KeyCode::End if ctrl => {
+                        self.scroll_offset = 0;
+                        return Action::None;
+                    }
+                    // `/` searches the scrollback when it is being read;
+                    // at the bottom it is typed as usual.
+                    KeyCode::Char('/') if self.scroll_offset > 0 && !ctrl => {
+                        self.open_find();
+                        return Action::None;
+                    }
 
                     // ── Blocked when locked ─────────────────────────────────
                     _ if self.is_locked() => return Action::None,
@@ -721,21 +1845,14 @@ impl Tab for TerminalTab {
                         return Action::None;
                     }
                     KeyCode::Char('l') if ctrl => {
-                        {
-                            let mut emu = self.emulator.lock().unwrap();
-                            let (rows, cols) = (emu.rows, emu.cols);
-                            *emu = TermEmulator::new(rows, cols);
-                        }
-                        self.output_log.lock().unwrap().clear();
-                        self.scroll_offset = 0;
-                        self.selection = None;
-                        self.send_bytes(&[0x0c]);
+                        self.clear_buffer();
                         return Action::None;
                     }
 
                     // ── PTY passthrough ─────────────────────────────────────
                     _ => {
                         self.scroll_offset = 0;
+                        self.find = None;
                         match code {
                             KeyCode::Char(ch) => {
                                 let mut bytes = [0u8; 4];
@@ -751,12 +1868,14 @@ impl Tab for TerminalTab {
                             KeyCode::Backspace => self.send_bytes(b"\x7f"),
                             KeyCode::Tab => self.send_bytes(b"\t"),
                             KeyCode::Esc => self.send_bytes(b"\x1b"),
-                            KeyCode::Left => self.send_bytes(b"\x1b[D"),
-                            KeyCode::Right => self.send_bytes(b"\x1b[C"),
-                            KeyCode::Up => self.send_bytes(b"\x1b[A"),
-                            KeyCode::Down => self.send_bytes(b"\x1b[B"),
-                            KeyCode::Home => self.send_bytes(b"\x1b[H"),
-                            KeyCode::End => self.send_bytes(b"\x1b[F"),
+                            KeyCode::Left => self.send_cursor_key(b'D'),
+                            KeyCode::Right => self.send_cursor_key(b'C'),
+                            KeyCode::Up if shift => self.send_bytes(b"\x1b[1;2A"),
+                            KeyCode::Down if shift => self.send_bytes(b"\x1b[1;2B"),
+                            KeyCode::Up => self.send_cursor_key(b'A'),
+                            KeyCode::Down => self.send_cursor_key(b'B'),
+                            KeyCode::Home => self.send_cursor_key(b'H'),
+                            KeyCode::End => self.send_cursor_key(b'F'),
                             KeyCode::Delete => self.send_bytes(b"\x1b[3~"),
                             KeyCode::PageUp => self.send_bytes(b"\x1b[5~"),
                             KeyCode::PageDown => self.send_bytes(b"\x1b[6~"),
@@ -768,19 +1887,21 @@ impl Tab for TerminalTab {
             }
 
             Event::Mouse(me) => {
+                if self.report_mouse(me) {
+                    return Action::None;
+                }
                 let inner = self.last_inner;
                 match me.kind {
-                    MouseEventKind::Down(MouseButton::Left) => {
+                    MouseEventKind::Down(MouseButton::Left)
                         if me.row >= inner.y
                             && me.row < inner.y + inner.height
                             && me.column >= inner.x
-                            && me.column < inner.x + inner.width
-                        {
-                            let sc = me.column - inner.x;
-                            let sr = me.row - inner.y;
-                            if let Some(pos) = self.screen_to_sel_pos(sc, sr) {
-                                self.selection = Some((pos, pos));
-                            }
+                            && me.column < inner.x + inner.width =>
+                    {
+                        let sc = me.column - inner.x;
+                        let sr = me.row - inner.y;
+                        if let Some(pos) = self.screen_to_sel_pos(sc, sr) {
+                            self.selection = Some((pos, pos));
                         }
                     }
                     MouseEventKind::Drag(MouseButton::Left) => {
@@ -799,10 +1920,19 @@ impl Tab for TerminalTab {
                             && a == b
                         {
                             self.selection = None;
+                        } else if self.selection.is_some() && self.copy_on_select != CopyOnSelect::Off {
+                            self.copy_selection_to(self.copy_on_select == CopyOnSelect::Primary);
                         }
                     }
-                    MouseEventKind::ScrollUp => self.scroll_up(),
-                    MouseEventKind::ScrollDown => self.scroll_down(),
+                    // Middle click pastes the primary selection, X11 style.
+                    MouseEventKind::Down(MouseButton::Middle)
+                        if self.copy_on_select == CopyOnSelect::Primary && !self.is_locked() =>
+                    {
+                        self.scroll_offset = 0;
+                        self.paste_from(true);
+                    }
+                    MouseEventKind::ScrollUp => self.scroll_wheel(true),
+                    MouseEventKind::ScrollDown => self.scroll_wheel(false),
                     _ => {}
                 }
                 Action::None
@@ -820,11 +1950,26 @@ impl Tab for TerminalTab {
         };
 
         let status = if self.is_alive() {
-            Span::styled(" ● ", Theme::key_hint_key())
+            Span::styled(if self.screen_reader { " ● connected " } else { " ● " }, Theme::key_hint_key())
         } else {
             Span::styled(" ○ disconnected ", Theme::error())
         };
 
+        // Exit status of the last command, when the shell reports it (OSC 133).
+        let exit_span = match self.emulator.lock().unwrap().last_exit() {
+            Some((_, status)) => {
+                let (text, style) = exit_badge(status);
+                Span::styled(format!(" {} ", text), style)
+            }
+            None => Span::raw(""),
+        };
+
+        let mark_span = if self.marking {
+            Span::styled(" selecting ", Theme::highlight())
+        } else {
+            Span::raw("")
+        };
+
         let lock_span = if self.user_locked {
             Span::styled(" 🔒 locked ", Theme::error())
         } else if self.tool_locked {
@@ -833,46 +1978,76 @@ impl Tab for TerminalTab {
             Span::raw("")
         };
 
-        let block = Block::bordered()
+        let mut block = Block::bordered()
             .border_type(BorderType::Rounded)
             .border_style(border_style)
             .title(Line::from(vec![
-                Span::styled(" Terminal ", Theme::title()),
+                Span::styled(if focused && self.screen_reader { " Terminal (focused) " } else { " Terminal " }, Theme::title()),
                 status,
+                exit_span,
+                mark_span,
                 lock_span,
             ]));
+        if let Some(find) = &self.find {
+            let cursor = if self.find_typing { "_" } else { "" };
+            let text = format!(" /{}{}{} ", find.query, cursor, find.tally());
+            block = block.title_bottom(Span::styled(text, Theme::key_hint_key()));
+        }
 
-        let inner = block.inner(area);
         frame.render_widget(block, area);
-
-        // Resize PTY and emulator when the visible area changes.
-        if inner != self.last_inner {
-            let rows = inner.height.max(1) as usize;
-            let cols = inner.width.max(1) as usize;
-            if let Some(ref master) = self.pty_master {
-                let _ = master.resize(PtySize {
-                    rows: rows as u16,
-                    cols: cols as u16,
-                    pixel_width: 0,
-                    pixel_height: 0,
-                });
-            }
-            self.emulator.lock().unwrap().resize(rows, cols);
+        self.fit(area);
+        let inner = self.last_inner;
+        // `fit` leaves the strips the first rows, as many as there is room for.
+        let top = Block::bordered().inner(area).y;
+        let style = Theme::error().add_modifier(Modifier::REVERSED);
+        let route = self.route.lock().unwrap().clone();
+        let route = route.as_ref().and_then(|r| {
+            let style = if let Progress::Broken { .. } = r.progress { style } else { Theme::highlight() };
+            Some((r.summary()?, style))
+        });
+        let warnings = self.warnings.iter().map(|w| (w.clone(), style));
+        for (i, (text, style)) in route.into_iter().chain(warnings).enumerate().take((inner.y - top) as usize) {
+            let strip = Rect { y: top + i as u16, height: 1, ..inner };
+            frame.render_widget(Paragraph::new(Span::styled(text, style)), strip);
         }
-        self.last_inner = inner;
 
         let visible_height = inner.height as usize;
         let sel = self.selection_range();
 
+        let replies = std::mem::take(&mut self.emulator.lock().unwrap().replies);
+        if !replies.is_empty() && self.answer_queries {
+            self.send_bytes(&replies);
+        }
+
         let (display, cursor_screen_pos): (Vec<Line>, Option<(u16, u16)>) = {
             let emu = self.emulator.lock().unwrap();
             let sb_len = emu.scrollback.len();
             let total = sb_len + emu.rows;
 
+            // Bring the current match to the upper third of the view.
+            if let Some(find) = &mut self.find {
+                let texts: Vec<String> =
+                    emu.scrollback.iter().chain(emu.screen.iter()).map(|row| row_text(row, 0, row.len())).collect();
+                find.update(texts.iter().map(String::as_str));
+                if std::mem::take(&mut self.find_jump)
+                    && let Some(line) = find.current_line()
+                {
+                    self.scroll_offset = total.saturating_sub(visible_height + line.saturating_sub(visible_height / 3));
+                }
+            }
+            let current_match = self.find.as_ref().and_then(FindBar::current_line);
+
             let max_scroll = total.saturating_sub(visible_height);
             self.scroll_offset = self.scroll_offset.min(max_scroll);
             let first_visible = total.saturating_sub(visible_height + self.scroll_offset);
 
+            // Exit-status badges for the finished commands' prompt rows.
+            let badges: Vec<(usize, i32)> = emu
+                .blocks
+                .iter()
+                .filter_map(|b| Some((emu.line_index(b.prompt)?, b.exit_status?)))
+                .collect();
+
             let mut display: Vec<Line<'static>> = Vec::with_capacity(visible_height);
             for vis_row in 0..visible_height {
                 let abs_row = first_visible + vis_row;
@@ -891,12 +2066,21 @@ impl Tab for TerminalTab {
                         continue;
                     }
                 };
-                display.push(render_term_row(row_data, abs_row, sel));
+                let found = match &self.find {
+                    Some(find) => found_columns(find, row_data, current_match == Some(abs_row)),
+                    None => vec![],
+                };
+                let mut line = render_term_row(row_data, abs_row, sel, &found);
+                if let Some((_, status)) = badges.iter().find(|(i, _)| *i == abs_row) {
+                    add_badge(&mut line, *status, inner.width as usize);
+                }
+                display.push(line);
             }
 
             // Compute cursor screen position.
             let abs_cursor = sb_len + emu.cursor_row;
-            let cursor_pos = if abs_cursor >= first_visible
+            let cursor_pos = if emu.cursor_visible
+                && abs_cursor >= first_visible
                 && abs_cursor < first_visible + visible_height
                 && emu.cursor_col < emu.cols
             {
@@ -928,6 +2112,67 @@ fn empty_row(cols: usize) -> TermRow {
     vec![TermCell::default(); cols]
 }
 
+/// `c` in the DEC special graphics set, which programs switch to for boxes
+/// (`ESC ( 0`, mc and dialog's borders, tmux's without UTF-8).
+fn line_drawing_char(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        '_' => ' ',
+        c => c,
+    }
+}
+
+/// Badge text and style for a command's exit status.
+fn exit_badge(status: i32) -> (String, Style) {
+    match status {
+        0 => ("✓".to_string(), Theme::success()),
+        n => (format!("✗ {}", n), Theme::error()),
+    }
+}
+
+/// Right-align the exit badge on a prompt row, if the row leaves room.
+fn add_badge(line: &mut Line<'static>, status: i32, width: usize) {
+    let (text, style) = exit_badge(status);
+    let badge = Span::styled(text, style);
+    let used = line.width();
+    if used + badge.width() + 2 <= width {
+        line.spans.push(Span::raw(" ".repeat(width - used - badge.width() - 1)));
+        line.spans.push(badge);
+    }
+}
+
+/// A dimmed row reading `text`, for markers such as dropped lines.
+fn marker_row(text: &str, cols: usize) -> TermRow {
+    let style = CellStyle { dim: true, ..CellStyle::default() };
+    let mut row: TermRow = text.chars().map(|ch| TermCell { ch, style }).collect();
+    row.resize(cols.max(row.len()), TermCell::default());
+    row
+}
+
 fn resize_grid(grid: &mut Vec<TermRow>, rows: usize, cols: usize) {
     grid.resize(rows, empty_row(cols));
     for row in grid.iter_mut() {
@@ -993,34 +2238,59 @@ fn cell_style_to_ratatui(style: &CellStyle) -> Style {
     s
 }
 
-fn render_term_row(row: &TermRow, abs_row: usize, sel: Option<(SelPos, SelPos)>) -> Line<'static> {
+/// The columns of `row` where the search's query is, with how to highlight
+/// them.
+fn found_columns(find: &FindBar, row: &TermRow, current: bool) -> Vec<(usize, usize, Style)> {
+    let text = row_text(row, 0, row.len());
+    // A cell holds one char, so a char's index in the text is its column.
+    let column = |byte: usize| text[..byte].chars().count();
+    let style = FindBar::match_style(current);
+    find.ranges(&text).into_iter().map(|(a, b)| (column(a), column(b), style)).collect()
+}
+
+/// `row` as a line, with the selection over the `found` search matches
+/// (column ranges and their style) over the cells' own style. Matches in
+/// the selection stay bold and underlined.
+fn render_term_row(
+    row: &TermRow,
+    abs_row: usize,
+    sel: Option<(SelPos, SelPos)>,
+    found: &[(usize, usize, Style)],
+) -> Line<'static> {
     let sel_style = Style::default().bg(Color::White).fg(Color::Black);
-    let mut spans: Vec<Span<'static>> = Vec::new();
-    let mut cur_text = String::new();
+    let mut spans: Vec<Span<'static>> = Vec::with_capacity(8);
+    let mut cur_text = String::with_capacity(row.len());
     let mut cur_style = Style::default();
+    // Neighbouring cells mostly share a style; convert it once per run.
+    let mut last_cell = CellStyle::default();
+    let mut last_style = Style::default();
 
     for (col, cell) in row.iter().enumerate() {
-        let style = if in_sel(abs_row, col as u16, sel) {
-            sel_style
-        } else {
-            cell_style_to_ratatui(&cell.style)
+        let found_style = found.iter().find(|(a, b, _)| (*a..*b).contains(&col)).map(|(_, _, style)| *style);
+        let style = match (in_sel(abs_row, col as u16, sel), found_style) {
+            (true, None) => sel_style,
+            (true, Some(_)) => sel_style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            (false, found_style) => {
+                if cell.style != last_cell {
+                    last_cell = cell.style;
+                    last_style = cell_style_to_ratatui(&cell.style);
+                }
+                found_style.map_or(last_style, |found| last_style.patch(found))
+            }
         };
-        if style == cur_style {
-            cur_text.push(cell.ch);
-        } else {
+        if style != cur_style {
             if !cur_text.is_empty() {
-                spans.push(Span::styled(cur_text.clone(), cur_style));
+                let rest = String::with_capacity(row.len() - col);
+                spans.push(Span::styled(std::mem::replace(&mut cur_text, rest), cur_style));
             }
-            cur_text = cell.ch.to_string();
             cur_style = style;
         }
+        cur_text.push(cell.ch);
     }
     // Only trim trailing spaces from the last span so column alignment is preserved.
+    cur_text.truncate(cur_text.trim_end_matches(' ').len());
     if !cur_text.is_empty() {
-        let trimmed = cur_text.trim_end_matches(' ').to_string();
-        if !trimmed.is_empty() {
-            spans.push(Span::styled(trimmed, cur_style));
-        }
+        spans.push(Span::styled(cur_text, cur_style));
     }
     Line::from(spans)
 }
@@ -1040,43 +2310,170 @@ fn in_sel(abs_row: usize, col: u16, sel: Option<(SelPos, SelPos)>) -> bool {
         && (abs_row < e.0 || (abs_row == e.0 && col < e.1))
 }
 
-fn strip_ansi(data: &[u8]) -> String {
-    let s = String::from_utf8_lossy(data);
-    let mut out = String::with_capacity(s.len());
-    let mut chars = s.chars().peekable();
-    while let Some(ch) = chars.next() {
-        match ch {
-            '\x1b' => match chars.peek() {
-                Some('[') => {
-                    chars.next();
-                    for c in chars.by_ref() {
-                        if c.is_ascii_alphabetic() {
-                            break;
-                        }
-                    }
-                }
-                Some(']') => {
-                    chars.next();
-                    loop {
-                        match chars.next() {
-                            Some('\x07') | None => break,
-                            Some('\x1b') => {
-                                if chars.peek() == Some(&'\\') {
-                                    chars.next();
-                                }
-                                break;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {
-                    chars.next();
-                }
-            },
-            '\r' => {}
-            c => out.push(c),
+/// Extract the path from an OSC 7 `file://host/path` URL, percent-decoding it.
+fn cwd_from_osc7(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = path.get(i + 1..i + 3)
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+        {
+            out.push(b);
+            i += 3;
+            continue;
         }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Extract the cwd from a `user@host: path` window title.
+fn cwd_from_title(title: &str) -> Option<String> {
+    let (who, path) = title.split_once(": ")?;
+    let path = path.trim();
+    if !who.contains('@') || !(path.starts_with('/') || path.starts_with('~')) {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+/// Session output with its escape sequences removed, for the output log.
+/// Keeps its own parser, so a sequence or UTF-8 character split across two
+/// reads is still recognised rather than leaking into the text.
+struct LogText {
+    parser: EscapeParser,
+}
+
+impl LogText {
+    fn new() -> Self {
+        Self { parser: EscapeParser::new() }
+    }
+
+    fn strip(&mut self, data: &[u8]) -> String {
+        let mut out = String::with_capacity(data.len());
+        self.parser.parse(data, |action| match action {
+            TwAction::Print(c) => out.push(c),
+            TwAction::PrintString(s) => out.push_str(&s),
+            // Carriage returns are kept so redrawn lines (spinners,
+            // progress bars) can be resolved by `visible_text`.
+            TwAction::Control(
+                cc @ (ControlCode::LineFeed
+                | ControlCode::CarriageReturn
+                | ControlCode::HorizontalTab
+                | ControlCode::Backspace),
+            ) => out.push(cc as u8 as char),
+            _ => {}
+        });
+        out
+    }
+}
+
+/// Log text as it ended up on screen: a carriage return not followed by a
+/// newline rewinds the line, so only what was written after the last one
+/// is kept, and `\r\n` line endings become `\n`.
+pub fn visible_text(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for (i, line) in raw.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let line = line.trim_end_matches('\r');
+        out.push_str(line.rsplit('\r').next().unwrap_or(line));
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_cwd_comes_from_osc_7_and_then_no_longer_from_titles() {
+        assert_eq!(cwd_from_osc7("file://web1/srv/My%20Site").as_deref(), Some("/srv/My Site"));
+        assert_eq!(cwd_from_osc7("file:///tmp").as_deref(), Some("/tmp"));
+        assert_eq!(cwd_from_osc7("http://web1/srv"), None);
+        assert_eq!(cwd_from_title("ops@web1: ~/src").as_deref(), Some("~/src"));
+        assert_eq!(cwd_from_title("vim notes.txt"), None);
+
+        let mut emu = TermEmulator::new(5, 40);
+        emu.process(b"\x1b]0;ops@web1: /etc\x07");
+        assert_eq!(emu.cwd.as_deref(), Some("/etc"));
+        emu.process(b"\x1b]7;file://web1/var/log\x07");
+        assert_eq!(emu.cwd.as_deref(), Some("/var/log"));
+        emu.process(b"\x1b]0;ops@web1: /etc\x07");
+        assert_eq!(emu.cwd.as_deref(), Some("/var/log"));
+    }
+
+    #[test]
+    fn osc_133_marks_bound_each_command_and_carry_its_status() {
+        let mut emu = TermEmulator::new(10, 40);
+        emu.process(b"\x1b]133;A\x07$ \x1b]133;B\x07ls /srv\r\n\x1b]133;C\x07app\r\ndata\r\n\x1b]133;D;2\x07");
+        // An empty command line ends without an output mark and doesn't count.
+        emu.process(b"\x1b]133;A\x07$ \x1b]133;B\x07\r\n\x1b]133;D;0\x07");
+        emu.process(b"\x1b]133;A\x07$ ");
+
+        let done = emu.last_command_output().unwrap();
+        assert_eq!(done.command, "ls /srv");
+        assert_eq!(done.output, "app\ndata");
+        assert_eq!(done.exit_status, Some(2));
+        assert_eq!(emu.finished, 1);
+        assert_eq!(emu.last_exit(), Some(("ls /srv".to_string(), 2)));
+    }
+
+    /// The screen's rows as text, without trailing blanks.
+    fn screen(emu: &TermEmulator) -> Vec<String> {
+        emu.screen.iter().map(|row| row_text(row, 0, row.len())).collect()
+    }
+
+    #[test]
+    fn output_lands_where_the_program_puts_it() {
+        let mut emu = TermEmulator::new(4, 10);
+        emu.process(b"hello\x1b[1;1HJ\x1b[2;3Hx\x1b[3b\x1b[1;4H\x1b[K");
+        assert_eq!(screen(&emu)[..2], ["Jel", "  xxxx"]);
+
+        // The last column waits for the next character before wrapping.
+        emu.process(b"\x1b[3;1H0123456789");
+        assert_eq!((emu.cursor_row, emu.cursor_col, emu.wrap_pending), (2, 9, true));
+        emu.process(b"!");
+        assert_eq!(screen(&emu)[2..], ["0123456789", "!"]);
+
+        emu.process(b"\x1b[4;1H\x1b[2K\x1b(0lqk\x1b(Bq");
+        assert_eq!(screen(&emu)[3], "┌─┐q");
+    }
+
+    #[test]
+    fn the_alternate_screen_and_scroll_regions_keep_the_scrollback() {
+        let mut emu = TermEmulator::new(3, 10);
+        emu.process(b"1\r\n2\r\n3\r\n4");
+        assert_eq!(screen(&emu), ["2", "3", "4"]);
+        assert_eq!(emu.scrollback.len(), 1);
+
+        // Rows scrolled inside a region below the top are not history.
+        emu.process(b"\x1b[2;3r\x1b[3;1H\r\n\x1b[r");
+        assert_eq!(screen(&emu), ["2", "4", ""]);
+        assert_eq!(emu.scrollback.len(), 1);
+
+        emu.process(b"\x1b[?1049h\x1b[H\x1b[2Jtop");
+        assert_eq!(screen(&emu)[0], "top");
+        emu.process(b"\x1b[?1049l");
+        assert_eq!(screen(&emu), ["2", "4", ""]);
+    }
+
+    #[test]
+    fn queries_are_answered_and_reset_keeps_the_scrollback() {
+        let mut emu = TermEmulator::new(3, 10);
+        emu.process(b"\x1b[2;5H\x1b[6n\x1b[c");
+        assert_eq!(emu.replies, b"\x1b[2;5R\x1b[?1;2c");
+
+        emu.process(b"1\r\n2\r\n3\r\n\x1b[?25l\x1b[31mred\x1bc");
+        assert_eq!(screen(&emu), ["", "", ""]);
+        assert_eq!((emu.cursor_row, emu.cursor_col, emu.cursor_visible), (0, 0, true));
+        assert!(emu.cur_style == CellStyle::default());
+        assert!(!emu.scrollback.is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+//! Just enough telnet (RFC 854) for the consoles of legacy network gear.
+//! sheesh never offers an option; of those the server offers it accepts
+//! echo and suppress-go-ahead, the usual character-at-a-time session, and
+//! refuses the rest. Negotiation is answered and stripped from the output.
+
+use std::io::{self, Cursor, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// How long to try each of the host's addresses.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connect to `host:port`, trying each address it resolves to.
+pub fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    let addrs = (host, port).to_socket_addrs().with_context(|| format!("resolving {}", host))?;
+    let mut last = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    match last {
+        Some(e) => Err(e).with_context(|| format!("connecting to {}:{}", host, port)),
+        None => bail!("{} has no addresses", host),
+    }
+}
+
+/// The two ends of a session over a TCP stream.
+pub type Ends = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+/// Connect to `host:port` on a thread of its own, so a host that doesn't
+/// answer holds up nothing but its own tab, and make the session's ends
+/// with `open`. Reading waits until it is up; a failure is read as a
+/// message, then the end. What is typed meanwhile is sent once it is up.
+pub fn connect_later(
+    host: &str,
+    port: u16,
+    open: impl FnOnce(TcpStream) -> Result<Ends> + Send + 'static,
+) -> (ConnectingOutput, ConnectingInput) {
+    let (tx, rx) = mpsc::channel();
+    let input = Arc::new(Mutex::new(Typed::Waiting(vec![])));
+    let (host, typed) = (host.to_string(), Arc::clone(&input));
+    thread::spawn(move || {
+        let output: Box<dyn Read + Send> = match connect(&host, port).and_then(open) {
+            Ok((output, mut writer)) => {
+                let mut typed = typed.lock().unwrap();
+                if let Typed::Waiting(bytes) = &*typed {
+                    let _ = writer.write_all(bytes);
+                }
+                *typed = Typed::Open(writer);
+                output
+            }
+            Err(e) => {
+                *typed.lock().unwrap() = Typed::Failed;
+                Box::new(Cursor::new(format!("{:#}\r\n", e).into_bytes()))
+            }
+        };
+        let _ = tx.send(output);
+    });
+    (ConnectingOutput { connected: rx, output: None }, ConnectingInput(input))
+}
+
+/// The output of a [`connect_later`] session.
+pub struct ConnectingOutput {
+    connected: Receiver<Box<dyn Read + Send>>,
+    output: Option<Box<dyn Read + Send>>,
+}
+
+impl Read for ConnectingOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_none() {
+            match self.connected.recv() {
+                Ok(output) => self.output = Some(output),
+                Err(_) => return Ok(0),
+            }
+        }
+        self.output.as_mut().map_or(Ok(0), |output| output.read(buf))
+    }
+}
+
+/// Where typing into a [`connect_later`] session goes.
+enum Typed {
+    /// Still connecting; held until it is up.
+    Waiting(Vec<u8>),
+    Open(Box<dyn Write + Send>),
+    Failed,
+}
+
+/// The input of a [`connect_later`] session.
+pub struct ConnectingInput(Arc<Mutex<Typed>>);
+
+impl Write for ConnectingInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0.lock().unwrap() {
+            Typed::Waiting(bytes) => {
+                bytes.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            Typed::Open(writer) => writer.write(buf),
+            Typed::Failed => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0.lock().unwrap() {
+            Typed::Open(writer) => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where the reader is in a command sequence.
+#[derive(Clone, Copy)]
+enum State {
+    Data,
+    Iac,
+    /// After `IAC WILL`, `DO`, `WONT` or `DONT`, waiting for the option.
+    Option(u8),
+    /// Inside a subnegotiation, which is skipped.
+    Sub,
+    SubIac,
+}
+
+/// The server's output with negotiation stripped. Replies go out on a
+/// clone of the stream.
+pub struct TelnetReader {
+    stream: TcpStream,
+    replies: TcpStream,
+    state: State,
+}
+
+impl TelnetReader {
+    pub fn new(stream: &TcpStream) -> Result<Self> {
+        Ok(Self { stream: stream.try_clone()?, replies: stream.try_clone()?, state: State::Data })
+    }
+
+    /// Filter `buf` in place; returns how much of it is data.
+    fn filter(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut kept = 0;
+        let mut replies = vec![];
+        for i in 0..buf.len() {
+            let byte = buf[i];
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, _) => {
+                    buf[kept] = byte;
+                    kept += 1;
+                    State::Data
+                }
+                // A doubled IAC is a literal 255.
+                (State::Iac, IAC) => {
+                    buf[kept] = byte;
+                    kept += 1;
+                    State::Data
+                }
+                (State::Iac, WILL | WONT | DO | DONT) => State::Option(byte),
+                (State::Iac, SB) => State::Sub,
+                // NOP, go-ahead and the like.
+                (State::Iac, _) => State::Data,
+                (State::Option(command), option) => {
+                    let reply = match command {
+                        WILL if matches!(option, ECHO | SUPPRESS_GO_AHEAD) => Some(DO),
+                        WILL => Some(DONT),
+                        DO => Some(WONT),
+                        // Already off on our side.
+                        _ => None,
+                    };
+                    if let Some(reply) = reply {
+                        replies.extend([IAC, reply, option]);
+                    }
+                    State::Data
+                }
+                (State::Sub, IAC) => State::SubIac,
+                (State::Sub, _) => State::Sub,
+                (State::SubIac, SE) => State::Data,
+                (State::SubIac, _) => State::Sub,
+            };
+        }
+        if !replies.is_empty() {
+            self.replies.write_all(&replies)?;
+        }
+        Ok(kept)
+    }
+}
+
+impl Read for TelnetReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.stream.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            // A read that was all negotiation is not the end of the stream.
+            let kept = self.filter(&mut buf[..n])?;
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// Typing into a telnet session: IAC is doubled and a lone carriage return
+/// is sent as CR NUL, as the protocol wants. Dropping it hangs up, which
+/// ends the [`TelnetReader`] too.
+pub struct TelnetWriter {
+    stream: TcpStream,
+}
+
+impl TelnetWriter {
+    pub fn new(stream: TcpStream) -> Self {
+        Self { stream }
+    }
+}
+
+impl Write for TelnetWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len() + 2);
+        for (i, &byte) in buf.iter().enumerate() {
+            out.push(byte);
+            match byte {
+                IAC => out.push(IAC),
+                b'\r' if buf.get(i + 1) != Some(&b'\n') => out.push(0),
+                _ => {}
+            }
+        }
+        self.stream.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Drop for TelnetWriter {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Both ends of a loopback connection: ours, then the server's.
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let ours = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (ours, listener.accept().unwrap().0)
+    }
+
+    fn replies(server: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        server.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn filter_strips_negotiation_and_answers_it() {
+        let (ours, mut server) = pair();
+        let mut reader = TelnetReader::new(&ours).unwrap();
+        let mut buf = [
+            b'a', IAC, WILL, ECHO, b'b', IAC, DO, 24, IAC, IAC, IAC, SB, 24, 1, IAC, SE, b'c', IAC, 241, IAC, WONT, 5,
+        ];
+        let kept = reader.filter(&mut buf).unwrap();
+        assert_eq!(&buf[..kept], [b'a', b'b', IAC, b'c']);
+        // Echo is taken, terminal type refused; a WONT needs no answer.
+        assert_eq!(replies(&mut server, 6), [IAC, DO, ECHO, IAC, WONT, 24]);
+    }
+
+    #[test]
+    fn filter_keeps_its_place_across_reads() {
+        let (ours, mut server) = pair();
+        let mut reader = TelnetReader::new(&ours).unwrap();
+        let mut first = [b'x', IAC];
+        assert_eq!(reader.filter(&mut first).unwrap(), 1);
+        let mut second = [WILL, 31, IAC, SB, 31];
+        assert_eq!(reader.filter(&mut second).unwrap(), 0);
+        let mut third = [0, IAC, SE, b'y'];
+        let kept = reader.filter(&mut third).unwrap();
+        assert_eq!(&third[..kept], b"y");
+        assert_eq!(replies(&mut server, 3), [IAC, DONT, 31]);
+    }
+
+    #[test]
+    fn a_read_that_was_all_negotiation_waits_for_data() {
+        let (ours, mut server) = pair();
+        let mut reader = TelnetReader::new(&ours).unwrap();
+        server.write_all(&[IAC, WILL, SUPPRESS_GO_AHEAD]).unwrap();
+        // The prompt only follows the answer, so the first read is all negotiation.
+        let server = std::thread::spawn(move || {
+            let answer = replies(&mut server, 3);
+            server.write_all(b"login: ").unwrap();
+            answer
+        });
+        let mut buf = [0; 16];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"login: ");
+        assert_eq!(server.join().unwrap(), [IAC, DO, SUPPRESS_GO_AHEAD]);
+    }
+
+    #[test]
+    fn writer_escapes_iac_and_lone_carriage_returns() {
+        let (ours, mut server) = pair();
+        let mut writer = TelnetWriter::new(ours);
+        assert_eq!(writer.write(&[b'a', IAC, b'\r', b'b', b'\r', b'\n']).unwrap(), 6);
+        drop(writer);
+        let mut sent = vec![];
+        server.read_to_end(&mut sent).unwrap();
+        assert_eq!(sent, [b'a', IAC, IAC, b'\r', 0, b'b', b'\r', b'\n']);
+    }
+
+    #[test]
+    fn connect_later_sends_what_was_typed_once_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (mut output, mut input) =
+            connect_later("127.0.0.1", port, |stream| Ok((Box::new(stream.try_clone()?), Box::new(stream))));
+        input.write_all(b"hello\n").unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        assert_eq!(replies(&mut server, 6), b"hello\n");
+        server.write_all(b"hi").unwrap();
+        let mut buf = [0; 2];
+        output.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hi");
+    }
+
+    #[test]
+    fn connect_later_reads_a_failure_as_a_message() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let (mut output, mut input) = connect_later("127.0.0.1", port, |_| bail!("not reached"));
+        let mut text = String::new();
+        output.read_to_string(&mut text).unwrap();
+        assert!(text.starts_with(&format!("connecting to 127.0.0.1:{}", port)), "{:?}", text);
+        assert!(text.ends_with("\r\n"));
+        assert_eq!(input.write(b"x").unwrap_err().kind(), io::ErrorKind::NotConnected);
+    }
+}
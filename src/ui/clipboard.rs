@@ -0,0 +1,80 @@
+//! Clipboard writes and reads that may target the primary selection, which
+//! only exists on Linux and the BSDs; elsewhere they use the clipboard.
+//! Also the history of what was copied and applied, so something copied a
+//! while ago can be had again after the clipboard moved on.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use arboard::Clipboard;
+
+/// Most snippets kept in the history.
+const HISTORY_LEN: usize = 30;
+
+/// How a snippet got into the history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnippetKind {
+    /// Copied to the clipboard from either panel.
+    Copied,
+    /// A suggestion sent to the terminal.
+    Applied,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub text: String,
+    pub kind: SnippetKind,
+    pub at: Instant,
+}
+
+/// Newest first, shared by every session of this run. Never written to
+/// disk: what gets copied is often a password or a token.
+static HISTORY: Mutex<VecDeque<Snippet>> = Mutex::new(VecDeque::new());
+
+/// Add `text` to the history, or move it to the top if it is already there.
+pub fn remember(text: &str, kind: SnippetKind) {
+    if text.trim().is_empty() {
+        return;
+    }
+    let mut history = HISTORY.lock().unwrap();
+    history.retain(|s| s.text != text);
+    history.push_front(Snippet { text: text.to_string(), kind, at: Instant::now() });
+    history.truncate(HISTORY_LEN);
+}
+
+/// The history, newest first.
+pub fn history() -> Vec<Snippet> {
+    HISTORY.lock().unwrap().iter().cloned().collect()
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+pub fn set_text(cb: &mut Clipboard, text: String, primary: bool) {
+    use arboard::{LinuxClipboardKind, SetExtLinux};
+    // The primary selection changes with every drag; only copies count.
+    if !primary {
+        remember(&text, SnippetKind::Copied);
+    }
+    let kind = if primary { LinuxClipboardKind::Primary } else { LinuxClipboardKind::Clipboard };
+    let _ = cb.set().clipboard(kind).text(text);
+}
+
+#[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+pub fn set_text(cb: &mut Clipboard, text: String, primary: bool) {
+    if !primary {
+        remember(&text, SnippetKind::Copied);
+    }
+    let _ = cb.set_text(text);
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+pub fn get_text(cb: &mut Clipboard, primary: bool) -> Option<String> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+    let kind = if primary { LinuxClipboardKind::Primary } else { LinuxClipboardKind::Clipboard };
+    cb.get().clipboard(kind).text().ok()
+}
+
+#[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+pub fn get_text(cb: &mut Clipboard, _primary: bool) -> Option<String> {
+    cb.get_text().ok()
+}
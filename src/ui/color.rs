@@ -0,0 +1,134 @@
+//! How many colors the host terminal shows, and a pass over each drawn
+//! frame that maps colors it can't show (RGB from the theme or from remote
+//! programs) to the nearest ones it can.
+
+use ratatui::{buffer::Buffer, style::Color};
+use serde::{Deserialize, Serialize};
+
+/// `[ui] colors`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ColorDepth {
+    /// Work it out from `$COLORTERM` and `$TERM`.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    /// 24-bit RGB.
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    /// The xterm 256-color palette.
+    #[serde(rename = "256")]
+    Indexed,
+    /// The 16 ANSI colors.
+    #[serde(rename = "16")]
+    Basic,
+}
+
+/// The 16 ANSI colors with xterm's values for them.
+const BASIC: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel values of the 6×6×6 cube's steps.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    /// The depth to draw with: this one, or for `Auto` what the environment
+    /// says the terminal supports.
+    pub fn resolve(self) -> ColorDepth {
+        if self != ColorDepth::Auto {
+            return self;
+        }
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default().to_lowercase();
+        let term = std::env::var("TERM").unwrap_or_default().to_lowercase();
+        if colorterm.contains("truecolor") || colorterm.contains("24bit") || term.contains("direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256") {
+            ColorDepth::Indexed
+        } else if term.is_empty() && cfg!(windows) {
+            // The Windows console and Windows Terminal don't set TERM.
+            ColorDepth::TrueColor
+        } else {
+            ColorDepth::Basic
+        }
+    }
+
+    /// Map every cell color of `buffer` this depth can't show.
+    pub fn downgrade(self, buffer: &mut Buffer) {
+        if matches!(self, ColorDepth::Auto | ColorDepth::TrueColor) {
+            return;
+        }
+        for cell in buffer.content.iter_mut() {
+            let (fg, bg) = (self.map(cell.fg), self.map(cell.bg));
+            cell.set_fg(fg).set_bg(bg);
+        }
+    }
+
+    fn map(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Indexed, Color::Rgb(r, g, b)) => Color::Indexed(nearest_indexed((r, g, b))),
+            (ColorDepth::Basic, Color::Rgb(r, g, b)) => nearest_basic((r, g, b)),
+            (ColorDepth::Basic, Color::Indexed(i)) if i < 16 => BASIC[i as usize].0,
+            (ColorDepth::Basic, Color::Indexed(i)) => nearest_basic(indexed_rgb(i)),
+            _ => color,
+        }
+    }
+}
+
+/// RGB of xterm 256-color index `i`.
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => BASIC[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (CUBE[(i / 36) as usize], CUBE[(i / 6 % 6) as usize], CUBE[(i % 6) as usize])
+        }
+        _ => {
+            let v = 8 + (i - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+/// Squared distance, weighted roughly by how sensitive the eye is to each
+/// channel.
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    2 * d(a.0, b.0) + 4 * d(a.1, b.1) + 3 * d(a.2, b.2)
+}
+
+/// The closer of the nearest cube color and the nearest gray.
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let step = |v: u8| CUBE.iter().enumerate().min_by_key(|(_, c)| (**c as i32 - v as i32).abs()).map_or(0, |(i, _)| i);
+    let cube = 16 + 36 * step(rgb.0) + 6 * step(rgb.1) + step(rgb.2);
+    let average = (rgb.0 as usize + rgb.1 as usize + rgb.2 as usize) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+    let (cube, gray) = (cube as u8, gray as u8);
+    if distance(indexed_rgb(gray), rgb) < distance(indexed_rgb(cube), rgb) { gray } else { cube }
+}
+
+/// The nearest ANSI color; a clearly colored `rgb` (the orange accent, say)
+/// stays a color rather than turning into the gray it is closest to.
+fn nearest_basic(rgb: (u8, u8, u8)) -> Color {
+    let (max, min) = (rgb.0.max(rgb.1).max(rgb.2), rgb.0.min(rgb.1).min(rgb.2));
+    let gray = |c: &Color| matches!(c, Color::Black | Color::DarkGray | Color::Gray | Color::White);
+    BASIC
+        .iter()
+        .filter(|(color, _)| max - min < 64 || !gray(color))
+        .min_by_key(|(_, c)| distance(*c, rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
@@ -0,0 +1,192 @@
+//! Incremental, case-insensitive find over a panel's lines: the query being
+//! typed, which lines match, which match is current, and highlighting of the
+//! matches in rendered lines.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+/// What a key did to the find bar.
+pub enum FindKey {
+    /// The query changed.
+    Edited,
+    /// Moved to another match.
+    Moved,
+    /// The bar was dismissed.
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub struct FindBar {
+    pub query: String,
+    /// Indices of the lines containing the query, in order.
+    matches: Vec<usize>,
+    /// Index into `matches` of the current match.
+    current: usize,
+}
+
+impl FindBar {
+    /// Recompute the matches over `lines`, keeping the current match on the
+    /// same line when it still matches, else the nearest one before it.
+    pub fn update<'a>(&mut self, lines: impl Iterator<Item = &'a str>) {
+        let line = self.current_line();
+        let query = self.query.to_ascii_lowercase();
+        self.matches = if query.is_empty() {
+            vec![]
+        } else {
+            lines
+                .enumerate()
+                .filter(|(_, text)| text.to_ascii_lowercase().contains(&query))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        self.current = match line {
+            Some(line) => self.matches.iter().rposition(|m| *m <= line).unwrap_or(0),
+            None => self.matches.len().saturating_sub(1),
+        };
+    }
+
+    /// Line of the current match.
+    pub fn current_line(&self) -> Option<usize> {
+        self.matches.get(self.current).copied()
+    }
+
+    /// Apply a key. Typing edits the query; Enter and Up go to the previous
+    /// (older) match, Shift+Enter and Down to the next one; Esc closes.
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<FindKey> {
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Esc => return Some(FindKey::Close),
+            KeyCode::Enter if shift => self.step(true),
+            KeyCode::Enter | KeyCode::Up => self.step(false),
+            KeyCode::Down => self.step(true),
+            KeyCode::Backspace => {
+                self.query.pop();
+                // Look again from the newest line.
+                self.matches.clear();
+                return Some(FindKey::Edited);
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.query.push(c);
+                self.matches.clear();
+                return Some(FindKey::Edited);
+            }
+            _ => return None,
+        }
+        Some(FindKey::Moved)
+    }
+
+    /// Go to the next (newer) match, or the previous one.
+    pub fn step(&mut self, forward: bool) {
+        let n = self.matches.len();
+        if n > 0 {
+            self.current = if forward { (self.current + 1) % n } else { (self.current + n - 1) % n };
+        }
+    }
+
+    /// Which match is current and how many there are, e.g. `  2/5`.
+    pub fn tally(&self) -> String {
+        match self.matches.len() {
+            _ if self.query.is_empty() => String::new(),
+            0 => "  no matches".to_string(),
+            n => format!("  {}/{}", self.current + 1, n),
+        }
+    }
+
+    /// One-line summary for a status bar.
+    pub fn status(&self) -> String {
+        format!("Find: {}_{}   (enter/↑ previous, shift+enter/↓ next, esc close)", self.query, self.tally())
+    }
+
+    /// How matches are highlighted; brighter on the current match's line.
+    pub fn match_style(current: bool) -> Style {
+        if current {
+            Style::default().bg(Color::Yellow).fg(Color::Black)
+        } else {
+            Style::default().bg(Color::Rgb(90, 80, 20))
+        }
+    }
+
+    /// Byte ranges of the occurrences of the query in `text`.
+    pub fn ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut ranges = vec![];
+        if self.query.is_empty() {
+            return ranges;
+        }
+        let haystack = text.to_ascii_lowercase();
+        let query = self.query.to_ascii_lowercase();
+        let mut from = 0;
+        while let Some(at) = haystack[from..].find(&query) {
+            ranges.push((from + at, from + at + query.len()));
+            from += at + query.len();
+        }
+        ranges
+    }
+
+    /// `line` with every occurrence of the query highlighted; brighter when
+    /// it is the current match's line.
+    pub fn highlight(&self, line: Line<'static>, current: bool) -> Line<'static> {
+        let style = Self::match_style(current);
+        let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+        let ranges = self.ranges(&text);
+        if ranges.is_empty() {
+            return line;
+        }
+
+        // Split the spans at the match boundaries.
+        let mut spans = vec![];
+        let mut pos = 0;
+        for span in line.spans {
+            let content = span.content.as_ref();
+            let end = pos + content.len();
+            let mut cut = pos;
+            for &(a, b) in ranges.iter().filter(|(a, b)| *a < end && *b > pos) {
+                let (a, b) = (a.max(pos), b.min(end));
+                if a > cut {
+                    spans.push(Span::styled(content[cut - pos..a - pos].to_string(), span.style));
+                }
+                spans.push(Span::styled(content[a - pos..b - pos].to_string(), span.style.patch(style)));
+                cut = b;
+            }
+            if cut < end {
+                spans.push(Span::styled(content[cut - pos..].to_string(), span.style));
+            }
+            pos = end;
+        }
+        Line::from(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(query: &str) -> FindBar {
+        FindBar { query: query.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn ranges_are_case_insensitive_and_do_not_overlap() {
+        assert_eq!(bar("err").ranges("Error: err ERR"), [(0, 3), (7, 10), (11, 14)]);
+        assert_eq!(bar("aa").ranges("aaaa"), [(0, 2), (2, 4)]);
+        assert!(bar("").ranges("anything").is_empty());
+        assert!(bar("x").ranges("").is_empty());
+    }
+
+    #[test]
+    fn update_keeps_the_current_line_or_the_one_before() {
+        let lines = ["ok", "error one", "ok", "error two", "error three"];
+        let mut find = bar("error");
+        find.update(lines.into_iter());
+        assert_eq!(find.current_line(), Some(4));
+        find.step(false);
+        assert_eq!(find.current_line(), Some(3));
+        // Line 3 no longer matches: the nearest match before it is current.
+        find.update(["ok", "error one", "ok", "fixed", "error three"].into_iter());
+        assert_eq!(find.current_line(), Some(1));
+        find.step(false);
+        assert_eq!(find.current_line(), Some(4));
+    }
+}
@@ -0,0 +1,107 @@
+//! The idle lock (`idle_lock_minutes` under `[ui]`): after a while without
+//! input the session is hidden behind a box that asks for Enter, or for the
+//! passphrase when one is configured, before showing it again.
+
+use std::time::Instant;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::theme::Theme;
+
+pub struct IdleLock {
+    /// What unlocks it besides Enter, if anything.
+    passphrase: Option<String>,
+    typed: String,
+    /// The last passphrase typed was wrong.
+    wrong: bool,
+    since: Instant,
+    /// Minutes of idleness that locked it, for the message.
+    minutes: u64,
+    /// Sessions whose auto-approval is paused until unlocking.
+    paused: usize,
+}
+
+impl IdleLock {
+    pub fn new(minutes: u64, passphrase: Option<String>, paused: usize) -> Self {
+        Self { passphrase, typed: String::new(), wrong: false, since: Instant::now(), minutes, paused }
+    }
+
+    /// Apply a key; true once it unlocks.
+    pub fn handle_key(&mut self, key: &KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Enter => match &self.passphrase {
+                None => return true,
+                Some(passphrase) if *passphrase == self.typed => return true,
+                Some(_) => {
+                    self.typed.clear();
+                    self.wrong = true;
+                }
+            },
+            KeyCode::Backspace => {
+                self.typed.pop();
+            }
+            KeyCode::Esc => self.typed.clear(),
+            KeyCode::Char(c) if self.passphrase.is_some() && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.typed.push(c);
+                self.wrong = false;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Blank `area` and draw the lock box in its middle.
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        frame.render_widget(Clear, area);
+        let width = area.width.saturating_sub(4).min(64);
+        let height = 8.min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        let locked_for = self.since.elapsed().as_secs() / 60;
+        let mut lines = vec![
+            Line::from(Span::styled(
+                format!(" Locked after {} minutes without input, {} minutes ago.", self.minutes, locked_for),
+                Theme::label(),
+            )),
+        ];
+        if self.paused > 0 {
+            let sessions = if self.paused == 1 { String::new() } else { format!(" in {} sessions", self.paused) };
+            lines.push(Line::from(Span::styled(
+                format!(" Auto-approval is paused{} until it is unlocked.", sessions),
+                Theme::dimmed(),
+            )));
+        }
+        lines.push(Line::default());
+        match &self.passphrase {
+            None => lines.push(Line::from(Span::styled(" enter: unlock", Theme::value()))),
+            Some(_) => {
+                lines.push(Line::from(vec![
+                    Span::styled(" Passphrase: ", Theme::label()),
+                    Span::styled(format!("{}_", "*".repeat(self.typed.chars().count())), Theme::value()),
+                ]));
+                if self.wrong {
+                    lines.push(Line::from(Span::styled(" That isn't it.", Theme::error())));
+                }
+            }
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(" Session locked ", Theme::title())),
+            ),
+            popup,
+        );
+    }
+}
@@ -1,9 +1,11 @@
+use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
     Frame,
     layout::Rect,
     text::{Line, Span},
     widgets::Paragraph,
 };
+use serde::{Deserialize, Serialize};
 
 use super::theme::Theme;
 
@@ -26,3 +28,106 @@ pub fn render_keybindings(frame: &mut Frame, area: Rect, hints: &[KeyHint]) {
     let para = Paragraph::new(line);
     frame.render_widget(para, area);
 }
+
+/// A key with its modifiers, as written in config.toml: `shift+F4`,
+/// `ctrl+g`, `alt+enter`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+    /// As written, for the key hints.
+    label: String,
+}
+
+impl KeyChord {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// True for the key press this chord names. Letters match either case,
+    /// since terminals differ in whether shift+letter reports the shift.
+    pub fn matches(&self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let held = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match (self.code, code) {
+            (KeyCode::Char(a), KeyCode::Char(b)) => {
+                a.eq_ignore_ascii_case(&b) && held - KeyModifiers::SHIFT == self.modifiers - KeyModifiers::SHIFT
+            }
+            (a, b) => a == b && held == self.modifiers,
+        }
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(label: String) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = label.split('+').collect();
+        let key = parts.pop().unwrap_or_default();
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", part, label)),
+            };
+        }
+        let lower = key.to_lowercase();
+        let code = match lower.as_str() {
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => KeyCode::F(n),
+                _ if key.chars().count() == 1 => KeyCode::Char(key.chars().next().unwrap_or_default()),
+                _ => return Err(format!("unknown key '{}' in '{}'", key, label)),
+            },
+        };
+        Ok(Self { code, modifiers, label })
+    }
+}
+
+impl From<KeyChord> for String {
+    fn from(chord: KeyChord) -> String {
+        chord.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(label: &str) -> Result<KeyChord, String> {
+        KeyChord::try_from(label.to_string())
+    }
+
+    #[test]
+    fn chords_read_modifiers_and_keys() {
+        let f4 = chord("shift+F4").unwrap();
+        assert_eq!((f4.code, f4.modifiers), (KeyCode::F(4), KeyModifiers::SHIFT));
+        let g = chord("Ctrl+Alt+g").unwrap();
+        assert_eq!((g.code, g.modifiers), (KeyCode::Char('g'), KeyModifiers::CONTROL | KeyModifiers::ALT));
+        assert_eq!(chord("space").unwrap().code, KeyCode::Char(' '));
+        assert_eq!(chord("alt+enter").unwrap().label(), "alt+enter");
+    }
+
+    #[test]
+    fn chords_refuse_unknown_names() {
+        assert_eq!(chord("hyper+g"), Err("unknown modifier 'hyper' in 'hyper+g'".to_string()));
+        assert_eq!(chord("ctrl+F13"), Err("unknown key 'F13' in 'ctrl+F13'".to_string()));
+        assert!(chord("ctrl+").is_err());
+    }
+
+    #[test]
+    fn letters_match_with_or_without_shift() {
+        let g = chord("ctrl+g").unwrap();
+        assert!(g.matches(KeyCode::Char('g'), KeyModifiers::CONTROL));
+        assert!(g.matches(KeyCode::Char('G'), KeyModifiers::CONTROL | KeyModifiers::SHIFT));
+        assert!(!g.matches(KeyCode::Char('g'), KeyModifiers::NONE));
+        let f4 = chord("shift+F4").unwrap();
+        assert!(!f4.matches(KeyCode::F(4), KeyModifiers::NONE));
+        assert!(f4.matches(KeyCode::F(4), KeyModifiers::SHIFT));
+    }
+}
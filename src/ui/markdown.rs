@@ -0,0 +1,193 @@
+//! The chat's markdown: role prefixes, headings, fenced code and inline
+//! `**bold**`, `*italic*` and `` `code` ``, rendered to spans and wrapped
+//! to the panel width. Runs on every frame for every visible line, so
+//! plain text is sliced rather than copied a character at a time.
+
+use std::borrow::Cow;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use super::theme::Theme;
+
+/// Starts the dim line under a message with its time, model and tokens.
+pub const META_PREFIX: &str = "      · ";
+
+/// Strip the role prefix / indent from a line to get the raw content.
+pub fn line_content(text: &str) -> &str {
+    if let Some(rest) = text.strip_prefix("You: ") {
+        rest
+    } else if let Some(rest) = text.strip_prefix("Claude: ") {
+        rest
+    } else if let Some(rest) = text.strip_prefix("System: ") {
+        rest
+    } else if let Some(rest) = text.strip_prefix("      ") {
+        rest
+    } else {
+        text
+    }
+}
+
+/// Which of `lines` are rendered as code: fences, the lines between them,
+/// and table rows.
+pub fn code_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<bool> {
+    let lines = lines.into_iter();
+    let mut flags = Vec::with_capacity(lines.size_hint().0);
+    let mut in_block = false;
+    for text in lines {
+        let trimmed = line_content(text).trim_start();
+        if trimmed.starts_with("```") {
+            in_block = !in_block;
+            flags.push(true);
+        } else {
+            flags.push(in_block || trimmed.starts_with('|'));
+        }
+    }
+    flags
+}
+
+/// Render a single history line with markdown styling applied.
+/// `in_code` means the line falls inside a fenced code block.
+pub fn render_line(full_text: &str, in_code: bool) -> Line<'static> {
+    if full_text.is_empty() {
+        return Line::raw("");
+    }
+    if full_text.starts_with(META_PREFIX) {
+        return Line::styled(full_text.to_string(), Theme::dimmed());
+    }
+
+    // Split prefix (role label / indent) from content.
+    let (prefix_str, prefix_style, content): (&'static str, Style, &str) =
+        if let Some(rest) = full_text.strip_prefix("You: ") {
+            ("You: ", Theme::chat_user(), rest)
+        } else if let Some(rest) = full_text.strip_prefix("Claude: ") {
+            ("Claude: ", Theme::chat_assistant(), rest)
+        } else if let Some(rest) = full_text.strip_prefix("System: ") {
+            ("System: ", Theme::dimmed(), rest)
+        } else if let Some(rest) = full_text.strip_prefix("      ") {
+            ("      ", Style::default(), rest)
+        } else {
+            ("", Style::default(), full_text)
+        };
+
+    let mut spans: Vec<Span<'static>> = Vec::with_capacity(4);
+    if !prefix_str.is_empty() {
+        spans.push(Span::styled(prefix_str, prefix_style));
+    }
+
+    // Code block lines: render as-is with code style.
+    if in_code {
+        spans.push(Span::styled(content.to_string(), Theme::md_code_block()));
+        return Line::from(spans);
+    }
+
+    // Headings (line-level).
+    if content.starts_with("### ") {
+        spans.push(Span::styled(content.to_string(), Style::default().add_modifier(Modifier::BOLD)));
+    } else if content.starts_with("## ") {
+        spans.push(Span::styled(content.to_string(), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
+    } else if content.starts_with("# ") {
+        spans.push(Span::styled(content.to_string(), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    } else {
+        parse_inline(content, &mut spans);
+    }
+
+    Line::from(spans)
+}
+
+/// Parse inline markdown (`**bold**`, `*italic*`, `` `code` ``) into styled
+/// spans appended to `spans`. The markers are ASCII, so the scan is over
+/// bytes and the text between them is pushed as one slice.
+pub fn parse_inline(text: &str, spans: &mut Vec<Span<'static>>) {
+    let bytes = text.as_bytes();
+    let before = spans.len();
+    // Start of the plain text not yet pushed.
+    let mut plain = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let found = match bytes[i] {
+            // **bold** or __bold__
+            b @ (b'*' | b'_') if bytes.get(i + 1) == Some(&b) => {
+                let seq = if b == b'*' { "**" } else { "__" };
+                text[i + 2..].find(seq).map(|p| (i + 2, i + 2 + p, 2, Style::default().add_modifier(Modifier::BOLD)))
+            }
+            // *italic* or _italic_, not empty
+            b @ (b'*' | b'_') => text[i + 1..]
+                .find(b as char)
+                .filter(|&p| p > 0)
+                .map(|p| (i + 1, i + 1 + p, 1, Style::default().add_modifier(Modifier::ITALIC))),
+            // `inline code`
+            b'`' => text[i + 1..].find('`').map(|p| (i + 1, i + 1 + p, 1, Theme::md_code_inline())),
+            _ => None,
+        };
+        match found {
+            Some((start, end, marker, style)) => {
+                if plain < i {
+                    spans.push(Span::raw(text[plain..i].to_string()));
+                }
+                spans.push(Span::styled(text[start..end].to_string(), style));
+                i = end + marker;
+                plain = i;
+            }
+            None => i += 1,
+        }
+    }
+
+    if plain < bytes.len() {
+        spans.push(Span::raw(text[plain..].to_string()));
+    }
+    if spans.len() == before {
+        spans.push(Span::raw(String::new()));
+    }
+}
+
+/// Split a vec of ratatui spans into visual rows of at most `width` chars.
+/// Returns `(chunk_spans, byte_offset_in_original_string)` per row. Spans
+/// that fit are moved, not copied; split ones reuse their allocation.
+pub fn wrap_spans(spans: Vec<Span<'static>>, width: usize) -> Vec<(Vec<Span<'static>>, usize)> {
+    if width == 0 {
+        return vec![(spans, 0)];
+    }
+    let mut rows: Vec<(Vec<Span<'static>>, usize)> = Vec::new();
+    let mut current: Vec<Span<'static>> = Vec::with_capacity(spans.len());
+    let mut chars_in_row: usize = 0;
+    let mut line_byte_offset: usize = 0;
+    let mut row_byte_start: usize = 0;
+
+    for span in spans {
+        let style = span.style;
+        let mut remaining = span.content;
+
+        while !remaining.is_empty() {
+            let capacity = width - chars_in_row;
+            let Some((split, _)) = remaining.char_indices().nth(capacity) else {
+                chars_in_row += remaining.chars().count();
+                line_byte_offset += remaining.len();
+                current.push(Span::styled(remaining, style));
+                break;
+            };
+            let (head, tail) = match remaining {
+                Cow::Borrowed(s) => (Cow::Borrowed(&s[..split]), Cow::Borrowed(&s[split..])),
+                Cow::Owned(mut s) => {
+                    let tail = s.split_off(split);
+                    (Cow::Owned(s), Cow::Owned(tail))
+                }
+            };
+            line_byte_offset += head.len();
+            if !head.is_empty() {
+                current.push(Span::styled(head, style));
+            }
+
+            rows.push((std::mem::take(&mut current), row_byte_start));
+            row_byte_start = line_byte_offset;
+            chars_in_row = 0;
+            remaining = tail;
+        }
+    }
+
+    rows.push((current, row_byte_start));
+    rows
+}
@@ -0,0 +1,110 @@
+//! Small popup menu anchored at a mouse position (the right-click menu).
+
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseButton, MouseEventKind};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::theme::Theme;
+
+/// What an event did to an open menu.
+pub enum MenuOutcome<T> {
+    /// Still open.
+    Open,
+    /// An item was chosen; the menu should close.
+    Chosen(T),
+    /// Dismissed without a choice.
+    Closed,
+}
+
+pub struct ContextMenu<T> {
+    items: Vec<(&'static str, T)>,
+    selected: usize,
+    area: Rect,
+}
+
+impl<T: Copy> ContextMenu<T> {
+    /// A menu whose top-left corner sits at (`col`, `row`), moved left or up
+    /// as needed to stay inside `bounds`.
+    pub fn new(items: Vec<(&'static str, T)>, col: u16, row: u16, bounds: Rect) -> Self {
+        let mut menu = Self { items, selected: 0, area: Rect::default() };
+        menu.place(col, row, bounds);
+        menu
+    }
+
+    fn place(&mut self, col: u16, row: u16, bounds: Rect) {
+        let width = self.items.iter().map(|(label, _)| label.chars().count() as u16).max().unwrap_or(0) + 4;
+        let height = self.items.len() as u16 + 2;
+        let x = col.min(bounds.right().saturating_sub(width)).max(bounds.x);
+        let y = row.min(bounds.bottom().saturating_sub(height)).max(bounds.y);
+        self.area = Rect { x, y, width, height }.intersection(bounds);
+    }
+
+    /// Move the menu back inside `bounds` after the screen changed size.
+    pub fn keep_within(&mut self, bounds: Rect) {
+        self.place(self.area.x, self.area.y, bounds);
+    }
+
+    /// Item under screen position (`col`, `row`), if any.
+    fn item_at(&self, col: u16, row: u16) -> Option<usize> {
+        let inside = col > self.area.x && col + 1 < self.area.right() && row > self.area.y;
+        let index = row.saturating_sub(self.area.y + 1) as usize;
+        (inside && index < self.items.len()).then_some(index)
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> MenuOutcome<T> {
+        match event {
+            Event::Key(KeyEvent { code, .. }) => match code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected = self.selected.checked_sub(1).unwrap_or(self.items.len() - 1);
+                }
+                KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+                    self.selected = (self.selected + 1) % self.items.len();
+                }
+                KeyCode::Enter => return MenuOutcome::Chosen(self.items[self.selected].1),
+                _ => return MenuOutcome::Closed,
+            },
+            Event::Mouse(me) => match me.kind {
+                MouseEventKind::Moved => {
+                    if let Some(index) = self.item_at(me.column, me.row) {
+                        self.selected = index;
+                    }
+                }
+                MouseEventKind::Down(MouseButton::Left) => {
+                    return match self.item_at(me.column, me.row) {
+                        Some(index) => MenuOutcome::Chosen(self.items[index].1),
+                        None => MenuOutcome::Closed,
+                    };
+                }
+                MouseEventKind::Down(_) | MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                    return MenuOutcome::Closed;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        MenuOutcome::Open
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let width = (self.area.width as usize).saturating_sub(4);
+        let lines: Vec<Line> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, (label, _))| {
+                let style = if i == self.selected { Theme::highlight() } else { Theme::value() };
+                Line::from(Span::styled(format!(" {:<width$} ", label, width = width), style))
+            })
+            .collect();
+        frame.render_widget(Clear, self.area);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().border_type(BorderType::Rounded).border_style(Theme::selected_border())),
+            self.area,
+        );
+    }
+}
@@ -1,2 +1,91 @@
+pub mod clipboard;
+pub mod color;
+pub mod find;
+pub mod idle;
 pub mod keybindings;
+pub mod markdown;
+pub mod menu;
+pub mod notes;
+pub mod notice;
+pub mod plain;
+pub mod popover;
+pub mod prompt;
+pub mod scroll;
+pub mod shell;
+pub mod snippets;
 pub mod theme;
+
+use serde::{Deserialize, Serialize};
+
+use color::ColorDepth;
+use keybindings::KeyChord;
+
+/// `[ui]` section of config.toml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// Lines moved per wheel notch and per Ctrl+Up/Down.
+    pub scroll_lines: usize,
+    /// Reverse the wheel direction (content follows the fingers).
+    pub natural_scroll: bool,
+    /// Move further per notch while the wheel spins quickly.
+    pub scroll_acceleration: bool,
+    /// Copy a mouse selection as soon as the button is released.
+    pub copy_on_select: CopyOnSelect,
+    /// Capture the mouse. Off leaves clicks, selection and the wheel to the
+    /// host terminal; everything the mouse does has a key as well.
+    pub mouse: bool,
+    /// Name in words what colors alone show (focus, connection state) and
+    /// start sessions in the plain view (F9), for screen readers.
+    pub screen_reader: bool,
+    /// Colors the terminal shows; RGB and 256-color cells beyond that are
+    /// drawn with the nearest it has.
+    pub colors: ColorDepth,
+    /// Show each chat message's time, model and token counts under it.
+    pub show_message_meta: bool,
+    /// Regex matching shell prompt lines, to find command boundaries in
+    /// the terminal when the shell doesn't send OSC 133 marks.
+    pub prompt_regex: Option<String>,
+    /// Key that runs the selected suggestion (F4 only types it), when
+    /// nothing would stop a tool call from running unasked.
+    pub run_suggestion_key: KeyChord,
+    /// Minutes without a key or the mouse after which the session is
+    /// hidden and auto-approval paused until unlocked; 0 never locks.
+    pub idle_lock_minutes: u64,
+    /// Environment variable holding the passphrase that unlocks the idle
+    /// lock; without one, Enter does.
+    pub idle_passphrase_env: Option<String>,
+}
+
+/// Where a finished mouse selection goes without pressing Ctrl+C.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyOnSelect {
+    /// Only Ctrl+C copies.
+    #[default]
+    Off,
+    /// The regular clipboard.
+    Clipboard,
+    /// The X11/Wayland primary selection (middle-click paste); the regular
+    /// clipboard on other systems.
+    Primary,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            scroll_lines: 3,
+            natural_scroll: false,
+            scroll_acceleration: true,
+            copy_on_select: CopyOnSelect::Off,
+            mouse: true,
+            screen_reader: false,
+            colors: ColorDepth::Auto,
+            show_message_meta: false,
+            prompt_regex: None,
+            run_suggestion_key: KeyChord::try_from("shift+F4".to_string()).expect("valid key"),
+            idle_lock_minutes: 0,
+            idle_passphrase_env: None,
+        }
+    }
+}
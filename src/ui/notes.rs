@@ -0,0 +1,195 @@
+//! The notes popup (F11): a small plain-text editor over the session for
+//! the connection's notes. Closing it keeps what was written.
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    Frame,
+    layout::{Position, Rect},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::theme::Theme;
+use crate::notes::Notes;
+
+/// Lines PgUp / PgDn move.
+const PAGE: usize = 10;
+
+pub struct NotesEditor {
+    alias: String,
+    lines: Vec<String>,
+    /// Cursor line, and character within it.
+    row: usize,
+    col: usize,
+    /// First line shown, kept so the cursor stays in view.
+    scroll: usize,
+    shared: bool,
+}
+
+impl NotesEditor {
+    pub fn new(alias: &str, notes: Notes) -> Self {
+        let mut lines: Vec<String> = notes.text.lines().map(String::from).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        let row = lines.len() - 1;
+        let col = lines[row].chars().count();
+        Self { alias: alias.to_string(), lines, row, col, scroll: 0, shared: notes.shared }
+    }
+
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// The notes as they are now.
+    pub fn notes(&self) -> Notes {
+        let text = self.lines.join("\n");
+        let text = if text.trim().is_empty() { String::new() } else { format!("{}\n", text.trim_end()) };
+        Notes { text, shared: self.shared }
+    }
+
+    /// Edit with the usual keys; ctrl+t shares the notes with the assistant
+    /// or stops sharing them. False once Esc or F11 closes the editor.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Key(KeyEvent { code, modifiers, .. }) => match code {
+                KeyCode::Esc | KeyCode::F(11) => return false,
+                KeyCode::Char('t') if modifiers.contains(KeyModifiers::CONTROL) => self.shared = !self.shared,
+                KeyCode::Char(c) if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                    self.insert(&c.to_string())
+                }
+                KeyCode::Tab => self.insert("    "),
+                KeyCode::Enter => self.insert("\n"),
+                KeyCode::Backspace => {
+                    if self.col > 0 {
+                        self.col -= 1;
+                        let at = self.byte(self.col);
+                        self.lines[self.row].remove(at);
+                    } else if self.row > 0 {
+                        let line = self.lines.remove(self.row);
+                        self.row -= 1;
+                        self.col = self.lines[self.row].chars().count();
+                        self.lines[self.row].push_str(&line);
+                    }
+                }
+                KeyCode::Delete => {
+                    if self.col < self.len() {
+                        let at = self.byte(self.col);
+                        self.lines[self.row].remove(at);
+                    } else if self.row + 1 < self.lines.len() {
+                        let line = self.lines.remove(self.row + 1);
+                        self.lines[self.row].push_str(&line);
+                    }
+                }
+                KeyCode::Left if self.col > 0 => self.col -= 1,
+                KeyCode::Left if self.row > 0 => {
+                    self.row -= 1;
+                    self.col = self.len();
+                }
+                KeyCode::Right if self.col < self.len() => self.col += 1,
+                KeyCode::Right if self.row + 1 < self.lines.len() => (self.row, self.col) = (self.row + 1, 0),
+                KeyCode::Up => self.move_to(self.row.saturating_sub(1)),
+                KeyCode::Down => self.move_to(self.row + 1),
+                KeyCode::PageUp => self.move_to(self.row.saturating_sub(PAGE)),
+                KeyCode::PageDown => self.move_to(self.row + PAGE),
+                KeyCode::Home => self.col = 0,
+                KeyCode::End => self.col = self.len(),
+                _ => {}
+            },
+            Event::Paste(text) => self.insert(&text.replace("\r\n", "\n").replace('\r', "\n")),
+            _ => {}
+        }
+        true
+    }
+
+    /// Characters in the cursor's line.
+    fn len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    /// Byte offset of character `col` in the cursor's line.
+    fn byte(&self, col: usize) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+    }
+
+    fn move_to(&mut self, row: usize) {
+        self.row = row.min(self.lines.len() - 1);
+        self.col = self.col.min(self.len());
+    }
+
+    /// Insert `text` at the cursor, which ends up after it.
+    fn insert(&mut self, text: &str) {
+        let at = self.byte(self.col);
+        let rest = self.lines[self.row].split_off(at);
+        let mut parts = text.split('\n');
+        self.lines[self.row].push_str(parts.next().unwrap_or(""));
+        for part in parts {
+            self.row += 1;
+            self.lines.insert(self.row, part.to_string());
+        }
+        self.col = self.len();
+        self.lines[self.row].push_str(&rest);
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(100);
+        let height = area.height.saturating_sub(4).min(30);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        let rows = height.saturating_sub(2).max(1) as usize;
+        let cols = width.saturating_sub(2).max(1) as usize;
+        self.scroll = self.scroll.min(self.row).max((self.row + 1).saturating_sub(rows));
+        // Long lines are not wrapped; the view slides sideways to the cursor.
+        let first_col = (self.col + 1).saturating_sub(cols);
+
+        let lines: Vec<Line> = if self.lines.len() == 1 && self.lines[0].is_empty() {
+            vec![Line::from(Span::styled(
+                "Addresses, where credentials are kept (not the credentials), quirks of this host…",
+                Theme::dimmed(),
+            ))]
+        } else {
+            let mut fenced = false;
+            self.lines
+                .iter()
+                .map(|line| {
+                    let fence = line.trim_start().starts_with("```");
+                    fenced ^= fence;
+                    let style = if fence || fenced {
+                        Theme::md_code_block()
+                    } else if line.starts_with('#') {
+                        Theme::title()
+                    } else {
+                        Theme::value()
+                    };
+                    Line::from(Span::styled(line.chars().skip(first_col).take(cols).collect::<String>(), style))
+                })
+                .skip(self.scroll)
+                .take(rows)
+                .collect()
+        };
+
+        let sharing = if self.shared { "on" } else { "off" };
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(format!(" Notes · {} ", self.alias), Theme::title()))
+                    .title_bottom(Span::styled(
+                        format!(" esc: keep and close · ctrl+t: share with the assistant ({}) ", sharing),
+                        Theme::dimmed(),
+                    )),
+            ),
+            popup,
+        );
+        let x = popup.x + 1 + (self.col - first_col) as u16;
+        let y = popup.y + 1 + (self.row - self.scroll) as u16;
+        frame.set_cursor_position(Position::new(x, y));
+    }
+}
@@ -0,0 +1,148 @@
+//! Queued error messages. Errors that stop what the user asked for (a
+//! connection that can't start, a broken config) are modal popups shown one
+//! at a time; the rest are banners over the footer that expire on their own.
+//! Keys that dismiss either are consumed, never forwarded to a panel.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseButton, MouseEventKind};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::theme::Theme;
+
+/// How long a banner stays up once it is shown.
+const BANNER_TIME: Duration = Duration::from_secs(8);
+
+#[derive(Default)]
+pub struct Notices {
+    modal: VecDeque<String>,
+    banners: VecDeque<String>,
+    /// When the front banner was first shown.
+    banner_since: Option<Instant>,
+}
+
+impl Notices {
+    /// Queue an error that needs acknowledging before anything else.
+    pub fn fatal(&mut self, msg: impl Into<String>) {
+        self.modal.push_back(msg.into());
+    }
+
+    /// Queue an error shown as a banner.
+    pub fn error(&mut self, msg: impl Into<String>) {
+        self.banners.push_back(msg.into());
+    }
+
+    /// Apply `event` if a notice takes it; returns true if it was consumed.
+    /// While a popup is up every key and click goes to it, and only Enter,
+    /// Esc or Space (or a click) dismiss it. A banner takes Esc and clicks
+    /// on `banner_area`; everything else passes through.
+    pub fn handle_event(&mut self, event: &Event, banner_area: Rect) -> bool {
+        let click = |event: &Event| match event {
+            Event::Mouse(me) if me.kind == MouseEventKind::Down(MouseButton::Left) => Some((me.column, me.row)),
+            _ => None,
+        };
+        if !self.modal.is_empty() {
+            match event {
+                Event::Key(KeyEvent { code: KeyCode::Enter | KeyCode::Esc | KeyCode::Char(' '), .. }) => {
+                    self.modal.pop_front();
+                }
+                _ if click(event).is_some() => {
+                    self.modal.pop_front();
+                }
+                _ => {}
+            }
+            return matches!(event, Event::Key(_) | Event::Mouse(_) | Event::Paste(_));
+        }
+        if self.banners.is_empty() {
+            return false;
+        }
+        let dismiss = match event {
+            Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => true,
+            _ => click(event).is_some_and(|(col, row)| banner_area.contains((col, row).into())),
+        };
+        if dismiss {
+            self.next_banner();
+        }
+        dismiss
+    }
+
+    fn next_banner(&mut self) {
+        self.banners.pop_front();
+        self.banner_since = None;
+    }
+
+    /// Draw the current popup over `area`, or else the current banner over
+    /// `banner_area`, dropping banners that have been up long enough.
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, banner_area: Rect) {
+        if let Some(msg) = self.modal.front() {
+            render_popup(frame, area, msg, self.modal.len());
+            return;
+        }
+        if self.banner_since.is_some_and(|since| since.elapsed() >= BANNER_TIME) {
+            self.next_banner();
+        }
+        let Some(msg) = self.banners.front() else {
+            return;
+        };
+        self.banner_since.get_or_insert_with(Instant::now);
+        let more = match self.banners.len() - 1 {
+            0 => String::new(),
+            n => format!("  (+{} more)", n),
+        };
+        let line = Line::from(vec![
+            Span::styled(format!(" ✗ {}", msg.lines().next().unwrap_or("")), Theme::error()),
+            Span::styled(format!("{}   esc: dismiss ", more), Theme::dimmed()),
+        ]);
+        frame.render_widget(Clear, banner_area);
+        frame.render_widget(Paragraph::new(line), banner_area);
+    }
+}
+
+fn render_popup(frame: &mut Frame, area: Rect, msg: &str, queued: usize) {
+    // Tall enough for multi-line diagnostics such as config parse errors.
+    let rows = msg.lines().count() + 5;
+    let percent_y = (rows * 100 / area.height.max(1) as usize).clamp(20, 90) as u16;
+    let popup_area = centered_rect(60, percent_y, area);
+    frame.render_widget(Clear, popup_area);
+
+    let title = match queued {
+        1 => " Error ".to_string(),
+        n => format!(" Error (1 of {}) ", n),
+    };
+    let mut lines = vec![Line::default()];
+    lines.extend(msg.lines().map(|line| Line::from(Span::styled(format!("  {}", line), Theme::error()))));
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled("  Press enter or esc to continue", Theme::dimmed())));
+    let para = Paragraph::new(lines).block(
+        Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(Theme::error())
+            .title(Span::styled(title, Theme::error())),
+    );
+
+    frame.render_widget(para, popup_area);
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let [_, mid_v, _] = Layout::vertical([
+        Constraint::Percentage((100 - percent_y) / 2),
+        Constraint::Percentage(percent_y),
+        Constraint::Percentage((100 - percent_y) / 2),
+    ])
+    .areas(area);
+
+    let [_, center, _] = Layout::horizontal([
+        Constraint::Percentage((100 - percent_x) / 2),
+        Constraint::Percentage(percent_x),
+        Constraint::Percentage((100 - percent_x) / 2),
+    ])
+    .areas(mid_v);
+
+    center
+}
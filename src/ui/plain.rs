@@ -0,0 +1,37 @@
+//! The plain view (F9): the focused panel as unstyled text without borders
+//! or columns, so screen readers and braille displays read it top to bottom.
+
+use ratatui::{
+    Frame,
+    layout::{Position, Rect},
+    text::Line,
+    widgets::Paragraph,
+};
+
+/// Draw `heading` on the first row of `area` and as much of `lines` as fits
+/// below it, wrapped, ending `scroll` rows above the last one. The cursor
+/// is left at the end of the last row shown.
+pub fn render(frame: &mut Frame, area: Rect, heading: &str, lines: &[String], scroll: usize) {
+    let width = area.width.max(1) as usize;
+    let rows: Vec<String> = lines
+        .iter()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+        })
+        .collect();
+    let height = area.height.saturating_sub(1) as usize;
+    let end = rows.len().saturating_sub(scroll).max(height.min(rows.len()));
+    let shown = &rows[end.saturating_sub(height)..end];
+
+    let mut text = vec![Line::raw(heading.to_string())];
+    text.extend(shown.iter().map(|row| Line::raw(row.clone())));
+    frame.render_widget(Paragraph::new(text), area);
+
+    let last = shown.last().map_or(heading, String::as_str);
+    let x = area.x + (last.chars().count() as u16).min(area.width.saturating_sub(1));
+    frame.set_cursor_position(Position::new(x, area.y + shown.len() as u16));
+}
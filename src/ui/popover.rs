@@ -0,0 +1,83 @@
+//! A small box of text next to something on screen: the answer to F5's
+//! "explain this" beside the terminal selection. It waits with a
+//! placeholder until the text arrives and closes on the next key or click.
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph, Wrap},
+};
+
+use super::theme::Theme;
+
+/// Widest and tallest a popover gets, borders included.
+const MAX_WIDTH: u16 = 64;
+const MAX_HEIGHT: u16 = 14;
+
+pub struct Popover {
+    title: String,
+    /// Screen cell the box hangs from (its top-left corner, room allowing).
+    anchor: (u16, u16),
+    /// `None` until the text arrives.
+    text: Option<String>,
+}
+
+impl Popover {
+    pub fn new(title: impl Into<String>, anchor: (u16, u16)) -> Self {
+        Self { title: title.into(), anchor, text: None }
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.text.is_none()
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        self.text = Some(text);
+    }
+
+    /// Draw within `bounds`: below the anchor if it fits there, else above.
+    /// Text that doesn't fit is cut off with a pointer to the chat.
+    pub fn render(&self, frame: &mut Frame, bounds: Rect) {
+        let width = MAX_WIDTH.min(bounds.width);
+        let text_width = width.saturating_sub(2).max(1) as usize;
+        let mut lines: Vec<Line> = match &self.text {
+            None => vec![Line::from(Span::styled("Asking…", Theme::dimmed()))],
+            Some(text) => text.lines().map(|l| Line::from(l.to_string())).collect(),
+        };
+        // Rows once wrapped, near enough: wrapping at words only adds a few.
+        let rows: usize = lines.iter().map(|l| l.width().max(1).div_ceil(text_width)).sum();
+        let max_rows = MAX_HEIGHT.min(bounds.height).saturating_sub(2) as usize;
+        if rows > max_rows {
+            let mut kept = 0;
+            lines.retain(|l| {
+                kept += l.width().max(1).div_ceil(text_width);
+                kept < max_rows
+            });
+            lines.push(Line::from(Span::styled("… the rest is in the chat", Theme::dimmed())));
+        }
+        let height = (rows.min(max_rows) as u16 + 2).min(bounds.height);
+
+        let (col, row) = self.anchor;
+        let x = col.min(bounds.right().saturating_sub(width)).max(bounds.x);
+        let y = if row + height <= bounds.bottom() {
+            row
+        } else {
+            // Above the selection's last row instead.
+            row.saturating_sub(height + 1).max(bounds.y)
+        };
+        let area = Rect { x, y, width, height };
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(format!(" {} ", self.title), Theme::title()))
+                    .title_bottom(Span::styled(" any key closes ", Theme::dimmed())),
+            ),
+            area,
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Popups that ask before acting: where to save a file (and whether with
+//! colors), whether to quit with sessions still open, and whether to add a
+//! key to ssh-agent before connecting.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::theme::Theme;
+
+/// What a key did to the prompt.
+pub enum PromptKey {
+    Confirm,
+    /// Quit but leave the sessions running.
+    Detach,
+    Cancel,
+}
+
+pub struct SavePrompt {
+    pub path: String,
+    /// Keep the ANSI color codes instead of writing plain text.
+    pub ansi: bool,
+}
+
+impl SavePrompt {
+    pub fn new(path: String) -> Self {
+        Self { path, ansi: false }
+    }
+
+    /// Apply a key: typing edits the path, Tab switches between plain text
+    /// and ANSI (and the matching extension), Enter saves, Esc cancels.
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<PromptKey> {
+        match key.code {
+            KeyCode::Enter if !self.path.trim().is_empty() => return Some(PromptKey::Confirm),
+            KeyCode::Esc => return Some(PromptKey::Cancel),
+            KeyCode::Tab => {
+                self.ansi = !self.ansi;
+                let (from, to) = if self.ansi { (".txt", ".ansi") } else { (".ansi", ".txt") };
+                if let Some(stem) = self.path.strip_suffix(from) {
+                    self.path = format!("{}{}", stem, to);
+                }
+            }
+            KeyCode::Backspace => {
+                self.path.pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => self.path.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(90);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(6) / 2,
+            width,
+            height: 6.min(area.height),
+        };
+        let format = if self.ansi { "ANSI colors" } else { "plain text" };
+        let lines = vec![
+            Line::from(Span::styled(format!(" {}_", self.path), Theme::value())),
+            Line::default(),
+            Line::from(vec![
+                Span::styled(format!(" {}", format), Theme::label()),
+                Span::styled("   tab: change format   enter: save   esc: cancel", Theme::dimmed()),
+            ]),
+        ];
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(" Save scrollback ", Theme::title())),
+            ),
+            popup,
+        );
+    }
+}
+
+/// Asks before quitting while sessions are open, listing each one and
+/// whatever it is in the middle of.
+pub struct QuitPrompt {
+    /// One line per open session.
+    pub sessions: Vec<String>,
+    /// Whether the sessions can be left running instead.
+    pub detachable: bool,
+}
+
+impl QuitPrompt {
+    /// Apply a key: `y`, `q` or Enter confirm, `d` detaches, `n` or Esc
+    /// cancel; other keys are ignored.
+    pub fn handle_key(&self, key: &KeyEvent) -> Option<PromptKey> {
+        match key.code {
+            KeyCode::Char('y' | 'q') | KeyCode::Enter => Some(PromptKey::Confirm),
+            KeyCode::Char('d') if self.detachable => Some(PromptKey::Detach),
+            KeyCode::Char('n') | KeyCode::Esc => Some(PromptKey::Cancel),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(80);
+        let height = (self.sessions.len() as u16 + 6).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        let mut lines = vec![Line::from(Span::styled(
+            " Quitting closes these sessions and stops what they are doing:",
+            Theme::label(),
+        ))];
+        lines.extend(self.sessions.iter().map(|s| Line::from(Span::styled(format!("   {}", s), Theme::value()))));
+        lines.push(Line::default());
+        let keys = if self.detachable {
+            " y/enter: quit   d: quit, leave them running (sheesh attach)   n/esc: stay"
+        } else {
+            " y/enter: quit   n/esc: stay"
+        };
+        lines.push(Line::from(Span::styled(keys, Theme::dimmed())));
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::error())
+                    .title(Span::styled(" Quit sheesh? ", Theme::title())),
+            ),
+            popup,
+        );
+    }
+}
+
+/// What to do about a key ssh-agent doesn't hold.
+pub enum AgentChoice {
+    /// Run `ssh-add` for it, then connect.
+    Add,
+    Connect,
+    Cancel,
+}
+
+/// Asks before connecting with a key the agent doesn't hold.
+pub struct AgentPrompt {
+    pub name: String,
+    pub identity: String,
+}
+
+impl AgentPrompt {
+    /// Apply a key: `a` adds the key, Enter connects anyway, Esc or `n`
+    /// cancels; other keys are ignored.
+    pub fn handle_key(&self, key: &KeyEvent) -> Option<AgentChoice> {
+        match key.code {
+            KeyCode::Char('a') => Some(AgentChoice::Add),
+            KeyCode::Enter => Some(AgentChoice::Connect),
+            KeyCode::Char('n') | KeyCode::Esc => Some(AgentChoice::Cancel),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(80);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(8) / 2,
+            width,
+            height: 8.min(area.height),
+        };
+        let lines = vec![
+            Line::from(Span::styled(format!(" {} isn't loaded in ssh-agent.", self.identity), Theme::label())),
+            Line::from(Span::styled(
+                " ssh will ask for its passphrase in the terminal; the assistant's own ssh sessions",
+                Theme::value(),
+            )),
+            Line::from(Span::styled(" (/man, the shell and OS check) can't, and fail with publickey.", Theme::value())),
+            Line::default(),
+            Line::from(Span::styled(" a: ssh-add it first   enter: connect anyway   esc: cancel", Theme::dimmed())),
+        ];
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(format!(" Connect to {} ", self.name), Theme::title())),
+            ),
+            popup,
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Wheel and key scrolling shared by the terminal and chat panels: the
+//! configured step, natural-scroll inversion, and acceleration while wheel
+//! notches arrive in quick succession.
+
+use std::time::{Duration, Instant};
+
+use super::UiConfig;
+
+/// Notches closer together than this count as one fast spin.
+const SPIN_GAP: Duration = Duration::from_millis(60);
+
+/// Largest multiple of the step a single notch can reach.
+const MAX_BOOST: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Scroller {
+    lines: usize,
+    natural: bool,
+    accelerate: bool,
+    /// Time and direction of the previous notch.
+    last: Option<(Instant, bool)>,
+    /// Notches in the current fast spin.
+    streak: usize,
+}
+
+impl Default for Scroller {
+    fn default() -> Self {
+        Self::new(&UiConfig::default())
+    }
+}
+
+impl Scroller {
+    pub fn new(cfg: &UiConfig) -> Self {
+        Self {
+            lines: cfg.scroll_lines.max(1),
+            natural: cfg.natural_scroll,
+            accelerate: cfg.scroll_acceleration,
+            last: None,
+            streak: 0,
+        }
+    }
+
+    /// Lines moved by one keyboard step.
+    pub fn step(&self) -> usize {
+        self.lines
+    }
+
+    /// Offset change for one wheel notch that the terminal reported as `up`:
+    /// positive moves back into the history.
+    pub fn wheel(&mut self, up: bool) -> isize {
+        let now = Instant::now();
+        let spinning = self
+            .last
+            .is_some_and(|(at, dir)| dir == up && now.duration_since(at) < SPIN_GAP);
+        self.streak = if spinning && self.accelerate { self.streak + 1 } else { 0 };
+        self.last = Some((now, up));
+        let lines = (self.lines * (1 + self.streak / 3).min(MAX_BOOST)) as isize;
+        if up != self.natural { lines } else { -lines }
+    }
+}
@@ -0,0 +1,150 @@
+//! Shell highlighting for the suggestion preview: command names, options,
+//! quoted strings, variables, operators and comments, plus heredoc bodies
+//! shown as the text they are. A lexer of sorts, not a parser; odd input
+//! comes out plain rather than wrong.
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use super::theme::Theme;
+
+fn command() -> Style {
+    Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+}
+
+fn option() -> Style {
+    Style::default().fg(Color::Yellow)
+}
+
+fn string() -> Style {
+    Style::default().fg(Color::Green)
+}
+
+fn variable() -> Style {
+    Style::default().fg(Color::Magenta)
+}
+
+fn operator() -> Style {
+    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+}
+
+/// Highlight `text`, one line per line of it.
+pub fn highlight(text: &str) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    // The word ending the heredoc being read, if inside one.
+    let mut heredoc: Option<String> = None;
+    // A line ending in `\` carries the command on to the next.
+    let mut continued = false;
+    for line in text.lines() {
+        if let Some(end) = &heredoc {
+            if line.trim() == end {
+                heredoc = None;
+                lines.push(Line::from(Span::styled(line.to_string(), operator())));
+            } else {
+                lines.push(Line::from(Span::styled(line.to_string(), string())));
+            }
+            continue;
+        }
+        let (spans, opened) = highlight_line(line, !continued);
+        heredoc = opened;
+        continued = line.trim_end().ends_with('\\');
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+/// One line of commands; `at_start` if its first word is a command name.
+/// Also returns the end word of a heredoc the line opens.
+fn highlight_line(line: &str, mut at_start: bool) -> (Vec<Span<'static>>, Option<String>) {
+    let mut spans = vec![];
+    let mut heredoc = None;
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    let take = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            spans.push(Span::raw(take(start, i)));
+        } else if c == '#' && (start == 0 || chars[start - 1].is_whitespace()) {
+            spans.push(Span::styled(take(start, chars.len()), Theme::dimmed()));
+            break;
+        } else if c == '\'' || c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                // Backslashes escape inside double quotes only.
+                i += if c == '"' && chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            spans.push(Span::styled(take(start, i), string()));
+            at_start = false;
+        } else if c == '$' {
+            i += 1;
+            if i < chars.len() && (chars[i] == '{' || chars[i] == '(') {
+                let close = if chars[i] == '{' { '}' } else { ')' };
+                while i < chars.len() && chars[i] != close {
+                    i += 1;
+                }
+                i = (i + 1).min(chars.len());
+            } else if i < chars.len() && "?#@*!$-0123456789".contains(chars[i]) {
+                i += 1;
+            } else {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+            }
+            spans.push(Span::styled(take(start, i), variable()));
+            at_start = false;
+        } else if "|&;<>()".contains(c) {
+            while i < chars.len() && "|&;<>".contains(chars[i]) && i - start < 3 {
+                i += 1;
+            }
+            i = i.max(start + 1);
+            let op = take(start, i);
+            if op.starts_with("<<") && !op.starts_with("<<<") {
+                heredoc = heredoc_end(&chars[i..]);
+            }
+            // A redirection is followed by a file, anything else by a command.
+            at_start = !op.contains('>') && !op.starts_with('<');
+            spans.push(Span::styled(op, operator()));
+        } else {
+            while i < chars.len() && !chars[i].is_whitespace() && !"|&;<>()'\"$".contains(chars[i]) {
+                i += 1;
+            }
+            let word = take(start, i);
+            // `NAME=value` before a command leaves the next word a command.
+            let assignment = at_start && word.contains('=') && !word.starts_with('=');
+            let style = if assignment {
+                variable()
+            } else if at_start {
+                command()
+            } else if word.starts_with('-') {
+                option()
+            } else {
+                Style::default()
+            };
+            if !assignment && !matches!(word.as_str(), "sudo" | "env" | "time" | "then" | "do" | "else" | "if") {
+                at_start = false;
+            }
+            spans.push(Span::styled(word, style));
+        }
+    }
+    (spans, heredoc)
+}
+
+/// The end word of a heredoc from what follows `<<`: `EOF`, `'EOF'`,
+/// `"EOF"` or `-EOF`.
+fn heredoc_end(rest: &[char]) -> Option<String> {
+    let word: String = rest
+        .iter()
+        .skip_while(|c| c.is_whitespace() || **c == '-')
+        .take_while(|c| !c.is_whitespace() && !";|&>".contains(**c))
+        .filter(|c| **c != '\'' && **c != '"')
+        .collect();
+    (!word.is_empty()).then_some(word)
+}
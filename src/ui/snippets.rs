@@ -0,0 +1,117 @@
+//! The clipboard history popup (F12): what was copied and applied this
+//! run, newest first, to copy again or type into the terminal again.
+
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseEventKind};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Clear, Paragraph},
+};
+
+use super::clipboard::{self, Snippet, SnippetKind};
+use super::menu::MenuOutcome;
+use super::theme::Theme;
+
+/// What to do with the chosen snippet.
+pub enum SnippetAction {
+    Copy(String),
+    Apply(String),
+}
+
+pub struct SnippetPicker {
+    snippets: Vec<Snippet>,
+    selected: usize,
+}
+
+impl SnippetPicker {
+    /// A picker over the history, or `None` while it is empty.
+    pub fn new() -> Option<Self> {
+        let snippets = clipboard::history();
+        (!snippets.is_empty()).then_some(Self { snippets, selected: 0 })
+    }
+
+    /// Arrows or `j / k` move, Enter copies, F4 applies, Esc, `q` or a
+    /// click closes; other keys are ignored.
+    pub fn handle_event(&mut self, event: &Event) -> MenuOutcome<SnippetAction> {
+        match event {
+            Event::Key(KeyEvent { code, .. }) => match code {
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(self.snippets.len() - 1),
+                KeyCode::Enter => return MenuOutcome::Chosen(SnippetAction::Copy(self.chosen())),
+                KeyCode::F(4) => return MenuOutcome::Chosen(SnippetAction::Apply(self.chosen())),
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::F(12) => return MenuOutcome::Closed,
+                _ => {}
+            },
+            Event::Mouse(me) if matches!(me.kind, MouseEventKind::Down(_)) => return MenuOutcome::Closed,
+            _ => {}
+        }
+        MenuOutcome::Open
+    }
+
+    fn chosen(&self) -> String {
+        self.snippets[self.selected].text.clone()
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(90);
+        let height = (self.snippets.len() as u16 + 4).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        // Rows left for snippets once the borders and key line are drawn.
+        let rows = height.saturating_sub(4).max(1) as usize;
+        let first = self.selected.saturating_sub(rows - 1);
+        let text_width = (width as usize).saturating_sub(20);
+
+        let mut lines: Vec<Line> = self
+            .snippets
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(i, snippet)| {
+                let kind = match snippet.kind {
+                    SnippetKind::Copied => "copied",
+                    SnippetKind::Applied => "applied",
+                };
+                let mut text: String = snippet.text.lines().next().unwrap_or("").chars().take(text_width).collect();
+                let more = snippet.text.lines().count().saturating_sub(1);
+                if more > 0 {
+                    text.push_str(&format!(" (+{} lines)", more));
+                }
+                let style = if i == self.selected { Theme::highlight() } else { Theme::value() };
+                Line::from(vec![
+                    Span::styled(format!(" {:<7} {:>4} ", kind, ago(snippet)), Theme::dimmed()),
+                    Span::styled(text, style),
+                ])
+            })
+            .collect();
+        lines.push(Line::default());
+        lines.push(Line::from(Span::styled(" enter: copy   F4: type into terminal   esc: close", Theme::dimmed())));
+
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Theme::selected_border())
+                    .title(Span::styled(" Clipboard history ", Theme::title())),
+            ),
+            popup,
+        );
+    }
+}
+
+/// How long ago `snippet` was copied, e.g. `40s` or `12m`.
+fn ago(snippet: &Snippet) -> String {
+    let secs = snippet.at.elapsed().as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    }
+}
@@ -39,6 +39,10 @@ impl Theme {
         Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
     }
 
+    pub fn success() -> Style {
+        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+    }
+
     pub fn label() -> Style {
         Style::default()
             .fg(Color::DarkGray)
@@ -57,6 +61,10 @@ impl Theme {
         Style::default().fg(Color::Green)
     }
 
+    pub fn chat_assistant() -> Style {
+        Style::default().fg(Color::Rgb(205, 115, 80))
+    }
+
     pub fn md_code_block() -> Style {
         Style::default().fg(Color::Yellow)
     }
@@ -64,4 +72,12 @@ impl Theme {
     pub fn md_code_inline() -> Style {
         Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
     }
+
+    pub fn diff_added() -> Style {
+        Style::default().fg(Color::Green)
+    }
+
+    pub fn diff_removed() -> Style {
+        Style::default().fg(Color::Red)
+    }
 }